@@ -0,0 +1,169 @@
+use crate::config::ConfigSpec;
+use crate::ssz_state::{Fork, StatePartial};
+use serde::Serialize;
+
+/// Size of the active validator set at an epoch and the committees it forms
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ActiveSetStats {
+    pub epoch: u64,
+    pub active_validators: u64,
+    /// Change of the active set since the previous epoch, activations minus exits
+    pub active_validators_delta: i64,
+    pub committees_per_slot: u64,
+    /// Validators not yet activated, and with an exit initiated but not yet effective
+    pub pending_activation: u64,
+    pub pending_exit: u64,
+    /// Validators activated per epoch at most, and the epochs to activate all pending at it
+    pub activation_churn_limit: u64,
+    pub epochs_to_activation: u64,
+}
+
+/// Minimum balance of a validator, the balance churn converts to validators at from Electra
+const MIN_ACTIVATION_BALANCE: u64 = 32_000_000_000;
+
+/// Committees per slot for `active_validators`, as `get_committee_count_per_slot` of the spec
+pub fn committee_count_per_slot(config: &ConfigSpec, active_validators: u64) -> u64 {
+    (active_validators / config.slots_per_epoch / config.target_committee_size)
+        .clamp(1, config.max_committees_per_slot)
+}
+
+/// Validators activated per epoch at most with `active_validators` of `total_active_balance`
+/// Gwei, as `get_validator_activation_churn_limit` of the spec. From Electra the churn limits
+/// balances, counted here in validators of the minimum activation balance.
+pub fn activation_churn_limit(
+    config: &ConfigSpec,
+    epoch: u64,
+    active_validators: u64,
+    total_active_balance: u64,
+) -> u64 {
+    let fork = Fork::at_epoch(config, epoch);
+    if fork >= Fork::Electra {
+        let churn = (total_active_balance / config.churn_limit_quotient)
+            .max(config.min_per_epoch_churn_limit_electra)
+            .min(config.max_per_epoch_activation_exit_churn_limit);
+        return (churn / MIN_ACTIVATION_BALANCE).max(1);
+    }
+    let churn =
+        (active_validators / config.churn_limit_quotient).max(config.min_per_epoch_churn_limit);
+    if fork >= Fork::Deneb {
+        churn.min(config.max_per_epoch_activation_churn_limit)
+    } else {
+        churn
+    }
+}
+
+/// Active set of `state` at `epoch`, the delta computed against the same state at `epoch - 1`
+pub fn active_set_stats(config: &ConfigSpec, state: &StatePartial, epoch: u64) -> ActiveSetStats {
+    let active_at = |epoch: u64| {
+        state
+            .validators
+            .iter()
+            .filter(|v| v.is_active(epoch))
+            .count() as u64
+    };
+    let active_validators = active_at(epoch);
+    let previous = active_at(epoch.saturating_sub(1));
+    let total_active_balance = state
+        .validators
+        .iter()
+        .filter(|v| v.is_active(epoch))
+        .map(|v| v.effective_balance)
+        .sum();
+    let pending_activation = state
+        .validators
+        .iter()
+        .filter(|v| v.activation_epoch > epoch)
+        .count() as u64;
+    let activation_churn_limit =
+        activation_churn_limit(config, epoch, active_validators, total_active_balance);
+    ActiveSetStats {
+        epoch,
+        active_validators,
+        active_validators_delta: active_validators as i64 - previous as i64,
+        committees_per_slot: committee_count_per_slot(config, active_validators),
+        pending_activation,
+        pending_exit: state
+            .validators
+            .iter()
+            .filter(|v| v.is_exiting(epoch))
+            .count() as u64,
+        activation_churn_limit,
+        epochs_to_activation: pending_activation.div_ceil(activation_churn_limit),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssz_state::fixtures::{test_state, test_validator};
+    use crate::ssz_state::{Validator, FAR_FUTURE_EPOCH};
+
+    #[test]
+    fn active_set_stats_test() {
+        let validator = |activation_epoch, exit_epoch| Validator {
+            activation_epoch,
+            exit_epoch,
+            ..test_validator()
+        };
+        let state = StatePartial {
+            slot: 80,
+            // One activated and two exited at epoch 10, two pending activation and one exit
+            validators: vec![
+                validator(0, FAR_FUTURE_EPOCH),
+                validator(10, FAR_FUTURE_EPOCH),
+                validator(0, 10),
+                validator(0, 10),
+                validator(11, FAR_FUTURE_EPOCH),
+                validator(FAR_FUTURE_EPOCH, FAR_FUTURE_EPOCH),
+                validator(0, 12),
+            ],
+            ..test_state(vec![])
+        };
+        assert_eq!(
+            active_set_stats(&ConfigSpec::minimal(), &state, 10),
+            ActiveSetStats {
+                epoch: 10,
+                active_validators: 3,
+                active_validators_delta: -1,
+                committees_per_slot: 1,
+                pending_activation: 2,
+                pending_exit: 1,
+                activation_churn_limit: 2,
+                epochs_to_activation: 1,
+            }
+        );
+
+        let config = ConfigSpec::mainnet();
+        assert_eq!(committee_count_per_slot(&config, 32 * 128 * 3 + 5), 3);
+        assert_eq!(committee_count_per_slot(&config, 1_000_000), 64);
+
+        let churn = |fork_epoch: Option<u64>| ConfigSpec {
+            deneb_fork_epoch: fork_epoch,
+            electra_fork_epoch: fork_epoch.map(|epoch| epoch + 10),
+            ..ConfigSpec::mainnet()
+        };
+        let balance = |active: u64| active * MIN_ACTIVATION_BALANCE;
+        assert_eq!(
+            activation_churn_limit(&churn(None), 5, 1000, balance(1000)),
+            4
+        );
+        assert_eq!(
+            activation_churn_limit(&churn(None), 5, 1_000_000, balance(1_000_000)),
+            15
+        );
+        // Capped from Deneb
+        assert_eq!(
+            activation_churn_limit(&churn(Some(0)), 5, 1_000_000, balance(1_000_000)),
+            8
+        );
+        // Balance churn from Electra, at least the minimum and at most the activation cap
+        assert_eq!(
+            activation_churn_limit(&churn(Some(0)), 10, 1000, balance(1000)),
+            4
+        );
+        assert_eq!(
+            activation_churn_limit(&churn(Some(0)), 10, 1_000_000, balance(1_000_000)),
+            8
+        );
+    }
+}
@@ -0,0 +1,337 @@
+use crate::http_client::default_client;
+use crate::participation::ParticipationByRange;
+use crate::ranges::{format_ranges, group_size, IndexGroups, ThresholdsByGroup};
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+
+/// Payload format of the alert webhook
+#[derive(Debug, Clone, Copy, Default, Serialize, ValueEnum)]
+pub enum AlertFormat {
+    /// The alert fields as JSON
+    #[default]
+    Json,
+    /// Slack incoming webhook message
+    Slack,
+    /// Discord webhook message
+    Discord,
+}
+
+/// Settings of `--alert-webhook-url`
+#[derive(Debug, Clone)]
+pub struct AlertConfig {
+    pub webhook_url: String,
+    /// Target participation below which a group's alert fires
+    pub threshold: f32,
+    /// Margin above `threshold` participation must recover to for the alert to resolve
+    pub hysteresis: f32,
+    pub format: AlertFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertStatus {
+    Firing,
+    Resolved,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Alert {
+    pub status: AlertStatus,
+    pub group: String,
+    pub range: String,
+    /// Target participation ratio
+    pub ratio: f32,
+    /// Target participation ratio of the group in the previous evaluated epoch
+    pub previous_ratio: Option<f32>,
+    pub epoch: u64,
+    pub threshold: f32,
+}
+
+impl Alert {
+    /// Human friendly summary for chat webhooks, with the group name between `bold` markers
+    pub fn message(&self, bold: &str) -> String {
+        let change = match self.previous_ratio {
+            Some(previous) => format!(
+                " ({:+.1} pp from the previous epoch)",
+                (self.ratio - previous) * 100.0
+            ),
+            None => String::new(),
+        };
+        match self.status {
+            AlertStatus::Firing => format!(
+                ":rotating_light: {bold}{}{bold} ({}) target participation {:.1}%{} is below {:.1}% at epoch {}",
+                self.group,
+                self.range,
+                self.ratio * 100.0,
+                change,
+                self.threshold * 100.0,
+                self.epoch
+            ),
+            AlertStatus::Resolved => format!(
+                ":white_check_mark: {bold}{}{bold} ({}) target participation recovered to {:.1}%{} at epoch {}",
+                self.group,
+                self.range,
+                self.ratio * 100.0,
+                change,
+                self.epoch
+            ),
+        }
+    }
+
+    pub fn payload(&self, format: AlertFormat) -> Value {
+        match format {
+            AlertFormat::Json => json!(self),
+            AlertFormat::Slack => json!({ "text": self.message("*") }),
+            AlertFormat::Discord => json!({ "content": self.message("**") }),
+        }
+    }
+}
+
+/// Groups with a firing alert, and the last evaluated ratio of each group
+#[derive(Default)]
+pub struct AlertState {
+    firing: HashSet<String>,
+    last_ratio: HashMap<String, f32>,
+}
+
+impl AlertState {
+    /// Alerts for groups crossing below the threshold, or recovering above threshold plus
+    /// hysteresis, in `epoch`
+    pub fn evaluate(
+        &mut self,
+        config: &AlertConfig,
+        participation_by_range: &ParticipationByRange,
+        epoch: u64,
+    ) -> Vec<Alert> {
+        let mut alerts = vec![];
+        for (range_name, range, summary) in participation_by_range.iter() {
+            let ratio = summary.target_participation_ratio;
+            let previous_ratio = self.last_ratio.insert(range_name.clone(), ratio);
+            let firing = self.firing.contains(range_name);
+            let status = if !firing && ratio < config.threshold {
+                self.firing.insert(range_name.clone());
+                AlertStatus::Firing
+            } else if firing && ratio >= config.threshold + config.hysteresis {
+                self.firing.remove(range_name);
+                AlertStatus::Resolved
+            } else {
+                continue;
+            };
+            alerts.push(Alert {
+                status,
+                group: range_name.clone(),
+                range: format_ranges(range),
+                ratio,
+                previous_ratio,
+                epoch,
+                threshold: config.threshold,
+            });
+        }
+        alerts
+    }
+}
+
+/// A threshold that a single validator of the group missing the target is enough to cross
+#[derive(Debug, PartialEq)]
+pub struct NoisyThreshold {
+    pub group: String,
+    pub size: usize,
+    /// Option or ranges file level the threshold comes from
+    pub source: String,
+    pub threshold: f32,
+    /// Highest whole percentage not crossed by a single missing validator
+    pub suggested: f32,
+}
+
+impl NoisyThreshold {
+    pub fn message(&self) -> String {
+        format!(
+            "group {} has {} validators, a single one missing the target drops participation to {:.1}%, below its {} of {:.1}%. Consider a threshold of at most {:.0}% or a larger group.",
+            self.group,
+            self.size,
+            single_miss_ratio(self.size) * 100.0,
+            self.source,
+            self.threshold * 100.0,
+            self.suggested * 100.0
+        )
+    }
+}
+
+/// Target participation ratio of a group of `size` with one validator missing the target
+fn single_miss_ratio(size: usize) -> f32 {
+    size.saturating_sub(1) as f32 / size as f32
+}
+
+/// Thresholds of `groups` crossed by the noise of a single validator, from `alert_threshold` if
+/// alerts are enabled and the levels in `group_thresholds`. These fire on every miss instead of
+/// on a group wide issue.
+pub fn noisy_thresholds(
+    groups: &IndexGroups,
+    alert_threshold: Option<f32>,
+    group_thresholds: &ThresholdsByGroup,
+) -> Vec<NoisyThreshold> {
+    let mut noisy = vec![];
+    for (group, ranges) in groups {
+        let size = group_size(ranges);
+        if size == 0 {
+            continue;
+        }
+        let thresholds = group_thresholds.get(group).copied().unwrap_or_default();
+        let sources = [
+            ("--alert-threshold", alert_threshold),
+            ("warn threshold", thresholds.warn),
+            ("crit threshold", thresholds.crit),
+        ];
+        for (source, threshold) in sources {
+            let Some(threshold) = threshold else {
+                continue;
+            };
+            let ratio = single_miss_ratio(size);
+            if ratio < threshold {
+                noisy.push(NoisyThreshold {
+                    group: group.clone(),
+                    size,
+                    source: source.to_owned(),
+                    threshold,
+                    suggested: (ratio * 100.0).floor() / 100.0,
+                });
+            }
+        }
+    }
+    noisy
+}
+
+/// POST each alert to the webhook in the configured format. Webhooks point to chat or paging
+/// services rather than the beacon node, so they use the [`default_client`], without the beacon
+/// CA, client certificate and `--proxy`.
+pub async fn send_alerts(config: &AlertConfig, alerts: &[Alert]) -> Result<()> {
+    let client = default_client();
+    for alert in alerts {
+        client
+            .post(&config.webhook_url)
+            .json(&alert.payload(config.format))
+            .send()
+            .await?
+            .error_for_status()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participation::RangeSummary;
+
+    #[allow(clippy::single_range_in_vec_init)]
+    fn participation(target: f32) -> ParticipationByRange {
+        vec![(
+            "lh-geth-0".to_owned(),
+            vec![0..100],
+            RangeSummary {
+                target_participation_ratio: target,
+                ..Default::default()
+            },
+        )]
+    }
+
+    #[test]
+    fn alert_hysteresis() {
+        let config = AlertConfig {
+            webhook_url: String::new(),
+            threshold: 0.9,
+            hysteresis: 0.05,
+            format: AlertFormat::Json,
+        };
+        let mut state = AlertState::default();
+        let statuses: Vec<Vec<AlertStatus>> = [0.95, 0.85, 0.8, 0.92, 0.89, 0.96, 0.97]
+            .into_iter()
+            .enumerate()
+            .map(|(epoch, target)| {
+                state
+                    .evaluate(&config, &participation(target), epoch as u64)
+                    .iter()
+                    .map(|alert| alert.status)
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                vec![],
+                vec![AlertStatus::Firing],
+                vec![],
+                // Above the threshold but within the hysteresis margin
+                vec![],
+                vec![],
+                vec![AlertStatus::Resolved],
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn noisy_thresholds_test() {
+        use crate::ranges::GroupThresholds;
+        let groups = vec![
+            ("small".to_owned(), vec![0..3, 10..12]),
+            ("large".to_owned(), vec![100..600, 700..1200]),
+        ];
+        let group_thresholds = ThresholdsByGroup::from([(
+            "large".to_owned(),
+            GroupThresholds {
+                warn: Some(0.9995),
+                crit: Some(0.9),
+            },
+        )]);
+        let noisy = noisy_thresholds(&groups, Some(0.9), &group_thresholds);
+        assert_eq!(
+            noisy,
+            vec![
+                NoisyThreshold {
+                    group: "small".to_owned(),
+                    size: 5,
+                    source: "--alert-threshold".to_owned(),
+                    threshold: 0.9,
+                    suggested: 0.8,
+                },
+                NoisyThreshold {
+                    group: "large".to_owned(),
+                    size: 1000,
+                    source: "warn threshold".to_owned(),
+                    threshold: 0.9995,
+                    suggested: 0.99,
+                },
+            ]
+        );
+        assert_eq!(
+            noisy[0].message(),
+            "group small has 5 validators, a single one missing the target drops participation to 80.0%, below its --alert-threshold of 90.0%. Consider a threshold of at most 80% or a larger group."
+        );
+        assert!(noisy_thresholds(&groups, None, &ThresholdsByGroup::new()).is_empty());
+    }
+
+    #[test]
+    fn alert_message() {
+        let alert = Alert {
+            status: AlertStatus::Firing,
+            group: "lh-geth-0".to_owned(),
+            range: "0..100".to_owned(),
+            ratio: 0.85,
+            previous_ratio: Some(0.95),
+            epoch: 10,
+            threshold: 0.9,
+        };
+        assert_eq!(
+            alert.message("*"),
+            ":rotating_light: *lh-geth-0* (0..100) target participation 85.0% (-10.0 pp from the previous epoch) is below 90.0% at epoch 10"
+        );
+        assert_eq!(
+            alert.payload(AlertFormat::Discord)["content"],
+            alert.message("**")
+        );
+        assert_eq!(alert.payload(AlertFormat::Json)["previous_ratio"], 0.95f32);
+    }
+}
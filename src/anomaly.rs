@@ -0,0 +1,77 @@
+use crate::participation::ParticipationByRange;
+
+/// Groups needed besides a group to compare it against the rest
+const MIN_OTHER_GROUPS: usize = 2;
+
+/// Whether the target participation of each group is more than `stddevs` standard deviations
+/// below the mean of the other groups. When the other groups all participate the same, any group
+/// below them is anomalous. Empty with fewer than 3 groups.
+pub fn participation_anomalies(
+    participation_by_range: &ParticipationByRange,
+    stddevs: f64,
+) -> Vec<(&str, bool)> {
+    let ratios: Vec<f64> = participation_by_range
+        .iter()
+        .map(|(_, _, summary)| summary.target_participation_ratio as f64)
+        .collect();
+    if ratios.len() < MIN_OTHER_GROUPS + 1 {
+        return vec![];
+    }
+    participation_by_range
+        .iter()
+        .enumerate()
+        .map(|(i, (range_name, _, _))| {
+            let others = ratios
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, ratio)| *ratio);
+            let count = (ratios.len() - 1) as f64;
+            let mean = others.clone().sum::<f64>() / count;
+            let variance = others.map(|ratio| (ratio - mean).powi(2)).sum::<f64>() / count;
+            let anomalous = ratios[i] < mean - stddevs * variance.sqrt();
+            (range_name.as_str(), anomalous)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participation::RangeSummary;
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn participation_anomalies_test() {
+        let by_range = |targets: &[f32]| -> ParticipationByRange {
+            targets
+                .iter()
+                .enumerate()
+                .map(|(i, target)| {
+                    let summary = RangeSummary {
+                        target_participation_ratio: *target,
+                        ..Default::default()
+                    };
+                    (format!("g{i}"), vec![i * 10..i * 10 + 10], summary)
+                })
+                .collect()
+        };
+        assert!(participation_anomalies(&by_range(&[1.0, 0.1]), 2.0).is_empty());
+        // The whole network degraded together is no anomaly
+        let degraded = by_range(&[0.62, 0.6, 0.61, 0.6]);
+        let flagged: Vec<bool> = participation_anomalies(&degraded, 2.0)
+            .into_iter()
+            .map(|(_, anomalous)| anomalous)
+            .collect();
+        assert_eq!(flagged, vec![false; 4]);
+        let one_broken = by_range(&[0.99, 0.97, 0.4, 0.98]);
+        assert_eq!(
+            participation_anomalies(&one_broken, 2.0),
+            vec![("g0", false), ("g1", false), ("g2", true), ("g3", false)]
+        );
+        assert_eq!(
+            participation_anomalies(&by_range(&[1.0, 1.0, 0.99]), 3.0)[2],
+            ("g2", true)
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use crate::common_args::BeaconArgs;
+use anyhow::{anyhow, Context, Result};
+use beacon_metrics_gazer::config::fetch_config;
+use beacon_metrics_gazer::participation::{
+    fetch_state, group_target_participation, ParticipationByRange,
+};
+use beacon_metrics_gazer::ranges::{format_ranges, GroupOrder, RangesArgs};
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use std::io::Write;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum BackfillFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Args)]
+pub struct BackfillArgs {
+    #[command(flatten)]
+    beacon: BeaconArgs,
+    #[command(flatten)]
+    ranges: RangesArgs,
+    /// First epoch to compute participation of
+    #[arg(long)]
+    from_epoch: u64,
+    /// Last epoch to compute participation of, inclusive
+    #[arg(long)]
+    to_epoch: u64,
+    /// Output format, CSV with a header row or one JSON object per line
+    #[arg(long, value_enum, default_value_t = BackfillFormat::Csv)]
+    format: BackfillFormat,
+    /// File to write to instead of stdout
+    #[arg(long)]
+    output: Option<String>,
+    /// Order of groups within each epoch
+    #[arg(long, value_enum, default_value_t = GroupOrder::Config)]
+    sort: GroupOrder,
+}
+
+#[derive(Serialize)]
+struct BackfillRecord<'a> {
+    epoch: u64,
+    name: &'a str,
+    range: String,
+    source: f32,
+    target: f32,
+    head: f32,
+    inactivity_scores_avg: f32,
+    balance_gwei_sum: u64,
+    slashed: u64,
+}
+
+const CSV_HEADER: &str =
+    "epoch,name,range,source,target,head,inactivity_scores_avg,balance_gwei_sum,slashed";
+
+/// Quote a CSV field if it contains a separator, quote or newline
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+impl BackfillRecord<'_> {
+    fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{}",
+            self.epoch,
+            csv_field(self.name),
+            csv_field(&self.range),
+            self.source,
+            self.target,
+            self.head,
+            self.inactivity_scores_avg,
+            self.balance_gwei_sum,
+            self.slashed
+        )
+    }
+}
+
+fn records(epoch: u64, participation_by_range: &ParticipationByRange) -> Vec<BackfillRecord<'_>> {
+    participation_by_range
+        .iter()
+        .map(|(range_name, range, summary)| BackfillRecord {
+            epoch,
+            name: range_name,
+            range: format_ranges(range),
+            source: summary.source_participation_ratio,
+            target: summary.target_participation_ratio,
+            head: summary.head_participation_ratio,
+            inactivity_scores_avg: summary.inactivity_scores_avg,
+            balance_gwei_sum: summary.balance_gwei_sum,
+            slashed: summary.slashed_validators,
+        })
+        .collect()
+}
+
+/// Compute the participation of each epoch in `from_epoch..=to_epoch` from the state at the start
+/// of the next epoch, whose previous epoch participation is complete. Epochs whose state can't be
+/// fetched, e.g. pruned by a non archive node, are logged and skipped.
+pub async fn backfill(args: &BackfillArgs) -> Result<()> {
+    if args.from_epoch > args.to_epoch {
+        return Err(anyhow!("--from-epoch must not be after --to-epoch"));
+    }
+    args.beacon.connect()?;
+    let ranges = args.ranges.require().await?;
+    let url = &args.beacon.url;
+    let config = fetch_config(url).await.context("fetch_config")?;
+
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(
+            std::fs::File::create(path).with_context(|| format!("create --output {path}"))?,
+        ),
+        None => Box::new(std::io::stdout()),
+    };
+    if let BackfillFormat::Csv = args.format {
+        writeln!(out, "{CSV_HEADER}")?;
+    }
+
+    for epoch in args.from_epoch..=args.to_epoch {
+        let slot = (epoch + 1) * config.slots_per_epoch;
+        let state = match fetch_state(&config, url, &slot.to_string(), true).await {
+            Ok(state) => state,
+            Err(e) => {
+                eprintln!(
+                    "error fetching state at slot {slot} for epoch {epoch}: {:?}",
+                    e
+                );
+                continue;
+            }
+        };
+        let mut participation_by_range =
+            group_target_participation(&config.participation_flags, &ranges, &state, epoch + 1);
+        args.sort.sort(
+            &mut participation_by_range,
+            |(range_name, _, _)| range_name,
+            |(_, _, summary)| summary.target_participation_ratio,
+        );
+        for record in records(epoch, &participation_by_range) {
+            match args.format {
+                BackfillFormat::Csv => writeln!(out, "{}", record.to_csv())?,
+                BackfillFormat::Json => writeln!(out, "{}", serde_json::to_string(&record)?)?,
+            }
+        }
+        eprintln!("backfilled epoch {epoch}");
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_field_test() {
+        assert_eq!(csv_field("lh-geth-0"), "lh-geth-0");
+        assert_eq!(csv_field("0..100,200..300"), "\"0..100,200..300\"");
+        assert_eq!(csv_field("a \"b\""), "\"a \"\"b\"\"\"");
+    }
+}
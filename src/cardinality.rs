@@ -0,0 +1,21 @@
+use crate::metrics::{registry, set_gauge, EXPORTED_SERIES};
+
+/// Count the series currently held by the default registry
+pub fn count_series() -> usize {
+    registry()
+        .gather()
+        .iter()
+        .map(|family| family.get_metric().len())
+        .sum()
+}
+
+/// Export the current series count and warn if it exceeds `budget`
+pub fn check_cardinality(budget: Option<usize>) {
+    let series = count_series();
+    set_gauge(&EXPORTED_SERIES, &[], series as f64);
+    if let Some(budget) = budget {
+        if series > budget {
+            eprintln!("WARN exporting {series} series, over the cardinality budget of {budget}");
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use crate::common_args::BeaconArgs;
+use anyhow::{anyhow, Context, Result};
+use beacon_metrics_gazer::config::{fetch_config, fetch_genesis, ConfigSpec};
+use beacon_metrics_gazer::participation::{
+    fetch_state, group_target_participation, parse_state_id, ParticipationByRange,
+};
+use beacon_metrics_gazer::ranges::{
+    clamp_ranges, group_ranges_by_name, GroupOrder, IndexRanges, RangesArgs,
+};
+use beacon_metrics_gazer::report::RunReport;
+use beacon_metrics_gazer::util::to_next_epoch_start;
+use clap::Args;
+use tokio::time;
+
+#[derive(Args)]
+pub struct CheckArgs {
+    #[command(flatten)]
+    beacon: BeaconArgs,
+    #[command(flatten)]
+    ranges: RangesArgs,
+    /// Target participation ratio every group must reach
+    #[arg(long, default_value_t = 0.95)]
+    min_participation: f32,
+    /// State to check: head, finalized, justified or a slot
+    #[arg(long, default_value = "head", value_parser = parse_state_id)]
+    state_id: String,
+    /// Instead of checking one state, check every epoch until all groups were perfect for this
+    /// many consecutive epochs, failing on the first epoch a group is not
+    #[arg(long, conflicts_with = "min_participation")]
+    min_streak: Option<u64>,
+    /// Target participation ratio at or above which a group's epoch counts as perfect for
+    /// --min-streak
+    #[arg(long, default_value_t = 1.0, requires = "min_streak")]
+    perfect_threshold: f32,
+}
+
+/// Group that failed the check, with its target participation or `None` if none of its
+/// validators are in the state
+#[derive(Debug, PartialEq)]
+struct Failure {
+    group: String,
+    target: Option<f32>,
+}
+
+/// Groups of `ranges` whose target participation is below `min`, in the order of the ranges
+fn failing_groups(
+    ranges: &IndexRanges,
+    participation_by_range: &ParticipationByRange,
+    min: f32,
+) -> Vec<Failure> {
+    group_ranges_by_name(ranges)
+        .into_iter()
+        .filter_map(|(group, _)| {
+            let target = participation_by_range
+                .iter()
+                .find(|(range_name, _, _)| *range_name == group)
+                .map(|(_, _, summary)| summary.target_participation_ratio);
+            target
+                .is_none_or(|target| target < min)
+                .then_some(Failure { group, target })
+        })
+        .collect()
+}
+
+fn list_failures(failures: &[Failure]) -> String {
+    let listed: Vec<String> = failures
+        .iter()
+        .map(|failure| match failure.target {
+            Some(target) => format!("{} ({target})", failure.group),
+            None => format!("{} (not in the state)", failure.group),
+        })
+        .collect();
+    listed.join(", ")
+}
+
+/// Whether every group of `report` was perfect for at least the last `min_streak` epochs
+fn streaks_reached(report: &RunReport, min_streak: u64) -> bool {
+    report
+        .perfect_streaks()
+        .all(|(_, streak)| streak >= min_streak)
+}
+
+/// Fetch the state `--state-id` and summarize it per group, with its slot and epoch
+async fn fetch_participation(
+    args: &CheckArgs,
+    config: &ConfigSpec,
+    ranges: &IndexRanges,
+) -> Result<(u64, u64, ParticipationByRange)> {
+    let state = fetch_state(config, &args.beacon.url, &args.state_id, true)
+        .await
+        .context("fetch state")?;
+    let (state_ranges, _) = clamp_ranges(ranges, state.validators.len());
+    let epoch = state.slot / config.slots_per_epoch;
+    let participation_by_range =
+        group_target_participation(&config.participation_flags, &state_ranges, &state, epoch);
+    Ok((state.slot, epoch, participation_by_range))
+}
+
+/// Fetch one state and fail listing the groups whose target participation is below
+/// `--min-participation`. With `--min-streak`, check every epoch instead.
+pub async fn check(args: &CheckArgs) -> Result<()> {
+    args.beacon.connect()?;
+    let ranges = args.ranges.require().await?;
+    let config = fetch_config(&args.beacon.url)
+        .await
+        .context("fetch_config")?;
+    if let Some(min_streak) = args.min_streak {
+        return check_streaks(args, &config, &ranges, min_streak).await;
+    }
+    let (slot, _, participation_by_range) = fetch_participation(args, &config, &ranges).await?;
+    for (range_name, _, summary) in &participation_by_range {
+        println!(
+            "{range_name}: target participation {}",
+            summary.target_participation_ratio
+        );
+    }
+
+    let failures = failing_groups(&ranges, &participation_by_range, args.min_participation);
+    if failures.is_empty() {
+        println!(
+            "all groups reached target participation {} at slot {}",
+            args.min_participation, slot
+        );
+        return Ok(());
+    }
+    Err(anyhow!(
+        "{} groups below target participation {} at slot {}: {}",
+        failures.len(),
+        args.min_participation,
+        slot,
+        list_failures(&failures)
+    ))
+}
+
+/// Check the state every epoch until all groups were perfect for `min_streak` consecutive
+/// epochs, failing on the first epoch any group is below `--perfect-threshold`
+async fn check_streaks(
+    args: &CheckArgs,
+    config: &ConfigSpec,
+    ranges: &IndexRanges,
+    min_streak: u64,
+) -> Result<()> {
+    if args.state_id.parse::<u64>().is_ok() {
+        return Err(anyhow!(
+            "--min-streak needs a state id that advances, not a slot"
+        ));
+    }
+    let genesis = fetch_genesis(&args.beacon.url)
+        .await
+        .context("fetch_genesis")?;
+    let mut report = RunReport::new(ranges, 0.0, args.perfect_threshold, GroupOrder::Config);
+    loop {
+        let (_, state_epoch, participation_by_range) =
+            fetch_participation(args, config, ranges).await?;
+        // previous_epoch_participation refers to the epoch before the state's
+        let epoch = state_epoch.saturating_sub(1);
+        if report.record_participation(epoch, &participation_by_range) {
+            let failures = failing_groups(ranges, &participation_by_range, args.perfect_threshold);
+            if !failures.is_empty() {
+                return Err(anyhow!(
+                    "{} groups below target participation {} in epoch {epoch}: {}",
+                    failures.len(),
+                    args.perfect_threshold,
+                    list_failures(&failures)
+                ));
+            }
+            if streaks_reached(&report, min_streak) {
+                println!(
+                    "all groups reached target participation {} for {min_streak} consecutive \
+                     epochs up to epoch {epoch}",
+                    args.perfect_threshold
+                );
+                return Ok(());
+            }
+            let shortest = report.perfect_streaks().map(|(_, streak)| streak).min();
+            println!(
+                "epoch {epoch}: all groups perfect, shortest streak {} of {min_streak}",
+                shortest.unwrap_or(0)
+            );
+        }
+        time::sleep(to_next_epoch_start(&genesis, config)?).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use beacon_metrics_gazer::participation::RangeSummary;
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn failing_groups_test() {
+        let ranges = vec![
+            ("a".to_owned(), 0..10),
+            ("b".to_owned(), 10..20),
+            ("a".to_owned(), 20..30),
+            ("c".to_owned(), 100..110),
+        ];
+        let summary = |target_participation_ratio| RangeSummary {
+            target_participation_ratio,
+            ..Default::default()
+        };
+        // c is past the end of the state
+        let participation_by_range = vec![
+            ("a".to_owned(), vec![0..10, 20..30], summary(0.95)),
+            ("b".to_owned(), vec![10..20], summary(0.9)),
+        ];
+        assert_eq!(
+            failing_groups(&ranges, &participation_by_range, 0.95),
+            vec![
+                Failure {
+                    group: "b".to_owned(),
+                    target: Some(0.9)
+                },
+                Failure {
+                    group: "c".to_owned(),
+                    target: None
+                },
+            ]
+        );
+        assert_eq!(
+            failing_groups(&ranges, &participation_by_range, 0.5).len(),
+            1
+        );
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn streaks_reached_test() {
+        let ranges = vec![("a".to_owned(), 0..10), ("b".to_owned(), 10..20)];
+        let summary = |target_participation_ratio| RangeSummary {
+            target_participation_ratio,
+            ..Default::default()
+        };
+        let epoch = |b: f32| {
+            vec![
+                ("a".to_owned(), vec![0..10], summary(1.0)),
+                ("b".to_owned(), vec![10..20], summary(b)),
+            ]
+        };
+        let mut report = RunReport::new(&ranges, 0.0, 1.0, GroupOrder::Config);
+        report.record_participation(10, &epoch(1.0));
+        report.record_participation(11, &epoch(1.0));
+        assert!(!streaks_reached(&report, 3));
+        report.record_participation(12, &epoch(1.0));
+        assert!(streaks_reached(&report, 3));
+        // A dip and a skipped epoch both start over
+        report.record_participation(13, &epoch(0.9));
+        report.record_participation(14, &epoch(1.0));
+        assert!(streaks_reached(&report, 1));
+        assert!(!streaks_reached(&report, 2));
+        report.record_participation(16, &epoch(1.0));
+        report.record_participation(17, &epoch(1.0));
+        assert!(streaks_reached(&report, 2));
+        assert!(!streaks_reached(&report, 3));
+    }
+}
@@ -0,0 +1,218 @@
+use crate::http_client::beacon_client;
+use crate::inclusion::parse_hex;
+use crate::ssz_state::StatePartial;
+use anyhow::{anyhow, Result};
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+pub type Root = [u8; 32];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockHeader {
+    pub root: Root,
+    pub slot: u64,
+    pub proposer_index: usize,
+    pub parent_root: Root,
+}
+
+#[derive(Deserialize)]
+struct StateRootResponse {
+    data: StateRootData,
+}
+
+#[derive(Deserialize)]
+struct StateRootData {
+    root: String,
+}
+
+#[derive(Deserialize)]
+struct HeaderResponse {
+    data: HeaderData,
+}
+
+#[derive(Deserialize)]
+struct HeaderData {
+    root: String,
+    header: SignedHeaderJson,
+}
+
+#[derive(Deserialize)]
+struct SignedHeaderJson {
+    message: HeaderMessageJson,
+}
+
+#[derive(Deserialize)]
+struct HeaderMessageJson {
+    slot: String,
+    proposer_index: String,
+    parent_root: String,
+}
+
+pub fn parse_root(input: &str) -> Result<Root> {
+    parse_hex(input)?
+        .try_into()
+        .map_err(|_| anyhow!("Invalid root, expected 32 bytes: {}", input))
+}
+
+pub fn format_root(root: &Root) -> String {
+    format!(
+        "0x{}",
+        root.iter().map(|b| format!("{b:02x}")).collect::<String>()
+    )
+}
+
+/// Fetch the header of the block `block_id`, a root or `head`. Returns `None` if the node does
+/// not know it.
+pub async fn fetch_block_header(url: &str, block_id: &str) -> Result<Option<BlockHeader>> {
+    let response = beacon_client()
+        .get(format!("{url}/eth/v1/beacon/headers/{block_id}"))
+        .send()
+        .await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let data: HeaderResponse = response.error_for_status()?.json().await?;
+    Ok(Some(BlockHeader {
+        root: parse_root(&data.data.root)?,
+        slot: data.data.header.message.slot.parse()?,
+        proposer_index: data.data.header.message.proposer_index.parse()?,
+        parent_root: parse_root(&data.data.header.message.parent_root)?,
+    }))
+}
+
+/// Fetch the root of the state `state_id`
+pub async fn fetch_state_root(url: &str, state_id: &str) -> Result<Root> {
+    let response = beacon_client()
+        .get(format!("{url}/eth/v1/beacon/states/{state_id}/root"))
+        .send()
+        .await?
+        .error_for_status()?;
+    let data: StateRootResponse = response.json().await?;
+    parse_root(&data.data.root)
+}
+
+/// Root of the canonical chain at `slot` according to the state's block_roots, i.e. the root of
+/// the latest block at or before `slot`. `None` if not covered.
+pub fn root_at(state: &StatePartial, slot: u64) -> Option<Root> {
+    let len = state.block_roots.len() as u64;
+    if len == 0 || slot >= state.slot || state.slot - slot > len {
+        return None;
+    }
+    Some(state.block_roots[(slot % len) as usize])
+}
+
+/// Block root trusted by the operator, every fetched state must be on the same chain. Guards
+/// against pointing the exporter at a node on a wrong fork. The anchor advances with each
+/// verified state so that later states can be checked against their own block_roots.
+#[derive(Debug, Clone)]
+pub struct TrustedCheckpoint {
+    /// Slot at which `root` is the chain's root
+    slot: u64,
+    root: Root,
+}
+
+impl TrustedCheckpoint {
+    /// Resolve the slot of the trusted block, errors if the node does not know it
+    pub async fn fetch(url: &str, root: Root) -> Result<Self> {
+        let header = fetch_block_header(url, &format_root(&root))
+            .await?
+            .ok_or_else(|| {
+                anyhow!(
+                    "node does not know the trusted block root {}, wrong fork or not synced",
+                    format_root(&root)
+                )
+            })?;
+        Ok(Self {
+            slot: header.slot,
+            root,
+        })
+    }
+
+    /// Check that `state` descends from the anchor, or precedes it on the same chain. Walks
+    /// block headers when the anchor is not covered by the state's block_roots.
+    pub async fn verify(&mut self, url: &str, state: &StatePartial) -> Result<()> {
+        if state.slot <= self.slot {
+            // The node is behind the checkpoint, its latest block must be an ancestor
+            let latest = root_at(state, state.slot.saturating_sub(1))
+                .ok_or_else(|| anyhow!("state without block_roots"))?;
+            let ancestor = walk_back(url, self.root, state.slot.saturating_sub(1)).await?;
+            return check(ancestor, latest, state.slot.saturating_sub(1));
+        }
+
+        let root = match root_at(state, self.slot) {
+            Some(root) => root,
+            None => {
+                let oldest_slot = state.slot - state.block_roots.len() as u64;
+                let oldest = root_at(state, oldest_slot)
+                    .ok_or_else(|| anyhow!("state without block_roots"))?;
+                walk_back(url, oldest, self.slot).await?
+            }
+        };
+        check(root, self.root, self.slot)?;
+
+        // Re-anchor half a block_roots window behind the state: deep enough to never be
+        // reorged, recent enough for the next states to cover it
+        let anchor_slot = state.slot - state.block_roots.len() as u64 / 2;
+        if anchor_slot > self.slot {
+            if let Some(root) = root_at(state, anchor_slot) {
+                self.slot = anchor_slot;
+                self.root = root;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check(root: Root, expected: Root, slot: u64) -> Result<()> {
+    if root == expected {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "chain root at slot {} is {}, expected trusted {}",
+            slot,
+            format_root(&root),
+            format_root(&expected)
+        ))
+    }
+}
+
+/// Follow parent roots from `root` to the chain's root at `slot`
+async fn walk_back(url: &str, mut root: Root, slot: u64) -> Result<Root> {
+    loop {
+        let header = fetch_block_header(url, &format_root(&root))
+            .await?
+            .ok_or_else(|| anyhow!("node does not know block {}", format_root(&root)))?;
+        if header.slot <= slot {
+            return Ok(root);
+        }
+        root = header.parent_root;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssz_state::fixtures::test_state;
+
+    #[test]
+    fn root_at_test() {
+        let state = StatePartial {
+            slot: 10,
+            // Slots 8, 9, 6, 7 by index
+            block_roots: vec![[8; 32], [9; 32], [6; 32], [7; 32]],
+            ..test_state(vec![])
+        };
+        assert_eq!(root_at(&state, 9), Some([9; 32]));
+        assert_eq!(root_at(&state, 6), Some([6; 32]));
+        assert_eq!(root_at(&state, 5), None);
+        assert_eq!(root_at(&state, 10), None);
+    }
+
+    #[test]
+    fn parse_root_test() {
+        let root = parse_root(&format!("0x{}", "ab".repeat(32))).unwrap();
+        assert_eq!(root, [0xab; 32]);
+        assert_eq!(format_root(&root), format!("0x{}", "ab".repeat(32)));
+        assert!(parse_root("0xabcd").is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::ranges::{group_ranges_by_name, IndexRanges};
+use crate::ssz_state::StatePartial;
+use std::collections::BTreeMap;
+
+/// Consensus clients and the name fragments that identify them in group names
+const CONSENSUS_CLIENTS: [(&str, &[&str]); 6] = [
+    ("lighthouse", &["lighthouse", "lh"]),
+    ("prysm", &["prysm"]),
+    ("teku", &["teku"]),
+    ("lodestar", &["lodestar"]),
+    ("nimbus", &["nimbus"]),
+    ("grandine", &["grandine"]),
+];
+/// Execution clients and the name fragments that identify them in group names
+const EXECUTION_CLIENTS: [(&str, &[&str]); 6] = [
+    ("geth", &["geth"]),
+    ("nethermind", &["nethermind"]),
+    ("besu", &["besu"]),
+    ("erigon", &["erigon"]),
+    ("reth", &["reth"]),
+    ("ethereumjs", &["ethereumjs"]),
+];
+
+/// Client implementations a group runs according to its name, as (layer, client), where layer is
+/// `consensus` or `execution`. Names are split at non alphanumeric characters, so client pair
+/// names like `lh-geth-0` or `prysm_nethermind_1` are recognized.
+pub fn clients_of(group: &str) -> Vec<(&'static str, &'static str)> {
+    let tokens: Vec<String> = group
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .map(str::to_lowercase)
+        .collect();
+    let layers = [
+        ("consensus", &CONSENSUS_CLIENTS),
+        ("execution", &EXECUTION_CLIENTS),
+    ];
+    layers
+        .into_iter()
+        .flat_map(|(layer, clients)| {
+            clients
+                .iter()
+                .filter(|(_, fragments)| {
+                    tokens
+                        .iter()
+                        .any(|token| fragments.contains(&token.as_str()))
+                })
+                .map(move |(client, _)| (layer, *client))
+        })
+        .collect()
+}
+
+/// Share of the network's active stake run by each recognized client, keyed by (layer, client).
+/// Validators of groups without a recognized client, and ungrouped ones, count towards the
+/// total only.
+pub fn client_stake_shares(
+    ranges: &IndexRanges,
+    state: &StatePartial,
+    epoch: u64,
+) -> BTreeMap<(&'static str, &'static str), f64> {
+    let active_balance = |index: usize| {
+        state
+            .validators
+            .get(index)
+            .filter(|validator| validator.is_active(epoch))
+            .map_or(0, |validator| validator.effective_balance)
+    };
+    let total: u64 = (0..state.validators.len()).map(active_balance).sum();
+    let mut stake: BTreeMap<(&'static str, &'static str), u64> = BTreeMap::new();
+    for (range_name, group_ranges) in group_ranges_by_name(ranges) {
+        let clients = clients_of(&range_name);
+        if clients.is_empty() {
+            continue;
+        }
+        let group_stake: u64 = group_ranges
+            .iter()
+            .flat_map(|range| range.clone())
+            .map(active_balance)
+            .sum();
+        for client in clients {
+            *stake.entry(client).or_default() += group_stake;
+        }
+    }
+    stake
+        .into_iter()
+        .map(|(client, stake)| (client, stake as f64 / total.max(1) as f64))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssz_state::fixtures::{test_state, test_validator};
+    use crate::ssz_state::{Validator, FAR_FUTURE_EPOCH};
+
+    #[test]
+    fn client_stake_shares_test() {
+        assert_eq!(
+            clients_of("lh-geth-0"),
+            vec![("consensus", "lighthouse"), ("execution", "geth")]
+        );
+        assert_eq!(
+            clients_of("Nethermind teku-1"),
+            vec![("consensus", "teku"), ("execution", "nethermind")]
+        );
+        // Fragments only match whole tokens
+        assert_eq!(clients_of("ethereum-flash-0"), vec![]);
+
+        let validator = |exit_epoch| Validator {
+            exit_epoch,
+            ..test_validator()
+        };
+        let mut validators = vec![validator(FAR_FUTURE_EPOCH); 10];
+        // Exited, not part of the active stake
+        validators[9] = validator(1);
+        let state = StatePartial {
+            slot: 80,
+            validators,
+            ..test_state(vec![])
+        };
+        let ranges = vec![
+            ("lh-geth-0".to_owned(), 0..6),
+            ("teku-geth-0".to_owned(), 6..8),
+            ("unknown".to_owned(), 8..10),
+        ];
+        let shares = client_stake_shares(&ranges, &state, 5);
+        assert_eq!(
+            shares.into_iter().collect::<Vec<_>>(),
+            vec![
+                (("consensus", "lighthouse"), 6.0 / 9.0),
+                (("consensus", "teku"), 2.0 / 9.0),
+                (("execution", "geth"), 8.0 / 9.0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,561 @@
+use crate::alerts::AlertConfig;
+use crate::checkpoint::TrustedCheckpoint;
+use crate::config::{fetch_config, fetch_genesis, ConfigSpec, Genesis};
+use crate::custom_metrics::parse_custom_metrics;
+use crate::db::ParticipationDb;
+use crate::events::task_subscribe_events;
+use crate::exporter::{
+    check_group_sizes, config_hash, detect_network, print_participation_once, record_start,
+    task_fetch_state_every_epoch, task_poll_head, task_poll_node_status, task_poll_node_version,
+    task_simulate, unix_time, warn_overlapping_ranges, Config, DumpFile, FetchTaskOptions,
+};
+use crate::gossip::task_subscribe_attestations;
+use crate::http_client::parse_headers;
+use crate::kurtosis::parse_kurtosis_participants;
+use crate::metrics::{
+    enable_target_label, register_process_collector, set_gauge, set_thread_target, EXPORTER_PAUSED,
+};
+use crate::miss_streaks::MissStreaks;
+use crate::my_validators::{
+    parse_validator_ids, ranges_of_indices, resolve_validator_indices, MY_VALIDATORS_GROUP,
+};
+use crate::participation::fetch_state;
+use crate::persistence::{parse_history_file, render_history, write_atomic, ConfigChange};
+use crate::pubkey_cache::PubkeyCache;
+use crate::ranges::{
+    dump_ranges, group_ranges_by_name, split_ranges, GroupThresholds, RangesArgs, ThresholdsByGroup,
+};
+use crate::redact::{load_mapping, save_mapping, GroupRedaction};
+use crate::reorgs::task_subscribe_reorgs;
+use crate::report::RunReport;
+use crate::rotating_file::RotatingFile;
+use crate::server::{serve, LatestParticipation, Staleness, TargetState};
+use crate::shutdown::Shutdown;
+use crate::simulate::Scenario;
+use crate::util::resolve_path_or_url;
+use crate::withdrawal::ranges_by_withdrawal_address;
+use anyhow::{anyhow, Context, Result};
+use std::future::Future;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{oneshot, Notify};
+use tokio::task::JoinHandle;
+use tokio::time;
+
+/// Run one collection per `--target`, each on its own thread so its metrics are labeled with the
+/// target's name, and serve them all from one metrics server
+pub(crate) async fn run_targets(args: Config, shutdown: &Shutdown) -> Result<()> {
+    enable_target_label()?;
+    register_process_collector()?;
+    let mut threads = vec![];
+    let mut started = vec![];
+    for target in &args.target {
+        let target_args = Config {
+            url: Some(target.url.clone()),
+            ranges: RangesArgs {
+                ranges: None,
+                ranges_file: Some(target.ranges_file.clone()),
+            },
+            target: vec![],
+            ..args.clone()
+        };
+        let name = target.name.clone();
+        let shutdown = shutdown.clone();
+        let (sender, receiver) = oneshot::channel();
+        started.push((target.name.clone(), receiver));
+        let thread = thread::Builder::new()
+            .name(format!("target-{name}"))
+            .spawn(move || -> Result<()> {
+                set_thread_target(&name);
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()?;
+                runtime.block_on(async {
+                    let mut collection =
+                        match start_collection(&target_args, &name, &shutdown).await {
+                            Ok(collection) => collection.expect("--once conflicts with --target"),
+                            Err(e) => {
+                                let _ = sender.send(Err(e));
+                                return Ok(());
+                            }
+                        };
+                    let _ = sender.send(Ok(collection.target.clone()));
+                    drain_within(collection.drain(), &shutdown, target_args.shutdown_timeout).await;
+                    collection.stop(&target_args)
+                })
+            })?;
+        threads.push(thread);
+    }
+
+    let mut targets = vec![];
+    for (name, receiver) in started {
+        let target = receiver
+            .await
+            .map_err(|_| anyhow!("target {name} stopped while starting"))
+            .and_then(|target| target.with_context(|| format!("start target {name}")));
+        match target {
+            Ok(target) => targets.push(target),
+            Err(e) => {
+                shutdown.trigger();
+                return Err(e);
+            }
+        }
+    }
+    serve(&args, targets, shutdown)?.await;
+    // Each target drains its fetch within --shutdown-timeout on its own thread
+    for thread in threads {
+        tokio::task::spawn_blocking(move || thread.join())
+            .await?
+            .map_err(|_| anyhow!("target thread panicked"))??;
+    }
+    Ok(())
+}
+
+/// Fetch loop of one beacon node and its tasks, running until shutdown
+pub(crate) struct Collection {
+    pub(crate) target: Arc<TargetState>,
+    task: JoinHandle<Result<()>>,
+    /// Tasks that only end with the exporter
+    background: Vec<JoinHandle<()>>,
+}
+
+impl Collection {
+    /// Wait for the fetch loop, which stops at its next wait after shutdown
+    pub(crate) async fn drain(&mut self) {
+        match (&mut self.task).await {
+            Ok(Err(e)) => eprintln!("error in fetch task: {:?}", e),
+            Err(e) => eprintln!("fetch task failed: {:?}", e),
+            Ok(Ok(())) => {}
+        }
+    }
+
+    /// Stop all tasks and emit the run report
+    pub(crate) fn stop(self, args: &Config) -> Result<()> {
+        self.task.abort();
+        for task in self.background {
+            task.abort();
+        }
+
+        // Emit the run report on shutdown
+        let json = self.target.report.lock().unwrap().render_json();
+        match self.target.name.as_str() {
+            "" => println!("run report ---\n{}\n---", json),
+            name => println!("run report of {name} ---\n{}\n---", json),
+        }
+        if let Some(report_file) = &args.report_file {
+            std::fs::write(report_file, &json).with_context(|| format!("write {report_file}"))?;
+        }
+        if let Some(history_file) = &args.history_file {
+            let target = &self.target;
+            save_history(
+                history_file,
+                &target.report,
+                &target.miss_streaks,
+                &target.participation,
+                &target.config_change,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Save the history of a collection to `path`, see --history-file
+pub(crate) fn save_history(
+    path: &str,
+    report: &Mutex<RunReport>,
+    miss_streaks: &Mutex<MissStreaks>,
+    participation: &LatestParticipation,
+    config: &ConfigChange,
+) -> Result<()> {
+    let json = render_history(
+        &report.lock().unwrap(),
+        &miss_streaks.lock().unwrap(),
+        &participation.history.lock().unwrap(),
+        config,
+    );
+    write_atomic(path, &json)
+}
+
+/// Run `drain`, giving up `timeout_secs` after shutdown was requested
+pub(crate) async fn drain_within(
+    drain: impl Future<Output = ()>,
+    shutdown: &Shutdown,
+    timeout_secs: u64,
+) {
+    let drain_timeout = async {
+        shutdown.wait().await;
+        time::sleep(Duration::from_secs(timeout_secs)).await;
+    };
+    tokio::select! {
+        _ = drain => {}
+        _ = drain_timeout => eprintln!(
+            "WARN shutdown did not complete within {}s, exiting",
+            timeout_secs
+        ),
+    }
+}
+
+/// Fetch the genesis, config and ranges of the beacon node of `args` and start collecting
+/// from it, `None` once `--once` printed the participation
+pub(crate) async fn start_collection(
+    args: &Config,
+    name: &str,
+    shutdown: &Shutdown,
+) -> Result<Option<Collection>> {
+    let beacon_url = match &args.url {
+        Some(url) => url.clone(),
+        None if args.simulate => String::new(),
+        None => return Err(anyhow!("Must set URL")),
+    };
+
+    // --once prints nothing but the participation to stdout
+    if args.simulate {
+        println!("simulating participation, no beacon node is read");
+    } else if !args.once {
+        println!("connecting to beacon URL {:?}", beacon_url);
+    }
+
+    if !args.beacon.headers.is_empty() && !args.once {
+        println!("extra headers {:?}", parse_headers(&args.beacon.headers)?);
+    }
+
+    let (genesis, mut config) = if args.simulate {
+        let genesis_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        (Genesis { genesis_time }, ConfigSpec::mainnet())
+    } else {
+        let genesis = fetch_genesis(&beacon_url).await.context("fetch_genesis")?;
+        if !args.once {
+            println!("beacon genesis {:?}", genesis);
+        }
+        let config = fetch_config(&beacon_url).await.context("fetch_config")?;
+        (genesis, config)
+    };
+    let flags = &mut config.participation_flags;
+    flags.source = args.source_flag_mask.unwrap_or(flags.source);
+    flags.target = args.target_flag_mask.unwrap_or(flags.target);
+    flags.head = args.head_flag_mask.unwrap_or(flags.head);
+    if !args.once {
+        println!("beacon config {:?}", config);
+    }
+
+    // Resolve the validators of --my-validators into indices
+    let my_validators = match &args.my_validators {
+        Some(ids) => {
+            let mut cache = PubkeyCache::load(args.pubkey_cache.as_deref())?;
+            resolve_validator_indices(&beacon_url, &parse_validator_ids(ids)?, &mut cache)
+                .await
+                .context("resolve --my-validators")?
+        }
+        None => vec![],
+    };
+
+    // Parse groups file mapping index ranges to host names
+    let loaded_ranges = if args.auto_groups.is_some() || args.group_by_withdrawal_address {
+        let state = fetch_state(&config, &beacon_url, "head", !args.no_compression)
+            .await
+            .context("fetch head state to derive groups")?;
+        let ranges = match args.auto_groups {
+            Some(groups) => split_ranges(
+                state.validators.len(),
+                groups as usize,
+                &args.auto_groups_name,
+            ),
+            None => ranges_by_withdrawal_address(&state, &args.withdrawal_addresses),
+        };
+        Some((ranges, ThresholdsByGroup::new()))
+    } else if let Some(path_or_url) = &args.ranges_from_kurtosis {
+        Some((
+            parse_kurtosis_participants(&resolve_path_or_url(path_or_url).await?)
+                .context("parse --ranges-from-kurtosis")?,
+            ThresholdsByGroup::new(),
+        ))
+    } else {
+        args.ranges.load().await?
+    };
+    let (mut ranges, group_thresholds) = match loaded_ranges {
+        Some(ranges) => ranges,
+        None if !my_validators.is_empty() => (vec![], ThresholdsByGroup::new()),
+        None => {
+            return Err(anyhow!(
+                "Must set --ranges, --ranges-file, --ranges-from-kurtosis, --auto-groups, --group-by-withdrawal-address or --my-validators"
+            ))
+        }
+    };
+    ranges.extend(ranges_of_indices(MY_VALIDATORS_GROUP, &my_validators));
+    if !args.once {
+        println!("index ranges ---\n{}\n---", dump_ranges(&ranges));
+    }
+    let network_label = detect_network(&config, &args.label, args.no_network_label);
+
+    let scenario = match &args.simulate_scenario {
+        Some(path_or_url) => Scenario::parse(&resolve_path_or_url(path_or_url).await?)
+            .context("parse --simulate-scenario")?,
+        None => Scenario::default(),
+    };
+
+    let trusted_checkpoint = match args.trusted_block_root {
+        Some(root) => Some(
+            TrustedCheckpoint::fetch(&beacon_url, root)
+                .await
+                .context("--trusted-block-root")?,
+        ),
+        None => None,
+    };
+
+    let custom_metrics = match &args.custom_metrics_file {
+        Some(path_or_url) => parse_custom_metrics(&resolve_path_or_url(path_or_url).await?)
+            .context("parse --custom-metrics-file")?,
+        None => vec![],
+    };
+
+    let mut run_report = RunReport::new(
+        &ranges,
+        args.incident_threshold,
+        args.perfect_threshold,
+        args.sort,
+    );
+    let mut miss_streaks = MissStreaks::default();
+    let participation = LatestParticipation::new(args.history_epochs);
+    let mut previous_config = None;
+    if let Some(history_file) = &args.history_file {
+        // A missing or unreadable history must not prevent an upgraded exporter from starting
+        match std::fs::read_to_string(history_file) {
+            Ok(json) => match parse_history_file(&json) {
+                Ok(previous) => {
+                    run_report.restore(previous.report);
+                    miss_streaks = previous.miss_streaks;
+                    participation.restore_history(previous.history);
+                    previous_config = previous.config;
+                    println!("restored history from {history_file}");
+                }
+                Err(e) => eprintln!("WARN ignoring history file {history_file}: {:?}", e),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => eprintln!("WARN ignoring history file {history_file}: {:?}", e),
+        }
+    }
+    let config_change = ConfigChange::since(
+        previous_config,
+        config_hash(args, &ranges),
+        unix_time() as u64,
+    );
+    record_start(&config_change);
+    let db = match &args.db {
+        Some(path) => {
+            let db = ParticipationDb::open(path).context("open --db")?;
+            participation.restore_history(db.latest(args.history_epochs)?);
+            let replayed = db.snapshots_after(run_report.last_epoch())?;
+            for snapshot in &replayed {
+                run_report.record_snapshot(snapshot);
+            }
+            if !replayed.is_empty() {
+                println!("replayed {} epochs from {path}", replayed.len());
+            }
+            Some(Mutex::new(db))
+        }
+        None => None,
+    };
+    let report = Arc::new(Mutex::new(run_report));
+
+    // Background task fetching state every interval and registering participation
+    // in metrics with provided index ranges
+    let task_report = report.clone();
+    let options = FetchTaskOptions {
+        dump_format: args.dump,
+        dump_sort: args.dump_sort,
+        dump_min: args.dump_min,
+        dump_missing: args.dump_missing,
+        dump_colors: args.color.enabled().then_some(GroupThresholds {
+            warn: Some(args.color_warn),
+            crit: Some(args.color_crit),
+        }),
+        dump_file: args.dump_file.as_ref().map(|path| DumpFile {
+            format: args.dump_file_format,
+            file: Mutex::new(RotatingFile::new(
+                path,
+                Some(args.dump_file_max_mb * 1_000_000).filter(|max| *max > 0),
+                args.dump_file_max_age.map(Duration::from_secs),
+                args.dump_file_keep,
+            )),
+        }),
+        proposal_correlation_window: args.proposal_correlation_window,
+        per_validator_metrics: args.per_validator_metrics || !my_validators.is_empty(),
+        my_validators,
+        cardinality_budget: args.cardinality_budget,
+        custom_metrics,
+        inclusion_delay: args.inclusion_delay,
+        wrong_target: args.wrong_target,
+        packing_efficiency: args.packing_efficiency,
+        attestation_inclusion: args.attestation_inclusion,
+        client_risk_thresholds: if args.client_diversity {
+            args.client_risk_threshold.clone()
+        } else {
+            vec![]
+        },
+        graffiti_prefixes: args.graffiti_groups.clone(),
+        group_by_fee_recipient: args.group_by_fee_recipient,
+        attestation_rewards: args.attestation_rewards,
+        estimated_penalties: args.estimated_penalties,
+        anomaly_stddevs: args.anomaly_stddevs,
+        miss_streak_epochs: args.miss_streak_epochs,
+        compression: !args.no_compression,
+        reorgs: args.reorgs,
+        gossip_seen: args.gossip_timeliness.then(Arc::default),
+        trusted_checkpoint,
+        max_sync_distance: args.max_sync_distance,
+        stagger_fraction: args.stagger_fraction,
+        fetch_jitter_secs: args.fetch_jitter_secs,
+        balance_unit: args.balance_unit,
+        group_order: args.sort,
+        follow_distance: args.follow_distance,
+        state_id: args.state_id.clone(),
+        group_thresholds,
+        alerts: args
+            .alert_webhook_url
+            .clone()
+            .map(|webhook_url| AlertConfig {
+                webhook_url,
+                threshold: args.alert_threshold,
+                hysteresis: args.alert_hysteresis,
+                format: args.alert_format,
+            }),
+        epoch_events: args.event_stream.then(|| Arc::new(Notify::new())),
+        active_set: Arc::default(),
+        participation: Arc::new(participation),
+        validators: Arc::default(),
+        miss_streaks: Arc::new(Mutex::new(miss_streaks)),
+        history_file: args.history_file.clone(),
+        config_change: config_change.clone(),
+        db,
+        parquet_dir: args.parquet_dir.clone(),
+        parquet_validators: args.parquet_validators,
+        paused: Arc::new(AtomicBool::new(false)),
+        shutdown: shutdown.clone(),
+    };
+    check_group_sizes(&ranges, &options);
+    warn_overlapping_ranges(&ranges);
+    if args.once {
+        print_participation_once(&genesis, &config, &beacon_url, &ranges, &options).await?;
+        return Ok(None);
+    }
+    set_gauge(&EXPORTER_PAUSED, &[], 0.0);
+    let paused = options.paused.clone();
+    let active_set = options.active_set.clone();
+    let participation = options.participation.clone();
+    let group_thresholds = options.group_thresholds.clone();
+    let validators = options.validators.clone();
+    let miss_streaks = options.miss_streaks.clone();
+    let seconds_per_epoch = config.seconds_per_slot * config.slots_per_epoch;
+    let group_names: Vec<String> = group_ranges_by_name(&ranges)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    let redaction = match args.redact_groups {
+        Some(mode) => {
+            let path = args.redact_mapping_file.as_deref();
+            let mapping = path.map(load_mapping).transpose()?.unwrap_or_default();
+            let redaction = GroupRedaction::new(mode, &group_names, &args.redact_salt, &mapping)?;
+            if let Some(path) = path {
+                save_mapping(path, &redaction.mapping())?;
+            }
+            Some(Arc::new(redaction))
+        }
+        None => None,
+    };
+    // Tasks that only end with the exporter
+    let mut background = vec![];
+    let task = if args.simulate {
+        tokio::spawn(async move {
+            task_simulate(&config, &ranges, &options, &task_report, &scenario).await;
+            Ok(())
+        })
+    } else {
+        if let Some(epoch_events) = options.epoch_events.clone() {
+            let events_url = beacon_url.clone();
+            let slots_per_epoch = config.slots_per_epoch;
+            background.push(tokio::spawn(async move {
+                task_subscribe_events(&events_url, slots_per_epoch, epoch_events).await
+            }));
+        }
+        if let Some(gossip_seen) = options.gossip_seen.clone() {
+            let gossip_url = beacon_url.clone();
+            let gossip_config = config.clone();
+            let gossip_ranges = ranges.clone();
+            let genesis_time = genesis.genesis_time;
+            background.push(tokio::spawn(async move {
+                task_subscribe_attestations(
+                    &gossip_url,
+                    genesis_time,
+                    &gossip_config,
+                    &gossip_ranges,
+                    gossip_seen,
+                )
+                .await
+            }));
+        }
+        if options.reorgs {
+            let reorgs_url = beacon_url.clone();
+            let reorgs_ranges = ranges.clone();
+            background.push(tokio::spawn(async move {
+                task_subscribe_reorgs(&reorgs_url, &reorgs_ranges).await
+            }));
+        }
+        let seconds_per_slot = config.seconds_per_slot;
+        let head_url = beacon_url.clone();
+        let head_paused = paused.clone();
+        background.push(tokio::spawn(async move {
+            task_poll_head(&head_url, seconds_per_slot, &head_paused).await
+        }));
+        let status_url = beacon_url.clone();
+        let status_paused = paused.clone();
+        background.push(tokio::spawn(async move {
+            task_poll_node_status(&status_url, seconds_per_slot, &status_paused).await
+        }));
+        let version_url = beacon_url.clone();
+        background.push(tokio::spawn(async move {
+            task_poll_node_version(&version_url).await
+        }));
+        tokio::spawn(async move {
+            task_fetch_state_every_epoch(
+                &genesis,
+                &config,
+                &beacon_url,
+                &ranges,
+                &options,
+                &task_report,
+            )
+            .await
+        })
+    };
+
+    let target = Arc::new(TargetState {
+        name: name.to_owned(),
+        static_labels: network_label.into_iter().collect(),
+        report,
+        paused,
+        active_set,
+        participation,
+        group_thresholds,
+        color_thresholds: GroupThresholds {
+            warn: Some(args.color_warn),
+            crit: Some(args.color_crit),
+        },
+        validators,
+        miss_streaks,
+        config_change,
+        staleness: args.stale_after_epochs.map(|epochs| Staleness {
+            max_age_secs: (epochs * seconds_per_epoch) as f64,
+            zero: args.zero_stale,
+            started_at: unix_time(),
+            groups: group_names,
+        }),
+        redaction,
+    });
+    Ok(Some(Collection {
+        target,
+        task,
+        background,
+    }))
+}
@@ -0,0 +1,20 @@
+use anyhow::Result;
+use beacon_metrics_gazer::http_client::{set_beacon_client, BeaconRequestArgs};
+use clap::Args;
+
+/// Beacon node options shared by the subcommands that read one node. The URL is required here,
+/// unlike in `serve` where `--simulate` and `--target` do without it.
+#[derive(Args)]
+pub struct BeaconArgs {
+    /// Beacon HTTP API URL: http://1.2.3.4:4000
+    pub url: String,
+    #[command(flatten)]
+    request: BeaconRequestArgs,
+}
+
+impl BeaconArgs {
+    /// Configure the client of the beacon API requests
+    pub fn connect(&self) -> Result<()> {
+        set_beacon_client(&self.request)
+    }
+}
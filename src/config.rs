@@ -1,13 +1,145 @@
-use anyhow::{Error, Result};
+use crate::http_client::beacon_client;
+use anyhow::{anyhow, Error, Result};
 use serde::Deserialize;
 
-#[derive(Debug)]
+/// Spec values of the beacon node's network
+#[derive(Debug, Clone)]
 pub struct ConfigSpec {
     pub seconds_per_slot: u64,
     pub slots_per_epoch: u64,
     pub slots_per_historical_root: usize,
     pub epochs_per_historical_vector: usize,
     pub epochs_per_slashings_vector: usize,
+    pub sync_committee_size: usize,
+    pub max_committees_per_slot: u64,
+    pub target_committee_size: u64,
+    /// Churn limits on validator counts, and from Electra on balances in Gwei
+    pub min_per_epoch_churn_limit: u64,
+    pub churn_limit_quotient: u64,
+    pub max_per_epoch_activation_churn_limit: u64,
+    pub min_per_epoch_churn_limit_electra: u64,
+    pub max_per_epoch_activation_exit_churn_limit: u64,
+    /// Fork schedule, `None` if the node does not report the fork
+    pub altair_fork_epoch: Option<u64>,
+    pub bellatrix_fork_epoch: Option<u64>,
+    pub capella_fork_epoch: Option<u64>,
+    pub deneb_fork_epoch: Option<u64>,
+    pub electra_fork_epoch: Option<u64>,
+    pub participation_flags: ParticipationFlags,
+    /// `CONFIG_NAME` and `DEPOSIT_CHAIN_ID` of the node's config, `None` if not reported
+    pub config_name: Option<String>,
+    pub deposit_chain_id: Option<u64>,
+}
+
+// https://github.com/ethereum/consensus-specs/blob/4a27f855439c16612ab1ae3995d71bed54f979ea/specs/altair/beacon-chain.md#participation-flag-indices
+const TIMELY_SOURCE_FLAG_INDEX: u8 = 0;
+const TIMELY_TARGET_FLAG_INDEX: u8 = 1;
+const TIMELY_HEAD_FLAG_INDEX: u8 = 2;
+
+/// Masks of the participation flags a validator must have set to count as timely source, target
+/// and head. Derived from the spec flag indices, research networks may override them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParticipationFlags {
+    pub source: u8,
+    pub target: u8,
+    pub head: u8,
+}
+
+impl ParticipationFlags {
+    fn from_indices(source: u8, target: u8, head: u8) -> Result<Self> {
+        let mask = |index: u8| {
+            1u8.checked_shl(index as u32)
+                .ok_or_else(|| anyhow!("Participation flag index {} out of bounds", index))
+        };
+        Ok(ParticipationFlags {
+            source: mask(source)?,
+            target: mask(target)?,
+            head: mask(head)?,
+        })
+    }
+}
+
+impl Default for ParticipationFlags {
+    fn default() -> Self {
+        ParticipationFlags {
+            source: 1 << TIMELY_SOURCE_FLAG_INDEX,
+            target: 1 << TIMELY_TARGET_FLAG_INDEX,
+            head: 1 << TIMELY_HEAD_FLAG_INDEX,
+        }
+    }
+}
+
+impl ConfigSpec {
+    /// Values of the mainnet preset, without fork schedule
+    pub fn mainnet() -> Self {
+        ConfigSpec {
+            seconds_per_slot: 12,
+            slots_per_epoch: 32,
+            slots_per_historical_root: 8192,
+            epochs_per_historical_vector: 65536,
+            epochs_per_slashings_vector: 8192,
+            sync_committee_size: 512,
+            max_committees_per_slot: 64,
+            target_committee_size: 128,
+            min_per_epoch_churn_limit: DEFAULT_MIN_PER_EPOCH_CHURN_LIMIT,
+            churn_limit_quotient: DEFAULT_CHURN_LIMIT_QUOTIENT,
+            max_per_epoch_activation_churn_limit: DEFAULT_MAX_PER_EPOCH_ACTIVATION_CHURN_LIMIT,
+            min_per_epoch_churn_limit_electra: DEFAULT_MIN_PER_EPOCH_CHURN_LIMIT_ELECTRA,
+            max_per_epoch_activation_exit_churn_limit:
+                DEFAULT_MAX_PER_EPOCH_ACTIVATION_EXIT_CHURN_LIMIT,
+            altair_fork_epoch: None,
+            bellatrix_fork_epoch: None,
+            capella_fork_epoch: None,
+            deneb_fork_epoch: None,
+            electra_fork_epoch: None,
+            participation_flags: ParticipationFlags::default(),
+            config_name: None,
+            deposit_chain_id: None,
+        }
+    }
+
+    /// Values of the minimal preset, without fork schedule
+    pub fn minimal() -> Self {
+        ConfigSpec {
+            seconds_per_slot: 6,
+            slots_per_epoch: 8,
+            slots_per_historical_root: 64,
+            epochs_per_historical_vector: 64,
+            epochs_per_slashings_vector: 64,
+            sync_committee_size: 32,
+            max_committees_per_slot: 4,
+            target_committee_size: 4,
+            min_per_epoch_churn_limit: 2,
+            churn_limit_quotient: 32,
+            max_per_epoch_activation_churn_limit: 4,
+            min_per_epoch_churn_limit_electra: 64_000_000_000,
+            max_per_epoch_activation_exit_churn_limit: 128_000_000_000,
+            ..Self::mainnet()
+        }
+    }
+
+    /// Name of the network: its `CONFIG_NAME`, or the well known network of its deposit chain id
+    pub fn network_name(&self) -> Option<String> {
+        match &self.config_name {
+            Some(name) if !name.is_empty() => Some(name.clone()),
+            _ => self
+                .deposit_chain_id
+                .and_then(known_network)
+                .map(str::to_owned),
+        }
+    }
+}
+
+/// Public network of a deposit chain id
+pub fn known_network(deposit_chain_id: u64) -> Option<&'static str> {
+    match deposit_chain_id {
+        1 => Some("mainnet"),
+        100 => Some("gnosis"),
+        17000 => Some("holesky"),
+        560048 => Some("hoodi"),
+        11155111 => Some("sepolia"),
+        _ => None,
+    }
 }
 
 #[allow(non_snake_case)]
@@ -18,6 +150,24 @@ struct ConfigSpecResponseData {
     SLOTS_PER_HISTORICAL_ROOT: String,
     EPOCHS_PER_HISTORICAL_VECTOR: String,
     EPOCHS_PER_SLASHINGS_VECTOR: String,
+    SYNC_COMMITTEE_SIZE: Option<String>,
+    MAX_COMMITTEES_PER_SLOT: Option<String>,
+    TARGET_COMMITTEE_SIZE: Option<String>,
+    MIN_PER_EPOCH_CHURN_LIMIT: Option<String>,
+    CHURN_LIMIT_QUOTIENT: Option<String>,
+    MAX_PER_EPOCH_ACTIVATION_CHURN_LIMIT: Option<String>,
+    MIN_PER_EPOCH_CHURN_LIMIT_ELECTRA: Option<String>,
+    MAX_PER_EPOCH_ACTIVATION_EXIT_CHURN_LIMIT: Option<String>,
+    ALTAIR_FORK_EPOCH: Option<String>,
+    BELLATRIX_FORK_EPOCH: Option<String>,
+    CAPELLA_FORK_EPOCH: Option<String>,
+    DENEB_FORK_EPOCH: Option<String>,
+    ELECTRA_FORK_EPOCH: Option<String>,
+    TIMELY_SOURCE_FLAG_INDEX: Option<String>,
+    TIMELY_TARGET_FLAG_INDEX: Option<String>,
+    TIMELY_HEAD_FLAG_INDEX: Option<String>,
+    CONFIG_NAME: Option<String>,
+    DEPOSIT_CHAIN_ID: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -25,8 +175,12 @@ struct ConfigSpecResponse {
     data: ConfigSpecResponseData,
 }
 
-pub async fn  fetch_config(url: &str) -> Result<ConfigSpec> {
-    let response = reqwest::get(format!("{url}/eth/v1/config/spec")).await?;
+/// Fetch the spec of the beacon node at `url`
+pub async fn fetch_config(url: &str) -> Result<ConfigSpec> {
+    let response = beacon_client()
+        .get(format!("{url}/eth/v1/config/spec"))
+        .send()
+        .await?;
     let data: ConfigSpecResponse = response.json().await?;
     Ok(ConfigSpec {
         seconds_per_slot: parse_usize(&data.data.SECONDS_PER_SLOT, "SECONDS_PER_SLOT")? as u64,
@@ -43,13 +197,114 @@ pub async fn  fetch_config(url: &str) -> Result<ConfigSpec> {
             &data.data.EPOCHS_PER_SLASHINGS_VECTOR,
             "EPOCHS_PER_SLASHINGS_VECTOR",
         )?,
+        sync_committee_size: match &data.data.SYNC_COMMITTEE_SIZE {
+            Some(size) => parse_usize(size, "SYNC_COMMITTEE_SIZE")?,
+            None => DEFAULT_SYNC_COMMITTEE_SIZE,
+        },
+        max_committees_per_slot: match &data.data.MAX_COMMITTEES_PER_SLOT {
+            Some(max) => parse_usize(max, "MAX_COMMITTEES_PER_SLOT")? as u64,
+            None => DEFAULT_MAX_COMMITTEES_PER_SLOT,
+        },
+        target_committee_size: match &data.data.TARGET_COMMITTEE_SIZE {
+            Some(size) => parse_usize(size, "TARGET_COMMITTEE_SIZE")? as u64,
+            None => DEFAULT_TARGET_COMMITTEE_SIZE,
+        },
+        min_per_epoch_churn_limit: parse_u64_opt(
+            &data.data.MIN_PER_EPOCH_CHURN_LIMIT,
+            "MIN_PER_EPOCH_CHURN_LIMIT",
+        )?
+        .unwrap_or(DEFAULT_MIN_PER_EPOCH_CHURN_LIMIT),
+        churn_limit_quotient: parse_u64_opt(
+            &data.data.CHURN_LIMIT_QUOTIENT,
+            "CHURN_LIMIT_QUOTIENT",
+        )?
+        .unwrap_or(DEFAULT_CHURN_LIMIT_QUOTIENT),
+        max_per_epoch_activation_churn_limit: parse_u64_opt(
+            &data.data.MAX_PER_EPOCH_ACTIVATION_CHURN_LIMIT,
+            "MAX_PER_EPOCH_ACTIVATION_CHURN_LIMIT",
+        )?
+        .unwrap_or(DEFAULT_MAX_PER_EPOCH_ACTIVATION_CHURN_LIMIT),
+        min_per_epoch_churn_limit_electra: parse_u64_opt(
+            &data.data.MIN_PER_EPOCH_CHURN_LIMIT_ELECTRA,
+            "MIN_PER_EPOCH_CHURN_LIMIT_ELECTRA",
+        )?
+        .unwrap_or(DEFAULT_MIN_PER_EPOCH_CHURN_LIMIT_ELECTRA),
+        max_per_epoch_activation_exit_churn_limit: parse_u64_opt(
+            &data.data.MAX_PER_EPOCH_ACTIVATION_EXIT_CHURN_LIMIT,
+            "MAX_PER_EPOCH_ACTIVATION_EXIT_CHURN_LIMIT",
+        )?
+        .unwrap_or(DEFAULT_MAX_PER_EPOCH_ACTIVATION_EXIT_CHURN_LIMIT),
+        altair_fork_epoch: parse_u64_opt(&data.data.ALTAIR_FORK_EPOCH, "ALTAIR_FORK_EPOCH")?,
+        bellatrix_fork_epoch: parse_u64_opt(
+            &data.data.BELLATRIX_FORK_EPOCH,
+            "BELLATRIX_FORK_EPOCH",
+        )?,
+        capella_fork_epoch: parse_u64_opt(&data.data.CAPELLA_FORK_EPOCH, "CAPELLA_FORK_EPOCH")?,
+        deneb_fork_epoch: parse_u64_opt(&data.data.DENEB_FORK_EPOCH, "DENEB_FORK_EPOCH")?,
+        electra_fork_epoch: parse_u64_opt(&data.data.ELECTRA_FORK_EPOCH, "ELECTRA_FORK_EPOCH")?,
+        participation_flags: ParticipationFlags::from_indices(
+            parse_flag_index(
+                &data.data.TIMELY_SOURCE_FLAG_INDEX,
+                TIMELY_SOURCE_FLAG_INDEX,
+            )?,
+            parse_flag_index(
+                &data.data.TIMELY_TARGET_FLAG_INDEX,
+                TIMELY_TARGET_FLAG_INDEX,
+            )?,
+            parse_flag_index(&data.data.TIMELY_HEAD_FLAG_INDEX, TIMELY_HEAD_FLAG_INDEX)?,
+        )?,
+        deposit_chain_id: parse_u64_opt(&data.data.DEPOSIT_CHAIN_ID, "DEPOSIT_CHAIN_ID")?,
+        config_name: data.data.CONFIG_NAME,
     })
 }
 
+/// Mainnet preset values, used if the node does not expose them
+const DEFAULT_SYNC_COMMITTEE_SIZE: usize = 512;
+const DEFAULT_MAX_COMMITTEES_PER_SLOT: u64 = 64;
+const DEFAULT_TARGET_COMMITTEE_SIZE: u64 = 128;
+const DEFAULT_MIN_PER_EPOCH_CHURN_LIMIT: u64 = 4;
+const DEFAULT_CHURN_LIMIT_QUOTIENT: u64 = 65536;
+const DEFAULT_MAX_PER_EPOCH_ACTIVATION_CHURN_LIMIT: u64 = 8;
+const DEFAULT_MIN_PER_EPOCH_CHURN_LIMIT_ELECTRA: u64 = 128_000_000_000;
+const DEFAULT_MAX_PER_EPOCH_ACTIVATION_EXIT_CHURN_LIMIT: u64 = 256_000_000_000;
+
 fn parse_usize(usize_str: &str, name: &'static str) -> Result<usize> {
     usize_str.parse().map_err(|e| Error::new(e).context(name))
 }
 
+fn parse_u64_opt(value: &Option<String>, name: &'static str) -> Result<Option<u64>> {
+    value
+        .as_ref()
+        .map(|value| value.parse().map_err(|e| Error::new(e).context(name)))
+        .transpose()
+}
+
+/// Flag indices are spec constants some clients do not include in the config response
+fn parse_flag_index(index_str: &Option<String>, default: u8) -> Result<u8> {
+    match index_str {
+        Some(index) => index
+            .parse()
+            .map_err(|e| Error::new(e).context("participation flag index")),
+        None => Ok(default),
+    }
+}
+
+/// Parse a flag mask as decimal, 0x prefixed hex or 0b prefixed binary
+pub fn parse_flag_mask(input: &str) -> Result<u8> {
+    let mask = if let Some(hex) = input.strip_prefix("0x") {
+        u8::from_str_radix(hex, 16)?
+    } else if let Some(bin) = input.strip_prefix("0b") {
+        u8::from_str_radix(bin, 2)?
+    } else {
+        input.parse()?
+    };
+    if mask == 0 {
+        return Err(anyhow!("Flag mask must have at least one bit set"));
+    }
+    Ok(mask)
+}
+
+/// Genesis of the beacon node's network
 #[derive(Debug, Deserialize)]
 pub struct Genesis {
     pub genesis_time: u64,
@@ -65,10 +320,68 @@ struct BeaconGenesisResponseData {
     genesis_time: String,
 }
 
+/// Fetch the genesis of the beacon node at `url`
 pub async fn fetch_genesis(url: &str) -> Result<Genesis> {
-    let response = reqwest::get(format!("{url}/eth/v1/beacon/genesis")).await?;
+    let response = beacon_client()
+        .get(format!("{url}/eth/v1/beacon/genesis"))
+        .send()
+        .await?;
     let data: BeaconGenesisResponse = response.json().await?;
     Ok(Genesis {
         genesis_time: data.data.genesis_time.parse()?,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn participation_flags_from_indices() {
+        assert_eq!(
+            ParticipationFlags::from_indices(0, 1, 2).unwrap(),
+            ParticipationFlags::default()
+        );
+        assert_eq!(
+            ParticipationFlags::from_indices(3, 4, 5).unwrap(),
+            ParticipationFlags {
+                source: 0b1000,
+                target: 0b10000,
+                head: 0b100000
+            }
+        );
+        assert!(ParticipationFlags::from_indices(0, 1, 8).is_err());
+    }
+
+    #[test]
+    fn network_name_test() {
+        let config = |config_name: Option<&str>, deposit_chain_id| ConfigSpec {
+            config_name: config_name.map(str::to_owned),
+            deposit_chain_id,
+            ..ConfigSpec::mainnet()
+        };
+        assert_eq!(
+            config(Some("devnet-12"), Some(1)).network_name().as_deref(),
+            Some("devnet-12")
+        );
+        assert_eq!(
+            config(Some(""), Some(17000)).network_name().as_deref(),
+            Some("holesky")
+        );
+        assert_eq!(
+            config(None, Some(560048)).network_name().as_deref(),
+            Some("hoodi")
+        );
+        assert_eq!(config(None, Some(1337)).network_name(), None);
+        assert_eq!(config(None, None).network_name(), None);
+    }
+
+    #[test]
+    fn parse_flag_mask_test() {
+        assert_eq!(parse_flag_mask("3").unwrap(), 3);
+        assert_eq!(parse_flag_mask("0x06").unwrap(), 6);
+        assert_eq!(parse_flag_mask("0b101").unwrap(), 5);
+        assert!(parse_flag_mask("0").is_err());
+        assert!(parse_flag_mask("256").is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use anyhow::{anyhow, Context, Result};
+use beacon_metrics_gazer::http_client::{build_client, BeaconRequestArgs};
+use beacon_metrics_gazer::util::resolve_path_or_url_with;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Command, FromArgMatches};
+use std::ffi::OsString;
+use toml::{Table, Value};
+
+/// Parse a TOML config file into its top level options
+pub fn parse_config_file(input: &str) -> Result<Table> {
+    Ok(input.parse::<Table>()?)
+}
+
+/// CLI value of a config file value, `None` for nested arrays and tables
+fn config_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Integer(value) => Some(value.to_string()),
+        Value::Float(value) => Some(value.to_string()),
+        Value::Boolean(value) => Some(value.to_string()),
+        Value::Datetime(value) => Some(value.to_string()),
+        Value::Array(_) | Value::Table(_) => None,
+    }
+}
+
+/// Render config entries as CLI arguments of `command`, skipping options already set on the
+/// command line or by environment variable so that both take precedence. Keys are option names, with `-` or `_`.
+/// Positional values come first.
+pub fn config_args(
+    command: &Command,
+    matches: &ArgMatches,
+    entries: &Table,
+) -> Result<Vec<String>> {
+    let mut positionals = vec![];
+    let mut options = vec![];
+    for (key, value) in entries {
+        let id = key.replace('-', "_");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_id().as_str() == id && id != "config")
+            .ok_or_else(|| anyhow!("unknown option {}", key))?;
+        if matches!(
+            matches.value_source(&id),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        ) {
+            continue;
+        }
+        let values = match value {
+            Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            let value = match (value, arg.get_action().takes_values()) {
+                (Value::Boolean(true), false) => None,
+                (Value::Boolean(false), false) => continue,
+                (value, true) => {
+                    Some(config_value(value).ok_or_else(|| anyhow!("invalid value for {}", key))?)
+                }
+                _ => return Err(anyhow!("invalid value for {}", key)),
+            };
+            match (arg.get_long(), value) {
+                (Some(long), Some(value)) => options.push(format!("--{long}={value}")),
+                (Some(long), None) => options.push(format!("--{long}")),
+                (None, Some(value)) => positionals.push(value),
+                (None, None) => return Err(anyhow!("invalid value for {}", key)),
+            }
+        }
+    }
+    positionals.extend(options);
+    Ok(positionals)
+}
+
+/// Insert the options of the `--config` file, if set, into the CLI `args` of `command`, or of its
+/// subcommand if it takes `--config` too
+pub async fn with_config_file(command: Command, args: Vec<OsString>) -> Result<Vec<OsString>> {
+    // Parsed leniently, the file may provide required options. Errors show on the final parse.
+    let Ok(matches) = command
+        .clone()
+        .ignore_errors(true)
+        .try_get_matches_from(&args)
+    else {
+        return Ok(args);
+    };
+    // Options of a subcommand go after its name, which must directly follow the binary
+    let (command, matches, prefix_len) = match matches.subcommand() {
+        None => (command, matches, 1),
+        Some((name, sub_matches)) => match command.find_subcommand(name) {
+            Some(sub) if sub.get_arguments().any(|arg| arg.get_id() == "config") => {
+                (sub.clone(), sub_matches.clone(), 2)
+            }
+            _ => return Ok(args),
+        },
+    };
+    let Some(path) = matches.get_one::<String>("config") else {
+        return Ok(args);
+    };
+    // A remote file is fetched with the beacon request options of the command line and environment
+    let request_args = BeaconRequestArgs::from_arg_matches(&matches).unwrap_or_default();
+    let client = build_client(&request_args)?;
+    let contents = resolve_path_or_url_with(&client, path)
+        .await
+        .with_context(|| format!("read --config {path}"))?;
+    let entries = parse_config_file(&contents).with_context(|| format!("parse --config {path}"))?;
+    let config_args =
+        config_args(&command, &matches, &entries).with_context(|| format!("--config {path}"))?;
+
+    let mut args = args.into_iter();
+    let prefix: Vec<OsString> = args.by_ref().take(prefix_len).collect();
+    Ok(prefix
+        .into_iter()
+        .chain(config_args.into_iter().map(OsString::from))
+        .chain(args)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config_file_test() {
+        let entries = parse_config_file(
+            r#"
+# Exporter settings
+url = "http://localhost:5052"
+ranges-file = 'ranges.txt'
+port = 9_090  # comment
+inclusion_delay = true
+alert-threshold = 0.9
+headers = [
+    "Authorization: Bearer abc",
+    "X-Escaped: \"quoted\" \u00e9",
+]
+"#,
+        )
+        .unwrap();
+        let values: Vec<(&str, Option<String>)> = entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), config_value(value)))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                ("url", Some("http://localhost:5052".to_owned())),
+                ("ranges-file", Some("ranges.txt".to_owned())),
+                ("port", Some("9090".to_owned())),
+                ("inclusion_delay", Some("true".to_owned())),
+                ("alert-threshold", Some("0.9".to_owned())),
+                ("headers", None),
+            ]
+        );
+        assert_eq!(
+            entries["headers"][1].as_str(),
+            Some("X-Escaped: \"quoted\" \u{e9}")
+        );
+        assert!(parse_config_file("port = 1 2").is_err());
+        assert!(parse_config_file("port = 1\nport = 2").is_err());
+    }
+
+    #[test]
+    fn config_args_test() {
+        use clap::CommandFactory;
+        let command = crate::Cli::command();
+        let matches = command
+            .clone()
+            .ignore_errors(true)
+            .try_get_matches_from(["gazer", "--port", "1234"])
+            .unwrap();
+        let entries = parse_config_file(
+            "url = \"http://localhost:5052\"\nport = 9090\ndump = 'table'\ninclusion-delay = true\nwrong_target = false\nheaders = [\"A: 1\", \"B: 2\"]",
+        )
+        .unwrap();
+        assert_eq!(
+            config_args(&command, &matches, &entries).unwrap(),
+            vec![
+                "http://localhost:5052",
+                "--dump=table",
+                "--inclusion-delay",
+                "--headers=A: 1",
+                "--headers=B: 2",
+            ]
+        );
+        let entries = parse_config_file("unknown = 1").unwrap();
+        assert!(config_args(&command, &matches, &entries).is_err());
+        let entries = parse_config_file("[dump]\nformat = 'json'").unwrap();
+        assert!(config_args(&command, &matches, &entries).is_err());
+        let description = "description = '''\nmultiline\nstring'''";
+        assert!(parse_config_file(description).is_ok());
+    }
+}
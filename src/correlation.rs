@@ -0,0 +1,222 @@
+use crate::config::ParticipationFlags;
+use crate::duties::{Committee, ProposerDuty};
+use crate::participation::has_flag;
+use crate::ranges::{range_name_of, IndexRanges};
+use crate::ssz_state::StatePartial;
+use std::collections::{HashMap, VecDeque};
+
+/// Attesters and how many of them missed the timely head flag
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct HeadVotes {
+    pub attesters: u64,
+    pub misses: u64,
+}
+
+/// Proposal outcome of a slot plus the head votes, per range, of the attesters assigned to that
+/// slot and the next one. Those are the votes a late or missing proposal affects.
+#[derive(Debug)]
+pub struct SlotSample {
+    pub proposer_range: Option<String>,
+    pub missed: bool,
+    adjacent_head_votes: HashMap<String, HeadVotes>,
+}
+
+impl SlotSample {
+    /// Head votes of all ranges except the proposer's
+    pub fn other_head_votes(&self) -> HeadVotes {
+        self.head_votes_excluding(self.proposer_range.as_deref())
+    }
+
+    fn head_votes_excluding(&self, excluded: Option<&str>) -> HeadVotes {
+        self.adjacent_head_votes
+            .iter()
+            .filter(|(range_name, _)| Some(range_name.as_str()) != excluded)
+            .fold(HeadVotes::default(), |acc, (_, votes)| HeadVotes {
+                attesters: acc.attesters + votes.attesters,
+                misses: acc.misses + votes.misses,
+            })
+    }
+}
+
+/// Build one sample per slot of `slots` whose proposal outcome is known from `state`.
+/// `state.previous_epoch_participation` must correspond to the epoch of `committees`.
+pub fn epoch_slot_samples(
+    flags: &ParticipationFlags,
+    ranges: &IndexRanges,
+    state: &StatePartial,
+    slots: std::ops::Range<u64>,
+    duties: &[ProposerDuty],
+    committees: &[Committee],
+) -> Vec<SlotSample> {
+    let mut votes_by_slot: HashMap<u64, HashMap<String, HeadVotes>> = HashMap::new();
+    for committee in committees {
+        let votes_by_range = votes_by_slot.entry(committee.slot).or_default();
+        for index in committee.validators.iter() {
+            let (Some(range_name), Some(participation)) = (
+                range_name_of(ranges, *index),
+                state.previous_epoch_participation.get(*index),
+            ) else {
+                continue;
+            };
+            let votes = votes_by_range.entry(range_name.to_string()).or_default();
+            votes.attesters += 1;
+            votes.misses += !has_flag(*participation, flags.head) as u64;
+        }
+    }
+
+    slots
+        .clone()
+        .filter_map(|slot| {
+            let missed = !state.block_proposed_at(slot)?;
+            let proposer_range = duties
+                .iter()
+                .find(|duty| duty.slot == slot)
+                .and_then(|duty| range_name_of(ranges, duty.validator_index))
+                .map(|name| name.to_string());
+
+            let mut adjacent_head_votes: HashMap<String, HeadVotes> = HashMap::new();
+            for adjacent_slot in [slot, slot + 1].into_iter().filter(|s| slots.contains(s)) {
+                for (range_name, votes) in votes_by_slot.get(&adjacent_slot).into_iter().flatten() {
+                    let acc = adjacent_head_votes.entry(range_name.clone()).or_default();
+                    acc.attesters += votes.attesters;
+                    acc.misses += votes.misses;
+                }
+            }
+
+            Some(SlotSample {
+                proposer_range,
+                missed,
+                adjacent_head_votes,
+            })
+        })
+        .collect()
+}
+
+/// Rolling window of slot samples to correlate each range's missed proposals with the head
+/// misses of all other ranges around those slots
+pub struct ProposalCorrelation {
+    window_slots: usize,
+    samples: VecDeque<SlotSample>,
+    last_epoch: Option<u64>,
+}
+
+impl ProposalCorrelation {
+    pub fn new(window_epochs: u64, slots_per_epoch: u64) -> Self {
+        Self {
+            window_slots: (window_epochs * slots_per_epoch) as usize,
+            samples: VecDeque::new(),
+            last_epoch: None,
+        }
+    }
+
+    /// Push the samples of `epoch` into the window. Returns false and ignores the samples if the
+    /// epoch was already recorded.
+    pub fn record(&mut self, epoch: u64, samples: Vec<SlotSample>) -> bool {
+        if self.last_epoch.is_some_and(|last| epoch <= last) {
+            return false;
+        }
+        self.last_epoch = Some(epoch);
+        self.samples.extend(samples);
+        while self.samples.len() > self.window_slots {
+            self.samples.pop_front();
+        }
+        true
+    }
+
+    /// Pearson correlation over the window between "`range_name` missed the proposal of the slot"
+    /// and the head miss ratio of all other ranges' adjacent attesters. `None` if either series
+    /// is constant, e.g. the range did not miss any proposal in the window.
+    pub fn correlation(&self, range_name: &str) -> Option<f64> {
+        let points: Vec<(f64, f64)> = self
+            .samples
+            .iter()
+            .filter_map(|sample| {
+                let votes = sample.head_votes_excluding(Some(range_name));
+                if votes.attesters == 0 {
+                    return None;
+                }
+                let missed_own_proposal =
+                    sample.missed && sample.proposer_range.as_deref() == Some(range_name);
+                Some((
+                    missed_own_proposal as u8 as f64,
+                    votes.misses as f64 / votes.attesters as f64,
+                ))
+            })
+            .collect();
+        pearson(&points)
+    }
+}
+
+fn pearson(points: &[(f64, f64)]) -> Option<f64> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (mut cov, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+    for (x, y) in points {
+        cov += (x - mean_x) * (y - mean_y);
+        var_x += (x - mean_x).powi(2);
+        var_y += (y - mean_y).powi(2);
+    }
+    if var_x == 0.0 || var_y == 0.0 {
+        return None;
+    }
+    Some(cov / (var_x * var_y).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(proposer: &str, missed: bool, other_misses: u64) -> SlotSample {
+        SlotSample {
+            proposer_range: Some(proposer.to_owned()),
+            missed,
+            adjacent_head_votes: HashMap::from([
+                (
+                    "a".to_owned(),
+                    HeadVotes {
+                        attesters: 10,
+                        misses: 0,
+                    },
+                ),
+                (
+                    "b".to_owned(),
+                    HeadVotes {
+                        attesters: 10,
+                        misses: other_misses,
+                    },
+                ),
+            ]),
+        }
+    }
+
+    #[test]
+    fn correlation_of_harmful_proposer() {
+        let mut correlation = ProposalCorrelation::new(1, 4);
+        correlation.record(
+            0,
+            vec![
+                sample("a", true, 8),
+                sample("a", false, 1),
+                sample("b", false, 0),
+                sample("a", true, 7),
+            ],
+        );
+        assert!(correlation.correlation("a").unwrap() > 0.9);
+        // "b" never missed a proposal
+        assert_eq!(correlation.correlation("b"), None);
+        assert_eq!(
+            correlation.samples[0].other_head_votes(),
+            HeadVotes {
+                attesters: 10,
+                misses: 8
+            }
+        );
+
+        // Already recorded epochs are ignored, the window drops old samples
+        assert!(!correlation.record(0, vec![sample("a", false, 0)]));
+        assert!(correlation.record(1, vec![sample("a", false, 0)]));
+        assert_eq!(correlation.samples.len(), 4);
+        assert_eq!(correlation.samples[0].other_head_votes().misses, 1);
+    }
+}
@@ -0,0 +1,264 @@
+use crate::participation::RangeSummary;
+use anyhow::{anyhow, Result};
+
+/// Per group values available to custom metric expressions
+pub const VARIABLES: &[&str] = &[
+    "source",
+    "target",
+    "head",
+    "inactivity",
+    "balance_gwei_sum",
+    "balance_gwei_avg",
+    "slashed",
+    "exiting",
+    "exited",
+    "withdrawable",
+];
+
+fn summary_variable(summary: &RangeSummary, name: &str) -> Option<f64> {
+    Some(match name {
+        "source" => summary.source_participation_ratio as f64,
+        "target" => summary.target_participation_ratio as f64,
+        "head" => summary.head_participation_ratio as f64,
+        "inactivity" => summary.inactivity_scores_avg as f64,
+        "balance_gwei_sum" => summary.balance_gwei_sum as f64,
+        "balance_gwei_avg" => summary.balance_gwei_avg,
+        "slashed" => summary.slashed_validators as f64,
+        "exiting" => summary.exiting_validators as f64,
+        "exited" => summary.exited_validators as f64,
+        "withdrawable" => summary.withdrawable_validators as f64,
+        _ => return None,
+    })
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Expr {
+    Num(f64),
+    Var(String),
+    Neg(Box<Expr>),
+    Bin(char, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn eval(&self, var: &impl Fn(&str) -> Option<f64>) -> Result<f64> {
+        Ok(match self {
+            Expr::Num(n) => *n,
+            Expr::Var(name) => var(name).ok_or_else(|| anyhow!("Unknown variable {}", name))?,
+            Expr::Neg(e) => -e.eval(var)?,
+            Expr::Bin(op, a, b) => {
+                let (a, b) = (a.eval(var)?, b.eval(var)?);
+                let value = match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    _ => a / b,
+                };
+                // Division by zero or an overflow must not be exported as NaN or inf
+                if !value.is_finite() {
+                    return Err(anyhow!("{} {} {} is not finite", a, op, b));
+                }
+                value
+            }
+        })
+    }
+
+    fn vars<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            Expr::Num(_) => {}
+            Expr::Var(name) => out.push(name),
+            Expr::Neg(e) => e.vars(out),
+            Expr::Bin(_, a, b) => {
+                a.vars(out);
+                b.vars(out);
+            }
+        }
+    }
+}
+
+/// Recursive descent parser of `+ - * /`, unary minus, parentheses, numbers and identifiers
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].trim_start().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        self.pos = self.input.len() - self.input[self.pos..].trim_start().len();
+        let c = self.input[self.pos..].chars().next()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+        self.pos = self.input.len() - self.input[self.pos..].trim_start().len();
+        let start = self.pos;
+        let len = self.input[start..]
+            .find(|c: char| !f(c))
+            .unwrap_or(self.input.len() - start);
+        self.pos += len;
+        &self.input[start..start + len]
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let mut lhs = self.term()?;
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.bump();
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.term()?));
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> Result<Expr> {
+        let mut lhs = self.factor()?;
+        while let Some(op @ ('*' | '/')) = self.peek() {
+            self.bump();
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.factor()?));
+        }
+        Ok(lhs)
+    }
+
+    fn factor(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Some('-') => {
+                self.bump();
+                Ok(Expr::Neg(Box::new(self.factor()?)))
+            }
+            Some('(') => {
+                self.bump();
+                let e = self.expr()?;
+                match self.bump() {
+                    Some(')') => Ok(e),
+                    _ => Err(anyhow!("Expected ')' at {}", self.pos)),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let num = self.take_while(|c| c.is_ascii_digit() || c == '.');
+                Ok(Expr::Num(
+                    num.parse().map_err(|_| anyhow!("Invalid number {}", num))?,
+                ))
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let name = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+                Ok(Expr::Var(name.to_string()))
+            }
+            Some(c) => Err(anyhow!("Unexpected '{}' at {}", c, self.pos)),
+            None => Err(anyhow!("Unexpected end of expression")),
+        }
+    }
+}
+
+/// Parse an arithmetic expression, errors on variables not in `VARIABLES`
+pub fn parse_expr(input: &str) -> Result<Expr> {
+    let mut parser = Parser { input, pos: 0 };
+    let expr = parser.expr()?;
+    if parser.peek().is_some() {
+        return Err(anyhow!(
+            "Unexpected trailing input: {}",
+            &input[parser.pos..]
+        ));
+    }
+    let mut vars = vec![];
+    expr.vars(&mut vars);
+    if let Some(unknown) = vars.iter().find(|v| !VARIABLES.contains(v)) {
+        return Err(anyhow!(
+            "Unknown variable {}, available: {}",
+            unknown,
+            VARIABLES.join(", ")
+        ));
+    }
+    Ok(expr)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CustomMetric {
+    pub name: String,
+    pub expr: Expr,
+}
+
+impl CustomMetric {
+    /// Evaluate the expression against a group's summary
+    pub fn eval(&self, summary: &RangeSummary) -> Result<f64> {
+        self.expr.eval(&|name| summary_variable(summary, name))
+    }
+}
+
+/// Parse custom metric definitions, one per line with format `name = expression`. Empty lines
+/// and lines starting with `#` are ignored.
+/// ```txt
+/// weighted_score = 0.6*target + 0.3*head + 0.1*source
+/// ```
+pub fn parse_custom_metrics(input: &str) -> Result<Vec<CustomMetric>> {
+    input
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (name, expr) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid custom metric, expected name = expr: {}", line))?;
+            let name = name.trim();
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(anyhow!("Invalid custom metric name: {}", name));
+            }
+            Ok(CustomMetric {
+                name: name.to_string(),
+                expr: parse_expr(expr).map_err(|e| anyhow!("custom metric {}: {}", name, e))?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(input: &str) -> f64 {
+        parse_expr(input)
+            .unwrap()
+            .eval(&|name| match name {
+                "target" => Some(0.5),
+                "head" => Some(0.25),
+                _ => Some(1.0),
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn eval_expr() {
+        assert_eq!(eval("1 + 2 * 3"), 7.0);
+        assert_eq!(eval("(1 + 2) * 3"), 9.0);
+        assert_eq!(eval("10 - 4 - 3"), 3.0);
+        assert_eq!(eval("-target / 2"), -0.25);
+        assert_eq!(eval("0.6*target + 0.4*head"), 0.6 * 0.5 + 0.4 * 0.25);
+        let zero = |_: &str| Some(0.0);
+        assert!(parse_expr("head / target").unwrap().eval(&zero).is_err());
+        assert!(parse_expr("1 / target").unwrap().eval(&zero).is_err());
+    }
+
+    #[test]
+    fn parse_expr_errors() {
+        assert!(parse_expr("1 +").is_err());
+        assert!(parse_expr("(1 + 2").is_err());
+        assert!(parse_expr("1 2").is_err());
+        assert!(parse_expr("unknown * 2").is_err());
+    }
+
+    #[test]
+    fn parse_custom_metrics_test() {
+        let metrics = parse_custom_metrics(
+            "
+# comment
+weighted_score = 0.6*target + 0.3*head + 0.1*source
+",
+        )
+        .unwrap();
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].name, "weighted_score");
+        assert!(parse_custom_metrics("no equals sign").is_err());
+        assert!(parse_custom_metrics("bad name = 1").is_err());
+    }
+}
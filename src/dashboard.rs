@@ -0,0 +1,243 @@
+use anyhow::{Context, Result};
+use beacon_metrics_gazer::metrics::{parse_metric_prefix, DEFAULT_METRIC_PREFIX};
+use beacon_metrics_gazer::ranges::{group_ranges_by_name, IndexRanges, RangesArgs};
+use clap::Args;
+use serde_json::{json, Value};
+
+const PANEL_HEIGHT: u64 = 8;
+/// Grafana's grid is 24 columns wide
+const GRID_WIDTH: u64 = 24;
+
+#[derive(Args)]
+pub struct DashboardArgs {
+    #[command(flatten)]
+    ranges: RangesArgs,
+    /// Dashboard title
+    #[arg(long, default_value = "Beacon network participation")]
+    title: String,
+    /// File to write to instead of stdout
+    #[arg(long)]
+    output: Option<String>,
+    /// Metric prefix the exporter runs with, see `serve --metric-prefix`
+    #[arg(long, default_value = DEFAULT_METRIC_PREFIX, value_parser = parse_metric_prefix)]
+    metric_prefix: String,
+}
+
+/// Quote `value` as a PromQL string literal
+fn promql_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Lays panels out left to right, wrapping into new lines, and numbers them
+struct Layout {
+    metric_prefix: String,
+    panels: Vec<Value>,
+    x: u64,
+    y: u64,
+}
+
+impl Layout {
+    fn row(&mut self, title: &str) {
+        self.newline();
+        self.push(
+            json!({"type": "row", "title": title, "collapsed": false}),
+            GRID_WIDTH,
+            1,
+        );
+    }
+
+    fn timeseries(&mut self, title: &str, unit: &str, targets: &[(String, &str)], width: u64) {
+        let targets: Vec<Value> = targets
+            .iter()
+            .zip('A'..)
+            .map(|((expr, legend), ref_id)| {
+                json!({
+                    "datasource": datasource(),
+                    "expr": expr.replace(
+                        &format!("{DEFAULT_METRIC_PREFIX}_"),
+                        &format!("{}_", self.metric_prefix),
+                    ),
+                    "legendFormat": legend,
+                    "refId": ref_id.to_string(),
+                })
+            })
+            .collect();
+        let panel = json!({
+            "type": "timeseries",
+            "title": title,
+            "datasource": datasource(),
+            "fieldConfig": {"defaults": {"unit": unit}, "overrides": []},
+            "targets": targets,
+        });
+        self.push(panel, width, PANEL_HEIGHT);
+    }
+
+    fn push(&mut self, mut panel: Value, width: u64, height: u64) {
+        if self.x + width > GRID_WIDTH {
+            self.newline();
+        }
+        panel["id"] = json!(self.panels.len() + 1);
+        panel["gridPos"] = json!({"x": self.x, "y": self.y, "w": width, "h": height});
+        self.panels.push(panel);
+        // Rows take a whole line
+        if height == 1 {
+            self.y += 1;
+        } else {
+            self.x += width;
+        }
+    }
+
+    fn newline(&mut self) {
+        if self.x > 0 {
+            self.x = 0;
+            self.y += PANEL_HEIGHT;
+        }
+    }
+}
+
+fn datasource() -> Value {
+    json!({"type": "prometheus", "uid": "${datasource}"})
+}
+
+/// Dashboard with an overview row comparing all groups, then one row per group with its
+/// participation flags, validator statuses and block proposals
+fn dashboard(title: &str, ranges: &IndexRanges, metric_prefix: &str) -> Value {
+    let mut layout = Layout {
+        metric_prefix: metric_prefix.to_owned(),
+        panels: vec![],
+        x: 0,
+        y: 0,
+    };
+    layout.row("Overview");
+    layout.timeseries(
+        "Target participation",
+        "percentunit",
+        &[(
+            "beacon_network_target_participation".to_owned(),
+            "{{range}}",
+        )],
+        GRID_WIDTH / 2,
+    );
+    layout.timeseries(
+        "Target participation change",
+        "percentunit",
+        &[(
+            "beacon_network_target_participation_delta".to_owned(),
+            "{{range}}",
+        )],
+        GRID_WIDTH / 2,
+    );
+    for (name, _) in group_ranges_by_name(ranges) {
+        let selector = format!("{{range={}}}", promql_string(&name));
+        layout.row(&name);
+        layout.timeseries(
+            "Participation",
+            "percentunit",
+            &[
+                (
+                    format!("beacon_network_source_participation{selector}"),
+                    "source",
+                ),
+                (
+                    format!("beacon_network_target_participation{selector}"),
+                    "target",
+                ),
+                (
+                    format!("beacon_network_head_participation{selector}"),
+                    "head",
+                ),
+            ],
+            GRID_WIDTH / 3,
+        );
+        layout.timeseries(
+            "Validators by status",
+            "none",
+            &[(
+                format!("beacon_network_validators_by_status{selector}"),
+                "{{status}}",
+            )],
+            GRID_WIDTH / 3,
+        );
+        layout.timeseries(
+            "Blocks per hour",
+            "none",
+            &[
+                (
+                    format!("increase(beacon_network_blocks_proposed_total{selector}[1h])"),
+                    "proposed",
+                ),
+                (
+                    format!("increase(beacon_network_blocks_missed_total{selector}[1h])"),
+                    "missed",
+                ),
+            ],
+            GRID_WIDTH / 3,
+        );
+    }
+    json!({
+        "title": title,
+        "uid": null,
+        "editable": true,
+        "schemaVersion": 39,
+        "time": {"from": "now-6h", "to": "now"},
+        "refresh": "1m",
+        "templating": {"list": [{
+            "name": "datasource",
+            "label": "Data source",
+            "type": "datasource",
+            "query": "prometheus",
+        }]},
+        "panels": layout.panels,
+    })
+}
+
+/// Print a Grafana dashboard for the groups of the ranges, to import as is
+pub async fn generate_dashboard(args: &DashboardArgs) -> Result<()> {
+    let ranges = args.ranges.require().await?;
+    let json = serde_json::to_string_pretty(&dashboard(&args.title, &ranges, &args.metric_prefix))?;
+    match &args.output {
+        Some(path) => std::fs::write(path, json).with_context(|| format!("write --output {path}")),
+        None => {
+            println!("{json}");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dashboard_test() {
+        let ranges = vec![
+            ("lh-geth-0".to_owned(), 0..10),
+            ("teku \"besu\"".to_owned(), 10..20),
+            ("lh-geth-0".to_owned(), 20..30),
+        ];
+        let dashboard = dashboard("devnet", &ranges, DEFAULT_METRIC_PREFIX);
+        let panels = dashboard["panels"].as_array().unwrap();
+        let rows: Vec<&str> = panels
+            .iter()
+            .filter(|panel| panel["type"] == "row")
+            .map(|panel| panel["title"].as_str().unwrap())
+            .collect();
+        assert_eq!(rows, vec!["Overview", "lh-geth-0", "teku \"besu\""]);
+        // Overview: row and 2 panels, then a row and 3 panels per group
+        assert_eq!(panels.len(), 3 + 2 * 4);
+        assert_eq!(panels[10]["id"], 11);
+        assert_eq!(
+            panels[10]["gridPos"],
+            json!({"x": 16, "y": 19, "w": 8, "h": 8})
+        );
+        assert_eq!(
+            panels[8]["targets"][0]["expr"],
+            r#"beacon_network_source_participation{range="teku \"besu\""}"#
+        );
+        let prefixed = super::dashboard("devnet", &ranges, "devnet_12");
+        assert_eq!(
+            prefixed["panels"][1]["targets"][0]["expr"],
+            "devnet_12_target_participation"
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use crate::participation::{GroupParticipation, ParticipationSnapshot};
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS participation (
+    epoch INTEGER NOT NULL,
+    slot INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    group_name TEXT NOT NULL,
+    is_total INTEGER NOT NULL,
+    ranges TEXT NOT NULL,
+    validators INTEGER NOT NULL,
+    source REAL NOT NULL,
+    target REAL NOT NULL,
+    head REAL NOT NULL,
+    inactivity_scores_avg REAL NOT NULL,
+    balance_gwei_sum INTEGER NOT NULL,
+    slashed_validators INTEGER NOT NULL,
+    PRIMARY KEY (epoch, is_total, group_name)
+)";
+
+const COLUMNS: &str = "epoch, slot, timestamp, group_name, is_total, ranges, validators, source, \
+                       target, head, inactivity_scores_avg, balance_gwei_sum, slashed_validators";
+
+/// Participation history of `--db`, one row per group and epoch in an SQLite database. The
+/// total over all validators is stored as a row with `is_total` set.
+pub struct ParticipationDb {
+    connection: Connection,
+}
+
+impl ParticipationDb {
+    /// Open the database at `path`, creating it and its table if missing
+    pub fn open(path: &str) -> Result<Self> {
+        let connection = Connection::open(path).with_context(|| format!("open {path}"))?;
+        connection
+            .execute_batch(SCHEMA)
+            .with_context(|| format!("create tables of {path}"))?;
+        Ok(Self { connection })
+    }
+
+    /// Store the rows of `snapshot`, replacing those of the same epoch
+    pub fn insert(&mut self, snapshot: &ParticipationSnapshot) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "DELETE FROM participation WHERE epoch = ?1",
+            params![snapshot.epoch],
+        )?;
+        {
+            let mut insert = transaction.prepare(&format!(
+                "INSERT INTO participation (position, {COLUMNS}) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)"
+            ))?;
+            let groups = snapshot.groups.iter().map(|group| (group, false));
+            for (position, (group, is_total)) in groups.chain([(&snapshot.total, true)]).enumerate()
+            {
+                insert.execute(params![
+                    position,
+                    snapshot.epoch,
+                    snapshot.slot,
+                    snapshot.timestamp,
+                    group.name,
+                    is_total,
+                    group.ranges,
+                    group.validators,
+                    group.source,
+                    group.target,
+                    group.head,
+                    group.inactivity_scores_avg,
+                    group.balance_gwei_sum,
+                    group.slashed_validators,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Snapshots of the epochs after `epoch`, or all without it, oldest first
+    pub fn snapshots_after(&self, epoch: Option<u64>) -> Result<Vec<ParticipationSnapshot>> {
+        self.query(
+            "epoch > ?1",
+            epoch.map_or(-1, |epoch| epoch.min(i64::MAX as u64) as i64),
+        )
+    }
+
+    /// Snapshots of the last `epochs` stored epochs, oldest first
+    pub fn latest(&self, epochs: usize) -> Result<Vec<ParticipationSnapshot>> {
+        self.query(
+            "epoch IN (SELECT DISTINCT epoch FROM participation ORDER BY epoch DESC LIMIT ?1)",
+            epochs.min(i64::MAX as usize) as i64,
+        )
+    }
+
+    fn query(&self, condition: &str, param: i64) -> Result<Vec<ParticipationSnapshot>> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT {COLUMNS} FROM participation WHERE {condition} ORDER BY epoch, position"
+        ))?;
+        let rows = statement.query_map([param], |row| {
+            let group = GroupParticipation {
+                name: row.get(3)?,
+                ranges: row.get(5)?,
+                validators: row.get(6)?,
+                source: row.get(7)?,
+                target: row.get(8)?,
+                head: row.get(9)?,
+                inactivity_scores_avg: row.get(10)?,
+                balance_gwei_sum: row.get(11)?,
+                slashed_validators: row.get(12)?,
+            };
+            let is_total: bool = row.get(4)?;
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, is_total, group))
+        })?;
+
+        let mut snapshots: Vec<ParticipationSnapshot> = vec![];
+        for row in rows {
+            let (epoch, slot, timestamp, is_total, group) = row?;
+            let snapshot = match snapshots.last_mut() {
+                Some(snapshot) if snapshot.epoch == epoch => snapshot,
+                _ => {
+                    snapshots.push(ParticipationSnapshot {
+                        slot,
+                        epoch,
+                        timestamp,
+                        groups: vec![],
+                        total: GroupParticipation::default(),
+                    });
+                    snapshots.last_mut().unwrap()
+                }
+            };
+            if is_total {
+                snapshot.total = group;
+            } else {
+                snapshot.groups.push(group);
+            }
+        }
+        Ok(snapshots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(epoch: u64, target: f32) -> ParticipationSnapshot {
+        let group = |name: &str| GroupParticipation {
+            name: name.to_owned(),
+            ranges: "0..100".to_owned(),
+            validators: 100,
+            source: target,
+            target,
+            head: target,
+            inactivity_scores_avg: 0.5,
+            balance_gwei_sum: 3_200_000_000_000,
+            slashed_validators: 0,
+        };
+        ParticipationSnapshot {
+            slot: (epoch + 1) * 32,
+            epoch,
+            timestamp: 1_700_000_000 + epoch * 384,
+            groups: vec![group("b"), group("a")],
+            total: group("__total__"),
+        }
+    }
+
+    #[test]
+    fn participation_db_test() {
+        let dir = std::env::temp_dir().join(format!("participation-db-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history.sqlite");
+        let path = path.to_str().unwrap();
+
+        let mut db = ParticipationDb::open(path).unwrap();
+        for epoch in 10..14 {
+            db.insert(&snapshot(epoch, 0.5)).unwrap();
+        }
+        // A repeated epoch replaces the earlier rows
+        db.insert(&snapshot(13, 1.0)).unwrap();
+        drop(db);
+
+        let db = ParticipationDb::open(path).unwrap();
+        let all = db.snapshots_after(None).unwrap();
+        assert_eq!(
+            all.iter().map(|s| s.epoch).collect::<Vec<_>>(),
+            vec![10, 11, 12, 13]
+        );
+        assert_eq!(all[3], snapshot(13, 1.0));
+        assert_eq!(all[0], snapshot(10, 0.5));
+        let after = db.snapshots_after(Some(11)).unwrap();
+        assert_eq!(after.iter().map(|s| s.epoch).collect::<Vec<_>>(), [12, 13]);
+        let latest = db.latest(3).unwrap();
+        assert_eq!(
+            latest.iter().map(|s| s.epoch).collect::<Vec<_>>(),
+            [11, 12, 13]
+        );
+        assert!(db.latest(0).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
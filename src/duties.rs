@@ -0,0 +1,111 @@
+use crate::http_client::beacon_client;
+use anyhow::Result;
+use serde::Deserialize;
+
+#[derive(Debug, Clone)]
+pub struct ProposerDuty {
+    pub slot: u64,
+    pub validator_index: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Committee {
+    pub slot: u64,
+    pub index: u64,
+    pub validators: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+struct ProposerDutiesResponse {
+    data: Vec<ProposerDutyData>,
+}
+
+#[derive(Deserialize)]
+struct ProposerDutyData {
+    validator_index: String,
+    slot: String,
+}
+
+#[derive(Deserialize)]
+struct SyncCommitteeResponse {
+    data: SyncCommitteeData,
+}
+
+#[derive(Deserialize)]
+struct SyncCommitteeData {
+    validators: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct CommitteesResponse {
+    data: Vec<CommitteeData>,
+}
+
+#[derive(Deserialize)]
+struct CommitteeData {
+    slot: String,
+    index: String,
+    validators: Vec<String>,
+}
+
+/// Fetch the proposer of each slot in `epoch`
+pub async fn fetch_proposer_duties(url: &str, epoch: u64) -> Result<Vec<ProposerDuty>> {
+    let response = beacon_client()
+        .get(format!("{url}/eth/v1/validator/duties/proposer/{epoch}"))
+        .send()
+        .await?
+        .error_for_status()?;
+    let data: ProposerDutiesResponse = response.json().await?;
+    data.data
+        .into_iter()
+        .map(|duty| {
+            Ok(ProposerDuty {
+                slot: duty.slot.parse()?,
+                validator_index: duty.validator_index.parse()?,
+            })
+        })
+        .collect()
+}
+
+/// Fetch the indices of the current sync committee members of the state `state_id`
+pub async fn fetch_sync_committee(url: &str, state_id: &str) -> Result<Vec<usize>> {
+    let response = beacon_client()
+        .get(format!(
+            "{url}/eth/v1/beacon/states/{state_id}/sync_committees"
+        ))
+        .send()
+        .await?
+        .error_for_status()?;
+    let data: SyncCommitteeResponse = response.json().await?;
+    data.data
+        .validators
+        .iter()
+        .map(|index| Ok(index.parse()?))
+        .collect()
+}
+
+/// Fetch all attestation committees of `epoch` computed from the state `state_id`
+pub async fn fetch_committees(url: &str, state_id: &str, epoch: u64) -> Result<Vec<Committee>> {
+    let response = beacon_client()
+        .get(format!(
+            "{url}/eth/v1/beacon/states/{state_id}/committees?epoch={epoch}"
+        ))
+        .send()
+        .await?
+        .error_for_status()?;
+    let data: CommitteesResponse = response.json().await?;
+    data.data
+        .into_iter()
+        .map(|committee| {
+            Ok(Committee {
+                slot: committee.slot.parse()?,
+                index: committee.index.parse()?,
+                validators: committee
+                    .validators
+                    .iter()
+                    .map(|index| index.parse())
+                    .collect::<Result<_, _>>()?,
+            })
+        })
+        .collect()
+}
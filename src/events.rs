@@ -0,0 +1,125 @@
+use crate::http_client::beacon_client;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time;
+
+/// Delay before reconnecting to the event stream after it ends or fails
+pub const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Splits a `text/event-stream` body into `(event, data)` pairs, chunks may end anywhere
+#[derive(Default)]
+pub struct SseParser {
+    buf: Vec<u8>,
+}
+
+impl SseParser {
+    /// Add a chunk of the body and return the events it completes
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<(String, String)> {
+        self.buf.extend(chunk.iter().filter(|b| **b != b'\r'));
+        let mut events = vec![];
+        while let Some(end) = self.buf.windows(2).position(|w| w == b"\n\n") {
+            let block: Vec<u8> = self.buf.drain(..end + 2).collect();
+            let mut event = String::from("message");
+            let mut data = vec![];
+            for line in String::from_utf8_lossy(&block).lines() {
+                if let Some(value) = line.strip_prefix("event:") {
+                    event = value.trim().to_owned();
+                } else if let Some(value) = line.strip_prefix("data:") {
+                    data.push(value.trim().to_owned());
+                }
+            }
+            if !data.is_empty() {
+                events.push((event, data.join("\n")));
+            }
+        }
+        events
+    }
+}
+
+#[derive(Deserialize)]
+struct HeadEvent {
+    slot: String,
+}
+
+#[derive(Deserialize)]
+struct FinalizedCheckpointEvent {
+    epoch: String,
+}
+
+/// Subscribe to head and finalized checkpoint events, notifying `epoch_start` on the first head
+/// of each new epoch. Reconnects when the stream ends or fails.
+pub async fn task_subscribe_events(url: &str, slots_per_epoch: u64, epoch_start: Arc<Notify>) {
+    let mut last_epoch = None;
+    loop {
+        if let Err(e) = subscribe_events(url, slots_per_epoch, &epoch_start, &mut last_epoch).await
+        {
+            eprintln!("error reading event stream: {:?}", e);
+        }
+        time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn subscribe_events(
+    url: &str,
+    slots_per_epoch: u64,
+    epoch_start: &Notify,
+    last_epoch: &mut Option<u64>,
+) -> Result<()> {
+    let mut response = beacon_client()
+        .get(format!(
+            "{url}/eth/v1/events?topics=head,finalized_checkpoint"
+        ))
+        .header(reqwest::header::ACCEPT, "text/event-stream")
+        .send()
+        .await?
+        .error_for_status()?;
+    println!("subscribed to beacon node events");
+
+    let mut parser = SseParser::default();
+    while let Some(chunk) = response.chunk().await? {
+        for (event, data) in parser.push(&chunk) {
+            match event.as_str() {
+                "head" => {
+                    let head: HeadEvent = serde_json::from_str(&data)?;
+                    let epoch = head.slot.parse::<u64>()? / slots_per_epoch;
+                    // The first head only sets the baseline, the fetch loop already ran on boot
+                    match *last_epoch {
+                        Some(last) if epoch > last => {
+                            epoch_start.notify_one();
+                            *last_epoch = Some(epoch);
+                        }
+                        None => *last_epoch = Some(epoch),
+                        _ => {}
+                    }
+                }
+                "finalized_checkpoint" => {
+                    let finalized: FinalizedCheckpointEvent = serde_json::from_str(&data)?;
+                    println!("finalized epoch {}", finalized.epoch);
+                }
+                _ => {}
+            }
+        }
+    }
+    Err(anyhow!("event stream ended"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sse_parser_test() {
+        let mut parser = SseParser::default();
+        assert!(parser.push(b"event: head\ndata: {\"slot\":").is_empty());
+        assert_eq!(
+            parser.push(b"\"10\"}\r\n\r\n: comment\n\nevent: finalized_checkpoint\ndata: {}\n\n"),
+            vec![
+                ("head".to_owned(), "{\"slot\":\"10\"}".to_owned()),
+                ("finalized_checkpoint".to_owned(), "{}".to_owned()),
+            ]
+        );
+    }
+}
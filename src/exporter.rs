@@ -0,0 +1,2394 @@
+use crate::config::{parse_flag_mask, ConfigSpec, Genesis, ParticipationFlags};
+use crate::metrics::{
+    inc_counter_by, parse_metric_alias, parse_metric_prefix, parse_static_label,
+    register_process_collector, remove_gauge, reset_gauge, set_gauge, set_metric_prefix,
+    set_registry, url_label, MetricAlias, StaticLabel, ACTIVATION_CHURN_LIMIT, ACTIVE_VALIDATORS,
+    ACTIVE_VALIDATORS_DELTA, ALL_RANGES_PERFECT_EPOCHS, ATTESTATIONS_INCLUDED,
+    ATTESTATIONS_SEEN_NOT_INCLUDED, ATTESTATION_DUTIES, ATTESTATION_EFFICIENCY,
+    ATTESTATION_IDEAL_REWARDS_GWEI, ATTESTATION_PACKING_EFFICIENCY, ATTESTATION_REWARDS_GWEI,
+    BALANCE_ETH_AVG, BALANCE_ETH_SUM, BALANCE_GWEI_AVG, BALANCE_GWEI_SUM,
+    BEACON_NODE_CONSECUTIVE_FAILURES, BEACON_NODE_INFO, BEACON_NODE_IS_SYNCING, BEACON_NODE_PEERS,
+    BEACON_NODE_SYNC_DISTANCE, BEACON_NODE_UP, BLOCKS_MISSED, BLOCKS_PROPOSED,
+    CLIENT_OVER_RISK_THRESHOLD, CLIENT_STAKE_SHARE, COMMITTEES_PER_SLOT, CONFIGURED_VALIDATORS,
+    CONSISTENTLY_MISSING_VALIDATORS, CURRENT_EPOCH, CUSTOM_METRIC, DEFAULT_METRIC_PREFIX,
+    EPOCHS_SINCE_FINALITY, EPOCHS_TO_ACTIVATION, EPOCH_JUSTIFIED, ESTIMATED_PENALTIES_GWEI,
+    EXITED_VALIDATORS, EXITING_VALIDATORS, EXPORTER_CONFIG_CHANGE_TIME, EXPORTER_CONFIG_INFO,
+    EXPORTER_STARTS, EXPORTER_START_TIME, FEE_RECIPIENT_BLOCKS_MISSED,
+    FEE_RECIPIENT_BLOCKS_PROPOSED, FINALIZED_EPOCH, GRAFFITI_BLOCKS_MISSED,
+    GRAFFITI_BLOCKS_PROPOSED, HAS_PROPOSER_DUTY, HAS_SYNC_DUTY, HEAD_PARTICIPATION, HEAD_ROOT_FLIP,
+    HEAD_SLOT, INACTIVITY_SCORES, INCLUSION_DELAY_AVG, JUSTIFIED_EPOCH, LAST_UPDATE_EPOCH,
+    LAST_UPDATE_TIMESTAMP, NETWORK_INFO, NOISY_THRESHOLDS, PARTICIPATION_ANOMALY,
+    PARTICIPATION_DEVIATION, PARTICIPATION_EPOCH, PENDING_ACTIVATION_VALIDATORS,
+    PENDING_EXIT_VALIDATORS, PERFECT_EPOCHS, PERFECT_EPOCH_STREAK, PROPOSAL_ADJACENT_ATTESTERS,
+    PROPOSAL_ADJACENT_HEAD_MISSES, PROPOSAL_HEAD_MISS_CORRELATION, SCHEDULED_PROPOSALS,
+    SLASHED_VALIDATORS, SOURCE_PARTICIPATION, STATE_INFO, STATE_SLOT, TARGET_PARTICIPATION,
+    TARGET_PARTICIPATION_DELTA, THRESHOLD_BREACH, UNCONFIGURED_VALIDATORS,
+    VALIDATORS_BY_FLAG_COUNT, VALIDATORS_BY_STATUS, VALIDATOR_BALANCE_ETH, VALIDATOR_BALANCE_GWEI,
+    VALIDATOR_INACTIVITY_SCORE, VALIDATOR_NEXT_PROPOSAL_SLOT, VALIDATOR_PARTICIPATION,
+    WITHDRAWABLE_VALIDATORS, WRONG_TARGET_ATTESTERS,
+};
+use crate::my_validators::ranges_of_indices;
+use crate::node_status::{fetch_peer_count, fetch_sync_status};
+use crate::node_version::{fetch_node_version, NodeVersion, NODE_VERSION_POLL_INTERVAL};
+use crate::participation::{
+    all_perfect, fetch_state, group_target_participation, has_flag, other_participation,
+    parse_state_id, total_participation, GroupParticipation, ParticipationByRange,
+    ParticipationSnapshot, RangeSummary, OTHER_GROUP, TOTAL_GROUP,
+};
+use crate::persistence::ConfigChange;
+use crate::ranges::{
+    clamp_ranges, count_by_group, dump_ranges, format_ranges, group_ranges_by_name, group_size,
+    overlapping_ranges, GroupOrder, GroupThresholds, IndexRanges, RangesArgs, ThresholdsByGroup,
+};
+use crate::redact::{fnv1a, Redaction};
+use crate::ssz_state::{
+    Fork, Justification, StatePartial, ValidatorStatus, JUSTIFICATION_BITS_LENGTH,
+};
+use crate::util::{
+    current_epoch_start_slot, fetch_delay, parse_jitter_secs, parse_stagger_fraction,
+    to_next_epoch_start,
+};
+use crate::withdrawal::{format_address, parse_address, Address};
+use anyhow::{anyhow, Context, Result};
+use clap::builder::BoolishValueParser;
+use clap::{Args, FromArgMatches, ValueEnum};
+
+use crate::active_set::{active_set_stats, ActiveSetStats};
+use crate::alerts::{noisy_thresholds, send_alerts, AlertConfig, AlertFormat, AlertState};
+use crate::anomaly::participation_anomalies;
+use crate::cardinality::check_cardinality;
+use crate::checkpoint::{
+    fetch_block_header, fetch_state_root, format_root, parse_root, root_at, Root, TrustedCheckpoint,
+};
+use crate::client_diversity::client_stake_shares;
+use crate::collection::{drain_within, run_targets, save_history, start_collection};
+use crate::correlation::{epoch_slot_samples, ProposalCorrelation};
+use crate::custom_metrics::CustomMetric;
+use crate::db::ParticipationDb;
+use crate::duties::{fetch_committees, fetch_proposer_duties, fetch_sync_committee};
+use crate::finality::fetch_checkpoint_finality;
+use crate::gossip::FirstSeen;
+use crate::graffiti::GraffitiGroups;
+use crate::history::DEFAULT_HISTORY_EPOCHS;
+use crate::http_client::{set_beacon_client, BeaconRequestArgs};
+use crate::inclusion::{
+    block_packing, fetch_block_attestations, group_inclusion, group_inclusion_delay,
+    group_packing_efficiency, inclusion_delays, wrong_target_attesters,
+};
+use crate::inventory::ValidatorInventory;
+use crate::miss_streaks::MissStreaks;
+use crate::parquet_sink::write_parquet_epoch;
+use crate::proposals::{
+    count_proposals, count_scheduled_proposals, fetch_block_origin, ProposerGroups,
+};
+use crate::rewards::{estimate_group_penalties, fetch_attestation_rewards, group_rewards};
+use crate::server::{export_skipped_syncing, serve, LatestParticipation};
+use crate::shutdown::Shutdown;
+use crate::simulate::Scenario;
+use crate::target::{parse_target, Target};
+
+use crate::report::{RunReport, HEALTHY_EPOCHS_SINCE_FINALITY};
+use crate::rotating_file::RotatingFile;
+use prettytable::{format, Cell, Row, Table};
+use prometheus::Registry;
+use serde::Serialize;
+use serde_json::to_string;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::io::IsTerminal;
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Notify;
+use tokio::time;
+
+#[derive(Clone, Copy, Serialize)]
+pub enum DumpFormat {
+    Json,
+    Table,
+}
+
+impl FromStr for DumpFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(DumpFormat::Json),
+            "table" => Ok(DumpFormat::Table),
+            _ => Ok(DumpFormat::Table),
+        }
+    }
+}
+
+/// When to color the participation columns of table dumps on stdout
+#[derive(Clone, Copy, Debug, Default, Serialize, ValueEnum)]
+pub enum ColorMode {
+    /// If stdout is a terminal and NO_COLOR is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    pub(crate) fn enabled(self) -> bool {
+        match self {
+            ColorMode::Auto => {
+                std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        }
+    }
+}
+
+/// Units of the exported balance metrics
+#[derive(Clone, Copy, Default, PartialEq, Serialize)]
+pub enum BalanceUnit {
+    #[default]
+    Gwei,
+    Eth,
+    Both,
+}
+
+impl BalanceUnit {
+    fn gwei(self) -> bool {
+        self != BalanceUnit::Eth
+    }
+
+    fn eth(self) -> bool {
+        self != BalanceUnit::Gwei
+    }
+}
+
+impl FromStr for BalanceUnit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gwei" => Ok(BalanceUnit::Gwei),
+            "eth" => Ok(BalanceUnit::Eth),
+            "both" => Ok(BalanceUnit::Both),
+            _ => Err(anyhow!(
+                "Unknown balance unit {}, expected gwei, eth or both",
+                s
+            )),
+        }
+    }
+}
+
+const GWEI_PER_ETH: f64 = 1e9;
+
+fn gwei_to_eth(gwei: f64) -> f64 {
+    gwei / GWEI_PER_ETH
+}
+
+/// Settings of [`run_exporter`], the options of the binary
+#[derive(Args, Clone, Serialize)]
+pub struct Config {
+    /// Beacon HTTP API URL: http://1.2.3.4:4000
+    #[arg(env = "BMG_URL", required_unless_present_any = ["simulate", "target"])]
+    pub url: Option<String>,
+    /// Local path or URL of a TOML file setting any of these options, keyed by option name, e.g.
+    /// `port = 9090` or `headers = ["Authorization: Bearer {token}"]`. Flags on the command line
+    /// take precedence.
+    #[arg(long, env = "BMG_CONFIG")]
+    pub config: Option<String>,
+    #[command(flatten)]
+    pub beacon: BeaconRequestArgs,
+    /// Do not offer gzip compression when downloading states. Compression cuts the transfer of
+    /// remote nodes several times at the cost of some CPU.
+    #[arg(long, env = "BMG_NO_COMPRESSION", value_parser = BoolishValueParser::new())]
+    pub no_compression: bool,
+    #[command(flatten)]
+    pub ranges: RangesArgs,
+    /// Local path or URL of an ethereum-package (Kurtosis) args file to derive the index ranges
+    /// and group names from, one group per node
+    #[arg(
+        long,
+        env = "BMG_RANGES_FROM_KURTOSIS",
+        conflicts_with_all = ["ranges", "ranges_file"]
+    )]
+    pub ranges_from_kurtosis: Option<String>,
+    /// Split the validator set of the head state at startup into this many groups of equal size,
+    /// for networks without a ranges file
+    #[arg(
+        long,
+        env = "BMG_AUTO_GROUPS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["ranges", "ranges_file", "ranges_from_kurtosis", "simulate"]
+    )]
+    pub auto_groups: Option<u64>,
+    /// Name of the groups of --auto-groups, `{i}` is replaced by the group number from 0
+    #[arg(
+        long,
+        env = "BMG_AUTO_GROUPS_NAME",
+        default_value = "group-{i}",
+        requires = "auto_groups"
+    )]
+    pub auto_groups_name: String,
+    /// Group the validators of the head state at startup by the execution address of their
+    /// 0x01 or 0x02 withdrawal credentials, one group per address
+    #[arg(
+        long,
+        env = "BMG_GROUP_BY_WITHDRAWAL_ADDRESS",
+        value_parser = BoolishValueParser::new(),
+        conflicts_with_all = ["ranges", "ranges_file", "ranges_from_kurtosis", "auto_groups", "simulate"]
+    )]
+    pub group_by_withdrawal_address: bool,
+    /// Only group validators withdrawing to these addresses, comma separated
+    #[arg(
+        long,
+        env = "BMG_WITHDRAWAL_ADDRESSES",
+        value_delimiter = ',',
+        value_parser = parse_address,
+        requires = "group_by_withdrawal_address"
+    )]
+    pub withdrawal_addresses: Vec<Address>,
+    /// Dump participation ranges print to stderr on each fetch
+    #[arg(long, env = "BMG_DUMP")]
+    pub dump: Option<DumpFormat>,
+    /// Fetch one state, print its participation in the --dump format, a table by default, and
+    /// exit without serving metrics
+    #[arg(long, env = "BMG_ONCE", value_parser = BoolishValueParser::new(), conflicts_with = "simulate")]
+    pub once: bool,
+    /// Also list the indices of the validators of each dumped group missing the timely target
+    /// flag
+    #[arg(long, env = "BMG_DUMP_MISSING", value_parser = BoolishValueParser::new())]
+    pub dump_missing: bool,
+    /// Order of groups in dumps, overriding --sort
+    #[arg(long, env = "BMG_DUMP_SORT", value_enum)]
+    pub dump_sort: Option<GroupOrder>,
+    /// Leave groups whose target participation reached this ratio out of dumps
+    #[arg(long, env = "BMG_DUMP_MIN")]
+    pub dump_min: Option<f32>,
+    /// Color participation in table dumps green, yellow below the warn and red below the crit
+    /// threshold
+    #[arg(long, env = "BMG_COLOR", value_enum, default_value_t = ColorMode::Auto)]
+    pub color: ColorMode,
+    /// Warn threshold of the dump and status page colors for groups without one in the ranges file
+    #[arg(long, env = "BMG_COLOR_WARN", default_value_t = 0.95)]
+    pub color_warn: f32,
+    /// Crit threshold of the dump and status page colors for groups without one in the ranges file
+    #[arg(long, env = "BMG_COLOR_CRIT", default_value_t = 0.8)]
+    pub color_crit: f32,
+    /// Also append participation dumps to this file, rotated by size and age
+    #[arg(long, env = "BMG_DUMP_FILE")]
+    pub dump_file: Option<String>,
+    /// Format of the dumps in --dump-file, json writes one line per fetch
+    #[arg(long, env = "BMG_DUMP_FILE_FORMAT", default_value = "json")]
+    pub dump_file_format: DumpFormat,
+    /// Rotate --dump-file once it would exceed this many MB, 0 for no size limit
+    #[arg(long, env = "BMG_DUMP_FILE_MAX_MB", default_value_t = 100)]
+    pub dump_file_max_mb: u64,
+    /// Rotate --dump-file after writing to it for this many seconds
+    #[arg(long, env = "BMG_DUMP_FILE_MAX_AGE")]
+    pub dump_file_max_age: Option<u64>,
+    /// Rotated dump files to keep, as <dump-file>.1 for the most recent
+    #[arg(long, env = "BMG_DUMP_FILE_KEEP", default_value_t = 5)]
+    pub dump_file_keep: usize,
+    /// Metrics server port
+    #[arg(long, short, env = "BMG_PORT", default_value_t = 8080)]
+    pub port: u16,
+    /// Metrics server bind address
+    #[arg(long, env = "BMG_ADDRESS", default_value = "127.0.0.1")]
+    pub address: String,
+    /// Serve the metrics and API on this Unix domain socket instead of TCP, e.g.
+    /// /run/bmg.sock. A stale socket file at the path is replaced.
+    #[arg(long, env = "BMG_LISTEN_UNIX", conflicts_with_all = ["port", "address"])]
+    pub listen_unix: Option<String>,
+
+    // poll metrics in a fixed interval
+    #[arg(long, env = "BMG_POLL")]
+    pub poll: Option<u64>,
+    /// Target participation ratio below which a group is recorded as an incident in the run
+    /// report served at /api/v1/report
+    #[arg(long, env = "BMG_INCIDENT_THRESHOLD", default_value_t = 0.9)]
+    pub incident_threshold: f32,
+    /// Target participation ratio at or above which a group's epoch counts as perfect, for the
+    /// perfect epoch counters and streaks
+    #[arg(long, env = "BMG_PERFECT_THRESHOLD", default_value_t = 1.0)]
+    pub perfect_threshold: f32,
+    /// POST a JSON alert to this URL when a group's target participation drops below
+    /// --alert-threshold, and when it recovers
+    #[arg(long, env = "BMG_ALERT_WEBHOOK_URL")]
+    pub alert_webhook_url: Option<String>,
+    /// Target participation ratio below which an alert fires
+    #[arg(long, env = "BMG_ALERT_THRESHOLD", default_value_t = 0.9)]
+    pub alert_threshold: f32,
+    /// Margin above --alert-threshold participation must recover to for the alert to resolve,
+    /// avoids flapping
+    #[arg(long, env = "BMG_ALERT_HYSTERESIS", default_value_t = 0.02)]
+    pub alert_hysteresis: f32,
+    /// Payload format of --alert-webhook-url
+    #[arg(long, env = "BMG_ALERT_FORMAT", value_enum, default_value_t = AlertFormat::Json)]
+    pub alert_format: AlertFormat,
+    /// Order of groups in dumps and the run report
+    #[arg(long, env = "BMG_SORT", value_enum, default_value_t = GroupOrder::Config)]
+    pub sort: GroupOrder,
+    /// Write the run report as JSON to this path on shutdown, in addition to stdout
+    #[arg(long, env = "BMG_REPORT_FILE")]
+    pub report_file: Option<String>,
+    /// Restore the run report, miss streaks and epoch history from this file on startup, and save
+    /// them to it every epoch and on shutdown, so means, streaks, incidents and `/api/v1/history`
+    /// survive restarts
+    #[arg(long, env = "BMG_HISTORY_FILE")]
+    pub history_file: Option<String>,
+    /// SQLite database storing the participation of every group each epoch. On startup the last
+    /// --history-epochs epochs are reloaded for `/api/v1/history`, and epochs the run report has
+    /// not recorded yet are replayed into it.
+    #[arg(long, env = "BMG_DB")]
+    pub db: Option<String>,
+    /// Directory to write the participation of every group each epoch to as Parquet, partitioned
+    /// as `groups/epoch=<n>/part-0.parquet`
+    #[arg(long, env = "BMG_PARQUET_DIR")]
+    pub parquet_dir: Option<String>,
+    /// Also write a row per monitored validator to --parquet-dir, as
+    /// `validators/epoch=<n>/part-0.parquet`
+    #[arg(
+        long,
+        env = "BMG_PARQUET_VALIDATORS",
+        value_parser = BoolishValueParser::new(),
+        requires = "parquet_dir"
+    )]
+    pub parquet_validators: bool,
+    /// Token required as `Authorization: Bearer <token>` by the control endpoints
+    /// `POST /api/v1/pause` and `POST /api/v1/resume`, which are disabled if unset
+    #[arg(long, env = "BMG_API_TOKEN", hide_env_values = true)]
+    pub api_token: Option<String>,
+    /// Correlate each range's missed proposals with other ranges' head misses in adjacent slots
+    /// over a rolling window of this many epochs. Fetches proposer duties and committees each
+    /// epoch.
+    #[arg(long, env = "BMG_PROPOSAL_CORRELATION_WINDOW")]
+    pub proposal_correlation_window: Option<u64>,
+    /// Monitor a single set of validators, given as comma separated indices or pubkeys, as one
+    /// group named `my-validators`. Enables per-validator metrics, upcoming proposal tracking
+    /// and a warning for each validator missing the target flag. No ranges file required.
+    #[arg(long, env = "BMG_MY_VALIDATORS")]
+    pub my_validators: Option<String>,
+    /// JSON file caching the indices of pubkeys resolved for --my-validators, created if
+    /// missing. Only pubkeys not yet cached are queried on start.
+    #[arg(long, env = "BMG_PUBKEY_CACHE")]
+    pub pubkey_cache: Option<String>,
+    /// Export participation flags, balance and inactivity score of each monitored validator.
+    /// Creates one series per validator, avoid on large ranges.
+    #[arg(long, env = "BMG_PER_VALIDATOR_METRICS", value_parser = BoolishValueParser::new())]
+    pub per_validator_metrics: bool,
+    /// Warn when the exporter produces more series than this, checked after each fetch. Check
+    /// before enabling --per-validator-metrics on a shared Prometheus server.
+    #[arg(long, env = "BMG_CARDINALITY_BUDGET")]
+    pub cardinality_budget: Option<usize>,
+    /// Local path or URL of a file defining derived metrics per group, one `name = expression`
+    /// per line. Example: `weighted_score = 0.6*target + 0.3*head + 0.1*source`
+    #[arg(long, env = "BMG_CUSTOM_METRICS_FILE")]
+    pub custom_metrics_file: Option<String>,
+    /// Export the average attestation inclusion delay per group. Fetches every block of the last
+    /// two epochs, once per epoch.
+    #[arg(long, env = "BMG_INCLUSION_DELAY", value_parser = BoolishValueParser::new())]
+    pub inclusion_delay: bool,
+    /// Export the count of validators per group whose included attestations vote for a target
+    /// checkpoint other than the canonical one. Fetches the same blocks as --inclusion-delay.
+    #[arg(long, env = "BMG_WRONG_TARGET", value_parser = BoolishValueParser::new())]
+    pub wrong_target: bool,
+    /// Export per group the share of available votes included by the blocks its validators
+    /// proposed, judged by the votes later blocks included. Fetches the same blocks as
+    /// --inclusion-delay.
+    #[arg(long, env = "BMG_PACKING_EFFICIENCY", value_parser = BoolishValueParser::new())]
+    pub packing_efficiency: bool,
+    /// Export per group the attestation duties and how many were included in a block, and with
+    /// --gossip-timeliness those seen on gossip but never included. Fetches the same blocks as
+    /// --inclusion-delay.
+    #[arg(long, env = "BMG_ATTESTATION_INCLUSION", value_parser = BoolishValueParser::new())]
+    pub attestation_inclusion: bool,
+    /// Export the share of the network's active stake run by each client implementation, as
+    /// recognized in group names like `lh-geth-0`, and whether it exceeds
+    /// --client-risk-threshold
+    #[arg(long, env = "BMG_CLIENT_DIVERSITY", value_parser = BoolishValueParser::new())]
+    pub client_diversity: bool,
+    /// Stake share above which a single client is a risk, 0.66 can finalize an invalid chain,
+    /// 0.5 and 0.33 to also watch fork choice and finality. Can be repeated.
+    #[arg(
+        long,
+        env = "BMG_CLIENT_RISK_THRESHOLD",
+        default_values_t = [0.66],
+        requires = "client_diversity"
+    )]
+    pub client_risk_threshold: Vec<f64>,
+    /// Graffiti prefixes to group block proposals by, comma separated. Scans the blocks of each
+    /// epoch and counts proposed and missed slots per prefix, missed slots by the proposer's
+    /// last seen graffiti.
+    #[arg(long, env = "BMG_GRAFFITI_GROUPS", value_delimiter = ',')]
+    pub graffiti_groups: Vec<String>,
+    /// Count proposed and missed blocks per fee recipient of the execution payload, scanning the
+    /// same blocks as --graffiti-groups. Missed slots count towards the proposer's last seen fee
+    /// recipient.
+    #[arg(long, env = "BMG_GROUP_BY_FEE_RECIPIENT", value_parser = BoolishValueParser::new())]
+    pub group_by_fee_recipient: bool,
+    /// Participation flags mask counted as timely source, as decimal, 0x hex or 0b binary.
+    /// Defaults to the spec's TIMELY_SOURCE_FLAG_INDEX, for networks with modified flag semantics.
+    #[arg(long, env = "BMG_SOURCE_FLAG_MASK", value_parser = parse_flag_mask)]
+    pub source_flag_mask: Option<u8>,
+    /// Participation flags mask counted as timely target, see --source-flag-mask
+    #[arg(long, env = "BMG_TARGET_FLAG_MASK", value_parser = parse_flag_mask)]
+    pub target_flag_mask: Option<u8>,
+    /// Participation flags mask counted as timely head, see --source-flag-mask
+    #[arg(long, env = "BMG_HEAD_FLAG_MASK", value_parser = parse_flag_mask)]
+    pub head_flag_mask: Option<u8>,
+    /// Export attestation reward sums per group from the beacon node rewards API, compared to the
+    /// rewards of perfect attesters
+    #[arg(long, env = "BMG_ATTESTATION_REWARDS", value_parser = BoolishValueParser::new())]
+    pub attestation_rewards: bool,
+    /// Export per group the source, target and inactivity penalties estimated with the spec
+    /// formulas from the previous epoch participation of each state
+    #[arg(long, env = "BMG_ESTIMATED_PENALTIES", value_parser = BoolishValueParser::new())]
+    pub estimated_penalties: bool,
+    /// Export per group the deviation of its target participation from the network-wide one,
+    /// and flag groups more than this many standard deviations below the other groups
+    #[arg(long, env = "BMG_ANOMALY_STDDEVS")]
+    pub anomaly_stddevs: Option<f64>,
+    /// Epochs of participation kept in memory for `/api/v1/history`
+    #[arg(long, env = "BMG_HISTORY_EPOCHS", default_value_t = DEFAULT_HISTORY_EPOCHS)]
+    pub history_epochs: usize,
+    /// Count per group the validators that missed the timely target flag for at least this many
+    /// consecutive epochs
+    #[arg(long, env = "BMG_MISS_STREAK_EPOCHS", default_value_t = 3)]
+    pub miss_streak_epochs: u64,
+    /// Also export a metric under another name, as `from=to`. Eases migrating dashboards that
+    /// expect other exporters' names. Can be repeated.
+    /// Example: `--metric-alias beacon_network_target_participation=validator_target_ratio`
+    #[arg(long, env = "BMG_METRIC_ALIAS", value_parser = parse_metric_alias)]
+    pub metric_alias: Vec<MetricAlias>,
+    /// Prefix of the exported metric names in place of beacon_network, to tell several exporters
+    /// of one scrape job apart. Aliases name metrics by their prefixed name.
+    #[arg(long, env = "BMG_METRIC_PREFIX", default_value = DEFAULT_METRIC_PREFIX, value_parser = parse_metric_prefix)]
+    pub metric_prefix: String,
+    /// Constant label added to every exported series, as `name=value`. Can be repeated.
+    /// Example: `--label network=devnet-12 --label region=eu`
+    #[arg(long, env = "BMG_LABEL", value_parser = parse_static_label)]
+    pub label: Vec<StaticLabel>,
+    /// Do not add the `network` label detected from the node's `CONFIG_NAME` or deposit chain id.
+    /// A `--label network=...` always takes precedence.
+    #[arg(long, env = "BMG_NO_NETWORK_LABEL", value_parser = BoolishValueParser::new())]
+    pub no_network_label: bool,
+    /// Beacon node to monitor next to others as `name=url=ranges-file`, can be repeated. Each
+    /// target is collected independently and its series are labeled `target="<name>"`. Other
+    /// options apply to all targets. Example: `--target holesky=http://1.2.3.4:5052=holesky.txt`
+    #[arg(
+        long,
+        env = "BMG_TARGET",
+        value_parser = parse_target,
+        conflicts_with_all = [
+            "url", "ranges", "ranges_file", "ranges_from_kurtosis", "auto_groups",
+            "group_by_withdrawal_address", "my_validators", "simulate", "once", "redact_groups",
+            "report_file", "history_file", "db", "parquet_dir", "dump_file",
+        ]
+    )]
+    pub target: Vec<Target>,
+    /// Block root trusted to be canonical, as 0x-prefixed hex. States not on the same chain, as
+    /// checked against their block_roots and the node's block headers, are not exported.
+    #[arg(long, env = "BMG_TRUSTED_BLOCK_ROOT", value_parser = parse_root)]
+    pub trusted_block_root: Option<Root>,
+    /// Fetch the state as soon as the beacon node announces the first head of a new epoch on its
+    /// event stream, instead of at the wall clock epoch start. Falls back to polling if no event
+    /// arrives.
+    #[arg(long, env = "BMG_EVENT_STREAM", value_parser = BoolishValueParser::new())]
+    pub event_stream: bool,
+    /// Export per group histograms of how far into the slot the beacon node first sees each
+    /// validator's attestation on gossip, from its attestation event stream. A leading indicator
+    /// of late attesters, limited to the subnets the node subscribes to.
+    #[arg(long, env = "BMG_GOSSIP_TIMELINESS", value_parser = BoolishValueParser::new())]
+    pub gossip_timeliness: bool,
+    /// Subscribe to the beacon node's chain reorg events, exporting a histogram of their depth
+    /// and counting the orphaned blocks per group of their proposer
+    #[arg(long, env = "BMG_REORGS", value_parser = BoolishValueParser::new())]
+    pub reorgs: bool,
+    /// Measure the state at the start of the epoch this many epochs behind the current one
+    /// instead of head. Trades freshness for stability on networks with frequent reorgs.
+    #[arg(long, env = "BMG_FOLLOW_DISTANCE", default_value_t = 0)]
+    pub follow_distance: u64,
+    /// State to measure each epoch: head, finalized, justified or a fixed slot. The finalized
+    /// state can no longer be reorged, at the cost of trailing head by at least two epochs.
+    #[arg(
+        long,
+        env = "BMG_STATE_ID",
+        default_value = "head",
+        value_parser = parse_state_id,
+        conflicts_with = "follow_distance"
+    )]
+    pub state_id: String,
+    /// Serve synthetic participation for the configured groups instead of reading a beacon node,
+    /// to develop dashboards and alert rules. Node specific trackers are disabled.
+    #[arg(long, env = "BMG_SIMULATE", value_parser = BoolishValueParser::new(), conflicts_with_all = ["url", "my_validators", "trusted_block_root", "event_stream", "gossip_timeliness", "reorgs"])]
+    pub simulate: bool,
+    /// Local path or URL of a JSON scenario for --simulate, setting the epoch duration, base
+    /// participation and incidents per group
+    #[arg(long, env = "BMG_SIMULATE_SCENARIO", requires = "simulate")]
+    pub simulate_scenario: Option<String>,
+    /// Seconds to wait on SIGTERM or SIGINT for in-flight requests and the current fetch to finish
+    /// before exiting
+    #[arg(long, env = "BMG_SHUTDOWN_TIMEOUT", default_value_t = 10)]
+    pub shutdown_timeout: u64,
+    /// Export `beacon_network_participation_stale` as 1 when the last successful update is older
+    /// than this many epochs, e.g. while the beacon node is unreachable
+    #[arg(long, env = "BMG_STALE_AFTER_EPOCHS")]
+    pub stale_after_epochs: Option<u64>,
+    /// Also export 0 as source, target and head participation while stale, so dashboards and
+    /// alerts do not show the last values as current
+    #[arg(
+        long,
+        env = "BMG_ZERO_STALE",
+        value_parser = BoolishValueParser::new(),
+        requires = "stale_after_epochs"
+    )]
+    pub zero_stale: bool,
+    /// Skip the state download while the beacon node reports it is syncing more than this many
+    /// slots behind, to not load a recovering node with state requests and export outdated
+    /// participation
+    #[arg(long, env = "BMG_MAX_SYNC_DISTANCE", default_value_t = 64)]
+    pub max_sync_distance: u64,
+    /// Delay each epoch's state fetch by this fraction of a slot, e.g. 0 and 0.5 for two
+    /// replicas against the same beacon node
+    #[arg(long, env = "BMG_STAGGER_FRACTION", default_value_t = 0.0, value_parser = parse_stagger_fraction)]
+    pub stagger_fraction: f64,
+    /// Delay each epoch's state fetch by a random share of up to this many seconds, on top of
+    /// --stagger-fraction
+    #[arg(long, env = "BMG_FETCH_JITTER_SECS", default_value_t = 0.0, value_parser = parse_jitter_secs)]
+    pub fetch_jitter_secs: f64,
+    /// Replace group names in exported metrics by aliases or salted hashes, to share metrics
+    /// without revealing who runs which validators. Logs, dumps and the run report keep the
+    /// original names.
+    #[arg(
+        long,
+        env = "BMG_REDACT_GROUPS",
+        value_enum,
+        conflicts_with_all = ["per_validator_metrics", "my_validators"]
+    )]
+    pub redact_groups: Option<Redaction>,
+    /// JSON file mapping redacted names back to group names, created if missing. Aliases it
+    /// holds are reused so they stay stable when groups are added.
+    #[arg(long, env = "BMG_REDACT_MAPPING_FILE", requires = "redact_groups")]
+    pub redact_mapping_file: Option<String>,
+    /// Secret mixed into the hashes of --redact-groups hash, without it names are easy to guess
+    #[arg(
+        long,
+        env = "BMG_REDACT_SALT",
+        hide_env_values = true,
+        requires = "redact_groups",
+        default_value = ""
+    )]
+    pub redact_salt: String,
+    /// Units of the exported balance metrics: gwei, eth or both
+    #[arg(long, env = "BMG_BALANCE_UNIT", default_value = "gwei")]
+    pub balance_unit: BalanceUnit,
+}
+
+impl Config {
+    /// Parse settings from command line style `args`, the first being the program name
+    pub fn from_args<I, T>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let matches = Config::augment_args(clap::Command::new("beacon-metrics-gazer"))
+            .try_get_matches_from(args)?;
+        Ok(Config::from_arg_matches(&matches)?)
+    }
+}
+
+/// Stops an exporter started with [`run_exporter`], and sets the registry its metrics are
+/// registered in
+#[derive(Clone, Default)]
+pub struct ExporterHandle {
+    shutdown: Shutdown,
+    registry: Option<Registry>,
+}
+
+impl ExporterHandle {
+    /// Register and serve the exporter's metrics in `registry` instead of the default registry.
+    /// Metrics are process wide, so only one exporter can run per process.
+    pub fn with_registry(mut self, registry: Registry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Stop fetching, let the metrics server finish in-flight requests and make
+    /// [`run_exporter`] return
+    pub fn shutdown(&self) {
+        self.shutdown.trigger();
+    }
+}
+
+async fn fetch_epoch_participation(
+    config: &ConfigSpec,
+    beacon_url: &str,
+    state_id: &str,
+    compression: bool,
+    trusted_checkpoint: Option<&mut TrustedCheckpoint>,
+) -> Result<StatePartial> {
+    let state = fetch_state(config, beacon_url, state_id, compression).await?;
+    if let Some(trusted_checkpoint) = trusted_checkpoint {
+        trusted_checkpoint
+            .verify(beacon_url, &state)
+            .await
+            .context("state is not on the chain of --trusted-block-root")?;
+    }
+    Ok(state)
+}
+
+/// Export the stake share of each client and whether it exceeds each of `risk_thresholds`
+fn set_client_diversity(
+    ranges: &IndexRanges,
+    state: &StatePartial,
+    epoch: u64,
+    risk_thresholds: &[f64],
+) {
+    for ((layer, client), share) in client_stake_shares(ranges, state, epoch) {
+        set_gauge(&CLIENT_STAKE_SHARE, &[layer, client], share);
+        for threshold in risk_thresholds {
+            let over = share > *threshold;
+            set_gauge(
+                &CLIENT_OVER_RISK_THRESHOLD,
+                &[layer, client, &threshold.to_string()],
+                over as u8 as f64,
+            );
+            if over {
+                eprintln!(
+                    "WARN {} client {} runs {:.1}% of the active stake, above the {:.1}% risk threshold",
+                    layer,
+                    client,
+                    share * 100.0,
+                    threshold * 100.0
+                );
+            }
+        }
+    }
+}
+
+/// Export the flag participation of a group outside the ranges, `__total__` or `__other__`
+fn set_flag_participation_to_metrics(range_name: &str, summary: &RangeSummary) {
+    set_gauge(
+        &SOURCE_PARTICIPATION,
+        &[range_name],
+        summary.source_participation_ratio as f64,
+    );
+    set_gauge(
+        &TARGET_PARTICIPATION,
+        &[range_name],
+        summary.target_participation_ratio as f64,
+    );
+    set_gauge(
+        &HEAD_PARTICIPATION,
+        &[range_name],
+        summary.head_participation_ratio as f64,
+    );
+}
+
+/// Export the participation of the validators in no range, logging their count when it changes
+fn set_other_participation_to_metrics(
+    flags: &ParticipationFlags,
+    ranges: &IndexRanges,
+    state: &StatePartial,
+    epoch: u64,
+    ungrouped_validators: &mut Option<usize>,
+) {
+    let other = other_participation(flags, ranges, state, epoch);
+    let count = other
+        .as_ref()
+        .map_or(0, |(_, other_ranges, _)| group_size(other_ranges));
+    set_gauge(&UNCONFIGURED_VALIDATORS, &[], count as f64);
+    set_gauge(
+        &CONFIGURED_VALIDATORS,
+        &[],
+        (state.validators.len() - count) as f64,
+    );
+    match &other {
+        Some((_, _, summary)) => set_flag_participation_to_metrics(OTHER_GROUP, summary),
+        None => {
+            for gauge in [
+                &*SOURCE_PARTICIPATION,
+                &*TARGET_PARTICIPATION,
+                &*HEAD_PARTICIPATION,
+            ] {
+                remove_gauge(gauge, &[OTHER_GROUP]);
+            }
+        }
+    }
+    if *ungrouped_validators != Some(count) {
+        if let Some((_, other_ranges, _)) = &other {
+            println!(
+                "{} validators are in no range, exported as group {}: {}",
+                count,
+                OTHER_GROUP,
+                format_ranges(other_ranges)
+            );
+        }
+        *ungrouped_validators = Some(count);
+    }
+}
+
+fn set_participation_to_metrics(
+    participation_by_range: &ParticipationByRange,
+    balance_unit: BalanceUnit,
+) {
+    for (range_name, _, summary) in participation_by_range.iter() {
+        set_gauge(
+            &SOURCE_PARTICIPATION,
+            &[range_name],
+            summary.source_participation_ratio as f64,
+        );
+        set_gauge(
+            &TARGET_PARTICIPATION,
+            &[range_name],
+            summary.target_participation_ratio as f64,
+        );
+        set_gauge(
+            &HEAD_PARTICIPATION,
+            &[range_name],
+            summary.head_participation_ratio as f64,
+        );
+        set_gauge(
+            &INACTIVITY_SCORES,
+            &[range_name],
+            summary.inactivity_scores_avg as f64,
+        );
+        if balance_unit.gwei() {
+            set_gauge(
+                &BALANCE_GWEI_SUM,
+                &[range_name],
+                summary.balance_gwei_sum as f64,
+            );
+            set_gauge(&BALANCE_GWEI_AVG, &[range_name], summary.balance_gwei_avg);
+        }
+        if balance_unit.eth() {
+            set_gauge(
+                &BALANCE_ETH_SUM,
+                &[range_name],
+                gwei_to_eth(summary.balance_gwei_sum as f64),
+            );
+            set_gauge(
+                &BALANCE_ETH_AVG,
+                &[range_name],
+                gwei_to_eth(summary.balance_gwei_avg),
+            );
+        }
+        set_gauge(
+            &SLASHED_VALIDATORS,
+            &[range_name],
+            summary.slashed_validators as f64,
+        );
+        set_gauge(
+            &EXITING_VALIDATORS,
+            &[range_name],
+            summary.exiting_validators as f64,
+        );
+        set_gauge(
+            &EXITED_VALIDATORS,
+            &[range_name],
+            summary.exited_validators as f64,
+        );
+        set_gauge(
+            &WITHDRAWABLE_VALIDATORS,
+            &[range_name],
+            summary.withdrawable_validators as f64,
+        );
+        for (status, count) in ValidatorStatus::ALL
+            .iter()
+            .zip(summary.validators_by_status.iter())
+        {
+            set_gauge(
+                &VALIDATORS_BY_STATUS,
+                &[range_name, status.as_str()],
+                *count as f64,
+            );
+        }
+        for (flag_count, count) in summary.validators_by_flag_count.iter().enumerate() {
+            set_gauge(
+                &VALIDATORS_BY_FLAG_COUNT,
+                &[range_name, &flag_count.to_string()],
+                *count as f64,
+            );
+        }
+    }
+}
+
+fn set_custom_metrics(
+    custom_metrics: &[CustomMetric],
+    participation_by_range: &ParticipationByRange,
+) {
+    for (range_name, _, summary) in participation_by_range.iter() {
+        for custom_metric in custom_metrics {
+            match custom_metric.eval(summary) {
+                Ok(value) => {
+                    set_gauge(&CUSTOM_METRIC, &[range_name, &custom_metric.name], value);
+                }
+                Err(e) => eprintln!("error evaluating {}: {:?}", custom_metric.name, e),
+            }
+        }
+    }
+}
+
+/// Increment the perfect epoch counters, must be called once per epoch
+fn count_perfect_epochs(participation_by_range: &ParticipationByRange, threshold: f32) {
+    for (range_name, _, summary) in participation_by_range.iter() {
+        inc_counter_by(
+            &PERFECT_EPOCHS,
+            &[range_name],
+            summary.is_perfect(threshold) as u64,
+        );
+    }
+    inc_counter_by(
+        &ALL_RANGES_PERFECT_EPOCHS,
+        &[],
+        all_perfect(participation_by_range, threshold) as u64,
+    );
+}
+
+/// Warn about ranges sharing indices, which count twice in their groups or belong to the first
+/// group in per-validator metrics
+pub(crate) fn warn_overlapping_ranges(ranges: &IndexRanges) {
+    for ((name_a, range_a), (name_b, range_b)) in overlapping_ranges(ranges) {
+        eprintln!(
+            "WARN range {:?} of {} overlaps range {:?} of {}",
+            range_b, name_b, range_a, name_a
+        );
+    }
+}
+
+/// Ranges cut to the validators of `state`, warning about ranges past its end once per change of
+/// the out of bounds ranges
+fn clamp_ranges_to_state(
+    ranges: &IndexRanges,
+    state: &StatePartial,
+    out_of_bounds_ranges: &mut IndexRanges,
+) -> IndexRanges {
+    let (clamped, out_of_bounds) = clamp_ranges(ranges, state.validators.len());
+    if out_of_bounds != *out_of_bounds_ranges {
+        for (name, range) in &out_of_bounds {
+            eprintln!(
+                "WARN range {:?} of {} is past the {} validators of the state, ignoring indices from {}",
+                range,
+                name,
+                state.validators.len(),
+                state.validators.len()
+            );
+        }
+        *out_of_bounds_ranges = out_of_bounds;
+    }
+    clamped
+}
+
+/// Export and warn about thresholds that groups are too small to measure meaningfully
+pub(crate) fn check_group_sizes(ranges: &IndexRanges, options: &FetchTaskOptions) {
+    let groups = group_ranges_by_name(ranges);
+    let noisy = noisy_thresholds(
+        &groups,
+        options.alerts.as_ref().map(|alerts| alerts.threshold),
+        &options.group_thresholds,
+    );
+    for (group, _) in &groups {
+        let count = noisy.iter().filter(|noisy| noisy.group == *group).count();
+        set_gauge(&NOISY_THRESHOLDS, &[group], count as f64);
+    }
+    for noisy in &noisy {
+        eprintln!("WARN {}", noisy.message());
+    }
+}
+
+/// Export per group the validators whose miss streak reached --miss-streak-epochs
+fn set_consistently_missing(
+    participation_by_range: &ParticipationByRange,
+    options: &FetchTaskOptions,
+) {
+    let miss_streaks = options.miss_streaks.lock().unwrap();
+    let counts = miss_streaks.consistently_missing(options.miss_streak_epochs);
+    for (range_name, _, _) in participation_by_range {
+        let count = counts.get(range_name.as_str()).copied().unwrap_or(0);
+        set_gauge(
+            &CONSISTENTLY_MISSING_VALIDATORS,
+            &[range_name],
+            count as f64,
+        );
+    }
+}
+
+/// Export which of the last epochs are justified and the justified checkpoint epochs
+fn set_justification_to_metrics(justification: &Justification) {
+    for epochs_ago in 0..JUSTIFICATION_BITS_LENGTH {
+        let justified = justification.is_justified(epochs_ago) as u8 as f64;
+        set_gauge(&EPOCH_JUSTIFIED, &[&epochs_ago.to_string()], justified);
+    }
+    let checkpoints = [
+        ("previous", justification.previous_justified_epoch),
+        ("current", justification.current_justified_epoch),
+    ];
+    for (checkpoint, epoch) in checkpoints {
+        set_gauge(&JUSTIFIED_EPOCH, &[checkpoint], epoch as f64);
+    }
+}
+
+/// Export the finalized epoch and the distance to it, warning if finality is stalling
+fn set_finality_to_metrics(current_epoch: u64, finalized_epoch: u64) {
+    let epochs_since_finality = current_epoch.saturating_sub(finalized_epoch);
+    set_gauge(&FINALIZED_EPOCH, &[], finalized_epoch as f64);
+    set_gauge(&EPOCHS_SINCE_FINALITY, &[], epochs_since_finality as f64);
+    if epochs_since_finality > HEALTHY_EPOCHS_SINCE_FINALITY {
+        eprintln!(
+            "WARN last finalized epoch {finalized_epoch} is {epochs_since_finality} epochs behind the current epoch {current_epoch}"
+        );
+    }
+}
+
+/// Export whether each group's target participation is below its thresholds, logging breaches
+fn set_threshold_breaches(
+    participation_by_range: &ParticipationByRange,
+    group_thresholds: &ThresholdsByGroup,
+) {
+    for (range_name, _, summary) in participation_by_range.iter() {
+        let Some(thresholds) = group_thresholds.get(range_name) else {
+            continue;
+        };
+        let ratio = summary.target_participation_ratio;
+        for (level, breached) in thresholds.breaches(ratio) {
+            set_gauge(
+                &THRESHOLD_BREACH,
+                &[range_name, level],
+                breached as u8 as f64,
+            );
+            if breached {
+                eprintln!(
+                    "WARN range {} target participation {} is below its {} threshold",
+                    range_name, ratio, level
+                );
+            }
+        }
+    }
+}
+
+/// Export the deviation of each group from the network-wide participation `total`, and whether
+/// it is anomalous compared to the other groups, logging anomalies
+fn set_participation_anomalies(
+    participation_by_range: &ParticipationByRange,
+    total: &RangeSummary,
+    stddevs: f64,
+) {
+    for (range_name, _, summary) in participation_by_range {
+        let deviation = summary.target_participation_ratio - total.target_participation_ratio;
+        set_gauge(&PARTICIPATION_DEVIATION, &[range_name], deviation as f64);
+    }
+    for (range_name, anomalous) in participation_anomalies(participation_by_range, stddevs) {
+        set_gauge(
+            &PARTICIPATION_ANOMALY,
+            &[range_name],
+            anomalous as u8 as f64,
+        );
+        if anomalous {
+            eprintln!(
+                "WARN range {} target participation is more than {} standard deviations below the other groups",
+                range_name, stddevs
+            );
+        }
+    }
+}
+
+/// Log a prominent warning for each group whose slashed validator count increased since the
+/// previous call. Counts seen for the first time are only recorded.
+fn warn_new_slashings(
+    slashed_by_range: &mut HashMap<String, u64>,
+    participation_by_range: &ParticipationByRange,
+) {
+    for (range_name, _, summary) in participation_by_range.iter() {
+        let previous = slashed_by_range.insert(range_name.clone(), summary.slashed_validators);
+        if let Some(previous) = previous.filter(|previous| summary.slashed_validators > *previous) {
+            eprintln!(
+                "!!! WARN {} new slashed validators in range {}, {} slashed in total !!!",
+                summary.slashed_validators - previous,
+                range_name,
+                summary.slashed_validators
+            );
+        }
+    }
+}
+
+/// Export the change of each group's target participation since the last observed epoch. Must
+/// be called once per epoch, groups start exporting on their second epoch.
+fn set_participation_deltas(
+    target_by_range: &mut HashMap<String, f32>,
+    participation_by_range: &ParticipationByRange,
+) {
+    for (range_name, _, summary) in participation_by_range.iter() {
+        let ratio = summary.target_participation_ratio;
+        if let Some(previous) = target_by_range.insert(range_name.clone(), ratio) {
+            set_gauge(
+                &TARGET_PARTICIPATION_DELTA,
+                &[range_name],
+                (ratio - previous) as f64,
+            );
+        }
+    }
+}
+
+/// Participation dump file of `--dump-file`
+pub(crate) struct DumpFile {
+    pub(crate) format: DumpFormat,
+    pub(crate) file: Mutex<RotatingFile>,
+}
+
+type DumpRow<'a> = &'a (String, Vec<Range<usize>>, RangeSummary);
+
+fn dump_participation(
+    slot: u64,
+    participation_by_range: &ParticipationByRange,
+    total: DumpRow,
+    options: &FetchTaskOptions,
+) {
+    println!("statistics for slot: {}: ", slot);
+    let rows = dump_rows(participation_by_range, options);
+    if let Some(format) = options.dump_format {
+        print_dump(slot, &rows, total, format, options);
+        if options.dump_missing {
+            print_missing_target(&rows, options);
+        }
+    }
+    if let Some(dump_file) = &options.dump_file {
+        let contents = format_participation(slot, &rows, total, dump_file.format);
+        if let Err(e) = dump_file.file.lock().unwrap().write(&contents) {
+            eprintln!("WARN failed to write dump file: {:?}", e);
+        }
+    }
+}
+
+/// Indices of the validators of each dumped group missing the timely target flag
+fn print_missing_target(rows: &[DumpRow], options: &FetchTaskOptions) {
+    let Some(inventory) = &*options.validators.lock().unwrap() else {
+        return;
+    };
+    for (range_name, _, _) in rows {
+        let missing = inventory.missing_target(Some(range_name)).validators;
+        if !missing.is_empty() {
+            let ranges: Vec<Range<usize>> = ranges_of_indices(range_name, &missing)
+                .into_iter()
+                .map(|(_, range)| range)
+                .collect();
+            println!(
+                "{range_name}: {} missing target {}",
+                missing.len(),
+                format_ranges(&ranges)
+            );
+        }
+    }
+}
+
+/// Groups to dump, without those reaching --dump-min and in the order of --dump-sort if set
+fn dump_rows<'a>(
+    participation_by_range: &'a ParticipationByRange,
+    options: &FetchTaskOptions,
+) -> Vec<DumpRow<'a>> {
+    let mut rows: Vec<DumpRow> = participation_by_range
+        .iter()
+        .filter(|(_, _, summary)| options.dump_min.is_none_or(|min| !summary.is_perfect(min)))
+        .collect();
+    if let Some(order) = options.dump_sort {
+        order.sort(
+            &mut rows,
+            |(range_name, _, _)| range_name,
+            |(_, _, summary)| summary.target_participation_ratio,
+        );
+    }
+    rows
+}
+
+/// Print a dump to stdout, colored if enabled
+fn print_dump(
+    slot: u64,
+    rows: &[DumpRow],
+    total: DumpRow,
+    format: DumpFormat,
+    options: &FetchTaskOptions,
+) {
+    match (format, options.dump_colors) {
+        (DumpFormat::Table, Some(defaults)) => {
+            let colors = (&options.group_thresholds, defaults);
+            // Falls back to plain output if the terminal does not support colors
+            let _ = participation_table(slot, rows, total, Some(colors)).print_tty(true);
+        }
+        (format, _) => print!("{}", format_participation(slot, rows, total, format)),
+    }
+}
+
+/// Dump of `rows`, tables end with a TOTAL row of `total`
+fn format_participation(slot: u64, rows: &[DumpRow], total: DumpRow, format: DumpFormat) -> String {
+    match format {
+        DumpFormat::Json => format_participation_json(slot, rows),
+        DumpFormat::Table => participation_table(slot, rows, total, None).to_string(),
+    }
+}
+
+/// Table of the dumped groups and the TOTAL row, with the participation columns colored by the
+/// group thresholds completed by the defaults if `colors` is set
+fn participation_table(
+    slot: u64,
+    rows: &[DumpRow],
+    total: DumpRow,
+    colors: Option<(&ThresholdsByGroup, GroupThresholds)>,
+) -> Table {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+
+    table.add_row(Row::new(vec![
+        Cell::new("Slot"),
+        Cell::new("Name"),
+        Cell::new("Range"),
+        Cell::new("Source"),
+        Cell::new("Target"),
+        Cell::new("Head"),
+        Cell::new("Balance sum (ETH)"),
+        Cell::new("Balance avg (ETH)"),
+    ]));
+
+    for (range_name, range, summary) in rows.iter().copied().chain([total]) {
+        let participation_cell = |ratio: f32| {
+            let cell = Cell::new(&ratio.to_string());
+            let Some((group_thresholds, defaults)) = colors else {
+                return cell;
+            };
+            let thresholds = group_thresholds
+                .get(range_name)
+                .copied()
+                .unwrap_or_default()
+                .or(defaults);
+            cell.style_spec(match thresholds.level(ratio) {
+                None => "Fg",
+                Some("warn") => "Fy",
+                Some(_) => "Fr",
+            })
+        };
+        table.add_row(Row::new(vec![
+            Cell::new(&format!("{:?}", slot)),
+            Cell::new(match range_name.as_str() {
+                TOTAL_GROUP => "TOTAL",
+                range_name => range_name,
+            }),
+            Cell::new(&format_ranges(range)),
+            participation_cell(summary.source_participation_ratio),
+            participation_cell(summary.target_participation_ratio),
+            participation_cell(summary.head_participation_ratio),
+            Cell::new(&format!(
+                "{:.2}",
+                gwei_to_eth(summary.balance_gwei_sum as f64)
+            )),
+            Cell::new(&format!("{:.4}", gwei_to_eth(summary.balance_gwei_avg))),
+        ]));
+    }
+
+    table
+}
+
+#[derive(Serialize)]
+struct ParticipationRecord {
+    slot: u64,
+    name: String,
+    range: String,
+    source: String,
+    target: String,
+    head: String,
+}
+
+fn format_participation_json(slot: u64, rows: &[DumpRow]) -> String {
+    let mut records = Vec::new();
+
+    for (range_name, range, summary) in rows {
+        records.push(ParticipationRecord {
+            slot,
+            name: range_name.clone(),
+            range: format_ranges(range),
+            source: summary.source_participation_ratio.to_string(),
+            target: summary.target_participation_ratio.to_string(),
+            head: summary.head_participation_ratio.to_string(),
+        });
+    }
+
+    let json = to_string(&records).unwrap();
+    format!("{{ \"participation\": {} }}\n", json)
+}
+
+/// Export the flags, balance and inactivity score of every validator in `ranges`
+fn set_validator_metrics(
+    flags: &ParticipationFlags,
+    ranges: &IndexRanges,
+    state: &StatePartial,
+    balance_unit: BalanceUnit,
+) {
+    for index in ranges.iter().flat_map(|(_, range)| range.clone()) {
+        let (Some(participation), Some(balance), Some(inactivity_score)) = (
+            state.previous_epoch_participation.get(index),
+            state.balances.get(index),
+            state.inactivity_scores.get(index),
+        ) else {
+            continue;
+        };
+        let validator = index.to_string();
+        for (flag_name, flag_mask) in [
+            ("source", flags.source),
+            ("target", flags.target),
+            ("head", flags.head),
+        ] {
+            set_gauge(
+                &VALIDATOR_PARTICIPATION,
+                &[&validator, flag_name],
+                has_flag(*participation, flag_mask) as u8 as f64,
+            );
+        }
+        if balance_unit.gwei() {
+            set_gauge(&VALIDATOR_BALANCE_GWEI, &[&validator], *balance as f64);
+        }
+        if balance_unit.eth() {
+            set_gauge(
+                &VALIDATOR_BALANCE_ETH,
+                &[&validator],
+                gwei_to_eth(*balance as f64),
+            );
+        }
+        set_gauge(
+            &VALIDATOR_INACTIVITY_SCORE,
+            &[&validator],
+            *inactivity_score as f64,
+        );
+    }
+}
+
+/// Log a warning for each of `indices` missing the timely target flag in `epoch`
+fn warn_missed_target(
+    flags: &ParticipationFlags,
+    indices: &[usize],
+    state: &StatePartial,
+    epoch: u64,
+) {
+    for index in indices {
+        match state.previous_epoch_participation.get(*index) {
+            None => eprintln!("WARN validator {index} not found in state"),
+            Some(participation) if !has_flag(*participation, flags.target) => {
+                eprintln!("WARN validator {index} missed timely target in epoch {epoch}")
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+/// Hash of the resolved configuration, whichever of the command line, environment or --config
+/// set it, and of the ranges, which may come from a remote file. Stable across restarts to be
+/// compared with the hash of the previous run.
+pub(crate) fn config_hash(args: &Config, ranges: &IndexRanges) -> String {
+    let config = to_string(args).unwrap();
+    format!("{:016x}", fnv1a(&config, &dump_ranges(ranges)))
+}
+
+pub(crate) fn unix_time() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// Export the start of the process and its configuration, to tell metric discontinuities caused
+/// by restarts or config changes apart from chain events
+pub(crate) fn record_start(config: &ConfigChange) {
+    inc_counter_by(&EXPORTER_STARTS, &[], 1);
+    set_gauge(&EXPORTER_START_TIME, &[], unix_time().floor());
+    set_gauge(&EXPORTER_CONFIG_INFO, &[&config.hash], 1.0);
+    set_gauge(&EXPORTER_CONFIG_CHANGE_TIME, &[], config.changed_at as f64);
+}
+
+/// Export the network of the node, and return it as `network` label for its series unless
+/// disabled or set with `--label`
+pub(crate) fn detect_network(
+    config: &ConfigSpec,
+    labels: &[StaticLabel],
+    disabled: bool,
+) -> Option<StaticLabel> {
+    let network = config.network_name();
+    set_gauge(
+        &NETWORK_INFO,
+        &[
+            network.as_deref().unwrap_or(""),
+            config.config_name.as_deref().unwrap_or(""),
+            &config
+                .deposit_chain_id
+                .map_or(String::new(), |id| id.to_string()),
+        ],
+        1.0,
+    );
+    let overridden = labels.iter().any(|label| label.name == "network");
+    network
+        .filter(|_| !disabled && !overridden)
+        .map(|network| StaticLabel {
+            name: "network".to_owned(),
+            value: network,
+        })
+}
+
+/// Export the slot and epochs of the fetched state, and its root as fetched from the node
+async fn track_chain_position(
+    config: &ConfigSpec,
+    beacon_url: &str,
+    state: &StatePartial,
+) -> Result<()> {
+    let state_epoch = state.slot / config.slots_per_epoch;
+    set_gauge(&STATE_SLOT, &[], state.slot as f64);
+    set_gauge(
+        &PARTICIPATION_EPOCH,
+        &[],
+        state_epoch.saturating_sub(1) as f64,
+    );
+    let state_root = fetch_state_root(beacon_url, &state.slot.to_string()).await?;
+    reset_gauge(&STATE_INFO);
+    set_gauge(&STATE_INFO, &[&format_root(&state_root)], 1.0);
+    Ok(())
+}
+
+/// Export the active set size, its change, the committee count and the activation and exit queues
+/// at `epoch`, and keep them for `/api/v1/active_set`
+fn track_active_set(
+    config: &ConfigSpec,
+    state: &StatePartial,
+    epoch: u64,
+    active_set: &Mutex<Option<ActiveSetStats>>,
+) {
+    let stats = active_set_stats(config, state, epoch);
+    set_gauge(&ACTIVE_VALIDATORS, &[], stats.active_validators as f64);
+    set_gauge(
+        &ACTIVE_VALIDATORS_DELTA,
+        &[],
+        stats.active_validators_delta as f64,
+    );
+    set_gauge(&COMMITTEES_PER_SLOT, &[], stats.committees_per_slot as f64);
+    set_gauge(
+        &PENDING_ACTIVATION_VALIDATORS,
+        &[],
+        stats.pending_activation as f64,
+    );
+    set_gauge(&PENDING_EXIT_VALIDATORS, &[], stats.pending_exit as f64);
+    set_gauge(
+        &ACTIVATION_CHURN_LIMIT,
+        &[],
+        stats.activation_churn_limit as f64,
+    );
+    set_gauge(
+        &EPOCHS_TO_ACTIVATION,
+        &[],
+        stats.epochs_to_activation as f64,
+    );
+    *active_set.lock().unwrap() = Some(stats);
+}
+
+/// Export whether each group has members in the current sync committee
+async fn track_sync_duties(beacon_url: &str, ranges: &IndexRanges) -> Result<()> {
+    let members = fetch_sync_committee(beacon_url, "head").await?;
+    for (range_name, count) in count_by_group(ranges, members.iter().copied()) {
+        set_gauge(&HAS_SYNC_DUTY, &[&range_name], (count > 0) as u8 as f64);
+    }
+    Ok(())
+}
+
+/// Export the proposals scheduled per group in the current and next epoch, and the next
+/// proposal slot of each of `indices`
+async fn track_upcoming_proposals(
+    config: &ConfigSpec,
+    beacon_url: &str,
+    ranges: &IndexRanges,
+    indices: &[usize],
+    current_slot: u64,
+) -> Result<()> {
+    let current_epoch = current_slot / config.slots_per_epoch;
+    let mut duties = fetch_proposer_duties(beacon_url, current_epoch).await?;
+    let next_duties = fetch_proposer_duties(beacon_url, current_epoch + 1).await?;
+    for (epoch, epoch_duties) in [("current", &duties), ("next", &next_duties)] {
+        for (range_name, scheduled) in count_scheduled_proposals(ranges, epoch_duties) {
+            set_gauge(
+                &SCHEDULED_PROPOSALS,
+                &[&range_name, epoch],
+                scheduled as f64,
+            );
+            if epoch == "current" {
+                set_gauge(
+                    &HAS_PROPOSER_DUTY,
+                    &[&range_name],
+                    (scheduled > 0) as u8 as f64,
+                );
+            }
+        }
+    }
+    duties.extend(next_duties);
+
+    reset_gauge(&VALIDATOR_NEXT_PROPOSAL_SLOT);
+    for index in indices {
+        let next_slot = duties
+            .iter()
+            .filter(|duty| duty.validator_index == *index && duty.slot >= current_slot)
+            .map(|duty| duty.slot)
+            .min();
+        if let Some(next_slot) = next_slot {
+            println!("validator {index} proposes at slot {next_slot}");
+            set_gauge(
+                &VALIDATOR_NEXT_PROPOSAL_SLOT,
+                &[&index.to_string()],
+                next_slot as f64,
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Settings of the fetch loop
+#[derive(Default)]
+pub(crate) struct FetchTaskOptions {
+    pub(crate) dump_format: Option<DumpFormat>,
+    pub(crate) dump_file: Option<DumpFile>,
+    /// Order of dumped groups if not that of the metrics, see --dump-sort
+    pub(crate) dump_sort: Option<GroupOrder>,
+    pub(crate) dump_min: Option<f32>,
+    pub(crate) dump_missing: bool,
+    /// Default thresholds of the table dump colors, None if colors are disabled
+    pub(crate) dump_colors: Option<GroupThresholds>,
+    pub(crate) proposal_correlation_window: Option<u64>,
+    pub(crate) per_validator_metrics: bool,
+    /// Indices set with `--my-validators`
+    pub(crate) my_validators: Vec<usize>,
+    pub(crate) cardinality_budget: Option<usize>,
+    pub(crate) custom_metrics: Vec<CustomMetric>,
+    pub(crate) inclusion_delay: bool,
+    pub(crate) wrong_target: bool,
+    pub(crate) packing_efficiency: bool,
+    pub(crate) attestation_inclusion: bool,
+    /// Risk thresholds of the client stake shares, empty without --client-diversity
+    pub(crate) client_risk_thresholds: Vec<f64>,
+    /// Prefixes of --graffiti-groups, empty if unset
+    pub(crate) graffiti_prefixes: Vec<String>,
+    pub(crate) group_by_fee_recipient: bool,
+    pub(crate) attestation_rewards: bool,
+    pub(crate) estimated_penalties: bool,
+    pub(crate) anomaly_stddevs: Option<f64>,
+    pub(crate) miss_streak_epochs: u64,
+    /// Whether states may be sent gzip compressed
+    pub(crate) compression: bool,
+    pub(crate) reorgs: bool,
+    /// Attestations seen by the gossip subscription, shared with the scan of included ones
+    pub(crate) gossip_seen: Option<Arc<Mutex<FirstSeen>>>,
+    pub(crate) trusted_checkpoint: Option<TrustedCheckpoint>,
+    /// Sync distance beyond which a syncing node's state is not fetched
+    pub(crate) max_sync_distance: u64,
+    /// Delay of each epoch's fetch, see --stagger-fraction and --fetch-jitter-secs
+    pub(crate) stagger_fraction: f64,
+    pub(crate) fetch_jitter_secs: f64,
+    pub(crate) balance_unit: BalanceUnit,
+    pub(crate) group_order: GroupOrder,
+    pub(crate) alerts: Option<AlertConfig>,
+    /// Thresholds set in the ranges file
+    pub(crate) group_thresholds: ThresholdsByGroup,
+    /// Epochs behind the current one of the fetched state, 0 for `state_id`
+    pub(crate) follow_distance: u64,
+    /// State fetched without a follow distance, see `--state-id`
+    pub(crate) state_id: String,
+    /// Notified on the first head event of each epoch if --event-stream is set
+    pub(crate) epoch_events: Option<Arc<Notify>>,
+    /// Latest active set stats, shared with the server's `/api/v1/active_set`
+    pub(crate) active_set: Arc<Mutex<Option<ActiveSetStats>>>,
+    /// Latest participation, shared with the server's `/api/v1/participation`, `/` and `/stream`
+    pub(crate) participation: Arc<LatestParticipation>,
+    /// Monitored validators of the latest state, shared with the server's `/api/v1/validators`
+    pub(crate) validators: Arc<Mutex<Option<ValidatorInventory>>>,
+    /// Miss streaks of the monitored validators, shared with the server's `/api/v1/worst`
+    pub(crate) miss_streaks: Arc<Mutex<MissStreaks>>,
+    /// Saved after each new epoch, see --history-file
+    pub(crate) history_file: Option<String>,
+    /// Saved to --history-file with the history
+    pub(crate) config_change: ConfigChange,
+    /// Stores each new epoch, see --db
+    pub(crate) db: Option<Mutex<ParticipationDb>>,
+    /// Writes each new epoch as Parquet, see --parquet-dir
+    pub(crate) parquet_dir: Option<String>,
+    pub(crate) parquet_validators: bool,
+    /// Skips fetching while set, shared with the server's pause and resume endpoints
+    pub(crate) paused: Arc<AtomicBool>,
+    /// Ends the loop at its next wait between epochs once requested
+    pub(crate) shutdown: Shutdown,
+}
+
+/// Sample the proposal outcome and adjacent head votes of every slot in the state's previous
+/// epoch and export the updated contingency counts and rolling correlations
+async fn track_proposal_correlation(
+    config: &ConfigSpec,
+    beacon_url: &str,
+    ranges: &IndexRanges,
+    state: &StatePartial,
+    proposal_correlation: &mut ProposalCorrelation,
+) -> Result<()> {
+    let epoch = (state.slot / config.slots_per_epoch).saturating_sub(1);
+    let duties = fetch_proposer_duties(beacon_url, epoch).await?;
+    let committees = fetch_committees(beacon_url, "head", epoch).await?;
+    let start_slot = epoch * config.slots_per_epoch;
+    let samples = epoch_slot_samples(
+        &config.participation_flags,
+        ranges,
+        state,
+        start_slot..(start_slot + config.slots_per_epoch),
+        &duties,
+        &committees,
+    );
+
+    for sample in samples.iter() {
+        let Some(proposer_range) = &sample.proposer_range else {
+            continue;
+        };
+        let outcome = if sample.missed { "missed" } else { "proposed" };
+        let votes = sample.other_head_votes();
+        inc_counter_by(
+            &PROPOSAL_ADJACENT_ATTESTERS,
+            &[proposer_range, outcome],
+            votes.attesters,
+        );
+        inc_counter_by(
+            &PROPOSAL_ADJACENT_HEAD_MISSES,
+            &[proposer_range, outcome],
+            votes.misses,
+        );
+    }
+
+    if proposal_correlation.record(epoch, samples) {
+        for (range_name, _) in ranges.iter() {
+            if let Some(correlation) = proposal_correlation.correlation(range_name) {
+                set_gauge(&PROPOSAL_HEAD_MISS_CORRELATION, &[range_name], correlation);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Count the proposed and missed blocks of the state's previous epoch
+async fn track_block_proposals(
+    config: &ConfigSpec,
+    beacon_url: &str,
+    ranges: &IndexRanges,
+    state: &StatePartial,
+    report: &Mutex<RunReport>,
+) -> Result<()> {
+    let epoch = (state.slot / config.slots_per_epoch).saturating_sub(1);
+    let duties = fetch_proposer_duties(beacon_url, epoch).await?;
+    let proposals = count_proposals(ranges, &duties, state);
+    for (range_name, counts) in proposals.iter() {
+        inc_counter_by(&BLOCKS_PROPOSED, &[range_name], counts.proposed);
+        inc_counter_by(&BLOCKS_MISSED, &[range_name], counts.missed);
+        if counts.missed > 0 {
+            eprintln!(
+                "WARN range {} missed {} proposals in epoch {}",
+                range_name, counts.missed, epoch
+            );
+        }
+    }
+    report.lock().unwrap().record_proposals(&proposals);
+    Ok(())
+}
+
+/// Count the proposed and missed blocks of the state's previous epoch per graffiti group and
+/// per fee recipient, fetching its blocks once for both
+async fn track_block_origins(
+    config: &ConfigSpec,
+    beacon_url: &str,
+    state: &StatePartial,
+    graffiti_groups: Option<&mut GraffitiGroups>,
+    fee_recipients: Option<&mut ProposerGroups>,
+) -> Result<()> {
+    let epoch = (state.slot / config.slots_per_epoch).saturating_sub(1);
+    let duties = fetch_proposer_duties(beacon_url, epoch).await?;
+    let mut blocks = HashMap::new();
+    for duty in &duties {
+        if state.block_proposed_at(duty.slot) == Some(true) {
+            if let Some(block) = fetch_block_origin(beacon_url, duty.slot).await? {
+                blocks.insert(duty.slot, block);
+            }
+        }
+    }
+    if let Some(graffiti_groups) = graffiti_groups {
+        for (group, counts) in graffiti_groups.count(&duties, state, &blocks) {
+            inc_counter_by(&GRAFFITI_BLOCKS_PROPOSED, &[&group], counts.proposed);
+            inc_counter_by(&GRAFFITI_BLOCKS_MISSED, &[&group], counts.missed);
+        }
+    }
+    if let Some(fee_recipients) = fee_recipients {
+        // Blocks before bellatrix have no fee recipient and are not counted
+        let by_fee_recipient = blocks
+            .iter()
+            .filter_map(|(slot, block)| {
+                let fee_recipient = format_address(&block.fee_recipient?);
+                Some((*slot, (block.proposer_index, fee_recipient)))
+            })
+            .collect();
+        for (fee_recipient, counts) in fee_recipients.count(&duties, state, &by_fee_recipient) {
+            inc_counter_by(
+                &FEE_RECIPIENT_BLOCKS_PROPOSED,
+                &[&fee_recipient],
+                counts.proposed,
+            );
+            inc_counter_by(
+                &FEE_RECIPIENT_BLOCKS_MISSED,
+                &[&fee_recipient],
+                counts.missed,
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Export the inclusion delay, inclusion and wrong target votes of the attestations of two
+/// epochs before `state_epoch`, the most recent epoch whose attestations can no longer be
+/// included
+async fn track_included_attestations(
+    config: &ConfigSpec,
+    beacon_url: &str,
+    ranges: &IndexRanges,
+    state: &StatePartial,
+    options: &FetchTaskOptions,
+    state_epoch: u64,
+) -> Result<()> {
+    let Some(epoch) = state_epoch.checked_sub(2) else {
+        return Ok(());
+    };
+    let start_slot = epoch * config.slots_per_epoch;
+    // Committees of the previous epoch are available from any state of the next one
+    let committees = fetch_committees(
+        beacon_url,
+        &(start_slot + config.slots_per_epoch).to_string(),
+        epoch,
+    )
+    .await?;
+
+    let mut blocks = vec![];
+    let mut proposers = HashMap::new();
+    for slot in (start_slot + 1)..(start_slot + 2 * config.slots_per_epoch) {
+        if let Some(block) = fetch_block_attestations(beacon_url, slot).await? {
+            blocks.push((slot, block.attestations));
+            proposers.insert(slot, block.proposer_index);
+        }
+    }
+
+    if options.inclusion_delay || options.attestation_inclusion {
+        let delays = inclusion_delays(&committees, &blocks);
+        if options.inclusion_delay {
+            for (range_name, delay) in group_inclusion_delay(ranges, &delays) {
+                set_gauge(&INCLUSION_DELAY_AVG, &[&range_name], delay);
+            }
+        }
+        if options.attestation_inclusion {
+            let first_seen = options
+                .gossip_seen
+                .as_ref()
+                .map(|seen| seen.lock().unwrap());
+            // No sightings if the gossip subscription started after the epoch
+            let none_seen = HashSet::new();
+            let seen = first_seen
+                .as_ref()
+                .map(|first_seen| first_seen.seen(epoch).unwrap_or(&none_seen));
+            for (range_name, inclusion) in group_inclusion(ranges, &committees, &delays, seen) {
+                set_gauge(&ATTESTATION_DUTIES, &[&range_name], inclusion.duties as f64);
+                set_gauge(
+                    &ATTESTATIONS_INCLUDED,
+                    &[&range_name],
+                    inclusion.included as f64,
+                );
+                if let Some(missed) = inclusion.seen_not_included {
+                    set_gauge(
+                        &ATTESTATIONS_SEEN_NOT_INCLUDED,
+                        &[&range_name],
+                        missed as f64,
+                    );
+                }
+            }
+        }
+    }
+
+    if options.wrong_target {
+        let canonical_target = root_at(state, start_slot).ok_or_else(|| {
+            anyhow!("state does not cover the target checkpoint of epoch {epoch}")
+        })?;
+        let wrong = wrong_target_attesters(&committees, &blocks, &canonical_target);
+        for (range_name, count) in count_by_group(ranges, wrong.iter().copied()) {
+            set_gauge(&WRONG_TARGET_ATTESTERS, &[&range_name], count as f64);
+            if count > 0 {
+                eprintln!(
+                    "WARN range {} has {} validators voting for a wrong target in epoch {}",
+                    range_name, count, epoch
+                );
+            }
+        }
+    }
+
+    if options.packing_efficiency {
+        let slots_per_epoch = config.slots_per_epoch;
+        // Since deneb votes can be included until the end of the next epoch
+        let deneb = Fork::at_epoch(config, epoch) >= Fork::Deneb;
+        let last_includable = |slot: u64| {
+            if deneb {
+                (slot / slots_per_epoch + 2) * slots_per_epoch - 1
+            } else {
+                slot + slots_per_epoch
+            }
+        };
+        let packing = block_packing(&committees, &blocks, last_includable);
+        for (range_name, efficiency) in group_packing_efficiency(ranges, &proposers, &packing) {
+            set_gauge(&ATTESTATION_PACKING_EFFICIENCY, &[&range_name], efficiency);
+        }
+    }
+    Ok(())
+}
+
+/// Export the attestation rewards of two epochs before `state_epoch`, the latest epoch whose
+/// rewards have been applied
+async fn track_attestation_rewards(
+    beacon_url: &str,
+    ranges: &IndexRanges,
+    state: &StatePartial,
+    state_epoch: u64,
+) -> Result<()> {
+    let Some(epoch) = state_epoch.checked_sub(2) else {
+        return Ok(());
+    };
+    let mut indices: Vec<usize> = ranges.iter().flat_map(|(_, range)| range.clone()).collect();
+    indices.sort_unstable();
+    indices.dedup();
+    let rewards = fetch_attestation_rewards(beacon_url, epoch, &indices).await?;
+
+    for (range_name, group) in group_rewards(ranges, &rewards, &state.validators) {
+        for (component, reward) in group.actual.named() {
+            set_gauge(
+                &ATTESTATION_REWARDS_GWEI,
+                &[&range_name, component],
+                reward as f64,
+            );
+        }
+        for (component, reward) in group.ideal.named() {
+            set_gauge(
+                &ATTESTATION_IDEAL_REWARDS_GWEI,
+                &[&range_name, component],
+                reward as f64,
+            );
+        }
+        if let Some(efficiency) = group.efficiency() {
+            set_gauge(&ATTESTATION_EFFICIENCY, &[&range_name], efficiency);
+        }
+    }
+    Ok(())
+}
+
+/// Participation state carried across the exported states
+#[derive(Default)]
+struct ParticipationTrackers {
+    slashed_by_range: HashMap<String, u64>,
+    /// Target participation of the last observed epoch
+    target_by_range: HashMap<String, f32>,
+    alert_state: AlertState,
+    /// Validators in no range at the last fetch, logged when it changes
+    ungrouped_validators: Option<usize>,
+    /// Ranges past the end of the last fetched state, warned about when they change
+    out_of_bounds_ranges: IndexRanges,
+}
+
+/// Export the participation of `state`, record it in the report and evaluate alerts. Shared by
+/// the fetch loop and the simulation. Returns whether the state's previous epoch is new to the
+/// report.
+async fn export_participation(
+    config: &ConfigSpec,
+    ranges: &IndexRanges,
+    state: &StatePartial,
+    slot: u64,
+    options: &FetchTaskOptions,
+    report: &Mutex<RunReport>,
+    trackers: &mut ParticipationTrackers,
+) -> bool {
+    let state_epoch = state.slot / config.slots_per_epoch;
+    set_gauge(&LAST_UPDATE_TIMESTAMP, &[], unix_time());
+    set_gauge(&LAST_UPDATE_EPOCH, &[], state_epoch as f64);
+    set_justification_to_metrics(&state.justification);
+    track_active_set(config, state, state_epoch, &options.active_set);
+    let inventory =
+        ValidatorInventory::new(&config.participation_flags, ranges, state, state_epoch);
+    options.miss_streaks.lock().unwrap().update(&inventory);
+    *options.validators.lock().unwrap() = Some(inventory);
+    let mut participation_by_range =
+        group_target_participation(&config.participation_flags, ranges, state, state_epoch);
+    options.group_order.sort(
+        &mut participation_by_range,
+        |(range_name, _, _)| range_name,
+        |(_, _, summary)| summary.target_participation_ratio,
+    );
+    set_participation_to_metrics(&participation_by_range, options.balance_unit);
+    let total = total_participation(&config.participation_flags, state, state_epoch);
+    set_flag_participation_to_metrics(TOTAL_GROUP, &total.2);
+    set_other_participation_to_metrics(
+        &config.participation_flags,
+        ranges,
+        state,
+        state_epoch,
+        &mut trackers.ungrouped_validators,
+    );
+    if !options.client_risk_thresholds.is_empty() {
+        set_client_diversity(ranges, state, state_epoch, &options.client_risk_thresholds);
+    }
+    if options.estimated_penalties {
+        for (range_name, penalties) in estimate_group_penalties(config, ranges, state, state_epoch)
+        {
+            for (component, penalty) in penalties.named() {
+                set_gauge(
+                    &ESTIMATED_PENALTIES_GWEI,
+                    &[&range_name, component],
+                    penalty as f64,
+                );
+            }
+        }
+    }
+    set_threshold_breaches(&participation_by_range, &options.group_thresholds);
+    set_consistently_missing(&participation_by_range, options);
+    if let Some(stddevs) = options.anomaly_stddevs {
+        set_participation_anomalies(&participation_by_range, &total.2, stddevs);
+    }
+    set_custom_metrics(&options.custom_metrics, &participation_by_range);
+    warn_new_slashings(&mut trackers.slashed_by_range, &participation_by_range);
+    dump_participation(slot, &participation_by_range, &total, options);
+    options.participation.publish(ParticipationSnapshot {
+        slot: state.slot,
+        epoch: state_epoch.saturating_sub(1),
+        timestamp: unix_time() as u64,
+        groups: participation_by_range
+            .iter()
+            .map(|(range_name, ranges, summary)| {
+                GroupParticipation::new(range_name, ranges, summary)
+            })
+            .collect(),
+        total: GroupParticipation::new(&total.0, &total.1, &total.2),
+    });
+    // previous_epoch_participation refers to the epoch before the state's
+    let (new_epoch, perfect_threshold) = {
+        let mut report = report.lock().unwrap();
+        let new_epoch =
+            report.record_participation(state_epoch.saturating_sub(1), &participation_by_range);
+        for (range_name, streak) in report.perfect_streaks() {
+            set_gauge(&PERFECT_EPOCH_STREAK, &[range_name], streak as f64);
+        }
+        (new_epoch, report.perfect_threshold())
+    };
+    if new_epoch {
+        count_perfect_epochs(&participation_by_range, perfect_threshold);
+        set_participation_deltas(&mut trackers.target_by_range, &participation_by_range);
+        if let Some(alert_config) = &options.alerts {
+            let alerts = trackers.alert_state.evaluate(
+                alert_config,
+                &participation_by_range,
+                state_epoch.saturating_sub(1),
+            );
+            if let Err(e) = send_alerts(alert_config, &alerts).await {
+                eprintln!("error sending alerts: {:?}", e);
+            }
+        }
+        let latest = options.participation.snapshot.lock().unwrap().clone();
+        if let (Some(db), Some(snapshot)) = (&options.db, &latest) {
+            if let Err(e) = db.lock().unwrap().insert(snapshot) {
+                eprintln!("error saving epoch to --db: {:?}", e);
+            }
+        }
+        if let (Some(parquet_dir), Some(snapshot)) = (&options.parquet_dir, &latest) {
+            let validators = options.validators.lock().unwrap();
+            let validators = validators.as_ref().filter(|_| options.parquet_validators);
+            if let Err(e) = write_parquet_epoch(parquet_dir, snapshot, validators) {
+                eprintln!("error writing epoch to --parquet-dir: {:?}", e);
+            }
+        }
+        if let Some(history_file) = &options.history_file {
+            let saved = save_history(
+                history_file,
+                report,
+                &options.miss_streaks,
+                &options.participation,
+                &options.config_change,
+            );
+            if let Err(e) = saved {
+                eprintln!("error saving history: {:?}", e);
+            }
+        }
+    }
+    if options.per_validator_metrics {
+        set_validator_metrics(
+            &config.participation_flags,
+            ranges,
+            state,
+            options.balance_unit,
+        );
+    }
+    if !options.my_validators.is_empty() {
+        warn_missed_target(
+            &config.participation_flags,
+            &options.my_validators,
+            state,
+            state_epoch.saturating_sub(1),
+        );
+    }
+    new_epoch
+}
+
+/// Export the synthetic states of `scenario` every `seconds_per_epoch`, in place of the fetch loop
+pub(crate) async fn task_simulate(
+    config: &ConfigSpec,
+    ranges: &IndexRanges,
+    options: &FetchTaskOptions,
+    report: &Mutex<RunReport>,
+    scenario: &Scenario,
+) {
+    let mut trackers = ParticipationTrackers::default();
+    for epoch in 0.. {
+        if options.paused.load(Ordering::Relaxed) {
+            println!("collection paused, going to sleep");
+        } else {
+            let state = scenario.state(config, ranges, epoch);
+            set_gauge(&CURRENT_EPOCH, &[], (epoch + 1) as f64);
+            set_gauge(&STATE_SLOT, &[], state.slot as f64);
+            set_gauge(&PARTICIPATION_EPOCH, &[], epoch as f64);
+            export_participation(
+                config,
+                ranges,
+                &state,
+                state.slot,
+                options,
+                report,
+                &mut trackers,
+            )
+            .await;
+            check_cardinality(options.cardinality_budget);
+        }
+        tokio::select! {
+            _ = time::sleep(Duration::from_secs(scenario.seconds_per_epoch)) => {}
+            _ = options.shutdown.wait() => return,
+        }
+    }
+}
+
+/// Slots after the epoch start to wait for a head event before fetching anyway
+const EVENT_FALLBACK_SLOTS: u64 = 2;
+
+/// State to fetch at the current epoch start `slot`, as set by --state-id or --follow-distance
+fn state_id_at(config: &ConfigSpec, options: &FetchTaskOptions, slot: u64) -> String {
+    match options.follow_distance {
+        0 => options.state_id.clone(),
+        distance => slot
+            .saturating_sub(distance * config.slots_per_epoch)
+            .to_string(),
+    }
+}
+
+/// Fetch a single state and print the participation of its groups, for --once
+pub(crate) async fn print_participation_once(
+    genesis: &Genesis,
+    config: &ConfigSpec,
+    beacon_url: &str,
+    ranges: &IndexRanges,
+    options: &FetchTaskOptions,
+) -> Result<()> {
+    let slot = current_epoch_start_slot(genesis, config)?;
+    let state = fetch_epoch_participation(
+        config,
+        beacon_url,
+        &state_id_at(config, options, slot),
+        options.compression,
+        options.trusted_checkpoint.clone().as_mut(),
+    )
+    .await
+    .context("fetch state")?;
+    let ranges = clamp_ranges_to_state(ranges, &state, &mut vec![]);
+    let state_epoch = state.slot / config.slots_per_epoch;
+    let mut participation_by_range =
+        group_target_participation(&config.participation_flags, &ranges, &state, state_epoch);
+    options.group_order.sort(
+        &mut participation_by_range,
+        |(range_name, _, _)| range_name,
+        |(_, _, summary)| summary.target_participation_ratio,
+    );
+    let total = total_participation(&config.participation_flags, &state, state_epoch);
+    let rows = dump_rows(&participation_by_range, options);
+    let format = options.dump_format.unwrap_or(DumpFormat::Table);
+    print_dump(state.slot, &rows, &total, format, options);
+    Ok(())
+}
+
+/// Export whether the fetch cycle got a state from the beacon node at `url` and the failed
+/// cycles in a row
+fn track_node_up(url: &str, consecutive_failures: &mut u64, up: bool) {
+    *consecutive_failures = if up { 0 } else { *consecutive_failures + 1 };
+    set_gauge(&BEACON_NODE_UP, &[url], up as u64 as f64);
+    set_gauge(
+        &BEACON_NODE_CONSECUTIVE_FAILURES,
+        &[url],
+        *consecutive_failures as f64,
+    );
+}
+
+/// Whether the state download should be skipped because the node is syncing more than
+/// `max_distance` slots behind. Fetches anyway if the sync status is unavailable.
+async fn skip_while_syncing(beacon_url: &str, max_distance: u64) -> bool {
+    let skip = match fetch_sync_status(beacon_url).await {
+        Ok(status) => status.is_behind(max_distance),
+        Err(e) => {
+            eprintln!("error fetching sync status: {:?}", e);
+            false
+        }
+    };
+    export_skipped_syncing(skip);
+    skip
+}
+
+pub(crate) async fn task_fetch_state_every_epoch(
+    genesis: &Genesis,
+    config: &ConfigSpec,
+    beacon_url: &str,
+    ranges: &IndexRanges,
+    options: &FetchTaskOptions,
+    report: &Mutex<RunReport>,
+) -> Result<()> {
+    let mut proposal_correlation = options
+        .proposal_correlation_window
+        .map(|window| ProposalCorrelation::new(window, config.slots_per_epoch));
+    let mut graffiti_groups = (!options.graffiti_prefixes.is_empty())
+        .then(|| GraffitiGroups::new(options.graffiti_prefixes.clone()));
+    let mut fee_recipients = options.group_by_fee_recipient.then(ProposerGroups::default);
+    let mut trackers = ParticipationTrackers::default();
+    let mut trusted_checkpoint = options.trusted_checkpoint.clone();
+    // Root of the last exported state, to not download and decode it again
+    let mut last_state_root = None;
+    let node_url = url_label(beacon_url);
+    let mut consecutive_failures = 0;
+
+    loop {
+        match current_epoch_start_slot(genesis, config) {
+            Err(e) => eprintln!("error computing current epoch: {:?}", e),
+            Ok(slot) => {
+                if slot == 0 {
+                    println!("before genesis, going to sleep")
+                } else if options.paused.load(Ordering::Relaxed) {
+                    println!("collection paused, going to sleep")
+                } else {
+                    // Only after genesis
+                    set_gauge(&CURRENT_EPOCH, &[], (slot / config.slots_per_epoch) as f64);
+                    let state_id = state_id_at(config, options, slot);
+                    // Roots are cheap to fetch, states are not
+                    let state_root = match fetch_state_root(beacon_url, &state_id).await {
+                        Ok(root) => Some(root),
+                        Err(e) => {
+                            eprintln!("error fetching state root: {:?}", e);
+                            None
+                        }
+                    };
+                    if state_root.is_some() && state_root == last_state_root {
+                        println!("state {state_id} unchanged since last fetched, skipping");
+                        track_node_up(&node_url, &mut consecutive_failures, true);
+                    } else if skip_while_syncing(beacon_url, options.max_sync_distance).await {
+                        println!("beacon node is syncing, skipping state {state_id}");
+                    } else {
+                        match fetch_epoch_participation(
+                            config,
+                            beacon_url,
+                            &state_id,
+                            options.compression,
+                            trusted_checkpoint.as_mut(),
+                        )
+                        .await
+                        {
+                            Err(e) => {
+                                eprintln!("error fetching state: {:?}", e);
+                                track_node_up(&node_url, &mut consecutive_failures, false);
+                            }
+                            Ok(state) => {
+                                track_node_up(&node_url, &mut consecutive_failures, true);
+                                last_state_root = state_root;
+                                // Slicing the state with ranges past its end would panic
+                                let ranges = &clamp_ranges_to_state(
+                                    ranges,
+                                    &state,
+                                    &mut trackers.out_of_bounds_ranges,
+                                );
+                                let state_epoch = state.slot / config.slots_per_epoch;
+                                if let Err(e) =
+                                    track_chain_position(config, beacon_url, &state).await
+                                {
+                                    eprintln!("error fetching state root: {:?}", e);
+                                }
+                                let new_epoch = export_participation(
+                                    config,
+                                    ranges,
+                                    &state,
+                                    slot,
+                                    options,
+                                    report,
+                                    &mut trackers,
+                                )
+                                .await;
+                                if new_epoch {
+                                    if let Err(e) = track_block_proposals(
+                                        config, beacon_url, ranges, &state, report,
+                                    )
+                                    .await
+                                    {
+                                        eprintln!("error tracking block proposals: {:?}", e);
+                                    }
+                                }
+
+                                if let Some(proposal_correlation) = proposal_correlation.as_mut() {
+                                    if let Err(e) = track_proposal_correlation(
+                                        config,
+                                        beacon_url,
+                                        ranges,
+                                        &state,
+                                        proposal_correlation,
+                                    )
+                                    .await
+                                    {
+                                        eprintln!("error tracking proposal correlation: {:?}", e);
+                                    }
+                                }
+
+                                if graffiti_groups.is_some() || fee_recipients.is_some() {
+                                    if let Err(e) = track_block_origins(
+                                        config,
+                                        beacon_url,
+                                        &state,
+                                        graffiti_groups.as_mut(),
+                                        fee_recipients.as_mut(),
+                                    )
+                                    .await
+                                    {
+                                        eprintln!("error tracking block origins: {:?}", e);
+                                    }
+                                }
+
+                                if options.inclusion_delay
+                                    || options.wrong_target
+                                    || options.packing_efficiency
+                                    || options.attestation_inclusion
+                                {
+                                    if let Err(e) = track_included_attestations(
+                                        config,
+                                        beacon_url,
+                                        ranges,
+                                        &state,
+                                        options,
+                                        state_epoch,
+                                    )
+                                    .await
+                                    {
+                                        eprintln!("error tracking included attestations: {:?}", e);
+                                    }
+                                }
+
+                                if options.attestation_rewards {
+                                    if let Err(e) = track_attestation_rewards(
+                                        beacon_url,
+                                        ranges,
+                                        &state,
+                                        state_epoch,
+                                    )
+                                    .await
+                                    {
+                                        eprintln!("error tracking attestation rewards: {:?}", e);
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    if let Err(e) = track_upcoming_proposals(
+                        config,
+                        beacon_url,
+                        ranges,
+                        &options.my_validators,
+                        slot,
+                    )
+                    .await
+                    {
+                        eprintln!("error fetching proposer duties: {:?}", e);
+                    }
+                    if let Err(e) = track_sync_duties(beacon_url, ranges).await {
+                        eprintln!("error fetching sync committee: {:?}", e);
+                    }
+
+                    match fetch_checkpoint_finality(beacon_url, "head").await {
+                        Err(e) => eprintln!("error fetching finality checkpoints: {:?}", e),
+                        Ok(data) => {
+                            let json = to_string(&data).unwrap();
+                            println!(
+                                "{{ \"slot\": {}, \"finality_checkpoint\": {} }}",
+                                slot, json
+                            );
+                            match data.finalized_epoch() {
+                                Err(e) => eprintln!("error parsing finalized epoch: {:?}", e),
+                                Ok(finalized_epoch) => {
+                                    let current_epoch = slot / config.slots_per_epoch;
+                                    set_finality_to_metrics(current_epoch, finalized_epoch);
+                                    report
+                                        .lock()
+                                        .unwrap()
+                                        .record_finality(current_epoch, finalized_epoch);
+                                }
+                            }
+                        }
+                    }
+
+                    check_cardinality(options.cardinality_budget);
+                }
+            }
+        }
+
+        // Run once on boot, then every interval at end of epoch, delayed to spread replicas
+        let delay = fetch_delay(
+            config.seconds_per_slot,
+            options.stagger_fraction,
+            options.fetch_jitter_secs,
+        );
+        let to_next_epoch = to_next_epoch_start(genesis, config).unwrap_or_else(|e| {
+            eprintln!("error computing to_next_epoch_start: {:?}", e);
+            Duration::from_secs(config.seconds_per_slot * config.slots_per_epoch)
+        });
+        let wait_next_epoch = async {
+            match &options.epoch_events {
+                None => time::sleep(to_next_epoch).await,
+                Some(epoch_events) => {
+                    let fallback =
+                        Duration::from_secs(config.seconds_per_slot * EVENT_FALLBACK_SLOTS);
+                    tokio::select! {
+                        _ = epoch_events.notified() => {}
+                        _ = time::sleep(to_next_epoch + fallback) => {
+                            eprintln!("no head event for the new epoch, polling instead");
+                        }
+                    }
+                }
+            }
+            time::sleep(delay).await;
+        };
+        tokio::select! {
+            _ = wait_next_epoch => {}
+            _ = options.shutdown.wait() => return Ok(()),
+        }
+    }
+}
+
+/// Poll the beacon node's head header every slot, to tell whether the node itself advances
+pub(crate) async fn task_poll_head(beacon_url: &str, seconds_per_slot: u64, paused: &AtomicBool) {
+    let mut head_root = None;
+    let mut flip = false;
+    loop {
+        if !paused.load(Ordering::Relaxed) {
+            match fetch_block_header(beacon_url, "head").await {
+                Err(e) => eprintln!("error fetching head header: {:?}", e),
+                Ok(None) => eprintln!("error fetching head header: not found"),
+                Ok(Some(header)) => {
+                    set_gauge(&HEAD_SLOT, &[], header.slot as f64);
+                    if head_root.replace(header.root) != Some(header.root) {
+                        flip = !flip;
+                        set_gauge(&HEAD_ROOT_FLIP, &[], flip as u8 as f64);
+                    }
+                }
+            }
+        }
+        time::sleep(Duration::from_secs(seconds_per_slot)).await;
+    }
+}
+
+/// Export the peer count and sync status of the beacon node every slot
+pub(crate) async fn task_poll_node_status(
+    beacon_url: &str,
+    seconds_per_slot: u64,
+    paused: &AtomicBool,
+) {
+    let url = url_label(beacon_url);
+    loop {
+        if !paused.load(Ordering::Relaxed) {
+            match fetch_peer_count(beacon_url).await {
+                Err(e) => eprintln!("error fetching peer count: {:?}", e),
+                Ok(peers) => {
+                    set_gauge(&BEACON_NODE_PEERS, &[&url], peers as f64);
+                }
+            }
+            match fetch_sync_status(beacon_url).await {
+                Err(e) => eprintln!("error fetching sync status: {:?}", e),
+                Ok(status) => {
+                    set_gauge(
+                        &BEACON_NODE_SYNC_DISTANCE,
+                        &[&url],
+                        status.sync_distance as f64,
+                    );
+                    set_gauge(
+                        &BEACON_NODE_IS_SYNCING,
+                        &[&url],
+                        status.is_syncing as u8 as f64,
+                    );
+                }
+            }
+        }
+        time::sleep(Duration::from_secs(seconds_per_slot)).await;
+    }
+}
+
+/// Export the client and version of the beacon node at startup and every
+/// `NODE_VERSION_POLL_INTERVAL`, replacing the series of the previous version
+pub(crate) async fn task_poll_node_version(beacon_url: &str) {
+    let url = url_label(beacon_url);
+    let mut exported: Option<NodeVersion> = None;
+    loop {
+        match fetch_node_version(beacon_url).await {
+            Err(e) => eprintln!("error fetching node version: {:?}", e),
+            Ok(version) if exported.as_ref() != Some(&version) => {
+                if let Some(old) = exported.take() {
+                    remove_gauge(
+                        &BEACON_NODE_INFO,
+                        &[&url, &old.client, &old.version, &old.version_string],
+                    );
+                }
+                set_gauge(
+                    &BEACON_NODE_INFO,
+                    &[
+                        &url,
+                        &version.client,
+                        &version.version,
+                        &version.version_string,
+                    ],
+                    1.0,
+                );
+                println!("beacon node version {}", version.version_string);
+                exported = Some(version);
+            }
+            Ok(_) => {}
+        }
+        time::sleep(NODE_VERSION_POLL_INTERVAL).await;
+    }
+}
+
+/// Run the exporter with `args` until `handle` is shut down: fetch states every epoch, export
+/// their participation per group and serve the metrics and API endpoints
+pub async fn run_exporter(args: Config, handle: ExporterHandle) -> Result<()> {
+    if let Some(registry) = handle.registry.clone() {
+        set_registry(registry)?;
+    }
+    set_metric_prefix(&args.metric_prefix)?;
+    set_beacon_client(&args.beacon)?;
+    if !args.target.is_empty() {
+        return run_targets(args, &handle.shutdown).await;
+    }
+    register_process_collector()?;
+    let shutdown = &handle.shutdown;
+    let Some(mut collection) = start_collection(&args, "", shutdown).await? else {
+        return Ok(());
+    };
+    let server = serve(&args, vec![collection.target.clone()], shutdown)?;
+    // Let in-flight requests and the current fetch finish, up to --shutdown-timeout
+    let drain = async {
+        server.await;
+        collection.drain().await;
+    };
+    drain_within(drain, shutdown, args.shutdown_timeout).await;
+    collection.stop(&args)
+}
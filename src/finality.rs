@@ -1,5 +1,6 @@
-use serde::{Serialize, Deserialize};
-use anyhow::{Result, Error};
+use crate::http_client::beacon_client;
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct FinalityCheckpointResposne {
@@ -21,9 +22,23 @@ struct CheckpointData {
     root: String,
 }
 
+impl FinalityCheckpointResposne {
+    /// Epoch of the finalized checkpoint
+    pub fn finalized_epoch(&self) -> Result<u64> {
+        Ok(self.data.finalized.epoch.parse()?)
+    }
+}
 
-pub async fn fetch_checkpoint_finality(url: &str, state_id: &str) -> Result<FinalityCheckpointResposne, Error> {
-    let response = reqwest::get(format!("{url}/eth/v1/beacon/states/{state_id}/finality_checkpoints")).await?;
+pub async fn fetch_checkpoint_finality(
+    url: &str,
+    state_id: &str,
+) -> Result<FinalityCheckpointResposne, Error> {
+    let response = beacon_client()
+        .get(format!(
+            "{url}/eth/v1/beacon/states/{state_id}/finality_checkpoints"
+        ))
+        .send()
+        .await?;
     let data: FinalityCheckpointResposne = response.json().await?;
     Ok(data)
 }
@@ -0,0 +1,226 @@
+use crate::config::ConfigSpec;
+use crate::duties::{fetch_committees, Committee};
+use crate::events::{SseParser, RECONNECT_DELAY};
+use crate::http_client::beacon_client;
+use crate::inclusion::{attesters, committees_by_key, AttestationJson};
+use crate::metrics::{observe_histogram, ATTESTATION_GOSSIP_DELAY};
+use crate::ranges::{range_name_of, IndexRanges};
+use anyhow::{anyhow, Result};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time;
+
+/// Unaggregated attestation of the `single_attestation` event topic, since electra
+#[derive(Deserialize)]
+struct SingleAttestationEvent {
+    attester_index: String,
+    data: SingleAttestationData,
+}
+
+#[derive(Deserialize)]
+struct SingleAttestationData {
+    slot: String,
+}
+
+/// Epochs before the latest whose sightings are kept, covering the epoch two behind scanned by
+/// --attestation-inclusion
+const SEEN_EPOCHS: u64 = 3;
+
+/// Validators whose attestation of each epoch was already seen, so that only the first sighting
+/// is timed and aggregates repeating known votes are ignored
+#[derive(Default)]
+pub struct FirstSeen {
+    by_epoch: HashMap<u64, HashSet<usize>>,
+}
+
+impl FirstSeen {
+    /// Keep the `attesters` not yet seen in `epoch`, forgetting epochs more than `SEEN_EPOCHS`
+    /// before it
+    pub fn filter(&mut self, epoch: u64, attesters: impl IntoIterator<Item = usize>) -> Vec<usize> {
+        self.by_epoch
+            .retain(|seen_epoch, _| seen_epoch + SEEN_EPOCHS >= epoch);
+        let seen = self.by_epoch.entry(epoch).or_default();
+        attesters
+            .into_iter()
+            .filter(|validator| seen.insert(*validator))
+            .collect()
+    }
+
+    /// Validators whose attestation of `epoch` was seen, `None` if the epoch is not kept
+    pub fn seen(&self, epoch: u64) -> Option<&HashSet<usize>> {
+        self.by_epoch.get(&epoch)
+    }
+}
+
+/// Seconds between the start of `slot` and `seen_at`, a unix timestamp
+pub fn delay_in_slot(genesis_time: u64, seconds_per_slot: u64, slot: u64, seen_at: f64) -> f64 {
+    (seen_at - (genesis_time + slot * seconds_per_slot) as f64).max(0.0)
+}
+
+/// Committees of recent epochs by slot, fetched on the first attestation of each epoch
+struct CommitteeCache {
+    by_epoch: HashMap<u64, HashMap<u64, Vec<Committee>>>,
+}
+
+impl CommitteeCache {
+    async fn slot_committees(
+        &mut self,
+        url: &str,
+        slots_per_epoch: u64,
+        slot: u64,
+    ) -> Option<&[Committee]> {
+        let epoch = slot / slots_per_epoch;
+        if !self.by_epoch.contains_key(&epoch) {
+            self.by_epoch
+                .retain(|cached_epoch, _| cached_epoch + 1 >= epoch);
+            // Cached even if empty so a failing epoch is only fetched and logged once
+            let mut by_slot: HashMap<u64, Vec<Committee>> = HashMap::new();
+            match fetch_committees(url, "head", epoch).await {
+                Ok(committees) => {
+                    for committee in committees {
+                        by_slot.entry(committee.slot).or_default().push(committee);
+                    }
+                }
+                Err(e) => eprintln!("error fetching committees of epoch {epoch}: {:?}", e),
+            }
+            self.by_epoch.insert(epoch, by_slot);
+        }
+        self.by_epoch
+            .get(&epoch)
+            .and_then(|by_slot| by_slot.get(&slot))
+            .map(Vec::as_slice)
+    }
+}
+
+/// Sightings and committees kept across reconnections
+struct GossipState {
+    /// Shared with the scan of included attestations
+    first_seen: Arc<Mutex<FirstSeen>>,
+    committees: CommitteeCache,
+}
+
+/// Subscribe to attestation events and export, per group, how far into the attested slot the
+/// node first sees each monitored validator's attestation, recording sightings in `first_seen`.
+/// Reconnects when the stream ends or fails.
+pub async fn task_subscribe_attestations(
+    url: &str,
+    genesis_time: u64,
+    config: &ConfigSpec,
+    ranges: &IndexRanges,
+    first_seen: Arc<Mutex<FirstSeen>>,
+) {
+    let mut state = GossipState {
+        first_seen,
+        committees: CommitteeCache {
+            by_epoch: HashMap::new(),
+        },
+    };
+    let mut topics = "attestation,single_attestation";
+    loop {
+        match subscribe_attestations(url, topics, genesis_time, config, ranges, &mut state).await {
+            // Nodes before electra reject the unknown topic
+            Ok(StatusCode::BAD_REQUEST) if topics != "attestation" => {
+                println!(
+                    "single_attestation events not supported, subscribing to attestation only"
+                );
+                topics = "attestation";
+                continue;
+            }
+            Ok(status) => eprintln!("error subscribing to attestation events: {status}"),
+            Err(e) => eprintln!("error reading attestation event stream: {:?}", e),
+        }
+        time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Returns the status if the node rejects the subscription
+async fn subscribe_attestations(
+    url: &str,
+    topics: &str,
+    genesis_time: u64,
+    config: &ConfigSpec,
+    ranges: &IndexRanges,
+    state: &mut GossipState,
+) -> Result<StatusCode> {
+    let mut response = beacon_client()
+        .get(format!("{url}/eth/v1/events?topics={topics}"))
+        .header(reqwest::header::ACCEPT, "text/event-stream")
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Ok(response.status());
+    }
+    println!("subscribed to beacon node {topics} events");
+
+    let mut parser = SseParser::default();
+    while let Some(chunk) = response.chunk().await? {
+        let seen_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64();
+        for (event, data) in parser.push(&chunk) {
+            let (slot, validators) = match event.as_str() {
+                "attestation" => {
+                    let attestation = serde_json::from_str::<AttestationJson>(&data)?.decode()?;
+                    let Some(slot_committees) = state
+                        .committees
+                        .slot_committees(url, config.slots_per_epoch, attestation.slot)
+                        .await
+                    else {
+                        continue;
+                    };
+                    let committees_by_key = committees_by_key(slot_committees);
+                    let validators: Vec<usize> =
+                        attesters(&committees_by_key, &attestation).collect();
+                    (attestation.slot, validators)
+                }
+                "single_attestation" => {
+                    let attestation: SingleAttestationEvent = serde_json::from_str(&data)?;
+                    (
+                        attestation.data.slot.parse()?,
+                        vec![attestation.attester_index.parse()?],
+                    )
+                }
+                _ => continue,
+            };
+            let delay = delay_in_slot(genesis_time, config.seconds_per_slot, slot, seen_at);
+            let monitored = validators
+                .into_iter()
+                .filter(|validator| range_name_of(ranges, *validator).is_some());
+            let first_seen = state
+                .first_seen
+                .lock()
+                .unwrap()
+                .filter(slot / config.slots_per_epoch, monitored);
+            for validator in first_seen {
+                if let Some(group) = range_name_of(ranges, validator) {
+                    observe_histogram(&ATTESTATION_GOSSIP_DELAY, &[group], delay);
+                }
+            }
+        }
+    }
+    Err(anyhow!("attestation event stream ended"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_seen_test() {
+        let mut first_seen = FirstSeen::default();
+        assert_eq!(first_seen.filter(10, [1, 2, 3]), vec![1, 2, 3]);
+        // An aggregate repeating seen votes only times the new one
+        assert_eq!(first_seen.filter(10, [2, 3, 4]), vec![4]);
+        assert_eq!(first_seen.filter(11, [1]), vec![1]);
+        assert_eq!(first_seen.filter(13, [4]), vec![4]);
+        assert!(first_seen.seen(10).unwrap().contains(&3));
+        assert_eq!(first_seen.filter(14, [4]), vec![4]);
+        // Epoch 10 was forgotten
+        assert!(first_seen.seen(10).is_none());
+        assert_eq!(first_seen.filter(10, [1]), vec![1]);
+
+        assert_eq!(delay_in_slot(1000, 12, 2, 1026.5), 2.5);
+        assert_eq!(delay_in_slot(1000, 12, 2, 1023.0), 0.0);
+    }
+}
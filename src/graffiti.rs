@@ -0,0 +1,75 @@
+use crate::duties::ProposerDuty;
+use crate::proposals::{BlockOrigin, ProposalCounts, ProposerGroups};
+use crate::ssz_state::StatePartial;
+use std::collections::{BTreeMap, HashMap};
+
+/// Group of blocks whose graffiti matches none of the prefixes
+pub const OTHER_GRAFFITI: &str = "other";
+
+/// Graffiti bytes as text, without the zero padding
+pub fn decode_graffiti(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .map_or(0, |i| i + 1);
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// Proposal outcomes grouped by the graffiti prefixes of `--graffiti-groups`
+pub struct GraffitiGroups {
+    prefixes: Vec<String>,
+    proposers: ProposerGroups,
+}
+
+impl GraffitiGroups {
+    pub fn new(prefixes: Vec<String>) -> Self {
+        Self {
+            prefixes,
+            proposers: ProposerGroups::default(),
+        }
+    }
+
+    /// The first prefix `graffiti` starts with, or `other`
+    pub fn group_of(&self, graffiti: &str) -> &str {
+        self.prefixes
+            .iter()
+            .find(|prefix| graffiti.starts_with(prefix.as_str()))
+            .map_or(OTHER_GRAFFITI, String::as_str)
+    }
+
+    /// Count the outcomes of `duties` per group, `blocks` holds the proposed blocks by slot
+    pub fn count(
+        &mut self,
+        duties: &[ProposerDuty],
+        state: &StatePartial,
+        blocks: &HashMap<u64, BlockOrigin>,
+    ) -> BTreeMap<String, ProposalCounts> {
+        let groups = blocks
+            .iter()
+            .map(|(slot, block)| {
+                let group = self.group_of(&block.graffiti).to_owned();
+                (*slot, (block.proposer_index, group))
+            })
+            .collect();
+        self.proposers.count(duties, state, &groups)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn graffiti_groups_test() {
+        let mut graffiti = b"lighthouse-geth-1".to_vec();
+        graffiti.resize(32, 0);
+        assert_eq!(decode_graffiti(&graffiti), "lighthouse-geth-1");
+        assert_eq!(decode_graffiti(&[0; 32]), "");
+
+        let groups = GraffitiGroups::new(vec!["lighthouse".to_owned(), "teku".to_owned()]);
+        assert_eq!(groups.group_of("lighthouse-geth-1"), "lighthouse");
+        assert_eq!(groups.group_of("teku/v24"), "teku");
+        // Matched case sensitively
+        assert_eq!(groups.group_of("Teku/v24"), "other");
+    }
+}
@@ -0,0 +1,119 @@
+use crate::participation::{GroupParticipation, ParticipationSnapshot};
+use serde::Serialize;
+use std::collections::VecDeque;
+
+/// Default of `--history-epochs`
+pub const DEFAULT_HISTORY_EPOCHS: usize = 64;
+
+/// Participation of the last fetched epochs, oldest first, as served by `/api/v1/history`
+#[derive(Debug)]
+pub struct ParticipationHistory {
+    capacity: usize,
+    snapshots: VecDeque<ParticipationSnapshot>,
+}
+
+/// One epoch of a history query, optionally restricted to a group
+#[derive(Debug, Serialize)]
+pub struct HistoryEntry<'a> {
+    pub slot: u64,
+    pub epoch: u64,
+    pub timestamp: u64,
+    pub groups: Vec<&'a GroupParticipation>,
+    pub total: &'a GroupParticipation,
+}
+
+impl ParticipationHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            snapshots: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Keep `snapshot`, replacing the last one if it is of the same epoch and dropping the oldest
+    /// beyond the capacity
+    pub fn push(&mut self, snapshot: ParticipationSnapshot) {
+        if self
+            .snapshots
+            .back()
+            .is_some_and(|last| last.epoch == snapshot.epoch)
+        {
+            self.snapshots.pop_back();
+        }
+        self.snapshots.push_back(snapshot);
+        while self.snapshots.len() > self.capacity {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// Kept snapshots, oldest first
+    pub fn snapshots(&self) -> impl Iterator<Item = &ParticipationSnapshot> {
+        self.snapshots.iter()
+    }
+
+    /// Kept epochs within the last `epochs` of the latest, with the groups named `group` or all
+    pub fn query(&self, group: Option<&str>, epochs: u64) -> Vec<HistoryEntry<'_>> {
+        let Some(latest) = self.snapshots.back().map(|snapshot| snapshot.epoch) else {
+            return vec![];
+        };
+        self.snapshots
+            .iter()
+            .filter(|snapshot| snapshot.epoch.saturating_add(epochs) > latest)
+            .map(|snapshot| HistoryEntry {
+                slot: snapshot.slot,
+                epoch: snapshot.epoch,
+                timestamp: snapshot.timestamp,
+                groups: snapshot
+                    .groups
+                    .iter()
+                    .filter(|participation| group.is_none_or(|group| participation.name == group))
+                    .collect(),
+                total: &snapshot.total,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participation::TOTAL_GROUP;
+
+    #[test]
+    fn participation_history_test() {
+        let group = |name: &str, target| GroupParticipation {
+            name: name.to_owned(),
+            ranges: "0..10".to_owned(),
+            validators: 10,
+            source: 1.0,
+            target,
+            head: 1.0,
+            inactivity_scores_avg: 0.0,
+            balance_gwei_sum: 320_000_000_000,
+            slashed_validators: 0,
+        };
+        let snapshot = |epoch, target| ParticipationSnapshot {
+            slot: (epoch + 1) * 32,
+            epoch,
+            timestamp: 0,
+            groups: vec![group("a", target), group("b", 1.0)],
+            total: group(TOTAL_GROUP, target),
+        };
+        let mut history = ParticipationHistory::new(3);
+        assert!(history.query(None, 64).is_empty());
+        for epoch in 1..=4 {
+            history.push(snapshot(epoch, 0.5));
+        }
+        // A later fetch of the same epoch replaces the earlier one
+        history.push(snapshot(4, 0.9));
+
+        let epochs: Vec<u64> = history.query(None, 64).iter().map(|e| e.epoch).collect();
+        assert_eq!(epochs, vec![2, 3, 4]);
+        let entries = history.query(Some("a"), 2);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].epoch, 3);
+        assert_eq!(entries[1].groups.len(), 1);
+        assert_eq!(entries[1].groups[0].target, 0.9);
+        assert!(history.query(Some("c"), 1)[0].groups.is_empty());
+    }
+}
@@ -0,0 +1,485 @@
+use crate::checkpoint::{parse_root, Root};
+use crate::duties::Committee;
+use crate::http_client::beacon_client;
+use crate::ranges::{group_ranges_by_name, range_name_of, IndexRanges};
+use anyhow::{anyhow, Result};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+/// Attestation as included in a block, with its bits decoded
+#[derive(Debug)]
+pub struct BlockAttestation {
+    pub slot: u64,
+    /// `data.index`, only meaningful before electra
+    pub committee_index: u64,
+    pub aggregation_bits: Vec<bool>,
+    /// Committees covered by `aggregation_bits` since electra
+    pub committee_bits: Option<Vec<bool>>,
+    /// `data.target.root`, the checkpoint block the attesters voted for
+    pub target_root: Root,
+}
+
+#[derive(Deserialize)]
+struct BlockResponse {
+    data: BlockData,
+}
+
+#[derive(Deserialize)]
+struct BlockData {
+    message: BlockMessage,
+}
+
+#[derive(Deserialize)]
+struct BlockMessage {
+    proposer_index: String,
+    body: BlockBody,
+}
+
+/// Proposer and decoded attestations of a block
+#[derive(Debug)]
+pub struct BlockAttestations {
+    pub proposer_index: usize,
+    pub attestations: Vec<BlockAttestation>,
+}
+
+#[derive(Deserialize)]
+struct BlockBody {
+    attestations: Vec<AttestationJson>,
+}
+
+/// Attestation as served by the beacon API, in blocks and on the `attestation` event topic
+#[derive(Deserialize)]
+pub struct AttestationJson {
+    aggregation_bits: String,
+    data: AttestationDataJson,
+    committee_bits: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AttestationDataJson {
+    slot: String,
+    index: String,
+    target: CheckpointJson,
+}
+
+#[derive(Deserialize)]
+struct CheckpointJson {
+    root: String,
+}
+
+/// Fetch and decode the attestations of the block at `slot`. Returns `None` for empty slots.
+pub async fn fetch_block_attestations(url: &str, slot: u64) -> Result<Option<BlockAttestations>> {
+    let response = beacon_client()
+        .get(format!("{url}/eth/v2/beacon/blocks/{slot}"))
+        .send()
+        .await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let block: BlockResponse = response.error_for_status()?.json().await?;
+    let message = block.data.message;
+    Ok(Some(BlockAttestations {
+        proposer_index: message.proposer_index.parse()?,
+        attestations: message
+            .body
+            .attestations
+            .into_iter()
+            .map(AttestationJson::decode)
+            .collect::<Result<_>>()?,
+    }))
+}
+
+impl AttestationJson {
+    pub fn decode(self) -> Result<BlockAttestation> {
+        Ok(BlockAttestation {
+            slot: self.data.slot.parse()?,
+            committee_index: self.data.index.parse()?,
+            aggregation_bits: parse_bitlist(&self.aggregation_bits)?,
+            committee_bits: self
+                .committee_bits
+                .map(|bits| parse_bitvector(&bits))
+                .transpose()?,
+            target_root: parse_root(&self.data.target.root)?,
+        })
+    }
+}
+
+pub fn parse_hex(input: &str) -> Result<Vec<u8>> {
+    let hex = input.strip_prefix("0x").unwrap_or(input);
+    if hex.len() % 2 == 1 {
+        return Err(anyhow!("Odd length hex: {}", input));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| e.into()))
+        .collect()
+}
+
+/// Decode a SSZ Bitvector serialized as hex, bits are little endian within each byte
+fn parse_bitvector(input: &str) -> Result<Vec<bool>> {
+    Ok(parse_hex(input)?
+        .iter()
+        .flat_map(|byte| (0..8).map(move |i| byte & (1 << i) != 0))
+        .collect())
+}
+
+/// Decode a SSZ Bitlist serialized as hex, the highest set bit marks the length
+fn parse_bitlist(input: &str) -> Result<Vec<bool>> {
+    let mut bits = parse_bitvector(input)?;
+    let len = bits
+        .iter()
+        .rposition(|bit| *bit)
+        .ok_or_else(|| anyhow!("Bitlist without length bit: {}", input))?;
+    bits.truncate(len);
+    Ok(bits)
+}
+
+pub type CommitteesByKey<'a> = HashMap<(u64, u64), &'a Committee>;
+
+pub fn committees_by_key(committees: &[Committee]) -> CommitteesByKey<'_> {
+    committees
+        .iter()
+        .map(|committee| ((committee.slot, committee.index), committee))
+        .collect()
+}
+
+/// Validators whose bit is set in `attestation`. Attesters of committees missing from
+/// `committees_by_key` are skipped.
+pub fn attesters<'a>(
+    committees_by_key: &'a CommitteesByKey,
+    attestation: &'a BlockAttestation,
+) -> impl Iterator<Item = usize> + 'a {
+    let committee_indices: Vec<u64> = match &attestation.committee_bits {
+        Some(bits) => (0..bits.len() as u64)
+            .filter(|i| bits[*i as usize])
+            .collect(),
+        None => vec![attestation.committee_index],
+    };
+    committee_indices
+        .into_iter()
+        .filter_map(|index| committees_by_key.get(&(attestation.slot, index)))
+        .flat_map(|committee| committee.validators.iter().copied())
+        .zip(attestation.aggregation_bits.iter())
+        .filter(|(_, bit)| **bit)
+        .map(|(validator, _)| validator)
+}
+
+/// Map each attester to the lowest inclusion delay of its attestations among `blocks`, as
+/// (block slot, attestations). `committees` must include those of every attested slot, in
+/// ascending committee index per slot.
+pub fn inclusion_delays(
+    committees: &[Committee],
+    blocks: &[(u64, Vec<BlockAttestation>)],
+) -> HashMap<usize, u64> {
+    let committees_by_key = committees_by_key(committees);
+    let mut delays: HashMap<usize, u64> = HashMap::new();
+    for (block_slot, attestations) in blocks {
+        for attestation in attestations {
+            let delay = block_slot.saturating_sub(attestation.slot);
+            for validator in attesters(&committees_by_key, attestation) {
+                delays
+                    .entry(validator)
+                    .and_modify(|d| *d = (*d).min(delay))
+                    .or_insert(delay);
+            }
+        }
+    }
+    delays
+}
+
+/// Attesters of `committees` with an attestation among `blocks` voting for a target other than
+/// `canonical_target`, the canonical checkpoint root of the committees' epoch
+pub fn wrong_target_attesters(
+    committees: &[Committee],
+    blocks: &[(u64, Vec<BlockAttestation>)],
+    canonical_target: &Root,
+) -> HashSet<usize> {
+    let committees_by_key = committees_by_key(committees);
+    blocks
+        .iter()
+        .flat_map(|(_, attestations)| attestations.iter())
+        .filter(|attestation| attestation.target_root != *canonical_target)
+        .flat_map(|attestation| attesters(&committees_by_key, attestation))
+        .collect()
+}
+
+/// Average inclusion delay per group, over the group's validators with an included attestation.
+/// Groups without any are omitted.
+pub fn group_inclusion_delay(
+    ranges: &IndexRanges,
+    delays: &HashMap<usize, u64>,
+) -> Vec<(String, f64)> {
+    group_ranges_by_name(ranges)
+        .into_iter()
+        .filter_map(|(range_name, group_ranges)| {
+            let included: Vec<u64> = group_ranges
+                .iter()
+                .flat_map(|range| range.clone())
+                .filter_map(|index| delays.get(&index).copied())
+                .collect();
+            if included.is_empty() {
+                return None;
+            }
+            let avg = included.iter().sum::<u64>() as f64 / included.len() as f64;
+            Some((range_name, avg))
+        })
+        .collect()
+}
+
+/// Attestation duties of a group's validators in one epoch and how many made it into blocks
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct GroupInclusion {
+    pub duties: u64,
+    pub included: u64,
+    /// Duties whose attestation was seen on gossip but not included, if gossip is followed
+    pub seen_not_included: Option<u64>,
+}
+
+/// Inclusion of the attestations of each group's validators with a duty in `committees`, given
+/// the included attesters in `delays` and those seen on gossip in `seen`
+pub fn group_inclusion(
+    ranges: &IndexRanges,
+    committees: &[Committee],
+    delays: &HashMap<usize, u64>,
+    seen: Option<&HashSet<usize>>,
+) -> Vec<(String, GroupInclusion)> {
+    let mut by_group: HashMap<&str, GroupInclusion> = HashMap::new();
+    for validator in committees.iter().flat_map(|c| c.validators.iter().copied()) {
+        let Some(group) = range_name_of(ranges, validator) else {
+            continue;
+        };
+        let inclusion = by_group.entry(group).or_default();
+        inclusion.duties += 1;
+        let included = delays.contains_key(&validator);
+        inclusion.included += included as u64;
+        if !included && seen.is_some_and(|seen| seen.contains(&validator)) {
+            *inclusion.seen_not_included.get_or_insert(0) += 1;
+        }
+    }
+    group_ranges_by_name(ranges)
+        .into_iter()
+        .map(|(range_name, _)| {
+            let mut inclusion = by_group
+                .get(range_name.as_str())
+                .copied()
+                .unwrap_or_default();
+            if seen.is_some() {
+                inclusion.seen_not_included.get_or_insert(0);
+            }
+            (range_name, inclusion)
+        })
+        .collect()
+}
+
+/// Votes a block included, out of those it could include, see `block_packing`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Packing {
+    pub included: u64,
+    pub available: u64,
+}
+
+/// Packing of each of `blocks`: the votes of `committees` it included first, out of the votes
+/// not included by an earlier block that it could include. Votes are only known to have been
+/// available if some block included them, so later blocks stand in for what the proposer could
+/// have seen. `last_includable` is the last slot that can include a vote of the given slot.
+pub fn block_packing(
+    committees: &[Committee],
+    blocks: &[(u64, Vec<BlockAttestation>)],
+    last_includable: impl Fn(u64) -> u64,
+) -> Vec<(u64, Packing)> {
+    let committees_by_key = committees_by_key(committees);
+    // Attested slot and first including block of each vote
+    let mut first_inclusions: HashMap<usize, (u64, u64)> = HashMap::new();
+    for (block_slot, attestations) in blocks {
+        for attestation in attestations {
+            for validator in attesters(&committees_by_key, attestation) {
+                first_inclusions
+                    .entry(validator)
+                    .and_modify(|(_, first)| *first = (*first).min(*block_slot))
+                    .or_insert((attestation.slot, *block_slot));
+            }
+        }
+    }
+    let mut votes: HashMap<(u64, u64), u64> = HashMap::new();
+    for slots in first_inclusions.into_values() {
+        *votes.entry(slots).or_default() += 1;
+    }
+
+    blocks
+        .iter()
+        .map(|(block_slot, _)| {
+            let mut packing = Packing::default();
+            for (&(attested_slot, first), count) in &votes {
+                if attested_slot < *block_slot
+                    && *block_slot <= last_includable(attested_slot)
+                    && first >= *block_slot
+                {
+                    packing.available += count;
+                    if first == *block_slot {
+                        packing.included += count;
+                    }
+                }
+            }
+            (*block_slot, packing)
+        })
+        .collect()
+}
+
+/// Share of the available votes included by the blocks each group proposed, with the proposer
+/// of each block slot in `proposers`. Groups without blocks with available votes are omitted.
+pub fn group_packing_efficiency(
+    ranges: &IndexRanges,
+    proposers: &HashMap<u64, usize>,
+    packing: &[(u64, Packing)],
+) -> Vec<(String, f64)> {
+    let mut by_group: HashMap<&str, Packing> = HashMap::new();
+    for (block_slot, block_packing) in packing {
+        let Some(group) = proposers
+            .get(block_slot)
+            .and_then(|proposer| range_name_of(ranges, *proposer))
+        else {
+            continue;
+        };
+        let group_packing = by_group.entry(group).or_default();
+        group_packing.included += block_packing.included;
+        group_packing.available += block_packing.available;
+    }
+    group_ranges_by_name(ranges)
+        .into_iter()
+        .filter_map(|(range_name, _)| {
+            let packing = by_group.get(range_name.as_str())?;
+            if packing.available == 0 {
+                return None;
+            }
+            let efficiency = packing.included as f64 / packing.available as f64;
+            Some((range_name, efficiency))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bitlist_test() {
+        // 0b0000_1101: bits [1, 0, 1] plus the length bit
+        assert_eq!(parse_bitlist("0x0d").unwrap(), vec![true, false, true]);
+        assert_eq!(parse_bitlist("0xff01").unwrap(), vec![true; 8]);
+        assert!(parse_bitlist("0x00").is_err());
+    }
+
+    #[test]
+    fn inclusion_delays_test() {
+        let committees = vec![
+            Committee {
+                slot: 10,
+                index: 0,
+                validators: vec![1, 2, 3],
+            },
+            Committee {
+                slot: 10,
+                index: 1,
+                validators: vec![4, 5],
+            },
+        ];
+        let blocks = vec![
+            (
+                11,
+                vec![BlockAttestation {
+                    slot: 10,
+                    committee_index: 0,
+                    aggregation_bits: vec![true, false, false],
+                    committee_bits: None,
+                    target_root: [1; 32],
+                }],
+            ),
+            (
+                13,
+                vec![BlockAttestation {
+                    slot: 10,
+                    committee_index: 0,
+                    // Committees 0 and 1 aggregated: validators 1, 2, 3, 4, 5
+                    aggregation_bits: vec![true, true, false, false, true],
+                    committee_bits: Some(vec![true, true]),
+                    target_root: [2; 32],
+                }],
+            ),
+        ];
+        let delays = inclusion_delays(&committees, &blocks);
+        assert_eq!(delays, HashMap::from([(1, 1), (2, 3), (5, 3)]));
+        assert_eq!(
+            wrong_target_attesters(&committees, &blocks, &[1; 32]),
+            HashSet::from([1, 2, 5])
+        );
+
+        let ranges = vec![("a".to_owned(), 0..3), ("b".to_owned(), 3..6)];
+        assert_eq!(
+            group_inclusion_delay(&ranges, &delays),
+            vec![("a".to_owned(), 2.0), ("b".to_owned(), 3.0)]
+        );
+        // 4 was seen on gossip but never included, 3 was not seen at all
+        let seen = HashSet::from([1, 2, 4]);
+        assert_eq!(
+            group_inclusion(&ranges, &committees, &delays, Some(&seen)),
+            vec![
+                (
+                    "a".to_owned(),
+                    GroupInclusion {
+                        duties: 2,
+                        included: 2,
+                        seen_not_included: Some(0)
+                    }
+                ),
+                (
+                    "b".to_owned(),
+                    GroupInclusion {
+                        duties: 3,
+                        included: 1,
+                        seen_not_included: Some(1)
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            group_inclusion(&ranges, &committees, &delays, None)[1].1,
+            GroupInclusion {
+                duties: 3,
+                included: 1,
+                seen_not_included: None
+            }
+        );
+
+        // Block 11 could include the votes of 1, 2 and 5 but only included 1
+        let packing = block_packing(&committees, &blocks, |slot| slot + 32);
+        assert_eq!(
+            packing,
+            vec![
+                (
+                    11,
+                    Packing {
+                        included: 1,
+                        available: 3
+                    }
+                ),
+                (
+                    13,
+                    Packing {
+                        included: 2,
+                        available: 2
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            block_packing(&committees, &blocks, |slot| slot + 1)[1].1,
+            Packing::default()
+        );
+        let proposers = HashMap::from([(11, 0), (13, 4)]);
+        assert_eq!(
+            group_packing_efficiency(&ranges, &proposers, &packing),
+            vec![("a".to_owned(), 1.0 / 3.0), ("b".to_owned(), 1.0)]
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use anyhow::{anyhow, Context, Result};
+use beacon_metrics_gazer::config::{fetch_config, ConfigSpec};
+use beacon_metrics_gazer::http_client::{beacon_client, set_beacon_client, BeaconRequestArgs};
+use beacon_metrics_gazer::participation::participation_avg;
+use beacon_metrics_gazer::ssz_state::{
+    describe_field_offsets, deserialize_partial_state, Fork, StatePartial, CONSENSUS_VERSION_HEADER,
+};
+use bytes::Bytes;
+use clap::{Args, ValueEnum};
+use url::Url;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Preset {
+    Mainnet,
+    Minimal,
+}
+
+#[derive(Args)]
+pub struct InspectStateArgs {
+    /// Local path to an SSZ state, or URL of a beacon node debug state endpoint:
+    /// http://1.2.3.4:4000/eth/v2/debug/beacon/states/head
+    state: String,
+    /// Beacon HTTP API URL to fetch the spec config from, instead of using --preset
+    #[arg(long)]
+    config_url: Option<String>,
+    /// Preset of the state's network, used if --config-url is not set
+    #[arg(long, value_enum, default_value_t = Preset::Mainnet)]
+    preset: Preset,
+    /// Fork of the state. Defaults to the Eth-Consensus-Version header of the response, then to
+    /// the fork schedule of --config-url, then to the newest fork whose layout fits the state.
+    #[arg(long)]
+    fork: Option<Fork>,
+    #[command(flatten)]
+    request: BeaconRequestArgs,
+}
+
+/// Decode the state at `args.state` and print what the partial decoder sees
+pub async fn inspect_state(args: &InspectStateArgs) -> Result<()> {
+    set_beacon_client(&args.request)?;
+    let config = match &args.config_url {
+        Some(url) => fetch_config(url).await.context("fetch_config")?,
+        None => match args.preset {
+            Preset::Mainnet => ConfigSpec::mainnet(),
+            Preset::Minimal => ConfigSpec::minimal(),
+        },
+    };
+
+    let (header_fork, state_buf) = load_state(&args.state).await?;
+    println!("state: {} ({} bytes)", args.state, state_buf.len());
+
+    let (fork, fork_source, state) = if let Some(fork) = args.fork.or(header_fork) {
+        let source = if args.fork.is_some() {
+            "--fork"
+        } else {
+            "header"
+        };
+        (
+            fork,
+            source,
+            deserialize_partial_state(&config, Some(fork), &state_buf)?,
+        )
+    } else if args.config_url.is_some() {
+        let state = deserialize_partial_state(&config, None, &state_buf)?;
+        let fork = Fork::at_epoch(&config, state.slot / config.slots_per_epoch);
+        (fork, "fork schedule", state)
+    } else {
+        detect_fork(&config, &state_buf)?
+    };
+
+    println!("fork: {:?} (from {})", fork, fork_source);
+    println!(
+        "slot: {} epoch: {}",
+        state.slot,
+        state.slot / config.slots_per_epoch
+    );
+    let validator_count = state.previous_epoch_participation.len();
+    println!("validators: {}", validator_count);
+
+    println!("field offsets:");
+    for (name, position, value) in describe_field_offsets(&config, fork, &state_buf)? {
+        println!("  {:<32} at {:>10} -> {:>10}", name, position, value);
+    }
+
+    let all = 0..validator_count;
+    let flags = &config.participation_flags;
+    for (epoch_name, participation) in [
+        ("previous", &state.previous_epoch_participation),
+        ("current", &state.current_epoch_participation),
+    ] {
+        println!(
+            "{} epoch participation: source {:.4} target {:.4} head {:.4}",
+            epoch_name,
+            participation_avg(participation, std::slice::from_ref(&all), flags.source),
+            participation_avg(participation, std::slice::from_ref(&all), flags.target),
+            participation_avg(participation, std::slice::from_ref(&all), flags.head),
+        );
+    }
+    println!(
+        "inactivity scores avg: {:.2}",
+        state.inactivity_scores.iter().sum::<u64>() as f64 / validator_count as f64
+    );
+    println!("balances sum: {} Gwei", state.balances.iter().sum::<u64>());
+
+    Ok(())
+}
+
+/// Read the state from a local path or download it, returning the fork announced by the node
+async fn load_state(path_or_url: &str) -> Result<(Option<Fork>, Bytes)> {
+    if Url::parse(path_or_url).is_ok() {
+        let response = beacon_client()
+            .get(path_or_url)
+            .header(reqwest::header::ACCEPT, "application/octet-stream")
+            .send()
+            .await?
+            .error_for_status()?;
+        let fork = match response.headers().get(CONSENSUS_VERSION_HEADER) {
+            Some(version) => Some(version.to_str()?.parse::<Fork>()?),
+            None => None,
+        };
+        Ok((fork, response.bytes().await?))
+    } else {
+        let state = tokio::fs::read(path_or_url)
+            .await
+            .with_context(|| format!("read {path_or_url}"))?;
+        Ok((None, Bytes::from(state)))
+    }
+}
+
+/// Position of the first byte of `fork.current_version` in the state
+const CURRENT_FORK_VERSION_OFFSET: usize = 8 + 32 + 8 + 4;
+
+/// Public networks number fork versions by fork in their first byte: 0x00 phase0, 0x01 altair,
+/// and so on. Use that convention if the layout fits, else try the layout of each fork, newest
+/// first. Layouts from bellatrix on only differ after the decoded fields.
+fn detect_fork(
+    config: &ConfigSpec,
+    state_buf: &Bytes,
+) -> Result<(Fork, &'static str, StatePartial)> {
+    let by_version = state_buf
+        .get(CURRENT_FORK_VERSION_OFFSET)
+        .and_then(|version| match version {
+            0x01 => Some(Fork::Altair),
+            0x02 => Some(Fork::Bellatrix),
+            0x03 => Some(Fork::Capella),
+            0x04 => Some(Fork::Deneb),
+            0x05 => Some(Fork::Electra),
+            _ => None,
+        });
+    if let Some(fork) = by_version {
+        if let Ok(state) = deserialize_partial_state(config, Some(fork), state_buf) {
+            return Ok((fork, "fork version", state));
+        }
+    }
+
+    for fork in Fork::WITH_PARTICIPATION {
+        if let Ok(state) = deserialize_partial_state(config, Some(fork), state_buf) {
+            return Ok((fork, "first fitting layout", state));
+        }
+    }
+    Err(anyhow!(
+        "no fork layout fits the state, is the preset correct?"
+    ))
+}
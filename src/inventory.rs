@@ -0,0 +1,171 @@
+use crate::config::ParticipationFlags;
+use crate::participation::has_flag;
+use crate::ranges::IndexRanges;
+use crate::ssz_state::StatePartial;
+use serde::Serialize;
+
+/// Default and maximum page size of `/api/v1/validators`
+pub const DEFAULT_PAGE_LIMIT: usize = 100;
+pub const MAX_PAGE_LIMIT: usize = 1000;
+
+/// Monitored validator of the latest fetched state, as listed by `/api/v1/validators`. Flags
+/// are those of the state's previous epoch.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValidatorEntry {
+    pub index: usize,
+    pub group: String,
+    pub source: bool,
+    pub target: bool,
+    pub head: bool,
+    pub balance_gwei: u64,
+    pub status: &'static str,
+}
+
+/// Monitored validators of a state, in index order
+#[derive(Debug)]
+pub struct ValidatorInventory {
+    /// Epoch of the state, flags are of the epoch before
+    pub epoch: u64,
+    validators: Vec<ValidatorEntry>,
+}
+
+/// One page of the inventory, optionally restricted to a group
+#[derive(Debug, Serialize)]
+pub struct ValidatorsPage<'a> {
+    pub epoch: u64,
+    /// Matching validators across all pages
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub validators: Vec<&'a ValidatorEntry>,
+}
+
+/// Monitored validators missing the timely target flag, as listed by `/api/v1/missing`
+#[derive(Debug, Serialize)]
+pub struct MissingTarget<'a> {
+    pub epoch: u64,
+    /// Group the validators are of, all groups if `None`
+    pub group: Option<&'a str>,
+    pub validators: Vec<usize>,
+}
+
+impl ValidatorInventory {
+    /// Inventory of the validators in `ranges` that exist in `state`. A validator in overlapping
+    /// ranges belongs to the first, as in the metrics.
+    pub fn new(
+        flags: &ParticipationFlags,
+        ranges: &IndexRanges,
+        state: &StatePartial,
+        epoch: u64,
+    ) -> Self {
+        let mut validators: Vec<ValidatorEntry> = ranges
+            .iter()
+            .flat_map(|(range_name, range)| range.clone().map(move |index| (range_name, index)))
+            .filter_map(|(range_name, index)| {
+                let validator = state.validators.get(index)?;
+                let participation = state
+                    .previous_epoch_participation
+                    .get(index)
+                    .copied()
+                    .unwrap_or(0);
+                Some(ValidatorEntry {
+                    index,
+                    group: range_name.clone(),
+                    source: has_flag(participation, flags.source),
+                    target: has_flag(participation, flags.target),
+                    head: has_flag(participation, flags.head),
+                    balance_gwei: state.balances.get(index).copied().unwrap_or(0),
+                    status: validator.status(epoch).as_str(),
+                })
+            })
+            .collect();
+        // Stable, so the first range of a validator wins the dedup
+        validators.sort_by_key(|entry| entry.index);
+        validators.dedup_by_key(|entry| entry.index);
+        Self { epoch, validators }
+    }
+
+    /// Indices of the validators of `group`, or all, without the timely target flag
+    pub fn missing_target<'a>(&self, group: Option<&'a str>) -> MissingTarget<'a> {
+        MissingTarget {
+            epoch: self.epoch,
+            group,
+            validators: self
+                .validators
+                .iter()
+                .filter(|entry| !entry.target && group.is_none_or(|group| entry.group == group))
+                .map(|entry| entry.index)
+                .collect(),
+        }
+    }
+
+    /// All monitored validators, in index order
+    pub fn entries(&self) -> &[ValidatorEntry] {
+        &self.validators
+    }
+
+    /// Validators of `group`, or all, from `offset` on, at most `limit`
+    pub fn page(&self, group: Option<&str>, offset: usize, limit: usize) -> ValidatorsPage<'_> {
+        let matching = self
+            .validators
+            .iter()
+            .filter(|entry| group.is_none_or(|group| entry.group == group));
+        let total = matching.clone().count();
+        ValidatorsPage {
+            epoch: self.epoch,
+            total,
+            offset,
+            limit,
+            validators: matching.skip(offset).take(limit).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssz_state::fixtures::{test_state, TEST_FLAGS};
+
+    #[test]
+    fn validator_inventory_pages() {
+        let state = StatePartial {
+            balances: vec![32_000_000_000, 31_000_000_000, 0, 32_000_000_000, 1],
+            ..test_state(vec![7, 3, 0, 7, 1])
+        };
+        // Index 9 is not in the state, 1 is in the first range only
+        let ranges = vec![
+            ("b".to_owned(), 3..4),
+            ("a".to_owned(), 0..2),
+            ("b".to_owned(), 1..2),
+            ("a".to_owned(), 4..10),
+        ];
+        let inventory = ValidatorInventory::new(&TEST_FLAGS, &ranges, &state, 2);
+        let page = inventory.page(None, 0, 10);
+        assert_eq!(page.total, 4);
+        let indices: Vec<usize> = page.validators.iter().map(|v| v.index).collect();
+        assert_eq!(indices, vec![0, 1, 3, 4]);
+        assert_eq!(
+            page.validators[1],
+            &ValidatorEntry {
+                index: 1,
+                group: "a".to_owned(),
+                source: true,
+                target: true,
+                head: false,
+                balance_gwei: 31_000_000_000,
+                status: "active_ongoing",
+            }
+        );
+
+        let page = inventory.page(Some("a"), 1, 1);
+        assert_eq!(page.total, 3);
+        assert_eq!(page.validators.len(), 1);
+        assert_eq!(page.validators[0].index, 1);
+        assert!(inventory.page(Some("a"), 5, 10).validators.is_empty());
+        assert_eq!(inventory.page(Some("c"), 0, 10).total, 0);
+
+        assert_eq!(inventory.missing_target(None).validators, vec![4]);
+        assert_eq!(inventory.missing_target(Some("a")).validators, vec![4]);
+        assert!(inventory.missing_target(Some("b")).validators.is_empty());
+    }
+}
@@ -0,0 +1,189 @@
+use crate::checkpoint::parse_root;
+use crate::inclusion::parse_hex;
+use crate::ssz_state::{Justification, StatePartial, Validator};
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// Content type of the JSON state response of the debug state API
+pub const JSON_CONTENT_TYPE: &str = "application/json";
+
+#[derive(Deserialize)]
+struct StateResponse {
+    data: StateJson,
+}
+
+/// Fields of the JSON state decoded, other fields are skipped
+#[derive(Deserialize)]
+struct StateJson {
+    slot: String,
+    block_roots: Vec<String>,
+    validators: Vec<ValidatorJson>,
+    balances: Vec<String>,
+    previous_epoch_participation: Vec<String>,
+    current_epoch_participation: Vec<String>,
+    justification_bits: String,
+    previous_justified_checkpoint: CheckpointJson,
+    current_justified_checkpoint: CheckpointJson,
+    finalized_checkpoint: CheckpointJson,
+    inactivity_scores: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct CheckpointJson {
+    epoch: String,
+}
+
+#[derive(Deserialize)]
+struct ValidatorJson {
+    withdrawal_credentials: String,
+    effective_balance: String,
+    slashed: bool,
+    activation_eligibility_epoch: String,
+    activation_epoch: String,
+    exit_epoch: String,
+    withdrawable_epoch: String,
+}
+
+impl ValidatorJson {
+    fn parse(&self) -> Result<Validator> {
+        Ok(Validator {
+            withdrawal_credentials: parse_root(&self.withdrawal_credentials)?,
+            effective_balance: parse_number(&self.effective_balance, "effective_balance")?,
+            slashed: self.slashed,
+            activation_eligibility_epoch: parse_number(
+                &self.activation_eligibility_epoch,
+                "activation_eligibility_epoch",
+            )?,
+            activation_epoch: parse_number(&self.activation_epoch, "activation_epoch")?,
+            exit_epoch: parse_number(&self.exit_epoch, "exit_epoch")?,
+            withdrawable_epoch: parse_number(&self.withdrawable_epoch, "withdrawable_epoch")?,
+        })
+    }
+}
+
+/// Decode the fields of `StatePartial` from the JSON response of the debug state API, for nodes
+/// that do not serve the state as SSZ. Unlike the SSZ decoder it needs the whole body.
+pub fn parse_json_state(body: &[u8]) -> Result<StatePartial> {
+    let state = serde_json::from_slice::<StateResponse>(body)?.data;
+    // The SSZ decoder gets these from one list length each, here every list must match
+    let validator_count = state.validators.len();
+    for (name, len) in [
+        ("balances", state.balances.len()),
+        (
+            "previous_epoch_participation",
+            state.previous_epoch_participation.len(),
+        ),
+        (
+            "current_epoch_participation",
+            state.current_epoch_participation.len(),
+        ),
+        ("inactivity_scores", state.inactivity_scores.len()),
+    ] {
+        if len != validator_count {
+            return Err(anyhow!(
+                "{name} has {len} entries for {validator_count} validators"
+            ));
+        }
+    }
+    let bits = parse_hex(&state.justification_bits)?;
+    if bits.len() != 1 {
+        return Err(anyhow!(
+            "Invalid justification_bits: {}",
+            state.justification_bits
+        ));
+    }
+    Ok(StatePartial {
+        slot: parse_number(&state.slot, "slot")?,
+        previous_epoch_participation: parse_numbers(
+            &state.previous_epoch_participation,
+            "previous_epoch_participation",
+        )?,
+        current_epoch_participation: parse_numbers(
+            &state.current_epoch_participation,
+            "current_epoch_participation",
+        )?,
+        inactivity_scores: parse_numbers(&state.inactivity_scores, "inactivity_scores")?,
+        block_roots: state
+            .block_roots
+            .iter()
+            .map(|root| parse_root(root))
+            .collect::<Result<_>>()?,
+        balances: parse_numbers(&state.balances, "balances")?,
+        validators: state
+            .validators
+            .iter()
+            .map(ValidatorJson::parse)
+            .collect::<Result<_>>()?,
+        justification: Justification {
+            bits: bits[0],
+            previous_justified_epoch: parse_number(
+                &state.previous_justified_checkpoint.epoch,
+                "previous_justified_checkpoint",
+            )?,
+            current_justified_epoch: parse_number(
+                &state.current_justified_checkpoint.epoch,
+                "current_justified_checkpoint",
+            )?,
+            finalized_epoch: parse_number(
+                &state.finalized_checkpoint.epoch,
+                "finalized_checkpoint",
+            )?,
+        },
+    })
+}
+
+fn parse_number<T: FromStr>(input: &str, name: &'static str) -> Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    input.parse().context(name)
+}
+
+fn parse_numbers<T: FromStr>(inputs: &[String], name: &'static str) -> Result<Vec<T>>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    inputs
+        .iter()
+        .map(|input| parse_number(input, name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigSpec;
+    use crate::ssz_state::{deserialize_partial_state, Fork};
+    use bytes::Bytes;
+    use std::fs;
+
+    #[test]
+    fn parse_json_state_test() {
+        let state_json = fs::read_to_string("src/fixtures/state_148990.json").unwrap();
+        let body = format!(r#"{{"version":"capella","data":{state_json}}}"#);
+        let state = parse_json_state(body.as_bytes()).unwrap();
+
+        let config = ConfigSpec {
+            slots_per_epoch: 16,
+            ..ConfigSpec::mainnet()
+        };
+        let ssz = Bytes::from(fs::read("src/fixtures/state_148990.ssz").unwrap());
+        let expected = deserialize_partial_state(&config, Some(Fork::Capella), &ssz).unwrap();
+        assert_eq!(state, expected);
+
+        assert!(parse_json_state(state_json.as_bytes()).is_err());
+        let invalid = body.replacen(r#""slot": "148990""#, r#""slot": "x""#, 1);
+        assert!(parse_json_state(invalid.as_bytes()).is_err());
+
+        let mut truncated: serde_json::Value = serde_json::from_str(&body).unwrap();
+        truncated["data"]["previous_epoch_participation"]
+            .as_array_mut()
+            .unwrap()
+            .pop();
+        let error = parse_json_state(truncated.to_string().as_bytes()).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("previous_epoch_participation has"));
+    }
+}
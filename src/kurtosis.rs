@@ -0,0 +1,189 @@
+use crate::ranges::IndexRanges;
+use anyhow::{anyhow, Result};
+
+/// ethereum-package defaults of participants that leave them unset
+const DEFAULT_CL_TYPE: &str = "lighthouse";
+const DEFAULT_EL_TYPE: &str = "geth";
+const DEFAULT_VALIDATOR_KEYS_PER_NODE: u64 = 64;
+
+/// Participant entry of an ethereum-package args file, which runs `count` nodes
+#[derive(Debug, Default, PartialEq)]
+struct Participant {
+    cl_type: Option<String>,
+    el_type: Option<String>,
+    count: Option<u64>,
+    validator_count: Option<u64>,
+}
+
+/// Derive the index ranges of an ethereum-package (Kurtosis) args file. As the package does, each
+/// participant runs `count` nodes, 1 by default, which take `validator_count` consecutive genesis
+/// keys each, `network_params.num_validator_keys_per_node` by default. Groups are named after the
+/// node's services, `<cl_type>-<el_type>-<node number>` numbered from 1. Nodes without keys get
+/// no group.
+///
+/// Only the YAML subset these files use is read: block mappings and sequences of scalars, with
+/// nested values of other keys skipped. `participants_matrix` is not supported.
+pub fn parse_kurtosis_participants(input: &str) -> Result<IndexRanges> {
+    let mut participants: Vec<Participant> = vec![];
+    let mut keys_per_node = DEFAULT_VALIDATOR_KEYS_PER_NODE;
+    let mut section = String::new();
+    // Indent of the dashes starting participants, and of the keys of the current participant or
+    // network_params
+    let mut item_indent = None;
+    let mut key_indent = None;
+    for (number, raw_line) in input.lines().enumerate() {
+        let line = strip_comment(raw_line).trim_end();
+        let content = line.trim_start();
+        if content.is_empty() || content == "---" {
+            continue;
+        }
+        let mut indent = line.len() - content.len();
+        if indent == 0 {
+            let (key, _) = content
+                .split_once(':')
+                .ok_or_else(|| anyhow!("line {}: expected a key", number + 1))?;
+            section = key.trim().to_owned();
+            item_indent = None;
+            key_indent = None;
+            continue;
+        }
+        let mut content = content;
+        if section == "participants" {
+            let item = content
+                .strip_prefix('-')
+                .filter(|_| *item_indent.get_or_insert(indent) == indent);
+            if let Some(item) = item {
+                participants.push(Participant::default());
+                indent += 1 + (item.len() - item.trim_start().len());
+                content = item.trim_start();
+                key_indent = Some(indent);
+                if content.is_empty() {
+                    key_indent = None;
+                    continue;
+                }
+            }
+        } else if section != "network_params" {
+            continue;
+        }
+        if *key_indent.get_or_insert(indent) != indent {
+            continue;
+        }
+        let Some((key, value)) = content.split_once(':') else {
+            continue;
+        };
+        let value = unquote(value.trim());
+        let parse_count = || {
+            value
+                .parse::<u64>()
+                .map_err(|_| anyhow!("line {}: invalid {key} {value}", number + 1))
+        };
+        match (section.as_str(), key.trim()) {
+            ("network_params", "num_validator_keys_per_node") => keys_per_node = parse_count()?,
+            ("participants", key) => {
+                let participant = participants
+                    .last_mut()
+                    .ok_or_else(|| anyhow!("line {}: expected a participant", number + 1))?;
+                match key {
+                    // Older releases of the package named them el_client_type and cl_client_type
+                    "cl_type" | "cl_client_type" => participant.cl_type = Some(value.to_owned()),
+                    "el_type" | "el_client_type" => participant.el_type = Some(value.to_owned()),
+                    "count" => participant.count = Some(parse_count()?),
+                    "validator_count" => participant.validator_count = Some(parse_count()?),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    if participants.is_empty() {
+        return Err(anyhow!("no participants"));
+    }
+
+    let mut ranges = IndexRanges::new();
+    let mut next_index = 0;
+    let mut node_number = 1;
+    for participant in &participants {
+        let keys = participant.validator_count.unwrap_or(keys_per_node) as usize;
+        for _ in 0..participant.count.unwrap_or(1) {
+            if keys > 0 {
+                let name = format!(
+                    "{}-{}-{}",
+                    participant.cl_type.as_deref().unwrap_or(DEFAULT_CL_TYPE),
+                    participant.el_type.as_deref().unwrap_or(DEFAULT_EL_TYPE),
+                    node_number
+                );
+                ranges.push((name, next_index..next_index + keys));
+                next_index += keys;
+            }
+            node_number += 1;
+        }
+    }
+    Ok(ranges)
+}
+
+/// Drop a `#` comment, unless it is inside a quoted value
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), c) if c == open => quote = None,
+            (None, '#') if i == 0 || line[..i].ends_with(char::is_whitespace) => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|value| value.strip_suffix(quote))
+        {
+            return inner;
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_kurtosis_participants_test() {
+        let input = r#"
+participants:
+  # Two lighthouse nodes
+  - el_type: geth
+    cl_type: lighthouse
+    count: 2
+    cl_extra_params:
+      - --count=9
+  - el_type: "nethermind"
+    cl_type: teku # keys: 100
+    validator_count: 100
+  -
+    cl_type: prysm
+    validator_count: 0
+  - cl_client_type: lodestar
+network_params:
+  num_validator_keys_per_node: 32
+  network_id: "3151908"
+additional_services:
+  - dora
+"#;
+        assert_eq!(
+            parse_kurtosis_participants(input).unwrap(),
+            vec![
+                ("lighthouse-geth-1".to_owned(), 0..32),
+                ("lighthouse-geth-2".to_owned(), 32..64),
+                ("teku-nethermind-3".to_owned(), 64..164),
+                ("lodestar-geth-5".to_owned(), 164..196),
+            ]
+        );
+        assert!(parse_kurtosis_participants("participants:\n  - count: two\n").is_err());
+        assert!(parse_kurtosis_participants("network_params:\n  seconds_per_slot: 6\n").is_err());
+    }
+}
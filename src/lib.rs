@@ -0,0 +1,91 @@
+//! Network wide participation metrics from beacon states, grouped by validator index ranges.
+//!
+//! [`participation::fetch_state`] downloads a state as SSZ and decodes only the fields needed for
+//! metrics into a [`ssz_state::StatePartial`]. [`ranges::parse_ranges`] reads the index ranges of
+//! each group, and [`participation::group_target_participation`] summarizes the state per group.
+//!
+//! ```no_run
+//! use beacon_metrics_gazer::config::fetch_config;
+//! use beacon_metrics_gazer::participation::{fetch_state, group_target_participation};
+//! use beacon_metrics_gazer::ranges::parse_ranges;
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let url = "http://localhost:5052";
+//! let config = fetch_config(url).await?;
+//! let (ranges, _) = parse_ranges(r#"{"0..100": "lh-geth-0", "100..200": "lh-geth-1"}"#)?;
+//! let state = fetch_state(&config, url, "head", true).await?;
+//! let epoch = state.slot / config.slots_per_epoch;
+//! for (name, _, summary) in
+//!     group_target_participation(&config.participation_flags, &ranges, &state, epoch)
+//! {
+//!     println!("{name}: {}", summary.target_participation_ratio);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`exporter::run_exporter`] runs the whole exporter in-process, as the binary does: it fetches
+//! states every epoch and serves the metrics until its [`exporter::ExporterHandle`] is shut down.
+//!
+//! ```no_run
+//! use beacon_metrics_gazer::exporter::{run_exporter, Config, ExporterHandle};
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let config = Config::from_args(["gazer", "http://localhost:5052", "--ranges-file", "ranges.txt"])?;
+//! let registry = prometheus::Registry::new();
+//! let handle = ExporterHandle::default().with_registry(registry.clone());
+//! let exporter = tokio::spawn(run_exporter(config, handle.clone()));
+//! // ... gather `registry` along with the agent's own metrics
+//! handle.shutdown();
+//! exporter.await??;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod active_set;
+pub mod alerts;
+pub mod anomaly;
+mod cardinality;
+pub mod checkpoint;
+pub mod client_diversity;
+pub mod collection;
+pub mod config;
+pub mod correlation;
+pub mod custom_metrics;
+pub mod db;
+pub mod duties;
+pub mod events;
+pub mod exporter;
+pub mod finality;
+pub mod gossip;
+pub mod graffiti;
+pub mod history;
+pub mod http_client;
+pub mod inclusion;
+pub mod inventory;
+pub mod json_state;
+pub mod kurtosis;
+pub mod metrics;
+pub mod miss_streaks;
+pub mod my_validators;
+pub mod node_status;
+pub mod node_version;
+pub mod parquet_sink;
+pub mod participation;
+pub mod persistence;
+pub mod proposals;
+pub mod pubkey_cache;
+pub mod ranges;
+pub mod redact;
+pub mod reorgs;
+pub mod report;
+pub mod rewards;
+pub mod rotating_file;
+pub mod server;
+pub mod shutdown;
+pub mod simulate;
+pub mod ssz_state;
+pub mod status_page;
+pub mod target;
+pub mod util;
+pub mod withdrawal;
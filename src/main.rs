@@ -1,380 +1,99 @@
-use crate::config::fetch_genesis;
-use crate::ranges::{dump_ranges, parse_ranges};
-use crate::util::{current_epoch_start_slot, resolve_path_or_url, to_next_epoch_start};
-use anyhow::{anyhow, Context, Result};
-use clap::Parser;
-use config::{fetch_config, ConfigSpec, Genesis};
-use hyper::header::HeaderName;
-use hyper::service::{make_service_fn, service_fn};
-use hyper::{Body, HeaderMap, Request, Response, Server};
-use metrics::{
-    set_gauge, HEAD_PARTICIPATION, INACTIVITY_SCORES, SOURCE_PARTICIPATION, TARGET_PARTICIPATION,
-};
-
-use finality::{fetch_checkpoint_finality};
-
-use prettytable::{format, Cell, Row, Table};
-use prometheus::{Encoder, TextEncoder};
-use ssz_state::{deserialize_partial_state, StatePartial};
-use std::convert::Infallible;
-use std::net::SocketAddr;
-use std::ops::Range;
-use std::str::FromStr;
-use std::time::Duration;
-use tokio::time;
-use serde::Serialize;
-use serde_json::to_string;
-
-
-//use ssz_state::parse_epoch_participation;
-//use ssz_state::ConfigSpec;
-
-mod config;
-mod metrics;
-mod ranges;
-mod ssz_state;
-mod util;
-mod finality;
-
-#[derive(Clone, Copy)]
-enum DumpFormat {
-    Json,
-    Table,
-}
-
-impl FromStr for DumpFormat {
-    type Err = anyhow::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "json" => Ok(DumpFormat::Json),
-            "table" => Ok(DumpFormat::Table),
-            _ => Ok(DumpFormat::Table),
-        }
-    }
-}
+use anyhow::{anyhow, Result};
+use backfill::{backfill, BackfillArgs};
+use beacon_metrics_gazer::exporter::{run_exporter, Config, ExporterHandle};
+use beacon_metrics_gazer::shutdown::wait_for_signal;
+use check::{check, CheckArgs};
+use clap::{CommandFactory, Parser, Subcommand};
+use config_file::with_config_file;
+use dashboard::{generate_dashboard, DashboardArgs};
+use inspect::{inspect_state, InspectStateArgs};
+
+mod backfill;
+mod check;
+mod common_args;
+mod config_file;
+mod dashboard;
+mod inspect;
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    subcommand_negates_reqs = true,
+    args_conflicts_with_subcommands = true
+)]
 struct Cli {
-    /// Beacon HTTP API URL: http://1.2.3.4:4000
-    url: String,
-    /// Extra headers sent to each request to the beacon node API at `url`.
-    /// Same format as curl: `-H "Authorization: Bearer {token}"`
-    #[arg(long, short = 'H')]
-    headers: Option<Vec<String>>,
-    /// Index ranges to group IDs as JSON or TXT. Example:
-    /// `{"0..100": "lh-geth-0", "100..200": "lh-geth-1"}
-    #[arg(long)]
-    ranges: Option<String>,
-    /// Local path or URL containing a file with index ranges
-    /// with the format as defined in --ranges
-    #[arg(long)]
-    ranges_file: Option<String>,
-    /// Dump participation ranges print to stderr on each fetch
-    #[arg(long)]
-    dump: Option<DumpFormat>,
-    /// Metrics server port
-    #[arg(long, short, default_value_t = 8080)]
-    port: u16,
-    /// Metrics server bind address
-    #[arg(long, default_value = "127.0.0.1")]
-    address: String,
-
-    // poll metrics in a fixed interval
-    #[arg(long, short)]
-    poll: Option<u64>,
-}
-
-type IndexRanges = Vec<(String, Range<usize>)>;
-struct RangeSummary {
-    target_participation_ratio: f32,
-    head_participation_ratio: f32,
-    source_participation_ratio: f32,
-    inactivity_scores_avg: f32,
-}
-type ParticipationByRange = Vec<(String, Range<usize>, RangeSummary)>;
-
-async fn handle_metrics_server_request(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
-    // Create the response
-    let encoder = TextEncoder::new();
-    let metric_families = prometheus::gather();
-    let mut buffer = vec![];
-    encoder.encode(&metric_families, &mut buffer).unwrap();
-
-    Ok(Response::builder()
-        .header("Content-Type", encoder.format_type())
-        .body(Body::from(buffer))
-        .unwrap())
-}
-
-async fn fetch_epoch_participation(
-    config: &ConfigSpec,
-    beacon_url: &str,
-    extra_headers: &HeaderMap,
-) -> Result<StatePartial> {
-    let req = reqwest::Client::new()
-        .get(format!("{beacon_url}/eth/v2/debug/beacon/states/head",))
-        .header(reqwest::header::ACCEPT, "application/octet-stream")
-        .headers(extra_headers.clone())
-        .send()
-        .await?;
-    let state_buf = req.bytes().await?;
-
-    deserialize_partial_state(config, &state_buf)
-}
-
-// https://github.com/ethereum/consensus-specs/blob/4a27f855439c16612ab1ae3995d71bed54f979ea/specs/altair/beacon-chain.md#participation-flag-indices
-const TIMELY_SOURCE_FLAG_INDEX: u8 = 0;
-const TIMELY_TARGET_FLAG_INDEX: u8 = 1;
-const TIMELY_HEAD_FLAG_INDEX: u8 = 2;
-const TIMELY_SOURCE: u8 = 1 << TIMELY_SOURCE_FLAG_INDEX;
-const TIMELY_TARGET: u8 = 1 << TIMELY_TARGET_FLAG_INDEX;
-const TIMELY_HEAD: u8 = 1 << TIMELY_HEAD_FLAG_INDEX;
-
-fn has_flag(flag: u8, mask: u8) -> bool {
-    flag & mask == mask
-}
-
-fn participation_avg(participation: &[u8], range: &Range<usize>, flag_mask: u8) -> f32 {
-    let participant_count: u32 = participation[range.clone()]
-        .iter()
-        .map(|f| has_flag(*f, flag_mask) as u32)
-        .sum();
-    participant_count as f32 / (range.end - range.start) as f32
-}
-
-fn group_target_participation(ranges: &IndexRanges, state: &StatePartial) -> ParticipationByRange {
-    ranges
-        .iter()
-        .map(|(range_name, range)| {
-            (
-                range_name.clone(),
-                range.clone(),
-                RangeSummary {
-                    target_participation_ratio: participation_avg(
-                        &state.previous_epoch_participation,
-                        range,
-                        TIMELY_TARGET,
-                    ),
-                    source_participation_ratio: participation_avg(
-                        &state.previous_epoch_participation,
-                        range,
-                        TIMELY_SOURCE,
-                    ),
-                    head_participation_ratio: participation_avg(
-                        &state.previous_epoch_participation,
-                        range,
-                        TIMELY_HEAD,
-                    ),
-                    inactivity_scores_avg: state.inactivity_scores[range.clone()]
-                        .iter()
-                        .sum::<u64>() as f32
-                        / (range.end - range.start) as f32,
-                },
-            )
-        })
-        .collect()
-}
-
-fn set_participation_to_metrics(participation_by_range: &ParticipationByRange) {
-    for (range_name, _, summary) in participation_by_range.iter() {
-        set_gauge(
-            &SOURCE_PARTICIPATION,
-            &[range_name],
-            summary.source_participation_ratio as f64,
-        );
-        set_gauge(
-            &TARGET_PARTICIPATION,
-            &[range_name],
-            summary.target_participation_ratio as f64,
-        );
-        set_gauge(
-            &HEAD_PARTICIPATION,
-            &[range_name],
-            summary.head_participation_ratio as f64,
-        );
-        set_gauge(
-            &INACTIVITY_SCORES,
-            &[range_name],
-            summary.inactivity_scores_avg as f64,
-        );
-    }
-}
-
-
-fn dump_participation(slot: u64, participation_by_range: &ParticipationByRange, format: Option<DumpFormat>) {
-    println!("statistics for slot: {}: ", slot);
-    match format {
-        Some(DumpFormat::Json) => dump_participation_to_stdout_json(slot, participation_by_range),
-        Some(DumpFormat::Table) => dump_participation_to_stdout(slot, participation_by_range),
-        None => ()
-    }
-}
-
-
-fn dump_participation_to_stdout(slot: u64, participation_by_range: &ParticipationByRange) {
-    let mut table = Table::new();
-    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
-
-    table.add_row(Row::new(vec![
-        Cell::new("Slot"),
-        Cell::new("Name"),
-        Cell::new("Range"),
-        Cell::new("Source"),
-        Cell::new("Target"),
-        Cell::new("Head"),
-    ]));
-
-    for (range_name, range, summary) in participation_by_range.iter() {
-        table.add_row(Row::new(vec![
-            Cell::new(&format!("{:?}", slot)),
-            Cell::new(range_name),
-            Cell::new(&format!("{:?}", &range)),
-            Cell::new(&summary.source_participation_ratio.to_string()),
-            Cell::new(&summary.target_participation_ratio.to_string()),
-            Cell::new(&summary.head_participation_ratio.to_string()),
-        ]));
-    }
-
-    table.printstd();
-}
-
-#[derive(Serialize)]
-struct ParticipationRecord {
-    slot: u64,
-    name: String,
-    range: String,
-    source: String,
-    target: String,
-    head: String,
-}
-
-fn dump_participation_to_stdout_json(slot: u64, participation_by_range: &ParticipationByRange) {
-    let mut records = Vec::new();
-
-    for (range_name, range, summary) in participation_by_range.iter() {
-        records.push(ParticipationRecord {
-            slot: slot,
-            name: range_name.clone(),
-            range: format!("{:?}", range),
-            source: summary.source_participation_ratio.to_string(),
-            target: summary.target_participation_ratio.to_string(),
-            head: summary.head_participation_ratio.to_string(),
-        });
-    }
-
-    let json = to_string(&records).unwrap();
-    println!("{{ \"participation\": {} }}", json);
-}
-
-async fn task_fetch_state_every_epoch(
-    genesis: &Genesis,
-    config: &ConfigSpec,
-    beacon_url: &str,
-    extra_headers: &HeaderMap,
-    ranges: &IndexRanges,
-    dump_format: Option<DumpFormat>,
-) -> Result<()> {
-    loop {
-        match current_epoch_start_slot(genesis, config) {
-            Err(e) => eprintln!("error computing current epoch: {:?}", e),
-            Ok(slot) => {
-                if slot == 0 {
-                    println!("before genesis, going to sleep")
-                } else {
-                    // Only after genesis
-                    match fetch_epoch_participation(config, beacon_url, extra_headers).await {
-                        Err(e) => eprintln!("error fetching state: {:?}", e),
-                        Ok(state) => {
-                            let participation_by_range = group_target_participation(ranges, &state);
-                            set_participation_to_metrics(&participation_by_range);
-                            dump_participation(slot, &participation_by_range, dump_format);
-                               
-                        }
-                    }
-
-                    let data = fetch_checkpoint_finality(beacon_url, "head").await?;
-                    let json = to_string(&data).unwrap();
-                    println!("{{ \"slot\": {}, \"finality_checkpoint\": {} }}", slot, json);
-                    
-                }
-            }
-        }
-
-        // Run once on boot, then every interval at end of epoch
-        time::sleep(to_next_epoch_start(genesis, config).unwrap_or_else(|e| {
-            eprintln!("error computing to_next_epoch_start: {:?}", e);
-            Duration::from_secs(config.seconds_per_slot * config.slots_per_epoch)
-        }))
-        .await;
-    }
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    exporter: Config,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Fetch states every epoch and serve their participation per group as metrics, the default
+    /// without a subcommand
+    Serve(Box<Config>),
+    /// Fetch one state, print its participation per group as a table, or JSON with --dump json,
+    /// and exit
+    Dump(Box<Config>),
+    /// Fetch one state and exit with an error listing the groups below a target participation
+    Check(CheckArgs),
+    /// Compute per group participation of past epochs from historical states, which requires an
+    /// archive node, and write it as CSV or JSON lines
+    Backfill(BackfillArgs),
+    /// Decode a state with the partial decoder and print its slot, fork, validator count,
+    /// participation summary and the field offsets used
+    InspectState(InspectStateArgs),
+    /// Print a Grafana dashboard JSON with an overview and one row per group of the ranges
+    Dashboard(DashboardArgs),
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let cli = Cli::parse();
-    let beacon_url = cli.url.clone();
-
-    println!("connecting to beacon URL {:?}", beacon_url);
-
-    let mut extra_headers = HeaderMap::new();
-    if let Some(headers_str) = cli.headers {
-        for header_str in headers_str {
-            let parts: Vec<&str> = header_str.split(':').collect();
-            if parts.len() != 2 {
-                return Err(anyhow!("Invalid header: {}", header_str));
-            }
-
-            let name = HeaderName::from_str(parts[0])?;
-            let value = parts[1].trim().parse()?;
-            extra_headers.insert(name, value);
+    let cli =
+        Cli::parse_from(with_config_file(Cli::command(), std::env::args_os().collect()).await?);
+    let config = match cli.command {
+        Some(Command::Serve(config)) => *config,
+        Some(Command::Dump(config)) if config.simulate => {
+            return Err(anyhow!(
+                "dump reads a beacon node, --simulate is not supported"
+            ))
         }
-        println!("extra headers {:?}", extra_headers);
-    }
-
-    // Parse groups file mapping index ranges to host names
-    let ranges_str = if let Some(ranges_str) = &cli.ranges {
-        ranges_str.clone()
-    } else if let Some(path_or_url) = &cli.ranges_file {
-        resolve_path_or_url(path_or_url).await?
-    } else {
-        return Err(anyhow!("Must set --groups or --groups_file"));
+        Some(Command::Dump(config)) => Config {
+            once: true,
+            ..*config
+        },
+        Some(Command::Check(args)) => return check(&args).await,
+        Some(Command::Backfill(args)) => return backfill(&args).await,
+        Some(Command::InspectState(args)) => return inspect_state(&args).await,
+        Some(Command::Dashboard(args)) => return generate_dashboard(&args).await,
+        None => cli.exporter,
     };
-    let ranges = parse_ranges(&ranges_str)?;
-    println!("index ranges ---\n{}\n---", dump_ranges(&ranges));
-
-    let genesis = fetch_genesis(&beacon_url).await.context("fetch_genesis")?;
-    println!("beacon genesis {:?}", genesis);
 
-    let config = fetch_config(&beacon_url).await.context("fetch_config")?;
-    println!("beacon config {:?}", config);
-
-    // Background task fetching state every interval and registering participation
-    // in metrics with provided index ranges
+    let handle = ExporterHandle::default();
+    let signal_handle = handle.clone();
     tokio::spawn(async move {
-        task_fetch_state_every_epoch(
-            &genesis,
-            &config,
-            &beacon_url,
-            &extra_headers,
-            &ranges,
-            cli.dump,
-        )
-        .await
+        match wait_for_signal().await {
+            Ok(signal) => println!("received {signal}, shutting down"),
+            Err(e) => {
+                eprintln!("error listening for shutdown signals: {:?}", e);
+                return;
+            }
+        }
+        signal_handle.shutdown();
     });
+    run_exporter(config, handle).await
+}
 
-    // Start metrics server
-
-    let addr = SocketAddr::new(cli.address.parse()?, cli.port);
-    let server = Server::bind(&addr).serve(make_service_fn(|_conn| async {
-        Ok::<_, Infallible>(service_fn(handle_metrics_server_request))
-    }));
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    println!("Server is running on http://{}", addr);
-    if let Err(e) = server.await {
-        eprintln!("server error: {}", e);
+    #[test]
+    fn cli_debug_assert() {
+        Cli::command().debug_assert();
     }
-
-    Ok(())
 }
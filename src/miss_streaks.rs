@@ -0,0 +1,100 @@
+use crate::inventory::ValidatorInventory;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Default and maximum number of validators listed by `/api/v1/worst`
+pub const DEFAULT_WORST_LIMIT: usize = 20;
+pub const MAX_WORST_LIMIT: usize = 1000;
+
+/// Validator that missed the timely target flag in its last `epochs` fetched epochs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MissStreak {
+    pub index: usize,
+    pub group: String,
+    pub epochs: u64,
+}
+
+/// Current miss streak of every monitored validator, kept in memory across fetches and in
+/// `--history-file` across restarts
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MissStreaks {
+    /// Epoch of the last counted inventory
+    epoch: Option<u64>,
+    streaks: HashMap<usize, MissStreak>,
+}
+
+impl MissStreaks {
+    /// Count the flags of `inventory`, once per epoch. Validators with the flag, or no longer
+    /// monitored, start over.
+    pub fn update(&mut self, inventory: &ValidatorInventory) {
+        if self.epoch.is_some_and(|epoch| epoch >= inventory.epoch) {
+            return;
+        }
+        self.epoch = Some(inventory.epoch);
+        self.streaks = inventory
+            .entries()
+            .iter()
+            .filter(|entry| !entry.target)
+            .map(|entry| {
+                let epochs = self.streaks.get(&entry.index).map_or(0, |s| s.epochs) + 1;
+                let streak = MissStreak {
+                    index: entry.index,
+                    group: entry.group.clone(),
+                    epochs,
+                };
+                (entry.index, streak)
+            })
+            .collect();
+    }
+
+    /// Up to `limit` validators with the longest streaks, longest first and then by index
+    pub fn worst(&self, limit: usize) -> Vec<&MissStreak> {
+        let mut worst: Vec<&MissStreak> = self.streaks.values().collect();
+        worst.sort_by_key(|streak| (std::cmp::Reverse(streak.epochs), streak.index));
+        worst.truncate(limit);
+        worst
+    }
+
+    /// Validators per group with a streak of at least `epochs`
+    pub fn consistently_missing(&self, epochs: u64) -> BTreeMap<&str, usize> {
+        let mut counts = BTreeMap::new();
+        for streak in self.streaks.values().filter(|s| s.epochs >= epochs) {
+            *counts.entry(streak.group.as_str()).or_default() += 1;
+        }
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssz_state::fixtures::{test_state, TEST_FLAGS};
+
+    #[test]
+    fn miss_streaks_test() {
+        let ranges = vec![("a".to_owned(), 0..2), ("b".to_owned(), 2..4)];
+        let inventory = |participation, epoch| {
+            ValidatorInventory::new(&TEST_FLAGS, &ranges, &test_state(participation), epoch)
+        };
+
+        let mut streaks = MissStreaks::default();
+        streaks.update(&inventory(vec![0, 7, 0, 0], 2));
+        // Counted once per epoch
+        streaks.update(&inventory(vec![0, 7, 0, 0], 2));
+        streaks.update(&inventory(vec![0, 0, 7, 0], 3));
+        streaks.update(&inventory(vec![0, 7, 7, 0], 4));
+
+        let worst: Vec<(usize, u64)> = streaks
+            .worst(10)
+            .iter()
+            .map(|streak| (streak.index, streak.epochs))
+            .collect();
+        assert_eq!(worst, vec![(0, 3), (3, 3)]);
+        assert_eq!(streaks.worst(1)[0].group, "a");
+        assert_eq!(
+            streaks.consistently_missing(3),
+            BTreeMap::from([("a", 1), ("b", 1)])
+        );
+        assert!(streaks.consistently_missing(4).is_empty());
+    }
+}
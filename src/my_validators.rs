@@ -0,0 +1,175 @@
+use crate::http_client::beacon_client;
+use crate::pubkey_cache::PubkeyCache;
+use crate::ranges::IndexRanges;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+/// Name of the single group set up by `--my-validators`
+pub const MY_VALIDATORS_GROUP: &str = "my-validators";
+
+/// Pubkeys resolved per request, keeps the query string within common URL length limits
+const PUBKEYS_PER_REQUEST: usize = 64;
+
+#[derive(Debug, PartialEq)]
+pub enum ValidatorId {
+    Index(usize),
+    Pubkey(String),
+}
+
+#[derive(Deserialize)]
+struct ValidatorsResponse {
+    data: Vec<ValidatorResponseData>,
+}
+
+#[derive(Deserialize)]
+struct ValidatorResponseData {
+    index: String,
+    validator: ValidatorJson,
+}
+
+#[derive(Deserialize)]
+struct ValidatorJson {
+    pubkey: String,
+}
+
+/// Parse a comma or whitespace separated list of validator indices and/or 0x prefixed pubkeys
+pub fn parse_validator_ids(input: &str) -> Result<Vec<ValidatorId>> {
+    input
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            if id.starts_with("0x") {
+                if id.len() != 2 + 2 * 48 {
+                    return Err(anyhow!("Invalid validator pubkey: {}", id));
+                }
+                Ok(ValidatorId::Pubkey(id.to_lowercase()))
+            } else {
+                id.parse()
+                    .map(ValidatorId::Index)
+                    .map_err(|_| anyhow!("Invalid validator index: {}", id))
+            }
+        })
+        .collect()
+}
+
+/// Resolve `ids` into sorted, de-duplicated validator indices, querying the beacon node for
+/// pubkeys missing from `cache`. Errors if any pubkey is unknown to the node.
+pub async fn resolve_validator_indices(
+    url: &str,
+    ids: &[ValidatorId],
+    cache: &mut PubkeyCache,
+) -> Result<Vec<usize>> {
+    let (mut indices, pubkeys) = split_cached(ids, cache);
+    for chunk in pubkeys.chunks(PUBKEYS_PER_REQUEST) {
+        let response = beacon_client()
+            .get(format!(
+                "{url}/eth/v1/beacon/states/head/validators?id={}",
+                chunk.join(",")
+            ))
+            .send()
+            .await?
+            .error_for_status()?;
+        let data: ValidatorsResponse = response.json().await?;
+        if data.data.len() != chunk.len() {
+            return Err(anyhow!(
+                "beacon node knows only {} of {} pubkeys",
+                data.data.len(),
+                chunk.len()
+            ));
+        }
+        for validator in data.data {
+            let index = validator.index.parse()?;
+            cache.insert(validator.validator.pubkey.to_lowercase(), index);
+            indices.push(index);
+        }
+    }
+    cache.save()?;
+
+    indices.sort_unstable();
+    indices.dedup();
+    Ok(indices)
+}
+
+/// Indices of `ids` known without querying the node, and the sorted, de-duplicated pubkeys
+/// missing from `cache`. The node answers a repeated pubkey once.
+fn split_cached<'a>(ids: &'a [ValidatorId], cache: &PubkeyCache) -> (Vec<usize>, Vec<&'a str>) {
+    let mut indices = vec![];
+    let mut pubkeys = vec![];
+    for id in ids {
+        match id {
+            ValidatorId::Index(index) => indices.push(*index),
+            ValidatorId::Pubkey(pubkey) => match cache.get(pubkey) {
+                Some(index) => indices.push(index),
+                None => pubkeys.push(pubkey.as_str()),
+            },
+        }
+    }
+    pubkeys.sort_unstable();
+    pubkeys.dedup();
+    (indices, pubkeys)
+}
+
+/// Single group named `name` covering `indices`, as one range per contiguous run of indices.
+/// `indices` must be sorted.
+pub fn ranges_of_indices(name: &str, indices: &[usize]) -> IndexRanges {
+    let mut ranges: IndexRanges = vec![];
+    for index in indices {
+        match ranges.last_mut() {
+            Some((_, range)) if range.end == *index => range.end += 1,
+            _ => ranges.push((name.to_string(), *index..(index + 1))),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_validator_ids_test() {
+        let pubkey = format!("0x{}", "AB".repeat(48));
+        assert_eq!(
+            parse_validator_ids(&format!("1, 2 {pubkey}")).unwrap(),
+            vec![
+                ValidatorId::Index(1),
+                ValidatorId::Index(2),
+                ValidatorId::Pubkey(pubkey.to_lowercase())
+            ]
+        );
+        assert!(parse_validator_ids("0x1234").is_err());
+        assert!(parse_validator_ids("abc").is_err());
+    }
+
+    #[test]
+    fn split_cached_test() {
+        let pubkey = |byte: &str| format!("0x{}", byte.repeat(48));
+        let mut cache = PubkeyCache::load(None).unwrap();
+        cache.insert(pubkey("cc"), 7);
+        let ids = parse_validator_ids(&format!(
+            "{} 3 {} {} {}",
+            pubkey("bb"),
+            pubkey("AA"),
+            pubkey("cc"),
+            pubkey("bb")
+        ))
+        .unwrap();
+        let (bb, aa) = (pubkey("bb"), pubkey("aa"));
+        assert_eq!(
+            split_cached(&ids, &cache),
+            (vec![3, 7], vec![aa.as_str(), bb.as_str()])
+        );
+    }
+
+    #[test]
+    fn ranges_of_indices_test() {
+        assert_eq!(
+            ranges_of_indices("g", &[1, 2, 3, 7, 9, 10]),
+            vec![
+                ("g".to_owned(), 1..4),
+                ("g".to_owned(), 7..8),
+                ("g".to_owned(), 9..11),
+            ]
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use crate::http_client::beacon_client;
+use anyhow::Result;
+use serde::Deserialize;
+
+/// Sync status of a beacon node, as reported by `/eth/v1/node/syncing`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncStatus {
+    pub head_slot: u64,
+    /// Slots between the node's head and the wall clock slot
+    pub sync_distance: u64,
+    pub is_syncing: bool,
+}
+
+impl SyncStatus {
+    /// Whether the node is syncing more than `max_distance` slots behind
+    pub fn is_behind(&self, max_distance: u64) -> bool {
+        self.is_syncing && self.sync_distance > max_distance
+    }
+}
+
+#[derive(Deserialize)]
+struct SyncingResponse {
+    data: SyncingData,
+}
+
+#[derive(Deserialize)]
+struct SyncingData {
+    head_slot: String,
+    sync_distance: String,
+    is_syncing: bool,
+}
+
+#[derive(Deserialize)]
+struct PeerCountResponse {
+    data: PeerCountData,
+}
+
+#[derive(Deserialize)]
+struct PeerCountData {
+    connected: String,
+}
+
+/// Fetch the sync status of the beacon node at `url`
+pub async fn fetch_sync_status(url: &str) -> Result<SyncStatus> {
+    let response = beacon_client()
+        .get(format!("{url}/eth/v1/node/syncing"))
+        .send()
+        .await?
+        .error_for_status()?;
+    let data: SyncingResponse = response.json().await?;
+    Ok(SyncStatus {
+        head_slot: data.data.head_slot.parse()?,
+        sync_distance: data.data.sync_distance.parse()?,
+        is_syncing: data.data.is_syncing,
+    })
+}
+
+/// Fetch the number of connected peers of the beacon node at `url`
+pub async fn fetch_peer_count(url: &str) -> Result<u64> {
+    let response = beacon_client()
+        .get(format!("{url}/eth/v1/node/peer_count"))
+        .send()
+        .await?
+        .error_for_status()?;
+    let data: PeerCountResponse = response.json().await?;
+    Ok(data.data.connected.parse()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sync_status_test() {
+        let status = |sync_distance, is_syncing| SyncStatus {
+            head_slot: 100,
+            sync_distance,
+            is_syncing,
+        };
+        assert!(status(65, true).is_behind(64));
+        assert!(!status(64, true).is_behind(64));
+        assert!(!status(1000, false).is_behind(64));
+    }
+}
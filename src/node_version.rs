@@ -0,0 +1,81 @@
+use crate::http_client::beacon_client;
+use anyhow::Result;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Interval between queries of the node version, which changes when the node is upgraded
+pub const NODE_VERSION_POLL_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Client of a beacon node, from its version string such as
+/// `Lighthouse/v4.5.0-441fc16/x86_64-linux`
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeVersion {
+    /// Client name in lower case
+    pub client: String,
+    /// Client version, empty if the string has none
+    pub version: String,
+    pub version_string: String,
+}
+
+impl NodeVersion {
+    pub fn parse(version_string: &str) -> Self {
+        let mut parts = version_string.split('/');
+        let client = parts.next().unwrap_or_default().trim().to_lowercase();
+        let version = parts.next().unwrap_or_default().trim().to_owned();
+        Self {
+            client,
+            version,
+            version_string: version_string.to_owned(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct NodeVersionResponse {
+    data: NodeVersionData,
+}
+
+#[derive(Deserialize)]
+struct NodeVersionData {
+    version: String,
+}
+
+/// Fetch the version of the beacon node at `url`
+pub async fn fetch_node_version(url: &str) -> Result<NodeVersion> {
+    let response = beacon_client()
+        .get(format!("{url}/eth/v1/node/version"))
+        .send()
+        .await?
+        .error_for_status()?;
+    let data: NodeVersionResponse = response.json().await?;
+    Ok(NodeVersion::parse(&data.data.version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn node_version_test() {
+        assert_eq!(
+            NodeVersion::parse("Lighthouse/v4.5.0-441fc16/x86_64-linux"),
+            NodeVersion {
+                client: "lighthouse".to_owned(),
+                version: "v4.5.0-441fc16".to_owned(),
+                version_string: "Lighthouse/v4.5.0-441fc16/x86_64-linux".to_owned(),
+            }
+        );
+        let teku = NodeVersion::parse(
+            "teku/v23.10.0/linux-x86_64/-eclipseadoptium-openjdk64bitservervm-java-17",
+        );
+        assert_eq!(
+            (teku.client.as_str(), teku.version.as_str()),
+            ("teku", "v23.10.0")
+        );
+        let unknown = NodeVersion::parse("custom");
+        assert_eq!(
+            (unknown.client.as_str(), unknown.version.as_str()),
+            ("custom", "")
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use crate::inventory::{ValidatorEntry, ValidatorInventory};
+use crate::participation::{GroupParticipation, ParticipationSnapshot};
+use crate::persistence::write_atomic;
+use anyhow::{Context, Result};
+use arrow::array::{ArrayRef, BooleanArray, Float32Array, StringArray, UInt64Array};
+use arrow::datatypes::{Field, Schema};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// File name of each epoch's partition
+const PART_FILE: &str = "part-0.parquet";
+
+/// Write the participation of an epoch below `dir` for `--parquet-dir`, partitioned by epoch as
+/// `groups/epoch=<n>/part-0.parquet` and, with `validators`, `validators/epoch=<n>/part-0.parquet`.
+/// Files of an epoch written before are replaced.
+pub fn write_parquet_epoch(
+    dir: &str,
+    snapshot: &ParticipationSnapshot,
+    validators: Option<&ValidatorInventory>,
+) -> Result<()> {
+    write_partition(dir, "groups", snapshot.epoch, group_batch(snapshot)?)?;
+    if let Some(inventory) = validators {
+        write_partition(
+            dir,
+            "validators",
+            snapshot.epoch,
+            validator_batch(inventory)?,
+        )?;
+    }
+    Ok(())
+}
+
+/// One row per group and one for the total over all validators, with `is_total` set
+fn group_batch(snapshot: &ParticipationSnapshot) -> Result<RecordBatch> {
+    let groups: Vec<(&GroupParticipation, bool)> = snapshot
+        .groups
+        .iter()
+        .map(|group| (group, false))
+        .chain([(&snapshot.total, true)])
+        .collect();
+    let u64s = |value: fn(&GroupParticipation) -> u64| -> ArrayRef {
+        Arc::new(UInt64Array::from_iter_values(
+            groups.iter().map(|(group, _)| value(group)),
+        ))
+    };
+    let f32s = |value: fn(&GroupParticipation) -> f32| -> ArrayRef {
+        Arc::new(Float32Array::from_iter_values(
+            groups.iter().map(|(group, _)| value(group)),
+        ))
+    };
+    let strings = |value: fn(&GroupParticipation) -> &str| -> ArrayRef {
+        Arc::new(StringArray::from_iter_values(
+            groups.iter().map(|(group, _)| value(group)),
+        ))
+    };
+    let columns: Vec<(&str, ArrayRef)> = vec![
+        (
+            "slot",
+            Arc::new(UInt64Array::from(vec![snapshot.slot; groups.len()])),
+        ),
+        (
+            "timestamp",
+            Arc::new(UInt64Array::from(vec![snapshot.timestamp; groups.len()])),
+        ),
+        ("group", strings(|group| &group.name)),
+        (
+            "is_total",
+            Arc::new(BooleanArray::from(
+                groups
+                    .iter()
+                    .map(|(_, is_total)| *is_total)
+                    .collect::<Vec<_>>(),
+            )),
+        ),
+        ("ranges", strings(|group| &group.ranges)),
+        ("validators", u64s(|group| group.validators)),
+        ("source", f32s(|group| group.source)),
+        ("target", f32s(|group| group.target)),
+        ("head", f32s(|group| group.head)),
+        (
+            "inactivity_scores_avg",
+            f32s(|group| group.inactivity_scores_avg),
+        ),
+        ("balance_gwei_sum", u64s(|group| group.balance_gwei_sum)),
+        ("slashed_validators", u64s(|group| group.slashed_validators)),
+    ];
+    record_batch(columns)
+}
+
+/// One row per monitored validator, with its flags of the epoch
+fn validator_batch(inventory: &ValidatorInventory) -> Result<RecordBatch> {
+    let entries = inventory.entries();
+    let flags = |flag: fn(&ValidatorEntry) -> bool| -> ArrayRef {
+        Arc::new(BooleanArray::from(
+            entries.iter().map(flag).collect::<Vec<_>>(),
+        ))
+    };
+    let columns: Vec<(&str, ArrayRef)> = vec![
+        (
+            "index",
+            Arc::new(UInt64Array::from_iter_values(
+                entries.iter().map(|entry| entry.index as u64),
+            )),
+        ),
+        (
+            "group",
+            Arc::new(StringArray::from_iter_values(
+                entries.iter().map(|entry| &entry.group),
+            )),
+        ),
+        ("source", flags(|entry| entry.source)),
+        ("target", flags(|entry| entry.target)),
+        ("head", flags(|entry| entry.head)),
+        (
+            "balance_gwei",
+            Arc::new(UInt64Array::from_iter_values(
+                entries.iter().map(|entry| entry.balance_gwei),
+            )),
+        ),
+        (
+            "status",
+            Arc::new(StringArray::from_iter_values(
+                entries.iter().map(|entry| entry.status),
+            )),
+        ),
+    ];
+    record_batch(columns)
+}
+
+fn record_batch(columns: Vec<(&str, ArrayRef)>) -> Result<RecordBatch> {
+    let schema = Schema::new(
+        columns
+            .iter()
+            .map(|(name, column)| Field::new(*name, column.data_type().clone(), false))
+            .collect::<Vec<_>>(),
+    );
+    let columns = columns.into_iter().map(|(_, column)| column).collect();
+    Ok(RecordBatch::try_new(Arc::new(schema), columns)?)
+}
+
+/// Write `batch` as the partition of `epoch` of `table`, through a file renamed into place so
+/// readers never see a partial file
+fn write_partition(dir: &str, table: &str, epoch: u64, batch: RecordBatch) -> Result<()> {
+    let partition = Path::new(dir).join(table).join(format!("epoch={epoch}"));
+    fs::create_dir_all(&partition).with_context(|| format!("create {}", partition.display()))?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut contents = vec![];
+    let mut writer = ArrowWriter::try_new(&mut contents, batch.schema(), Some(properties))?;
+    writer.write(&batch)?;
+    writer.close()?;
+    write_atomic(&partition.join(PART_FILE).to_string_lossy(), contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssz_state::fixtures::{test_state, TEST_FLAGS};
+    use arrow::array::AsArray;
+    use arrow::datatypes::UInt64Type;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    fn read(path: &Path) -> RecordBatch {
+        let file = fs::File::open(path).unwrap();
+        let mut reader = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap();
+        reader.next().unwrap().unwrap()
+    }
+
+    #[test]
+    fn write_parquet_epoch_test() {
+        let dir = std::env::temp_dir().join(format!("parquet-sink-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let group = |name: &str, target: f32| GroupParticipation {
+            name: name.to_owned(),
+            ranges: "0..2".to_owned(),
+            validators: 2,
+            source: target,
+            target,
+            head: target,
+            inactivity_scores_avg: 0.0,
+            balance_gwei_sum: 64_000_000_000,
+            slashed_validators: 0,
+        };
+        let snapshot = ParticipationSnapshot {
+            slot: 64,
+            epoch: 1,
+            timestamp: 100,
+            groups: vec![group("a", 0.5), group("b", 1.0)],
+            total: group("__total__", 0.75),
+        };
+        let ranges = vec![("a".to_owned(), 0..2), ("b".to_owned(), 2..4)];
+        let inventory =
+            ValidatorInventory::new(&TEST_FLAGS, &ranges, &test_state(vec![7, 0, 7, 7]), 2);
+
+        let dir_str = dir.to_str().unwrap();
+        write_parquet_epoch(dir_str, &snapshot, None).unwrap();
+        assert!(!dir.join("validators").exists());
+        write_parquet_epoch(dir_str, &snapshot, Some(&inventory)).unwrap();
+
+        let groups = read(&dir.join("groups/epoch=1").join(PART_FILE));
+        assert_eq!(groups.num_rows(), 3);
+        let names = groups.column_by_name("group").unwrap().as_string::<i32>();
+        assert_eq!(
+            names.iter().flatten().collect::<Vec<_>>(),
+            ["a", "b", "__total__"]
+        );
+        let is_total = groups.column_by_name("is_total").unwrap().as_boolean();
+        assert_eq!(is_total.true_count(), 1);
+        assert!(is_total.value(2));
+
+        let validators = read(&dir.join("validators/epoch=1").join(PART_FILE));
+        assert_eq!(validators.num_rows(), 4);
+        let target = validators.column_by_name("target").unwrap().as_boolean();
+        assert_eq!(
+            target.iter().flatten().collect::<Vec<_>>(),
+            [true, false, true, true]
+        );
+        let index = validators.column_by_name("index").unwrap();
+        assert_eq!(index.as_primitive::<UInt64Type>().value(3), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,322 @@
+use crate::config::{ConfigSpec, ParticipationFlags};
+use crate::http_client::beacon_client;
+use crate::json_state::{parse_json_state, JSON_CONTENT_TYPE};
+use crate::ranges::{
+    format_ranges, group_ranges_by_name, group_size, uncovered_ranges, IndexRanges,
+};
+use crate::ssz_state::{
+    Fork, StateDecoder, StatePartial, Validator, ValidatorStatus, CONSENSUS_VERSION_HEADER,
+};
+use anyhow::{anyhow, Result};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+#[derive(Default)]
+/// Participation and validator status summary of a group
+pub struct RangeSummary {
+    pub target_participation_ratio: f32,
+    pub head_participation_ratio: f32,
+    pub source_participation_ratio: f32,
+    pub inactivity_scores_avg: f32,
+    pub balance_gwei_sum: u64,
+    pub balance_gwei_avg: f64,
+    pub slashed_validators: u64,
+    pub exiting_validators: u64,
+    pub exited_validators: u64,
+    pub withdrawable_validators: u64,
+    /// Count per status, in the order of `ValidatorStatus::ALL`
+    pub validators_by_status: [u64; ValidatorStatus::ALL.len()],
+    /// Count of validators with 0, 1, 2 and all 3 of the timely source, target and head flags
+    pub validators_by_flag_count: [u64; 4],
+}
+/// Summary of each group, with the group's name and ranges
+pub type ParticipationByRange = Vec<(String, Vec<Range<usize>>, RangeSummary)>;
+
+/// Group name of the summary over all validators of the state
+pub const TOTAL_GROUP: &str = "__total__";
+/// Group name of the summary over the validators of the state in no range
+pub const OTHER_GROUP: &str = "__other__";
+
+/// Participation of the latest fetched state, as served by `/api/v1/participation`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParticipationSnapshot {
+    pub slot: u64,
+    /// Epoch the participation is of, the one before the state's
+    pub epoch: u64,
+    /// Unix time the state was exported
+    pub timestamp: u64,
+    pub groups: Vec<GroupParticipation>,
+    /// Over all validators of the state
+    pub total: GroupParticipation,
+}
+
+/// Participation of one group in a [`ParticipationSnapshot`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GroupParticipation {
+    pub name: String,
+    pub ranges: String,
+    pub validators: u64,
+    pub source: f32,
+    pub target: f32,
+    pub head: f32,
+    pub inactivity_scores_avg: f32,
+    pub balance_gwei_sum: u64,
+    pub slashed_validators: u64,
+}
+
+impl GroupParticipation {
+    pub fn new(name: &str, ranges: &[Range<usize>], summary: &RangeSummary) -> Self {
+        Self {
+            name: name.to_owned(),
+            ranges: format_ranges(ranges),
+            validators: summary.validators_by_status.iter().sum(),
+            source: summary.source_participation_ratio,
+            target: summary.target_participation_ratio,
+            head: summary.head_participation_ratio,
+            inactivity_scores_avg: summary.inactivity_scores_avg,
+            balance_gwei_sum: summary.balance_gwei_sum,
+            slashed_validators: summary.slashed_validators,
+        }
+    }
+}
+
+impl RangeSummary {
+    /// Target participation reached `threshold`, 1.0 if every validator of the group attested
+    /// the correct target timely
+    pub fn is_perfect(&self, threshold: f32) -> bool {
+        self.target_participation_ratio >= threshold
+    }
+}
+
+/// Whether all groups are perfect, false without groups
+pub fn all_perfect(participation_by_range: &ParticipationByRange, threshold: f32) -> bool {
+    !participation_by_range.is_empty()
+        && participation_by_range
+            .iter()
+            .all(|(_, _, summary)| summary.is_perfect(threshold))
+}
+
+/// Fetch and partially decode the state `state_id` as SSZ, decoding the body as it streams in
+/// and closing the connection once the decoded fields were received. With `compression` the
+/// node may send the state gzip compressed, which the client decompresses as it streams in.
+/// Nodes rejecting SSZ with 406 or 415, or answering with JSON, have the JSON state decoded
+/// instead.
+pub async fn fetch_state(
+    config: &ConfigSpec,
+    beacon_url: &str,
+    state_id: &str,
+    compression: bool,
+) -> Result<StatePartial> {
+    let state_url = format!("{beacon_url}/eth/v2/debug/beacon/states/{state_id}");
+    let request = |accept: &str| -> RequestBuilder {
+        let mut request = beacon_client()
+            .get(&state_url)
+            .header(reqwest::header::ACCEPT, accept);
+        // The client offers gzip by itself
+        if !compression {
+            request = request.header(reqwest::header::ACCEPT_ENCODING, "identity");
+        }
+        request
+    };
+    let response = request("application/octet-stream").send().await?;
+    if matches!(
+        response.status(),
+        StatusCode::NOT_ACCEPTABLE | StatusCode::UNSUPPORTED_MEDIA_TYPE
+    ) {
+        let response = request(JSON_CONTENT_TYPE)
+            .send()
+            .await?
+            .error_for_status()?;
+        return decode_json_state(response).await;
+    }
+    let mut response = response.error_for_status()?;
+    let json = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with(JSON_CONTENT_TYPE));
+    if json {
+        return decode_json_state(response).await;
+    }
+    // Select the SSZ layout from the fork the node says the state belongs to
+    let fork = match response.headers().get(CONSENSUS_VERSION_HEADER) {
+        Some(version) => Some(version.to_str()?.parse::<Fork>()?),
+        None => None,
+    };
+    let mut decoder = StateDecoder::new(config, fork);
+    while let Some(chunk) = response.chunk().await? {
+        decoder.push(&chunk)?;
+        if decoder.is_complete() {
+            break;
+        }
+    }
+    decoder.finish()
+}
+
+/// Receive the whole JSON state and decode it on a blocking thread
+async fn decode_json_state(response: Response) -> Result<StatePartial> {
+    let body = response.bytes().await?;
+    tokio::task::spawn_blocking(move || parse_json_state(&body)).await?
+}
+
+/// Validate a state id of the debug state API: `head`, `finalized`, `justified` or a slot
+pub fn parse_state_id(input: &str) -> Result<String> {
+    match input {
+        "head" | "finalized" | "justified" => Ok(input.to_owned()),
+        slot => match slot.parse::<u64>() {
+            Ok(_) => Ok(slot.to_owned()),
+            Err(_) => Err(anyhow!(
+                "Invalid state id, expected head, finalized, justified or a slot: {}",
+                input
+            )),
+        },
+    }
+}
+
+/// Whether participation `flag` has all bits of `mask` set
+pub fn has_flag(flag: u8, mask: u8) -> bool {
+    flag & mask == mask
+}
+
+/// Share of the validators in `ranges` with all bits of `flag_mask` set in `participation`
+pub fn participation_avg(participation: &[u8], ranges: &[Range<usize>], flag_mask: u8) -> f32 {
+    let participant_count: u32 = ranges
+        .iter()
+        .flat_map(|range| participation[range.clone()].iter())
+        .map(|f| has_flag(*f, flag_mask) as u32)
+        .sum();
+    participant_count as f32 / group_size(ranges) as f32
+}
+
+/// Count the validators in `ranges` by how many of the timely flags they have set
+pub fn count_by_flags(
+    participation: &[u8],
+    ranges: &[Range<usize>],
+    flags: &ParticipationFlags,
+) -> [u64; 4] {
+    let mut counts = [0; 4];
+    for flag in ranges
+        .iter()
+        .flat_map(|range| participation[range.clone()].iter())
+    {
+        let set = [flags.source, flags.target, flags.head]
+            .into_iter()
+            .filter(|mask| has_flag(*flag, *mask))
+            .count();
+        counts[set] += 1;
+    }
+    counts
+}
+
+fn sum_u64(values: &[u64], ranges: &[Range<usize>]) -> u64 {
+    ranges
+        .iter()
+        .map(|range| values[range.clone()].iter().sum::<u64>())
+        .sum()
+}
+
+fn count_validators(
+    validators: &[Validator],
+    ranges: &[Range<usize>],
+    predicate: impl Fn(&Validator) -> bool,
+) -> u64 {
+    ranges
+        .iter()
+        .map(|range| {
+            validators[range.clone()]
+                .iter()
+                .filter(|v| predicate(v))
+                .count() as u64
+        })
+        .sum()
+}
+
+/// Summarize participation per group. Ranges with the same name are aggregated into one group.
+/// `epoch` is the epoch of `state`, against which exit status is evaluated.
+pub fn group_target_participation(
+    flags: &ParticipationFlags,
+    ranges: &IndexRanges,
+    state: &StatePartial,
+    epoch: u64,
+) -> ParticipationByRange {
+    group_ranges_by_name(ranges)
+        .into_iter()
+        .map(|(range_name, group_ranges)| {
+            let size = group_size(&group_ranges);
+            let balance_gwei_sum = sum_u64(&state.balances, &group_ranges);
+            let summary = RangeSummary {
+                target_participation_ratio: participation_avg(
+                    &state.previous_epoch_participation,
+                    &group_ranges,
+                    flags.target,
+                ),
+                source_participation_ratio: participation_avg(
+                    &state.previous_epoch_participation,
+                    &group_ranges,
+                    flags.source,
+                ),
+                head_participation_ratio: participation_avg(
+                    &state.previous_epoch_participation,
+                    &group_ranges,
+                    flags.head,
+                ),
+                inactivity_scores_avg: sum_u64(&state.inactivity_scores, &group_ranges) as f32
+                    / size as f32,
+                balance_gwei_sum,
+                balance_gwei_avg: balance_gwei_sum as f64 / size as f64,
+                slashed_validators: count_validators(&state.validators, &group_ranges, |v| {
+                    v.slashed
+                }),
+                exiting_validators: count_validators(&state.validators, &group_ranges, |v| {
+                    v.is_exiting(epoch)
+                }),
+                exited_validators: count_validators(&state.validators, &group_ranges, |v| {
+                    v.is_exited(epoch)
+                }),
+                withdrawable_validators: count_validators(&state.validators, &group_ranges, |v| {
+                    v.is_withdrawable(epoch)
+                }),
+                validators_by_status: ValidatorStatus::ALL.map(|status| {
+                    count_validators(&state.validators, &group_ranges, |v| {
+                        v.status(epoch) == status
+                    })
+                }),
+                validators_by_flag_count: count_by_flags(
+                    &state.previous_epoch_participation,
+                    &group_ranges,
+                    flags,
+                ),
+            };
+            (range_name, group_ranges, summary)
+        })
+        .collect()
+}
+
+/// Summary of every validator in `state`, whether in a range or not, as group `__total__`
+pub fn total_participation(
+    flags: &ParticipationFlags,
+    state: &StatePartial,
+    epoch: u64,
+) -> (String, Vec<Range<usize>>, RangeSummary) {
+    let ranges = vec![(TOTAL_GROUP.to_owned(), 0..state.validators.len())];
+    group_target_participation(flags, &ranges, state, epoch).remove(0)
+}
+
+/// Summary of the validators in `state` that are in none of `ranges`, as group `__other__`.
+/// `None` if the ranges cover all validators.
+pub fn other_participation(
+    flags: &ParticipationFlags,
+    ranges: &IndexRanges,
+    state: &StatePartial,
+    epoch: u64,
+) -> Option<(String, Vec<Range<usize>>, RangeSummary)> {
+    let other_ranges: IndexRanges = uncovered_ranges(ranges, state.validators.len())
+        .into_iter()
+        .map(|range| (OTHER_GROUP.to_owned(), range))
+        .collect();
+    if other_ranges.is_empty() {
+        return None;
+    }
+    group_target_participation(flags, &other_ranges, state, epoch).pop()
+}
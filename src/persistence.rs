@@ -0,0 +1,174 @@
+use crate::history::ParticipationHistory;
+use crate::miss_streaks::MissStreaks;
+use crate::participation::ParticipationSnapshot;
+use crate::report::RunReport;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// In-memory history of a collection kept in `--history-file` across restarts: the run report,
+/// the miss streaks of `/api/v1/worst`, the epochs of `/api/v1/history` and the configuration
+#[derive(Debug, Deserialize)]
+pub struct HistorySnapshot {
+    pub report: RunReport,
+    #[serde(default)]
+    pub miss_streaks: MissStreaks,
+    /// Oldest first
+    #[serde(default)]
+    pub history: Vec<ParticipationSnapshot>,
+    #[serde(default)]
+    pub config: Option<ConfigChange>,
+}
+
+/// Hash of the configuration of a run and when it last changed
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ConfigChange {
+    pub hash: String,
+    /// Unix time
+    pub changed_at: u64,
+}
+
+impl ConfigChange {
+    /// Configuration `hash` started at `now`, changed then unless `previous` has the same hash
+    pub fn since(previous: Option<ConfigChange>, hash: String, now: u64) -> Self {
+        match previous {
+            Some(previous) if previous.hash == hash => previous,
+            _ => ConfigChange {
+                hash,
+                changed_at: now,
+            },
+        }
+    }
+}
+
+/// Borrowed [`HistorySnapshot`], to serialize it without copying the live state
+#[derive(Serialize)]
+struct HistorySnapshotRef<'a> {
+    report: &'a RunReport,
+    miss_streaks: &'a MissStreaks,
+    history: Vec<&'a ParticipationSnapshot>,
+    config: &'a ConfigChange,
+}
+
+/// Serialize the history of a collection as read by [`parse_history_file`]
+pub fn render_history(
+    report: &RunReport,
+    miss_streaks: &MissStreaks,
+    history: &ParticipationHistory,
+    config: &ConfigChange,
+) -> String {
+    serde_json::to_string(&HistorySnapshotRef {
+        report,
+        miss_streaks,
+        history: history.snapshots().collect(),
+        config,
+    })
+    .unwrap()
+}
+
+/// Parse a history file, or the run report alone that earlier versions wrote
+pub fn parse_history_file(input: &str) -> Result<HistorySnapshot> {
+    match serde_json::from_str(input) {
+        Ok(snapshot) => Ok(snapshot),
+        Err(e) => match RunReport::parse_json(input) {
+            Ok(report) => Ok(HistorySnapshot {
+                report,
+                miss_streaks: MissStreaks::default(),
+                history: vec![],
+                config: None,
+            }),
+            Err(_) => Err(e.into()),
+        },
+    }
+}
+
+/// Replace the file at `path` with `contents` through a temporary file renamed over it, so a
+/// crash mid-write leaves the previous version whole
+pub fn write_atomic(path: &str, contents: impl AsRef<[u8]>) -> Result<()> {
+    let tmp_path = format!("{path}.tmp");
+    fs::write(&tmp_path, contents).with_context(|| format!("write {tmp_path}"))?;
+    fs::rename(&tmp_path, Path::new(path)).with_context(|| format!("rename {tmp_path}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inventory::ValidatorInventory;
+    use crate::participation::{group_target_participation, GroupParticipation};
+    use crate::ranges::GroupOrder;
+    use crate::ssz_state::fixtures::{test_state, TEST_FLAGS};
+
+    #[test]
+    fn history_file_test() {
+        let state = test_state(vec![7, 0, 7, 7]);
+        let ranges = vec![("a".to_owned(), 0..2), ("b".to_owned(), 2..4)];
+
+        let mut report = RunReport::new(&ranges, 0.9, 1.0, GroupOrder::Config);
+        let participation = group_target_participation(&TEST_FLAGS, &ranges, &state, 2);
+        report.record_participation(1, &participation);
+        let mut miss_streaks = MissStreaks::default();
+        miss_streaks.update(&ValidatorInventory::new(&TEST_FLAGS, &ranges, &state, 2));
+        let mut history = ParticipationHistory::new(4);
+        let group = |name: &str| {
+            GroupParticipation::new(
+                name,
+                std::slice::from_ref(&ranges[0].1),
+                &participation[0].2,
+            )
+        };
+        history.push(ParticipationSnapshot {
+            slot: 64,
+            epoch: 1,
+            timestamp: 100,
+            groups: vec![group("a"), group("b")],
+            total: group("TOTAL"),
+        });
+
+        let config = ConfigChange {
+            hash: "0123456789abcdef".to_owned(),
+            changed_at: 1_700_000_000,
+        };
+        let json = render_history(&report, &miss_streaks, &history, &config);
+        let restored = parse_history_file(&json).unwrap();
+        assert_eq!(restored.config.as_ref(), Some(&config));
+        // The change time is kept until the hash changes
+        let same = ConfigChange::since(restored.config, config.hash.clone(), 1_800_000_000);
+        assert_eq!(same, config);
+        let changed = ConfigChange::since(Some(same), "fedcba9876543210".to_owned(), 1_800_000_000);
+        assert_eq!(changed.changed_at, 1_800_000_000);
+        assert_eq!(
+            restored.history,
+            history.snapshots().cloned().collect::<Vec<_>>()
+        );
+        let worst = restored.miss_streaks.worst(10);
+        assert_eq!(worst.len(), 1);
+        assert_eq!((worst[0].index, worst[0].epochs), (1, 1));
+        // The restored report ignores the epoch it already recorded
+        let mut restored_report = RunReport::new(&ranges, 0.9, 1.0, GroupOrder::Config);
+        restored_report.restore(restored.report);
+        assert!(!restored_report.record_participation(1, &participation));
+        assert!(restored_report.record_participation(2, &participation));
+
+        // History files of earlier versions hold the report only
+        let legacy = parse_history_file(&report.render_json()).unwrap();
+        assert!(legacy.history.is_empty());
+        assert!(legacy.miss_streaks.worst(10).is_empty());
+        assert!(legacy.config.is_none());
+        assert!(parse_history_file("{}").is_err());
+    }
+
+    #[test]
+    fn write_atomic_test() {
+        let dir = std::env::temp_dir().join(format!("write-atomic-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history.json");
+        let path = path.to_str().unwrap();
+        write_atomic(path, "first").unwrap();
+        write_atomic(path, "second").unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), "second");
+        assert!(!Path::new(&format!("{path}.tmp")).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,242 @@
+use crate::duties::ProposerDuty;
+use crate::graffiti::decode_graffiti;
+use crate::http_client::beacon_client;
+use crate::inclusion::parse_hex;
+use crate::ranges::{count_by_group, group_ranges_by_name, IndexRanges};
+use crate::ssz_state::StatePartial;
+use crate::withdrawal::{parse_address, Address};
+use anyhow::Result;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Group of missed slots whose proposer has not been seen proposing yet
+pub const UNKNOWN_GROUP: &str = "unknown";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ProposalCounts {
+    pub proposed: u64,
+    pub missed: u64,
+}
+
+/// Count the proposed and missed blocks of each group among `duties`. Duties of ungrouped
+/// validators or of slots not covered by the state's block_roots are skipped.
+pub fn count_proposals(
+    ranges: &IndexRanges,
+    duties: &[ProposerDuty],
+    state: &StatePartial,
+) -> Vec<(String, ProposalCounts)> {
+    group_ranges_by_name(ranges)
+        .into_iter()
+        .map(|(range_name, group_ranges)| {
+            let mut counts = ProposalCounts::default();
+            for duty in duties.iter().filter(|duty| {
+                group_ranges
+                    .iter()
+                    .any(|range| range.contains(&duty.validator_index))
+            }) {
+                match state.block_proposed_at(duty.slot) {
+                    Some(true) => counts.proposed += 1,
+                    Some(false) => counts.missed += 1,
+                    None => {}
+                }
+            }
+            (range_name, counts)
+        })
+        .collect()
+}
+
+/// Proposer and the operator tags of a block
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockOrigin {
+    pub proposer_index: usize,
+    pub graffiti: String,
+    /// Fee recipient of the execution payload, since bellatrix
+    pub fee_recipient: Option<Address>,
+}
+
+#[derive(Deserialize)]
+struct BlockResponse {
+    data: BlockData,
+}
+
+#[derive(Deserialize)]
+struct BlockData {
+    message: BlockMessage,
+}
+
+#[derive(Deserialize)]
+struct BlockMessage {
+    proposer_index: String,
+    body: BlockBody,
+}
+
+#[derive(Deserialize)]
+struct BlockBody {
+    graffiti: String,
+    execution_payload: Option<ExecutionPayload>,
+}
+
+#[derive(Deserialize)]
+struct ExecutionPayload {
+    fee_recipient: String,
+}
+
+/// Fetch the proposer, graffiti and fee recipient of the block at `slot`. Returns `None` for
+/// empty slots.
+pub async fn fetch_block_origin(url: &str, slot: u64) -> Result<Option<BlockOrigin>> {
+    let response = beacon_client()
+        .get(format!("{url}/eth/v2/beacon/blocks/{slot}"))
+        .send()
+        .await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let block: BlockResponse = response.error_for_status()?.json().await?;
+    let message = block.data.message;
+    Ok(Some(BlockOrigin {
+        proposer_index: message.proposer_index.parse()?,
+        graffiti: decode_graffiti(&parse_hex(&message.body.graffiti)?),
+        fee_recipient: message
+            .body
+            .execution_payload
+            .map(|payload| parse_address(&payload.fee_recipient))
+            .transpose()?,
+    }))
+}
+
+/// Proposal outcomes per group of blocks, e.g. by graffiti or fee recipient. Missed slots have
+/// no block, so they count towards the group of their proposer's last seen block, or `unknown`.
+#[derive(Default)]
+pub struct ProposerGroups {
+    group_of_proposer: HashMap<usize, String>,
+}
+
+impl ProposerGroups {
+    /// Count the outcomes of `duties` per group. `blocks` holds the proposer and group of the
+    /// blocks of proposed slots, proposed slots missing from it and duties of slots not covered
+    /// by the state are skipped.
+    pub fn count(
+        &mut self,
+        duties: &[ProposerDuty],
+        state: &StatePartial,
+        blocks: &HashMap<u64, (usize, String)>,
+    ) -> BTreeMap<String, ProposalCounts> {
+        let mut counts: BTreeMap<String, ProposalCounts> = BTreeMap::new();
+        for duty in duties {
+            match (state.block_proposed_at(duty.slot), blocks.get(&duty.slot)) {
+                (Some(true), Some((proposer_index, group))) => {
+                    counts.entry(group.clone()).or_default().proposed += 1;
+                    self.group_of_proposer
+                        .insert(*proposer_index, group.clone());
+                }
+                (Some(false), _) => {
+                    let group = self
+                        .group_of_proposer
+                        .get(&duty.validator_index)
+                        .map_or(UNKNOWN_GROUP, String::as_str);
+                    counts.entry(group.to_owned()).or_default().missed += 1;
+                }
+                _ => {}
+            }
+        }
+        counts
+    }
+}
+
+/// Count the proposals scheduled for each group's validators among `duties`
+pub fn count_scheduled_proposals(
+    ranges: &IndexRanges,
+    duties: &[ProposerDuty],
+) -> Vec<(String, u64)> {
+    count_by_group(ranges, duties.iter().map(|duty| duty.validator_index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssz_state::fixtures::test_state;
+
+    #[test]
+    fn count_proposals_test() {
+        // Slot 2 repeats the root of slot 1, so it is empty
+        let state = StatePartial {
+            slot: 4,
+            block_roots: vec![[0; 32], [1; 32], [1; 32], [3; 32]],
+            ..test_state(vec![])
+        };
+        let duties: Vec<ProposerDuty> = [(1, 5), (2, 6), (3, 20), (4, 5)]
+            .into_iter()
+            .map(|(slot, validator_index)| ProposerDuty {
+                slot,
+                validator_index,
+            })
+            .collect();
+        let ranges = vec![("a".to_owned(), 0..10), ("b".to_owned(), 10..20)];
+        assert_eq!(
+            count_proposals(&ranges, &duties, &state),
+            vec![
+                (
+                    "a".to_owned(),
+                    ProposalCounts {
+                        proposed: 1,
+                        missed: 1
+                    }
+                ),
+                ("b".to_owned(), ProposalCounts::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn proposer_groups_count() {
+        // Slots 2 and 4 are empty
+        let state = StatePartial {
+            slot: 5,
+            block_roots: vec![
+                [0; 32], [1; 32], [1; 32], [3; 32], [3; 32], [5; 32], [6; 32],
+            ],
+            ..test_state(vec![])
+        };
+        let duties: Vec<ProposerDuty> = [(1, 10), (2, 30), (3, 20), (4, 10)]
+            .into_iter()
+            .map(|(slot, validator_index)| ProposerDuty {
+                slot,
+                validator_index,
+            })
+            .collect();
+        let blocks = HashMap::from([(1, (10, "a".to_owned())), (3, (20, "b".to_owned()))]);
+        let counts = |proposed, missed| ProposalCounts { proposed, missed };
+        assert_eq!(
+            ProposerGroups::default()
+                .count(&duties, &state, &blocks)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![
+                ("a".to_owned(), counts(1, 1)),
+                ("b".to_owned(), counts(1, 0)),
+                ("unknown".to_owned(), counts(0, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn count_scheduled_proposals_test() {
+        let duties: Vec<ProposerDuty> = [(1, 5), (2, 6), (3, 20), (4, 5)]
+            .into_iter()
+            .map(|(slot, validator_index)| ProposerDuty {
+                slot,
+                validator_index,
+            })
+            .collect();
+        let ranges = vec![
+            ("a".to_owned(), 0..6),
+            ("b".to_owned(), 10..20),
+            ("a".to_owned(), 6..7),
+        ];
+        assert_eq!(
+            count_scheduled_proposals(&ranges, &duties),
+            vec![("a".to_owned(), 3), ("b".to_owned(), 0)]
+        );
+    }
+}
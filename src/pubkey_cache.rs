@@ -0,0 +1,73 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+
+/// Validator indices by lowercase 0x prefixed pubkey, persisted as a JSON object. An index never
+/// changes once assigned, so entries never expire.
+#[derive(Debug, Default)]
+pub struct PubkeyCache {
+    path: Option<String>,
+    indices: HashMap<String, usize>,
+    dirty: bool,
+}
+
+impl PubkeyCache {
+    /// Load the cache file at `path`, empty if it does not exist yet. Without a path the cache
+    /// only lives in memory.
+    pub fn load(path: Option<&str>) -> Result<Self> {
+        let indices = match path {
+            Some(path) => match std::fs::read_to_string(path) {
+                Ok(contents) => serde_json::from_str(&contents)
+                    .with_context(|| format!("parse pubkey cache {path}"))?,
+                Err(e) if e.kind() == ErrorKind::NotFound => HashMap::new(),
+                Err(e) => return Err(e).with_context(|| format!("read pubkey cache {path}")),
+            },
+            None => HashMap::new(),
+        };
+        Ok(Self {
+            path: path.map(str::to_owned),
+            indices,
+            dirty: false,
+        })
+    }
+
+    pub fn get(&self, pubkey: &str) -> Option<usize> {
+        self.indices.get(pubkey).copied()
+    }
+
+    pub fn insert(&mut self, pubkey: String, index: usize) {
+        if self.indices.insert(pubkey, index) != Some(index) {
+            self.dirty = true;
+        }
+    }
+
+    /// Write the cache file if entries were added since loading
+    pub fn save(&mut self) -> Result<()> {
+        if let (Some(path), true) = (&self.path, self.dirty) {
+            std::fs::write(path, serde_json::to_string(&self.indices)?)
+                .with_context(|| format!("write pubkey cache {path}"))?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pubkey_cache_roundtrip() {
+        let path = std::env::temp_dir().join(format!("pubkey-cache-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut cache = PubkeyCache::load(Some(path)).unwrap();
+        assert_eq!(cache.get("0xab"), None);
+        cache.insert("0xab".to_owned(), 7);
+        cache.save().unwrap();
+
+        let cache = PubkeyCache::load(Some(path)).unwrap();
+        assert_eq!(cache.get("0xab"), Some(7));
+        assert_eq!(cache.get("0xcd"), None);
+        std::fs::remove_file(path).unwrap();
+    }
+}
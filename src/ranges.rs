@@ -1,9 +1,69 @@
+use crate::util::resolve_path_or_url;
 use anyhow::{anyhow, Result};
+use clap::{Args, ValueEnum};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, ops::Range};
 
+/// Named validator index ranges, as defined in the ranges file
 pub type IndexRanges = Vec<(String, Range<usize>)>;
-type IndexRangesJson = HashMap<String, String>;
+/// Named range of an `IndexRanges`
+pub type NamedRange = (String, Range<usize>);
+/// Ranges sharing a name form a single, possibly non-contiguous, group
+pub type IndexGroups = Vec<(String, Vec<Range<usize>>)>;
+/// Thresholds of the groups that set any in the ranges file
+pub type ThresholdsByGroup = HashMap<String, GroupThresholds>;
+type IndexRangesJson = HashMap<String, RangeEntryJson>;
+
+/// Value of a JSON ranges file entry, the group name or an object with optional thresholds
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RangeEntryJson {
+    Name(String),
+    Group {
+        name: String,
+        #[serde(flatten)]
+        thresholds: GroupThresholds,
+    },
+}
+
+/// Target participation thresholds of a group
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+pub struct GroupThresholds {
+    pub warn: Option<f32>,
+    pub crit: Option<f32>,
+}
+
+impl GroupThresholds {
+    /// Each set level, with whether `ratio` is below it
+    pub fn breaches(&self, ratio: f32) -> Vec<(&'static str, bool)> {
+        [("warn", self.warn), ("crit", self.crit)]
+            .into_iter()
+            .filter_map(|(level, threshold)| threshold.map(|threshold| (level, ratio < threshold)))
+            .collect()
+    }
+
+    /// Most severe level `ratio` is below, if any
+    pub fn level(&self, ratio: f32) -> Option<&'static str> {
+        self.breaches(ratio)
+            .into_iter()
+            .rev()
+            .find_map(|(level, breached)| breached.then_some(level))
+    }
+
+    /// These thresholds, with unset levels taken from `defaults`
+    pub fn or(self, defaults: GroupThresholds) -> Self {
+        Self {
+            warn: self.warn.or(defaults.warn),
+            crit: self.crit.or(defaults.crit),
+        }
+    }
+
+    /// Number of set levels
+    pub fn count(&self) -> usize {
+        self.warn.is_some() as usize + self.crit.is_some() as usize
+    }
+}
 
 /// Render ranges for CLI dumps
 pub fn dump_ranges(ranges: &IndexRanges) -> String {
@@ -14,13 +74,221 @@ pub fn dump_ranges(ranges: &IndexRanges) -> String {
         .join("\n")
 }
 
-/// Parse group file contents flexibly, either as JSON first or then TXT
-pub fn parse_ranges(input: &str) -> Result<IndexRanges> {
+/// Merge ranges with the same name into one group, in order of first appearance
+pub fn group_ranges_by_name(ranges: &IndexRanges) -> IndexGroups {
+    let mut groups: IndexGroups = vec![];
+    for (name, range) in ranges {
+        match groups.iter_mut().find(|(group_name, _)| group_name == name) {
+            Some((_, group_ranges)) => group_ranges.push(range.clone()),
+            None => groups.push((name.clone(), vec![range.clone()])),
+        }
+    }
+    groups
+}
+
+/// Order of groups in dumps, reports and backfill output
+#[derive(Clone, Copy, Debug, Default, Serialize, ValueEnum)]
+pub enum GroupOrder {
+    /// As first defined in the ranges
+    #[default]
+    Config,
+    /// Alphabetical by group name
+    Name,
+    /// By target participation, lowest first, then by name
+    Participation,
+}
+
+impl GroupOrder {
+    /// Stable sort of `groups`, given the name and target participation of each
+    pub fn sort<T>(
+        self,
+        groups: &mut [T],
+        name: impl Fn(&T) -> &str,
+        participation: impl Fn(&T) -> f32,
+    ) {
+        match self {
+            GroupOrder::Config => {}
+            GroupOrder::Name => groups.sort_by(|a, b| name(a).cmp(name(b))),
+            GroupOrder::Participation => groups.sort_by(|a, b| {
+                participation(a)
+                    .total_cmp(&participation(b))
+                    .then_with(|| name(a).cmp(name(b)))
+            }),
+        }
+    }
+}
+
+/// Count the `indices` falling in each group, repeated indices count each time
+pub fn count_by_group(
+    ranges: &IndexRanges,
+    indices: impl Iterator<Item = usize> + Clone,
+) -> Vec<(String, u64)> {
+    group_ranges_by_name(ranges)
+        .into_iter()
+        .map(|(name, group_ranges)| {
+            let count = indices
+                .clone()
+                .filter(|index| group_ranges.iter().any(|range| range.contains(index)))
+                .count() as u64;
+            (name, count)
+        })
+        .collect()
+}
+
+/// Total count of indices in `ranges`
+pub fn group_size(ranges: &[Range<usize>]) -> usize {
+    ranges.iter().map(|range| range.len()).sum()
+}
+
+/// Render the ranges of a group for CLI dumps, i.e. `0..100,200..300`
+pub fn format_ranges(ranges: &[Range<usize>]) -> String {
+    ranges
+        .iter()
+        .map(|range| format!("{:?}", range))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Name of the first range containing validator `index`
+pub fn range_name_of(ranges: &IndexRanges, index: usize) -> Option<&str> {
+    ranges
+        .iter()
+        .find(|(_, range)| range.contains(&index))
+        .map(|(name, _)| name.as_str())
+}
+
+/// Split the indices `0..validator_count` into `groups` contiguous ranges whose sizes differ by at
+/// most one, named by `template` with `{i}` replaced by the group number from 0. Groups that
+/// would be empty are left out.
+pub fn split_ranges(validator_count: usize, groups: usize, template: &str) -> IndexRanges {
+    let groups = groups.max(1);
+    let (size, larger_groups) = (validator_count / groups, validator_count % groups);
+    let mut start = 0;
+    (0..groups)
+        .map(|i| {
+            let end = start + size + (i < larger_groups) as usize;
+            let range = start..end;
+            start = end;
+            (template.replace("{i}", &i.to_string()), range)
+        })
+        .filter(|(_, range)| !range.is_empty())
+        .collect()
+}
+
+/// Ranges cut to the indices `0..validator_count`, leaving out those past the end, with the ranges
+/// that reached past it
+pub fn clamp_ranges(ranges: &IndexRanges, validator_count: usize) -> (IndexRanges, IndexRanges) {
+    let mut out_of_bounds = IndexRanges::new();
+    let clamped = ranges
+        .iter()
+        .filter_map(|(name, range)| {
+            if range.end > validator_count {
+                out_of_bounds.push((name.clone(), range.clone()));
+            }
+            let clamped = range.start..range.end.min(validator_count);
+            (!clamped.is_empty()).then(|| (name.clone(), clamped))
+        })
+        .collect();
+    (clamped, out_of_bounds)
+}
+
+/// Pairs of ranges sharing indices, each overlapping range paired with the earlier starting range
+/// reaching furthest before it
+pub fn overlapping_ranges(ranges: &IndexRanges) -> Vec<(&NamedRange, &NamedRange)> {
+    let mut sorted: Vec<&NamedRange> = ranges.iter().collect();
+    sorted.sort_by_key(|(_, range)| range.start);
+    let mut overlaps = vec![];
+    let mut furthest: Option<&NamedRange> = None;
+    for entry in sorted {
+        if entry.1.is_empty() {
+            continue;
+        }
+        match furthest {
+            Some(previous) if entry.1.start < previous.1.end => {
+                overlaps.push((previous, entry));
+                if entry.1.end > previous.1.end {
+                    furthest = Some(entry);
+                }
+            }
+            _ => furthest = Some(entry),
+        }
+    }
+    overlaps
+}
+
+/// Ranges of the indices `0..validator_count` that are in none of `ranges`, in order
+pub fn uncovered_ranges(ranges: &IndexRanges, validator_count: usize) -> Vec<Range<usize>> {
+    let mut covered: Vec<&Range<usize>> = ranges.iter().map(|(_, range)| range).collect();
+    covered.sort_by_key(|range| range.start);
+    let mut uncovered = vec![];
+    let mut next = 0;
+    for range in covered {
+        if range.start > next {
+            uncovered.push(next..range.start.min(validator_count));
+        }
+        next = next.max(range.end);
+        if next >= validator_count {
+            break;
+        }
+    }
+    if next < validator_count {
+        uncovered.push(next..validator_count);
+    }
+    uncovered.retain(|range| !range.is_empty());
+    uncovered
+}
+
+/// Index ranges options shared by the exporter and the subcommands
+#[derive(Args, Clone, Debug, Default, Serialize)]
+pub struct RangesArgs {
+    /// Index ranges to group IDs as JSON or TXT. Example:
+    /// `{"0..100": "lh-geth-0", "100..200": "lh-geth-1"}
+    #[arg(long, env = "BMG_RANGES")]
+    pub ranges: Option<String>,
+    /// Local path or URL containing a file with index ranges
+    /// with the format as defined in --ranges
+    #[arg(long, env = "BMG_RANGES_FILE")]
+    pub ranges_file: Option<String>,
+}
+
+impl RangesArgs {
+    /// Load the ranges and their thresholds, see [`load_ranges`]
+    pub async fn load(&self) -> Result<Option<(IndexRanges, ThresholdsByGroup)>> {
+        load_ranges(self.ranges.as_deref(), self.ranges_file.as_deref()).await
+    }
+
+    /// Load the index ranges, for the subcommands that require them
+    pub async fn require(&self) -> Result<IndexRanges> {
+        let (ranges, _) = self
+            .load()
+            .await?
+            .ok_or_else(|| anyhow!("Must set --ranges or --ranges-file"))?;
+        Ok(ranges)
+    }
+}
+
+/// Parse the ranges of `--ranges`, or else of the file at `--ranges-file`, with the groups'
+/// thresholds. `None` if neither is set.
+pub async fn load_ranges(
+    ranges: Option<&str>,
+    ranges_file: Option<&str>,
+) -> Result<Option<(IndexRanges, ThresholdsByGroup)>> {
+    let ranges_str = match (ranges, ranges_file) {
+        (Some(ranges_str), _) => ranges_str.to_owned(),
+        (None, Some(path_or_url)) => resolve_path_or_url(path_or_url).await?,
+        (None, None) => return Ok(None),
+    };
+    parse_ranges(&ranges_str).map(Some)
+}
+
+/// Parse group file contents flexibly, either as JSON first or then TXT. Only JSON can set
+/// thresholds.
+pub fn parse_ranges(input: &str) -> Result<(IndexRanges, ThresholdsByGroup)> {
     if let Ok(groups) = parse_ranges_as_json(input) {
         return Ok(groups);
     }
 
-    parse_ranges_as_txt(input)
+    Ok((parse_ranges_as_txt(input)?, HashMap::new()))
 }
 
 /// Parse a file contents defining group ranges with format:
@@ -48,18 +316,36 @@ fn parse_ranges_as_txt(input: &str) -> Result<IndexRanges> {
 /// ```json
 /// {
 ///   "0..1000": "entityA lighthouse-geth-0",
-///   "1000..2000": "entityB lodestar-nethermind-0",
+///   "1000..2000": {"name": "entityB lodestar-nethermind-0", "warn": 0.95, "crit": 0.8},
 /// }
 /// ```
-fn parse_ranges_as_json(input: &str) -> Result<IndexRanges> {
+/// Thresholds of ranges sharing a name are merged, later ranges in index order win.
+fn parse_ranges_as_json(input: &str) -> Result<(IndexRanges, ThresholdsByGroup)> {
     let data: IndexRangesJson = serde_json::from_str(input)?;
     let mut result = Vec::new();
-    for (range_str, name) in data {
-        result.push((name, parse_range(&range_str)?));
+    for (range_str, entry) in data {
+        let (name, thresholds) = match entry {
+            RangeEntryJson::Name(name) => (name, GroupThresholds::default()),
+            RangeEntryJson::Group { name, thresholds } => (name, thresholds),
+        };
+        result.push((name, parse_range(&range_str)?, thresholds));
     }
     // serde_json uses HashMap which does not preserve order. Enforce ascending index order
-    result.sort_by_key(|(_, range)| range.start);
-    Ok(result)
+    result.sort_by_key(|(_, range, _)| range.start);
+
+    let mut thresholds_by_group = ThresholdsByGroup::new();
+    for (name, _, thresholds) in &result {
+        if thresholds.count() > 0 {
+            let group: &mut GroupThresholds = thresholds_by_group.entry(name.clone()).or_default();
+            group.warn = thresholds.warn.or(group.warn);
+            group.crit = thresholds.crit.or(group.crit);
+        }
+    }
+    let ranges = result
+        .into_iter()
+        .map(|(name, range, _)| (name, range))
+        .collect();
+    Ok((ranges, thresholds_by_group))
 }
 
 /// Parses a string representing a range with format:
@@ -78,15 +364,117 @@ fn parse_range(input: &str) -> Result<Range<usize>> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn split_ranges_test() {
+        assert_eq!(
+            split_ranges(10, 3, "group-{i}"),
+            vec![
+                ("group-0".to_owned(), 0..4),
+                ("group-1".to_owned(), 4..7),
+                ("group-2".to_owned(), 7..10),
+            ]
+        );
+        assert_eq!(
+            split_ranges(2, 3, "{i}"),
+            vec![("0".to_owned(), 0..1), ("1".to_owned(), 1..2)]
+        );
+    }
+
+    #[test]
+    fn clamp_ranges_test() {
+        let ranges = vec![
+            ("a".to_owned(), 0..10),
+            ("b".to_owned(), 10..30),
+            ("c".to_owned(), 30..40),
+        ];
+        assert_eq!(
+            clamp_ranges(&ranges, 20),
+            (
+                vec![("a".to_owned(), 0..10), ("b".to_owned(), 10..20)],
+                vec![("b".to_owned(), 10..30), ("c".to_owned(), 30..40)]
+            )
+        );
+        assert_eq!(clamp_ranges(&ranges, 40), (ranges, vec![]));
+    }
+
+    #[test]
+    fn overlapping_ranges_test() {
+        let ranges = vec![
+            ("a".to_owned(), 0..10),
+            ("b".to_owned(), 10..20),
+            ("c".to_owned(), 5..12),
+            ("a".to_owned(), 18..19),
+            ("d".to_owned(), 20..30),
+        ];
+        let names: Vec<(&str, &str)> = overlapping_ranges(&ranges)
+            .into_iter()
+            .map(|(a, b)| (a.0.as_str(), b.0.as_str()))
+            .collect();
+        assert_eq!(names, vec![("a", "c"), ("c", "b"), ("b", "a")]);
+        assert!(
+            overlapping_ranges(&vec![("a".to_owned(), 0..10), ("b".to_owned(), 10..20)]).is_empty()
+        );
+    }
+
+    #[test]
+    fn uncovered_ranges_test() {
+        let ranges = vec![
+            ("b".to_owned(), 10..20),
+            ("a".to_owned(), 2..5),
+            ("a".to_owned(), 4..8),
+            ("c".to_owned(), 30..40),
+        ];
+        assert_eq!(
+            uncovered_ranges(&ranges, 50),
+            vec![0..2, 8..10, 20..30, 40..50]
+        );
+        // Ranges past the end of the validator set
+        assert_eq!(uncovered_ranges(&ranges, 25), vec![0..2, 8..10, 20..25]);
+        assert_eq!(uncovered_ranges(&ranges, 1), vec![0..1]);
+        assert!(uncovered_ranges(&vec![("a".to_owned(), 0..10)], 10).is_empty());
+    }
+
+    #[test]
+    fn group_order_sort() {
+        let groups = vec![("b", 0.5), ("c", 0.9), ("a", 0.9)];
+        let sorted = |order: GroupOrder| {
+            let mut groups = groups.clone();
+            order.sort(&mut groups, |g| g.0, |g| g.1);
+            groups.iter().map(|g| g.0).collect::<Vec<_>>()
+        };
+        assert_eq!(sorted(GroupOrder::Config), vec!["b", "c", "a"]);
+        assert_eq!(sorted(GroupOrder::Name), vec!["a", "b", "c"]);
+        assert_eq!(sorted(GroupOrder::Participation), vec!["b", "a", "c"]);
+    }
+
     #[test]
     fn parse_range_test() {
         let inputs = ["0-10", "0..10", "[0..10]", "[0-10]", "(0..10)", "[0-10)"];
 
         for input in inputs {
-            assert_eq!(parse_range(&input).unwrap(), 0..10);
+            assert_eq!(parse_range(input).unwrap(), 0..10);
         }
     }
 
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn group_ranges_by_name_test() {
+        let groups = group_ranges_by_name(&vec![
+            ("a".to_owned(), 0..10),
+            ("b".to_owned(), 10..20),
+            ("a".to_owned(), 20..25),
+        ]);
+        assert_eq!(
+            groups,
+            vec![
+                ("a".to_owned(), vec![0..10, 20..25]),
+                ("b".to_owned(), vec![10..20]),
+            ]
+        );
+        assert_eq!(group_size(&groups[0].1), 15);
+        assert_eq!(format_ranges(&groups[0].1), "0..10,20..25");
+    }
+
     #[test]
     fn parse_ranges_file_txt_test() {
         assert_eq!(
@@ -97,7 +485,8 @@ mod tests {
 
 ",
             )
-            .unwrap(),
+            .unwrap()
+            .0,
             vec![
                 ("entityA lighthouse-geth".to_owned(), 0..100),
                 ("entityB lodestar-nethermind-1".to_owned(), 100..200),
@@ -111,7 +500,8 @@ mod tests {
             parse_ranges(
                 "{\"0..100\": \"entityA lighthouse-geth\", \"100..200\": \"entityB lodestar-nethermind-1\"}"
             )
-            .unwrap(),
+            .unwrap()
+            .0,
             vec![
                 ("entityA lighthouse-geth".to_owned(), 0..100),
                 ("entityB lodestar-nethermind-1".to_owned(), 100..200),
@@ -119,6 +509,47 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_ranges_file_json_thresholds_test() {
+        let (ranges, thresholds) = parse_ranges(
+            r#"{
+                "0..100": {"name": "lh-geth-0", "warn": 0.95},
+                "100..200": "lh-geth-1",
+                "200..300": {"name": "lh-geth-0", "crit": 0.8}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            ranges,
+            vec![
+                ("lh-geth-0".to_owned(), 0..100),
+                ("lh-geth-1".to_owned(), 100..200),
+                ("lh-geth-0".to_owned(), 200..300),
+            ]
+        );
+        let lh_geth_0 = GroupThresholds {
+            warn: Some(0.95),
+            crit: Some(0.8),
+        };
+        assert_eq!(
+            thresholds,
+            ThresholdsByGroup::from([("lh-geth-0".to_owned(), lh_geth_0)])
+        );
+        assert_eq!(
+            lh_geth_0.breaches(0.9),
+            vec![("warn", true), ("crit", false)]
+        );
+        assert_eq!(lh_geth_0.level(0.96), None);
+        assert_eq!(lh_geth_0.level(0.9), Some("warn"));
+        assert_eq!(lh_geth_0.level(0.5), Some("crit"));
+        let crit_only = GroupThresholds {
+            warn: None,
+            crit: Some(0.5),
+        };
+        assert_eq!(crit_only.or(lh_geth_0).warn, Some(0.95));
+        assert_eq!(crit_only.or(lh_geth_0).level(0.6), Some("warn"));
+    }
+
     #[test]
     fn parse_ranges_file_yaml_test() {
         assert_eq!(
@@ -127,7 +558,8 @@ mod tests {
 100..200: entityB lodestar-nethermind-1
 ",
             )
-            .unwrap(),
+            .unwrap()
+            .0,
             vec![
                 ("entityA lighthouse-geth".to_owned(), 0..100),
                 ("entityB lodestar-nethermind-1".to_owned(), 100..200),
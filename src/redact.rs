@@ -0,0 +1,264 @@
+use crate::participation::{ParticipationSnapshot, OTHER_GROUP, TOTAL_GROUP};
+use crate::ranges::ThresholdsByGroup;
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use prometheus::proto::MetricFamily;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::io::ErrorKind;
+
+/// Label holding group names in exported metrics
+const GROUP_LABEL: &str = "range";
+/// Exported for label values that are not a known group, never the original value
+const UNKNOWN_GROUP: &str = "redacted";
+
+/// How `--redact-groups` renames groups in exported metrics
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ValueEnum)]
+pub enum Redaction {
+    /// Sequential `group-N` aliases, kept stable across restarts by the mapping file
+    Alias,
+    /// `group-` followed by a salted hash of the name, stable without a mapping file
+    Hash,
+}
+
+/// Exported name of each group, see `--redact-groups`
+#[derive(Debug)]
+pub struct GroupRedaction {
+    redacted_by_group: HashMap<String, String>,
+}
+
+impl GroupRedaction {
+    /// Redact `groups`, reusing the aliases of `mapping`, keyed by redacted name
+    pub fn new(
+        mode: Redaction,
+        groups: &[String],
+        salt: &str,
+        mapping: &BTreeMap<String, String>,
+    ) -> Result<Self> {
+        let mut redacted_by_group: HashMap<String, String> = mapping
+            .iter()
+            .map(|(redacted, group)| (group.clone(), redacted.clone()))
+            .filter(|(_, redacted)| mode == Redaction::Hash || alias_number(redacted).is_some())
+            .collect();
+        let mut next_alias = mapping
+            .keys()
+            .filter_map(|redacted| alias_number(redacted))
+            .max()
+            .map_or(1, |max| max + 1);
+        for group in groups {
+            let redacted = match mode {
+                Redaction::Alias => {
+                    if redacted_by_group.contains_key(group) {
+                        continue;
+                    }
+                    let alias = format!("group-{next_alias}");
+                    next_alias += 1;
+                    alias
+                }
+                Redaction::Hash => format!("group-{:08x}", fnv1a(salt, group) as u32),
+            };
+            redacted_by_group.insert(group.clone(), redacted);
+        }
+        let redaction = Self { redacted_by_group };
+        if redaction.mapping().len() != redaction.redacted_by_group.len() {
+            return Err(anyhow!(
+                "redacted group names collide, change --redact-salt"
+            ));
+        }
+        Ok(redaction)
+    }
+
+    /// Original name of each redacted name, to save to the mapping file
+    pub fn mapping(&self) -> BTreeMap<String, String> {
+        self.redacted_by_group
+            .iter()
+            .map(|(group, redacted)| (redacted.clone(), group.clone()))
+            .collect()
+    }
+
+    /// Replace group names in the `range` label of `families`
+    pub fn apply(&self, families: &mut [MetricFamily]) {
+        for family in families {
+            for metric in family.mut_metric().iter_mut() {
+                for label in metric.mut_label().iter_mut() {
+                    // The groups outside the ranges reveal no group name
+                    let value = label.get_value();
+                    if label.get_name() == GROUP_LABEL
+                        && value != TOTAL_GROUP
+                        && value != OTHER_GROUP
+                    {
+                        let redacted = self
+                            .redacted_by_group
+                            .get(label.get_value())
+                            .map_or(UNKNOWN_GROUP, String::as_str)
+                            .to_owned();
+                        label.set_value(redacted);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replace group names among the strings and object keys of the JSON `json`, as served by
+    /// the API. Other strings are kept.
+    pub fn redact_json(&self, json: &str) -> String {
+        let mut value: Value = serde_json::from_str(json).unwrap();
+        self.redact_value(&mut value);
+        value.to_string()
+    }
+
+    fn redact_value(&self, value: &mut Value) {
+        match value {
+            Value::String(name) => {
+                if let Some(redacted) = self.redacted_by_group.get(name) {
+                    *name = redacted.clone();
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(|value| self.redact_value(value)),
+            Value::Object(object) => {
+                *object = std::mem::take(object)
+                    .into_iter()
+                    .map(|(key, mut value)| {
+                        self.redact_value(&mut value);
+                        let key = self.redacted_by_group.get(&key).cloned().unwrap_or(key);
+                        (key, value)
+                    })
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+
+    /// Copy of `snapshot` with redacted group names, for the status page
+    pub fn redact_snapshot(&self, snapshot: &ParticipationSnapshot) -> ParticipationSnapshot {
+        let mut redacted = snapshot.clone();
+        for group in &mut redacted.groups {
+            if let Some(name) = self.redacted_by_group.get(&group.name) {
+                group.name = name.clone();
+            }
+        }
+        redacted
+    }
+
+    /// `thresholds` keyed by redacted group names
+    pub fn redact_thresholds(&self, thresholds: &ThresholdsByGroup) -> ThresholdsByGroup {
+        thresholds
+            .iter()
+            .map(|(group, levels)| {
+                let group = self.redacted_by_group.get(group).unwrap_or(group);
+                (group.clone(), *levels)
+            })
+            .collect()
+    }
+
+    /// Group named by the redacted `name` of a `group` query parameter. Real names match no
+    /// group, so the API can't be probed for them.
+    pub fn original(&self, name: &str) -> String {
+        self.redacted_by_group
+            .iter()
+            .find(|(_, redacted)| *redacted == name)
+            .map_or(UNKNOWN_GROUP, |(group, _)| group)
+            .to_owned()
+    }
+}
+
+fn alias_number(redacted: &str) -> Option<u64> {
+    redacted.strip_prefix("group-")?.parse().ok()
+}
+
+/// 64 bit FNV-1a of `salt` then `name`, stable across builds unlike `DefaultHasher`
+pub(crate) fn fnv1a(salt: &str, name: &str) -> u64 {
+    salt.bytes()
+        .chain([0])
+        .chain(name.bytes())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+}
+
+/// Load the redacted to original name mapping at `path`, empty if it does not exist yet
+pub fn load_mapping(path: &str) -> Result<BTreeMap<String, String>> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => {
+            serde_json::from_str(&contents).with_context(|| format!("parse mapping file {path}"))
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e).with_context(|| format!("read mapping file {path}")),
+    }
+}
+
+pub fn save_mapping(path: &str, mapping: &BTreeMap<String, String>) -> Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(mapping)?)
+        .with_context(|| format!("write mapping file {path}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::{GaugeVec, Opts, Registry};
+
+    fn names(groups: &[&str]) -> Vec<String> {
+        groups.iter().map(|group| group.to_string()).collect()
+    }
+
+    #[test]
+    fn alias_redaction() {
+        let mapping = BTreeMap::from([
+            ("group-1".to_owned(), "lh-geth-0".to_owned()),
+            ("group-4".to_owned(), "removed".to_owned()),
+        ]);
+        let redaction = GroupRedaction::new(
+            Redaction::Alias,
+            &names(&["prysm-nethermind-0", "lh-geth-0"]),
+            "",
+            &mapping,
+        )
+        .unwrap();
+        assert_eq!(
+            redaction.mapping(),
+            BTreeMap::from([
+                ("group-1".to_owned(), "lh-geth-0".to_owned()),
+                ("group-4".to_owned(), "removed".to_owned()),
+                ("group-5".to_owned(), "prysm-nethermind-0".to_owned()),
+            ])
+        );
+
+        let gauge = GaugeVec::new(Opts::new("participation", "help"), &["range"]).unwrap();
+        let registry = Registry::new();
+        registry.register(Box::new(gauge.clone())).unwrap();
+        gauge.with_label_values(&["lh-geth-0"]).set(1.0);
+        gauge.with_label_values(&["unknown"]).set(1.0);
+        let mut families = registry.gather();
+        redaction.apply(&mut families);
+        let labels: Vec<&str> = families[0]
+            .get_metric()
+            .iter()
+            .map(|metric| metric.get_label()[0].get_value())
+            .collect();
+        assert_eq!(labels, vec!["group-1", "redacted"]);
+
+        let json = r#"{"lh-geth-0":[{"group":"lh-geth-0","ranges":"0..10"},"unknown",1]}"#;
+        assert_eq!(
+            redaction.redact_json(json),
+            r#"{"group-1":[{"group":"group-1","ranges":"0..10"},"unknown",1]}"#
+        );
+        assert_eq!(redaction.original("group-1"), "lh-geth-0");
+        assert_eq!(redaction.original("lh-geth-0"), UNKNOWN_GROUP);
+    }
+
+    #[test]
+    fn hash_redaction() {
+        let groups = names(&["lh-geth-0"]);
+        let redact = |salt| {
+            GroupRedaction::new(Redaction::Hash, &groups, salt, &BTreeMap::new())
+                .unwrap()
+                .mapping()
+        };
+        assert_eq!(redact(""), redact(""));
+        assert_ne!(redact(""), redact("secret"));
+        assert!(redact("secret")
+            .keys()
+            .all(|redacted| redacted.len() == "group-".len() + 8));
+    }
+}
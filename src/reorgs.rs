@@ -0,0 +1,135 @@
+use crate::checkpoint::{fetch_block_header, format_root, parse_root, BlockHeader, Root};
+use crate::events::{SseParser, RECONNECT_DELAY};
+use crate::http_client::beacon_client;
+use crate::metrics::{inc_counter_by, observe_histogram, ORPHANED_BLOCKS, REORG_DEPTH};
+use crate::ranges::{count_by_group, IndexRanges};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use tokio::time;
+
+/// Event of the `chain_reorg` topic
+#[derive(Deserialize)]
+struct ChainReorgJson {
+    slot: String,
+    depth: String,
+    old_head_block: String,
+}
+
+/// Head change announced by a `chain_reorg` event
+#[derive(Debug, PartialEq)]
+pub struct ChainReorg {
+    /// Slot of the new head
+    pub slot: u64,
+    /// Slots between the new head and the common ancestor
+    pub depth: u64,
+    pub old_head_block: Root,
+}
+
+impl ChainReorg {
+    pub fn parse(data: &str) -> Result<Self> {
+        let event: ChainReorgJson = serde_json::from_str(data)?;
+        Ok(Self {
+            slot: event.slot.parse()?,
+            depth: event.depth.parse()?,
+            old_head_block: parse_root(&event.old_head_block)?,
+        })
+    }
+
+    /// Slot of the common ancestor of the old and new chains, blocks of the old chain after it
+    /// are orphaned
+    pub fn ancestor_slot(&self) -> u64 {
+        self.slot.saturating_sub(self.depth)
+    }
+}
+
+/// Blocks of the old chain of `reorg`, newest first. Stops early at blocks the node no longer
+/// knows.
+async fn fetch_orphaned_blocks(url: &str, reorg: &ChainReorg) -> Result<Vec<BlockHeader>> {
+    let mut orphaned = vec![];
+    let mut root = reorg.old_head_block;
+    while let Some(header) = fetch_block_header(url, &format_root(&root)).await? {
+        if header.slot <= reorg.ancestor_slot() {
+            break;
+        }
+        root = header.parent_root;
+        orphaned.push(header);
+    }
+    Ok(orphaned)
+}
+
+/// Subscribe to chain reorg events, exporting their depth and counting the orphaned blocks per
+/// group of their proposer. Reconnects when the stream ends or fails.
+pub async fn task_subscribe_reorgs(url: &str, ranges: &IndexRanges) {
+    loop {
+        if let Err(e) = subscribe_reorgs(url, ranges).await {
+            eprintln!("error reading chain_reorg event stream: {:?}", e);
+        }
+        time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn subscribe_reorgs(url: &str, ranges: &IndexRanges) -> Result<()> {
+    let mut response = beacon_client()
+        .get(format!("{url}/eth/v1/events?topics=chain_reorg"))
+        .header(reqwest::header::ACCEPT, "text/event-stream")
+        .send()
+        .await?
+        .error_for_status()?;
+    println!("subscribed to beacon node chain_reorg events");
+
+    let mut parser = SseParser::default();
+    while let Some(chunk) = response.chunk().await? {
+        for (event, data) in parser.push(&chunk) {
+            if event != "chain_reorg" {
+                continue;
+            }
+            let reorg = ChainReorg::parse(&data)?;
+            observe_histogram(&REORG_DEPTH, &[], reorg.depth as f64);
+            let orphaned = match fetch_orphaned_blocks(url, &reorg).await {
+                Ok(orphaned) => orphaned,
+                Err(e) => {
+                    eprintln!("error fetching orphaned blocks: {:?}", e);
+                    vec![]
+                }
+            };
+            let proposers = orphaned.iter().map(|header| header.proposer_index);
+            for (range_name, count) in count_by_group(ranges, proposers) {
+                inc_counter_by(&ORPHANED_BLOCKS, &[&range_name], count);
+            }
+            println!(
+                "reorg of depth {} at slot {}, {} blocks orphaned",
+                reorg.depth,
+                reorg.slot,
+                orphaned.len()
+            );
+        }
+    }
+    Err(anyhow!("chain_reorg event stream ended"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chain_reorg_test() {
+        let data = format!(
+            r#"{{"slot":"200","depth":"2","old_head_block":"0x{}","new_head_block":"0x{}","old_head_state":"0x{}","new_head_state":"0x{}","epoch":"6","execution_optimistic":false}}"#,
+            "01".repeat(32),
+            "02".repeat(32),
+            "03".repeat(32),
+            "04".repeat(32),
+        );
+        let reorg = ChainReorg::parse(&data).unwrap();
+        assert_eq!(
+            reorg,
+            ChainReorg {
+                slot: 200,
+                depth: 2,
+                old_head_block: [1; 32],
+            }
+        );
+        assert_eq!(reorg.ancestor_slot(), 198);
+        assert!(ChainReorg::parse(r#"{"slot":"200"}"#).is_err());
+    }
+}
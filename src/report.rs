@@ -0,0 +1,493 @@
+use crate::participation::{
+    all_perfect, ParticipationByRange, ParticipationSnapshot, RangeSummary,
+};
+use crate::proposals::ProposalCounts;
+use crate::ranges::{format_ranges, group_ranges_by_name, GroupOrder, IndexRanges};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A healthy network finalizes the epoch two behind the current one. Any larger distance is
+/// recorded as a finality stall.
+pub const HEALTHY_EPOCHS_SINCE_FINALITY: u64 = 2;
+
+/// Summary of a whole exporter run, served at `/api/v1/report` and emitted on shutdown. With
+/// `--history-file` it is restored on startup, so a run spans restarts.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunReport {
+    started_at: u64,
+    generated_at: u64,
+    incident_threshold: f32,
+    perfect_threshold: f32,
+    #[serde(skip)]
+    group_order: GroupOrder,
+    epochs_observed: u64,
+    first_epoch: Option<u64>,
+    last_epoch: Option<u64>,
+    groups: Vec<GroupReport>,
+    /// Epochs where every group had perfect target participation
+    all_groups_perfect_epochs: Vec<u64>,
+    incidents: Vec<Incident>,
+    finality_stalls: Vec<FinalityStall>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GroupReport {
+    name: String,
+    range: String,
+    source: RatioStats,
+    target: RatioStats,
+    head: RatioStats,
+    /// Epochs with perfect target participation
+    perfect_epochs: Vec<u64>,
+    /// Consecutive perfect epochs up to the last observed. Resets on a dip or a gap in
+    /// observations.
+    perfect_streak: u64,
+    longest_perfect_streak: u64,
+    blocks_proposed: u64,
+    blocks_missed: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RatioStats {
+    min: Option<f32>,
+    mean: Option<f32>,
+    #[serde(skip)]
+    sum: f64,
+    /// Epochs recorded
+    #[serde(default)]
+    epochs: u64,
+}
+
+/// Contiguous run of epochs where a group's target participation stayed below the incident
+/// threshold. `end_epoch` is inclusive and keeps moving while the incident is `ongoing`.
+#[derive(Debug, Serialize, Deserialize)]
+struct Incident {
+    group: String,
+    start_epoch: u64,
+    end_epoch: u64,
+    duration_epochs: u64,
+    min_target_participation: f32,
+    ongoing: bool,
+}
+
+/// Contiguous run of epochs where the finalized checkpoint lagged more than
+/// `HEALTHY_EPOCHS_SINCE_FINALITY` epochs behind the current epoch.
+#[derive(Debug, Serialize, Deserialize)]
+struct FinalityStall {
+    start_epoch: u64,
+    end_epoch: u64,
+    duration_epochs: u64,
+    max_epochs_since_finality: u64,
+    ongoing: bool,
+}
+
+impl RatioStats {
+    fn record(&mut self, value: f32) {
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.sum += value as f64;
+        self.epochs += 1;
+        self.mean = Some((self.sum / self.epochs as f64) as f32);
+    }
+
+    /// Rebuild the sum, which is not serialized, from a deserialized mean
+    fn restore_sum(&mut self) {
+        self.sum = self.mean.unwrap_or(0.0) as f64 * self.epochs as f64;
+    }
+}
+
+impl RunReport {
+    pub fn new(
+        ranges: &IndexRanges,
+        incident_threshold: f32,
+        perfect_threshold: f32,
+        group_order: GroupOrder,
+    ) -> Self {
+        Self {
+            started_at: unix_now(),
+            generated_at: unix_now(),
+            incident_threshold,
+            perfect_threshold,
+            group_order,
+            epochs_observed: 0,
+            first_epoch: None,
+            last_epoch: None,
+            groups: group_ranges_by_name(ranges)
+                .into_iter()
+                .map(|(name, group_ranges)| GroupReport {
+                    name,
+                    range: format_ranges(&group_ranges),
+                    source: RatioStats::default(),
+                    target: RatioStats::default(),
+                    head: RatioStats::default(),
+                    perfect_epochs: vec![],
+                    perfect_streak: 0,
+                    longest_perfect_streak: 0,
+                    blocks_proposed: 0,
+                    blocks_missed: 0,
+                })
+                .collect(),
+            all_groups_perfect_epochs: vec![],
+            incidents: vec![],
+            finality_stalls: vec![],
+        }
+    }
+
+    /// Continue the history of `previous`, the report of an earlier run. Groups that no longer
+    /// exist are dropped, new ones start empty. The current thresholds and group ranges are kept.
+    pub fn restore(&mut self, previous: RunReport) {
+        self.started_at = previous.started_at;
+        self.epochs_observed = previous.epochs_observed;
+        self.first_epoch = previous.first_epoch;
+        self.last_epoch = previous.last_epoch;
+        self.all_groups_perfect_epochs = previous.all_groups_perfect_epochs;
+        self.finality_stalls = previous.finality_stalls;
+        let mut previous_groups = previous.groups;
+        for group in &mut self.groups {
+            if let Some(position) = previous_groups.iter().position(|g| g.name == group.name) {
+                let mut previous_group = previous_groups.swap_remove(position);
+                for stats in [
+                    &mut previous_group.source,
+                    &mut previous_group.target,
+                    &mut previous_group.head,
+                ] {
+                    stats.restore_sum();
+                }
+                previous_group.range = std::mem::take(&mut group.range);
+                *group = previous_group;
+            }
+        }
+        let groups = &self.groups;
+        self.incidents = previous
+            .incidents
+            .into_iter()
+            .filter(|incident| groups.iter().any(|g| g.name == incident.group))
+            .collect();
+    }
+
+    /// Record the participation of `epoch`. Repeated observations of an already recorded epoch
+    /// are ignored so polling more than once per epoch does not skew the means. Returns false if
+    /// ignored.
+    pub fn record_participation(
+        &mut self,
+        epoch: u64,
+        participation_by_range: &ParticipationByRange,
+    ) -> bool {
+        if self.last_epoch.is_some_and(|last| epoch <= last) {
+            return false;
+        }
+        let contiguous = self.last_epoch.is_some_and(|last| last + 1 == epoch);
+        self.first_epoch.get_or_insert(epoch);
+        self.last_epoch = Some(epoch);
+        self.epochs_observed += 1;
+
+        for (range_name, _, summary) in participation_by_range.iter() {
+            if let Some(group) = self.groups.iter_mut().find(|g| &g.name == range_name) {
+                group.source.record(summary.source_participation_ratio);
+                group.target.record(summary.target_participation_ratio);
+                group.head.record(summary.head_participation_ratio);
+                if summary.is_perfect(self.perfect_threshold) {
+                    group.perfect_epochs.push(epoch);
+                    group.perfect_streak = if contiguous {
+                        group.perfect_streak + 1
+                    } else {
+                        1
+                    };
+                    group.longest_perfect_streak =
+                        group.longest_perfect_streak.max(group.perfect_streak);
+                } else {
+                    group.perfect_streak = 0;
+                }
+            }
+
+            let ratio = summary.target_participation_ratio;
+            let ongoing = self
+                .incidents
+                .iter_mut()
+                .find(|i| i.ongoing && &i.group == range_name);
+            match (ongoing, ratio < self.incident_threshold) {
+                (Some(incident), true) => {
+                    incident.end_epoch = epoch;
+                    incident.duration_epochs = epoch - incident.start_epoch + 1;
+                    incident.min_target_participation =
+                        incident.min_target_participation.min(ratio);
+                }
+                (Some(incident), false) => incident.ongoing = false,
+                (None, true) => self.incidents.push(Incident {
+                    group: range_name.clone(),
+                    start_epoch: epoch,
+                    end_epoch: epoch,
+                    duration_epochs: 1,
+                    min_target_participation: ratio,
+                    ongoing: true,
+                }),
+                (None, false) => {}
+            }
+        }
+
+        if all_perfect(participation_by_range, self.perfect_threshold) {
+            self.all_groups_perfect_epochs.push(epoch);
+        }
+        true
+    }
+
+    /// Record the participation of a snapshot, e.g. replayed from `--db`, as
+    /// [`Self::record_participation`] does
+    pub fn record_snapshot(&mut self, snapshot: &ParticipationSnapshot) -> bool {
+        let participation_by_range = snapshot
+            .groups
+            .iter()
+            .map(|group| {
+                let summary = RangeSummary {
+                    target_participation_ratio: group.target,
+                    head_participation_ratio: group.head,
+                    source_participation_ratio: group.source,
+                    inactivity_scores_avg: group.inactivity_scores_avg,
+                    balance_gwei_sum: group.balance_gwei_sum,
+                    slashed_validators: group.slashed_validators,
+                    ..Default::default()
+                };
+                (group.name.clone(), vec![], summary)
+            })
+            .collect();
+        self.record_participation(snapshot.epoch, &participation_by_range)
+    }
+
+    /// Latest recorded epoch
+    pub fn last_epoch(&self) -> Option<u64> {
+        self.last_epoch
+    }
+
+    pub fn perfect_threshold(&self) -> f32 {
+        self.perfect_threshold
+    }
+
+    /// Current perfect epoch streak of each group
+    pub fn perfect_streaks(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.groups
+            .iter()
+            .map(|group| (group.name.as_str(), group.perfect_streak))
+    }
+
+    /// Add the proposal outcomes of an epoch, must be called once per epoch
+    pub fn record_proposals(&mut self, proposals: &[(String, ProposalCounts)]) {
+        for (range_name, counts) in proposals {
+            if let Some(group) = self.groups.iter_mut().find(|g| &g.name == range_name) {
+                group.blocks_proposed += counts.proposed;
+                group.blocks_missed += counts.missed;
+            }
+        }
+    }
+
+    /// Record the finalized checkpoint epoch observed while `current_epoch` is the wall clock
+    /// epoch.
+    pub fn record_finality(&mut self, current_epoch: u64, finalized_epoch: u64) {
+        let epochs_since_finality = current_epoch.saturating_sub(finalized_epoch);
+        let stalled = epochs_since_finality > HEALTHY_EPOCHS_SINCE_FINALITY;
+        match (
+            self.finality_stalls.last_mut().filter(|s| s.ongoing),
+            stalled,
+        ) {
+            (Some(stall), true) => {
+                stall.end_epoch = current_epoch;
+                stall.duration_epochs = current_epoch - stall.start_epoch + 1;
+                stall.max_epochs_since_finality =
+                    stall.max_epochs_since_finality.max(epochs_since_finality);
+            }
+            (Some(stall), false) => stall.ongoing = false,
+            (None, true) => self.finality_stalls.push(FinalityStall {
+                start_epoch: current_epoch,
+                end_epoch: current_epoch,
+                duration_epochs: 1,
+                max_epochs_since_finality: epochs_since_finality,
+                ongoing: true,
+            }),
+            (None, false) => {}
+        }
+    }
+
+    /// Serialize the report as of now
+    pub fn render_json(&mut self) -> String {
+        self.generated_at = unix_now();
+        self.group_order.sort(
+            &mut self.groups,
+            |group| &group.name,
+            |group| group.target.mean.unwrap_or(0.0),
+        );
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Parse a report written by `render_json`
+    pub fn parse_json(input: &str) -> Result<Self> {
+        Ok(serde_json::from_str(input)?)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participation::GroupParticipation;
+
+    #[allow(clippy::single_range_in_vec_init)]
+    fn participation(target: f32) -> ParticipationByRange {
+        vec![(
+            "lh-geth-0".to_owned(),
+            vec![0..100],
+            RangeSummary {
+                target_participation_ratio: target,
+                head_participation_ratio: target,
+                source_participation_ratio: target,
+                inactivity_scores_avg: 0.0,
+                balance_gwei_sum: 0,
+                balance_gwei_avg: 0.0,
+                slashed_validators: 0,
+                exiting_validators: 0,
+                exited_validators: 0,
+                withdrawable_validators: 0,
+                validators_by_status: Default::default(),
+                validators_by_flag_count: Default::default(),
+            },
+        )]
+    }
+
+    #[test]
+    fn report_tracks_incidents() {
+        let mut report = RunReport::new(
+            &vec![("lh-geth-0".to_owned(), 0..100)],
+            0.9,
+            1.0,
+            GroupOrder::Config,
+        );
+        for (epoch, target) in [
+            (10, 1.0),
+            (11, 0.5),
+            (12, 0.7),
+            (12, 0.1),
+            (13, 0.95),
+            (14, 0.2),
+        ] {
+            report.record_participation(epoch, &participation(target));
+        }
+
+        assert_eq!(report.epochs_observed, 5);
+        assert!(!report.record_participation(14, &participation(1.0)));
+        assert_eq!(report.groups[0].target.min, Some(0.2));
+        let mean = report.groups[0].target.mean.unwrap();
+        assert!((mean - (1.0 + 0.5 + 0.7 + 0.95 + 0.2) / 5.0).abs() < 1e-6);
+        assert_eq!(report.incidents.len(), 2);
+        assert_eq!(report.incidents[0].start_epoch, 11);
+        assert_eq!(report.incidents[0].duration_epochs, 2);
+        assert_eq!(report.incidents[0].min_target_participation, 0.5);
+        assert!(!report.incidents[0].ongoing);
+        assert!(report.incidents[1].ongoing);
+        assert_eq!(report.groups[0].perfect_epochs, vec![10]);
+        assert_eq!(report.all_groups_perfect_epochs, vec![10]);
+    }
+
+    #[test]
+    fn report_tracks_perfect_streaks() {
+        let mut report = RunReport::new(
+            &vec![("lh-geth-0".to_owned(), 0..100)],
+            0.9,
+            0.95,
+            GroupOrder::Config,
+        );
+        // Epoch 15 is not observed, breaking the streak
+        for (epoch, target) in [
+            (10, 1.0),
+            (11, 0.96),
+            (12, 0.9),
+            (13, 1.0),
+            (14, 1.0),
+            (16, 1.0),
+        ] {
+            report.record_participation(epoch, &participation(target));
+            if epoch == 14 {
+                assert_eq!(report.groups[0].perfect_streak, 2);
+            }
+        }
+        assert_eq!(report.groups[0].perfect_streak, 1);
+        assert_eq!(report.groups[0].longest_perfect_streak, 2);
+        assert_eq!(
+            report.perfect_streaks().collect::<Vec<_>>(),
+            vec![("lh-geth-0", 1)]
+        );
+    }
+
+    #[test]
+    fn report_tracks_finality_stalls() {
+        let mut report = RunReport::new(&vec![], 0.9, 1.0, GroupOrder::Config);
+        for (current, finalized) in [(10, 8), (11, 8), (12, 8), (13, 11), (14, 12)] {
+            report.record_finality(current, finalized);
+        }
+
+        assert_eq!(report.finality_stalls.len(), 1);
+        assert_eq!(report.finality_stalls[0].start_epoch, 11);
+        assert_eq!(report.finality_stalls[0].duration_epochs, 2);
+        assert_eq!(report.finality_stalls[0].max_epochs_since_finality, 4);
+        assert!(!report.finality_stalls[0].ongoing);
+    }
+
+    #[test]
+    fn report_restores_history() {
+        let ranges = vec![("lh-geth-0".to_owned(), 0..100)];
+        let mut report = RunReport::new(&ranges, 0.9, 0.95, GroupOrder::Config);
+        for (epoch, target) in [(10, 1.0), (11, 0.5), (12, 1.0)] {
+            report.record_participation(epoch, &participation(target));
+        }
+        let json = report.render_json();
+
+        // The new run monitors one more group
+        let ranges = vec![
+            ("lh-geth-0".to_owned(), 0..100),
+            ("teku-besu-0".to_owned(), 100..200),
+        ];
+        let mut restored = RunReport::new(&ranges, 0.9, 0.95, GroupOrder::Config);
+        restored.restore(RunReport::parse_json(&json).unwrap());
+        assert!(!restored.record_participation(12, &participation(1.0)));
+        restored.record_participation(13, &participation(0.0));
+        restored.record_participation(14, &participation(1.0));
+        restored.record_participation(15, &participation(1.0));
+
+        assert_eq!(restored.epochs_observed, 6);
+        assert_eq!(restored.first_epoch, Some(10));
+        let group = &restored.groups[0];
+        assert_eq!(group.perfect_epochs, vec![10, 12, 14, 15]);
+        assert_eq!(group.perfect_streak, 2);
+        let mean = group.target.mean.unwrap();
+        assert!((mean - 4.5 / 6.0).abs() < 1e-6);
+        assert_eq!(restored.groups[1].target.epochs, 0);
+        assert_eq!(restored.incidents.len(), 2);
+    }
+
+    #[test]
+    fn report_replays_snapshots() {
+        let ranges = vec![("lh-geth-0".to_owned(), 0..100)];
+        let mut report = RunReport::new(&ranges, 0.9, 0.95, GroupOrder::Config);
+        let snapshot = |epoch: u64, target: f32| {
+            let mut group = GroupParticipation::new("lh-geth-0", &[], &participation(target)[0].2);
+            group.target = target;
+            ParticipationSnapshot {
+                slot: (epoch + 1) * 32,
+                epoch,
+                timestamp: 0,
+                groups: vec![group.clone()],
+                total: group,
+            }
+        };
+        for (epoch, target) in [(10, 1.0), (11, 1.0), (12, 0.5)] {
+            assert!(report.record_snapshot(&snapshot(epoch, target)));
+        }
+        assert!(!report.record_snapshot(&snapshot(12, 1.0)));
+        assert_eq!(report.last_epoch(), Some(12));
+        assert_eq!(report.groups[0].longest_perfect_streak, 2);
+        assert_eq!(report.incidents.len(), 1);
+    }
+}
@@ -0,0 +1,373 @@
+use crate::config::ConfigSpec;
+use crate::http_client::beacon_client;
+use crate::participation::has_flag;
+use crate::ranges::{group_ranges_by_name, IndexRanges};
+use crate::ssz_state::{Fork, StatePartial, Validator};
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Validators queried per request, keeps request bodies and node work bounded
+const VALIDATORS_PER_REQUEST: usize = 4096;
+
+// https://github.com/ethereum/consensus-specs/blob/dev/specs/altair/beacon-chain.md#incentivization-weights
+const TIMELY_SOURCE_WEIGHT: u64 = 14;
+const TIMELY_TARGET_WEIGHT: u64 = 26;
+const WEIGHT_DENOMINATOR: u64 = 64;
+const BASE_REWARD_FACTOR: u64 = 64;
+const EFFECTIVE_BALANCE_INCREMENT: u64 = 1_000_000_000;
+const INACTIVITY_SCORE_BIAS: u64 = 4;
+const INACTIVITY_PENALTY_QUOTIENT_ALTAIR: u64 = 3 * (1 << 24);
+const INACTIVITY_PENALTY_QUOTIENT_BELLATRIX: u64 = 1 << 24;
+
+/// Attestation reward components in Gwei. Penalties are negative.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct RewardComponents {
+    pub head: i64,
+    pub target: i64,
+    pub source: i64,
+    pub inactivity: i64,
+}
+
+impl RewardComponents {
+    fn add(&mut self, other: &RewardComponents) {
+        self.head += other.head;
+        self.target += other.target;
+        self.source += other.source;
+        self.inactivity += other.inactivity;
+    }
+
+    pub fn named(&self) -> [(&'static str, i64); 4] {
+        [
+            ("head", self.head),
+            ("target", self.target),
+            ("source", self.source),
+            ("inactivity", self.inactivity),
+        ]
+    }
+}
+
+#[derive(Deserialize)]
+struct RewardComponentsJson {
+    head: String,
+    target: String,
+    source: String,
+    #[serde(default)]
+    inactivity: Option<String>,
+}
+
+impl RewardComponentsJson {
+    fn parse(&self) -> Result<RewardComponents> {
+        Ok(RewardComponents {
+            head: self.head.parse()?,
+            target: self.target.parse()?,
+            source: self.source.parse()?,
+            inactivity: match &self.inactivity {
+                Some(inactivity) => inactivity.parse()?,
+                None => 0,
+            },
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct IdealRewardJson {
+    effective_balance: String,
+    #[serde(flatten)]
+    rewards: RewardComponentsJson,
+}
+
+#[derive(Deserialize)]
+struct TotalRewardJson {
+    validator_index: String,
+    #[serde(flatten)]
+    rewards: RewardComponentsJson,
+}
+
+#[derive(Deserialize)]
+struct AttestationRewardsData {
+    ideal_rewards: Vec<IdealRewardJson>,
+    total_rewards: Vec<TotalRewardJson>,
+}
+
+#[derive(Deserialize)]
+struct AttestationRewardsResponse {
+    data: AttestationRewardsData,
+}
+
+/// Attestation rewards of one epoch as returned by the beacon node
+#[derive(Debug, Default)]
+pub struct AttestationRewards {
+    /// Rewards of a perfect attester, by effective balance
+    pub ideal: HashMap<u64, RewardComponents>,
+    pub total: HashMap<usize, RewardComponents>,
+}
+
+/// Fetch the attestation rewards of `indices` in `epoch`
+pub async fn fetch_attestation_rewards(
+    url: &str,
+    epoch: u64,
+    indices: &[usize],
+) -> Result<AttestationRewards> {
+    let client = beacon_client();
+    let mut rewards = AttestationRewards::default();
+    for chunk in indices.chunks(VALIDATORS_PER_REQUEST) {
+        let body: Vec<String> = chunk.iter().map(|index| index.to_string()).collect();
+        let response = client
+            .post(format!("{url}/eth/v1/beacon/rewards/attestations/{epoch}"))
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        let data: AttestationRewardsResponse = response.json().await?;
+        for ideal in data.data.ideal_rewards {
+            rewards
+                .ideal
+                .insert(ideal.effective_balance.parse()?, ideal.rewards.parse()?);
+        }
+        for total in data.data.total_rewards {
+            rewards
+                .total
+                .insert(total.validator_index.parse()?, total.rewards.parse()?);
+        }
+    }
+    Ok(rewards)
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct GroupRewards {
+    pub actual: RewardComponents,
+    pub ideal: RewardComponents,
+}
+
+impl GroupRewards {
+    /// Ratio of the head, target and source rewards earned to those of perfect attesters with
+    /// the same effective balances. `None` if the ideal rewards are zero.
+    pub fn efficiency(&self) -> Option<f64> {
+        let sum = |r: &RewardComponents| (r.head + r.target + r.source) as f64;
+        let ideal = sum(&self.ideal);
+        if ideal == 0.0 {
+            return None;
+        }
+        Some(sum(&self.actual) / ideal)
+    }
+}
+
+/// Sum the actual and ideal rewards of each group. Validators missing from `rewards` are not
+/// counted, e.g. those not active in the epoch.
+pub fn group_rewards(
+    ranges: &IndexRanges,
+    rewards: &AttestationRewards,
+    validators: &[Validator],
+) -> Vec<(String, GroupRewards)> {
+    group_ranges_by_name(ranges)
+        .into_iter()
+        .map(|(range_name, group_ranges)| {
+            let mut group = GroupRewards::default();
+            for index in group_ranges.iter().flat_map(|range| range.clone()) {
+                let Some(actual) = rewards.total.get(&index) else {
+                    continue;
+                };
+                group.actual.add(actual);
+                let ideal = validators
+                    .get(index)
+                    .and_then(|validator| rewards.ideal.get(&validator.effective_balance));
+                if let Some(ideal) = ideal {
+                    group.ideal.add(ideal);
+                }
+            }
+            (range_name, group)
+        })
+        .collect()
+}
+
+/// Penalties in Gwei a group incurs for the attestation flags it missed in an epoch
+#[derive(Debug, Default, PartialEq)]
+pub struct EstimatedPenalties {
+    pub source: u64,
+    pub target: u64,
+    pub inactivity: u64,
+}
+
+impl EstimatedPenalties {
+    pub fn named(&self) -> [(&'static str, u64); 3] {
+        [
+            ("source", self.source),
+            ("target", self.target),
+            ("inactivity", self.inactivity),
+        ]
+    }
+}
+
+/// Estimate with the spec formulas the penalties each group will get for the previous epoch
+/// participation of `state`, at the start of `state_epoch`. Missed head votes forgo a reward
+/// but are not penalized. Inactivity scores are those before the epoch's own update, so the
+/// inactivity penalty lags one epoch behind the node's.
+pub fn estimate_group_penalties(
+    config: &ConfigSpec,
+    ranges: &IndexRanges,
+    state: &StatePartial,
+    state_epoch: u64,
+) -> Vec<(String, EstimatedPenalties)> {
+    let previous_epoch = state_epoch.saturating_sub(1);
+    let total_active_balance = state
+        .validators
+        .iter()
+        .filter(|validator| validator.is_active(state_epoch))
+        .map(|validator| validator.effective_balance)
+        .sum::<u64>()
+        .max(EFFECTIVE_BALANCE_INCREMENT);
+    let base_reward_per_increment =
+        EFFECTIVE_BALANCE_INCREMENT * BASE_REWARD_FACTOR / integer_squareroot(total_active_balance);
+    let inactivity_penalty_quotient = match Fork::at_epoch(config, state_epoch) {
+        Fork::Phase0 | Fork::Altair => INACTIVITY_PENALTY_QUOTIENT_ALTAIR,
+        _ => INACTIVITY_PENALTY_QUOTIENT_BELLATRIX,
+    };
+    let flags = &config.participation_flags;
+
+    group_ranges_by_name(ranges)
+        .into_iter()
+        .map(|(range_name, group_ranges)| {
+            let mut penalties = EstimatedPenalties::default();
+            for index in group_ranges.iter().flat_map(|range| range.clone()) {
+                let Some(validator) = state.validators.get(index) else {
+                    continue;
+                };
+                let eligible = validator.is_active(previous_epoch)
+                    || (validator.slashed && previous_epoch + 1 < validator.withdrawable_epoch);
+                if !eligible {
+                    continue;
+                }
+                // Slashed validators count as not participating
+                let participation = match state.previous_epoch_participation.get(index) {
+                    Some(participation) if !validator.slashed => *participation,
+                    _ => 0,
+                };
+                let base_reward = validator.effective_balance / EFFECTIVE_BALANCE_INCREMENT
+                    * base_reward_per_increment;
+                if !has_flag(participation, flags.source) {
+                    penalties.source += base_reward * TIMELY_SOURCE_WEIGHT / WEIGHT_DENOMINATOR;
+                }
+                if !has_flag(participation, flags.target) {
+                    penalties.target += base_reward * TIMELY_TARGET_WEIGHT / WEIGHT_DENOMINATOR;
+                    let inactivity_score = state.inactivity_scores.get(index).copied().unwrap_or(0);
+                    penalties.inactivity += (validator.effective_balance as u128
+                        * inactivity_score as u128
+                        / (INACTIVITY_SCORE_BIAS * inactivity_penalty_quotient) as u128)
+                        as u64;
+                }
+            }
+            (range_name, penalties)
+        })
+        .collect()
+}
+
+/// Largest integer whose square is at most `n`
+fn integer_squareroot(n: u64) -> u64 {
+    let mut x = n;
+    let mut y = x.div_ceil(2);
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssz_state::fixtures::{test_state, test_validator};
+
+    fn validator(effective_balance: u64) -> Validator {
+        Validator {
+            effective_balance,
+            ..test_validator()
+        }
+    }
+
+    fn components(head: i64, target: i64, source: i64) -> RewardComponents {
+        RewardComponents {
+            head,
+            target,
+            source,
+            inactivity: 0,
+        }
+    }
+
+    #[test]
+    fn estimated_penalties() {
+        assert_eq!(integer_squareroot(0), 0);
+        assert_eq!(integer_squareroot(15), 3);
+        assert_eq!(integer_squareroot(u64::MAX), u32::MAX as u64);
+
+        let config = ConfigSpec {
+            altair_fork_epoch: Some(0),
+            bellatrix_fork_epoch: Some(0),
+            ..ConfigSpec::mainnet()
+        };
+        let flags = &config.participation_flags;
+        let count = 64;
+        let slashed = Validator {
+            slashed: true,
+            ..validator(32_000_000_000)
+        };
+        let mut state = StatePartial {
+            slot: 10 * config.slots_per_epoch,
+            inactivity_scores: vec![100; count],
+            validators: [vec![slashed], vec![validator(32_000_000_000); count - 1]].concat(),
+            ..test_state(vec![flags.source | flags.target | flags.head; count])
+        };
+        state.previous_epoch_participation[1] = flags.source;
+        let ranges = vec![("a".to_owned(), 0..2), ("b".to_owned(), 2..count)];
+
+        // 64 validators of 32 ETH, sqrt(2048e9) = 1431083
+        let base_reward = 32 * (EFFECTIVE_BALANCE_INCREMENT * 64 / 1431083);
+        let inactivity = 32_000_000_000 * 100 / (4 * (1 << 24));
+        let penalties = estimate_group_penalties(&config, &ranges, &state, 10);
+        assert_eq!(
+            penalties,
+            vec![
+                (
+                    "a".to_owned(),
+                    EstimatedPenalties {
+                        source: base_reward * 14 / 64,
+                        target: 2 * (base_reward * 26 / 64),
+                        inactivity: 2 * inactivity,
+                    }
+                ),
+                ("b".to_owned(), EstimatedPenalties::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn group_rewards_test() {
+        let rewards = AttestationRewards {
+            ideal: HashMap::from([
+                (32_000_000_000, components(10, 20, 10)),
+                (16_000_000_000, components(5, 10, 5)),
+            ]),
+            total: HashMap::from([
+                (0, components(10, 20, 10)),
+                (1, components(0, -20, -10)),
+                (2, components(5, 10, 5)),
+            ]),
+        };
+        let validators = vec![
+            validator(32_000_000_000),
+            validator(32_000_000_000),
+            validator(16_000_000_000),
+        ];
+        let ranges = vec![("a".to_owned(), 0..2), ("b".to_owned(), 2..4)];
+        let groups = group_rewards(&ranges, &rewards, &validators);
+        assert_eq!(
+            groups[0].1,
+            GroupRewards {
+                actual: components(10, 0, 0),
+                ideal: components(20, 40, 20),
+            }
+        );
+        assert_eq!(groups[0].1.efficiency(), Some(10.0 / 80.0));
+        assert_eq!(groups[1].1.efficiency(), Some(1.0));
+    }
+}
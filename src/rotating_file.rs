@@ -0,0 +1,132 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Append-only file rotated once it exceeds `max_bytes` or has been written to for `max_age`.
+/// Rotation renames `path` to `path.1`, shifting older files up to `path.<keep>` and deleting the
+/// oldest.
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: Option<u64>,
+    max_age: Option<Duration>,
+    keep: usize,
+    file: Option<File>,
+    size: u64,
+    /// When the exporter started writing to the current file
+    opened_at: Instant,
+}
+
+impl RotatingFile {
+    pub fn new(
+        path: impl Into<PathBuf>,
+        max_bytes: Option<u64>,
+        max_age: Option<Duration>,
+        keep: usize,
+    ) -> Self {
+        Self {
+            path: path.into(),
+            max_bytes,
+            max_age,
+            keep,
+            file: None,
+            size: 0,
+            opened_at: Instant::now(),
+        }
+    }
+
+    /// Append `contents`, rotating first if the current file is due. A single write is never
+    /// split across files.
+    pub fn write(&mut self, contents: &str) -> Result<()> {
+        if self.file.is_some() && self.due(contents.len() as u64) {
+            self.rotate()?;
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)
+                    .with_context(|| format!("open {}", self.path.display()))?;
+                self.size = file.metadata()?.len();
+                self.opened_at = Instant::now();
+                self.file.insert(file)
+            }
+        };
+        file.write_all(contents.as_bytes())
+            .with_context(|| format!("write {}", self.path.display()))?;
+        self.size += contents.len() as u64;
+        Ok(())
+    }
+
+    fn due(&self, next_write: u64) -> bool {
+        let too_big = self
+            .max_bytes
+            .is_some_and(|max| self.size > 0 && self.size + next_write > max);
+        let too_old = self
+            .max_age
+            .is_some_and(|max| self.opened_at.elapsed() >= max);
+        too_big || too_old
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        self.file = None;
+        if self.keep == 0 {
+            return fs::remove_file(&self.path)
+                .with_context(|| format!("remove {}", self.path.display()));
+        }
+        for n in (1..self.keep).rev() {
+            let from = rotated_path(&self.path, n);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.path, n + 1))
+                    .with_context(|| format!("rename {}", from.display()))?;
+            }
+        }
+        fs::rename(&self.path, rotated_path(&self.path, 1))
+            .with_context(|| format!("rename {}", self.path.display()))
+    }
+}
+
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    name.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotating_file_test() {
+        let dir = std::env::temp_dir().join(format!("rotating-file-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dump.log");
+        let read = |path: &Path| fs::read_to_string(path).unwrap_or_default();
+
+        let mut file = RotatingFile::new(&path, Some(3), None, 2);
+        // Larger than max_bytes but the file is empty, so it is kept whole
+        file.write("0123456789ab\n").unwrap();
+        file.write("a\n").unwrap();
+        file.write("b\n").unwrap();
+        file.write("c\n").unwrap();
+        assert_eq!(read(&path), "c\n");
+        assert_eq!(read(&rotated_path(&path, 1)), "b\n");
+        assert_eq!(read(&rotated_path(&path, 2)), "a\n");
+        assert!(!rotated_path(&path, 3).exists());
+
+        // Appends to the existing file after a restart, and rotates by age
+        let mut file = RotatingFile::new(&path, None, Some(Duration::ZERO), 2);
+        file.write("d\n").unwrap();
+        assert_eq!(read(&path), "c\nd\n");
+        file.write("e\n").unwrap();
+        assert_eq!(read(&path), "e\n");
+        assert_eq!(read(&rotated_path(&path, 1)), "c\nd\n");
+        assert_eq!(read(&rotated_path(&path, 2)), "b\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,682 @@
+use crate::active_set::ActiveSetStats;
+use crate::exporter::{unix_time, Config};
+use crate::history::{ParticipationHistory, DEFAULT_HISTORY_EPOCHS};
+use crate::inventory::{ValidatorInventory, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+use crate::metrics::{
+    get_gauge, registry, set_gauge, with_aliases, with_static_labels, with_target, MetricAlias,
+    StaticLabel, EXPORTER_PAUSED, HEAD_PARTICIPATION, LAST_UPDATE_TIMESTAMP, PARTICIPATION_STALE,
+    SOURCE_PARTICIPATION, STATE_FETCH_SKIPPED_SYNCING, TARGET_PARTICIPATION,
+};
+use crate::miss_streaks::{MissStreaks, DEFAULT_WORST_LIMIT, MAX_WORST_LIMIT};
+use crate::participation::ParticipationSnapshot;
+use crate::persistence::ConfigChange;
+use crate::ranges::{GroupThresholds, ThresholdsByGroup};
+use crate::redact::GroupRedaction;
+use crate::report::RunReport;
+use crate::shutdown::Shutdown;
+use crate::status_page::{render_status_page, StatusSection};
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use hyper::server::accept::Accept;
+use hyper::server::conn::AddrIncoming;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use prometheus::{Encoder, TextEncoder};
+use serde_json::to_string;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time;
+
+/// Latest participation of a beacon node and the subscribers to new ones
+pub(crate) struct LatestParticipation {
+    pub(crate) snapshot: Mutex<Option<ParticipationSnapshot>>,
+    /// Snapshots of the last `--history-epochs` epochs
+    pub(crate) history: Mutex<ParticipationHistory>,
+    /// JSON of every new snapshot
+    events: broadcast::Sender<String>,
+}
+
+impl Default for LatestParticipation {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_EPOCHS)
+    }
+}
+
+impl LatestParticipation {
+    pub(crate) fn new(history_epochs: usize) -> Self {
+        Self {
+            snapshot: Mutex::default(),
+            history: Mutex::new(ParticipationHistory::new(history_epochs)),
+            events: broadcast::channel(STREAM_BUFFERED_EVENTS).0,
+        }
+    }
+
+    /// Continue the history of an earlier run, oldest first. Epochs it already has are skipped.
+    pub(crate) fn restore_history(&self, snapshots: Vec<ParticipationSnapshot>) {
+        let mut history = self.history.lock().unwrap();
+        for snapshot in snapshots {
+            let latest = history.snapshots().last().map(|last| last.epoch);
+            if latest.is_none_or(|latest| snapshot.epoch > latest) {
+                history.push(snapshot);
+            }
+        }
+    }
+
+    pub(crate) fn publish(&self, snapshot: ParticipationSnapshot) {
+        let json = to_string(&snapshot).unwrap();
+        self.history.lock().unwrap().push(snapshot.clone());
+        *self.snapshot.lock().unwrap() = Some(snapshot);
+        // Fails while no one is subscribed
+        let _ = self.events.send(json);
+    }
+}
+
+/// Events kept for a slow `/stream` client before it skips ahead
+const STREAM_BUFFERED_EVENTS: usize = 16;
+/// Seconds between comments sent to idle `/stream` clients, keeping proxies from closing them
+const STREAM_KEEPALIVE_SECS: u64 = 15;
+
+fn server_sent_event(json: &str) -> Bytes {
+    Bytes::from(format!("event: participation\ndata: {json}\n\n"))
+}
+
+/// Push the latest and every new participation snapshot as server-sent events, until the client
+/// disconnects or the exporter shuts down
+fn handle_stream_request(target: &TargetState, shutdown: &Shutdown) -> Response<Body> {
+    let mut events = target.participation.events.subscribe();
+    let latest = target
+        .participation
+        .snapshot
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|snapshot| to_string(snapshot).unwrap());
+    let redaction = target.redaction.clone();
+    let redact = move |json: String| match &redaction {
+        Some(redaction) => redaction.redact_json(&json),
+        None => json,
+    };
+    let shutdown = shutdown.clone();
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        if let Some(json) = latest {
+            if sender
+                .send_data(server_sent_event(&redact(json)))
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+        let keepalive_period = Duration::from_secs(STREAM_KEEPALIVE_SECS);
+        let mut keepalive =
+            time::interval_at(time::Instant::now() + keepalive_period, keepalive_period);
+        loop {
+            let chunk = tokio::select! {
+                event = events.recv() => match event {
+                    Ok(json) => server_sent_event(&redact(json)),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return,
+                },
+                _ = keepalive.tick() => Bytes::from_static(b": keepalive\n\n"),
+                // Open streams would hold up the graceful shutdown of the server
+                _ = shutdown.wait() => return,
+            };
+            if sender.send_data(chunk).await.is_err() {
+                return;
+            }
+        }
+    });
+    Response::builder()
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .body(body)
+        .unwrap()
+}
+
+/// State of the metrics server
+struct ServerState {
+    metric_aliases: Vec<MetricAlias>,
+    static_labels: Vec<StaticLabel>,
+    api_token: Option<String>,
+    /// One per `--target`, or the single beacon node
+    targets: Vec<Arc<TargetState>>,
+    shutdown: Shutdown,
+}
+
+/// State shared by the fetch loop of a beacon node and the metrics server
+pub(crate) struct TargetState {
+    /// Value of the target label, empty without `--target`
+    pub(crate) name: String,
+    /// Added to the series of the target only
+    pub(crate) static_labels: Vec<StaticLabel>,
+    pub(crate) report: Arc<Mutex<RunReport>>,
+    /// Toggled by `/api/v1/pause` and `/api/v1/resume`, see `FetchTaskOptions::paused`
+    pub(crate) paused: Arc<AtomicBool>,
+    pub(crate) active_set: Arc<Mutex<Option<ActiveSetStats>>>,
+    pub(crate) participation: Arc<LatestParticipation>,
+    /// Color the participation of the status page
+    pub(crate) group_thresholds: ThresholdsByGroup,
+    pub(crate) color_thresholds: GroupThresholds,
+    pub(crate) validators: Arc<Mutex<Option<ValidatorInventory>>>,
+    pub(crate) miss_streaks: Arc<Mutex<MissStreaks>>,
+    /// Saved to --history-file on shutdown
+    pub(crate) config_change: ConfigChange,
+    pub(crate) staleness: Option<Staleness>,
+    pub(crate) redaction: Option<Arc<GroupRedaction>>,
+}
+
+/// Staleness guard of --stale-after-epochs, evaluated on each scrape
+pub(crate) struct Staleness {
+    pub(crate) max_age_secs: f64,
+    pub(crate) zero: bool,
+    /// Unix time of the start, the reference until the first update
+    pub(crate) started_at: f64,
+    pub(crate) groups: Vec<String>,
+}
+
+impl Staleness {
+    /// Export whether the last update is too old or downloads are skipped while the node is
+    /// syncing, zeroing participation with --zero-stale
+    fn check(&self) {
+        let last_update = get_gauge(&LAST_UPDATE_TIMESTAMP, &[]).unwrap_or(0.0);
+        let syncing = get_gauge(&STATE_FETCH_SKIPPED_SYNCING, &[]) == Some(1.0);
+        let stale = syncing || unix_time() - last_update.max(self.started_at) > self.max_age_secs;
+        set_gauge(&PARTICIPATION_STALE, &[], stale as u8 as f64);
+        if stale && self.zero {
+            for group in &self.groups {
+                for gauge in [
+                    &*SOURCE_PARTICIPATION,
+                    &*TARGET_PARTICIPATION,
+                    &*HEAD_PARTICIPATION,
+                ] {
+                    set_gauge(gauge, &[group], 0.0);
+                }
+            }
+        }
+    }
+}
+
+/// Export whether downloads are skipped while syncing. The participation is then stale even
+/// without --stale-after-epochs, whose check on scrape also zeroes it with --zero-stale.
+pub(crate) fn export_skipped_syncing(skip: bool) {
+    let was_skipping = get_gauge(&STATE_FETCH_SKIPPED_SYNCING, &[]) == Some(1.0);
+    set_gauge(&STATE_FETCH_SKIPPED_SYNCING, &[], skip as u8 as f64);
+    if skip || was_skipping {
+        set_gauge(&PARTICIPATION_STALE, &[], skip as u8 as f64);
+    }
+}
+
+fn json_response(status: StatusCode, json: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(json))
+        .unwrap()
+}
+
+/// Response with the JSON of `target`, whose group names are redacted with --redact-groups
+fn target_json_response(target: &TargetState, json: String) -> Response<Body> {
+    let json = match &target.redaction {
+        Some(redaction) => redaction.redact_json(&json),
+        None => json,
+    };
+    json_response(StatusCode::OK, json)
+}
+
+/// Group selected by the `group` query parameter, a redacted name with --redact-groups
+fn query_group(target: &TargetState, group: String) -> String {
+    match &target.redaction {
+        Some(redaction) => redaction.original(&group),
+        None => group,
+    }
+}
+
+/// Pause or resume collection if the request carries the API token
+fn handle_pause_request(
+    req: &Request<Body>,
+    state: &ServerState,
+    target: &TargetState,
+    pause: bool,
+) -> Response<Body> {
+    let Some(api_token) = &state.api_token else {
+        return json_response(
+            StatusCode::FORBIDDEN,
+            r#"{"error":"control endpoints are disabled, set --api-token"}"#.to_owned(),
+        );
+    };
+    let authorized = req
+        .headers()
+        .get(hyper::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| token == api_token);
+    if !authorized {
+        return json_response(
+            StatusCode::UNAUTHORIZED,
+            r#"{"error":"invalid or missing bearer token"}"#.to_owned(),
+        );
+    }
+    target.paused.store(pause, Ordering::Relaxed);
+    with_target(&target.name, || {
+        set_gauge(&EXPORTER_PAUSED, &[], pause as u8 as f64)
+    });
+    println!("collection {}", if pause { "paused" } else { "resumed" });
+    json_response(StatusCode::OK, format!(r#"{{"paused":{pause}}}"#))
+}
+
+/// Decoded query parameters of `req`, in order
+fn query_params(req: &Request<Body>) -> Vec<(String, String)> {
+    // The path is only needed to resolve the query
+    let url = reqwest::Url::parse(&format!("http://localhost{}", req.uri()));
+    url.iter()
+        .flat_map(|url| url.query_pairs())
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect()
+}
+
+/// Response to a query parameter `key` whose value is invalid
+fn invalid_param_response(key: &str) -> Response<Body> {
+    json_response(
+        StatusCode::BAD_REQUEST,
+        format!(r#"{{"error":"invalid {key}"}}"#),
+    )
+}
+
+/// List monitored validators of the latest state, filtered by the `group` query parameter and
+/// paginated with `offset` and `limit`
+fn handle_validators_request(req: &Request<Body>, target: &TargetState) -> Response<Body> {
+    let mut group = None;
+    let mut offset = 0;
+    let mut limit = DEFAULT_PAGE_LIMIT;
+    for (key, value) in query_params(req) {
+        let parsed = match key.as_str() {
+            "group" => {
+                group = Some(query_group(target, value));
+                Ok(())
+            }
+            "offset" => value.parse().map(|value| offset = value),
+            "limit" => value.parse().map(|value| limit = value),
+            _ => Ok(()),
+        };
+        if parsed.is_err() {
+            return invalid_param_response(&key);
+        }
+    }
+    match &*target.validators.lock().unwrap() {
+        Some(inventory) => {
+            let page = inventory.page(group.as_deref(), offset, limit.min(MAX_PAGE_LIMIT));
+            target_json_response(target, to_string(&page).unwrap())
+        }
+        None => json_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            r#"{"error":"no state fetched yet"}"#.to_owned(),
+        ),
+    }
+}
+
+/// Validators missing the timely target flag, of the group of the `group` query parameter or
+/// of all groups
+fn handle_missing_request(req: &Request<Body>, target: &TargetState) -> Response<Body> {
+    let group = query_params(req)
+        .into_iter()
+        .find(|(key, _)| key == "group")
+        .map(|(_, value)| query_group(target, value));
+    match &*target.validators.lock().unwrap() {
+        Some(inventory) => {
+            let missing = inventory.missing_target(group.as_deref());
+            target_json_response(target, to_string(&missing).unwrap())
+        }
+        None => json_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            r#"{"error":"no state fetched yet"}"#.to_owned(),
+        ),
+    }
+}
+
+/// Validators with the longest miss streaks, at most the `limit` query parameter
+fn handle_worst_request(req: &Request<Body>, target: &TargetState) -> Response<Body> {
+    let mut limit = DEFAULT_WORST_LIMIT;
+    for (key, value) in query_params(req) {
+        if key == "limit" {
+            match value.parse::<usize>() {
+                Ok(value) => limit = value.min(MAX_WORST_LIMIT),
+                Err(_) => return invalid_param_response(&key),
+            }
+        }
+    }
+    let worst = to_string(&target.miss_streaks.lock().unwrap().worst(limit)).unwrap();
+    target_json_response(target, worst)
+}
+
+/// Participation of the kept epochs, of the group of the `group` query parameter or all groups,
+/// and of the last `epochs` only if set
+fn handle_history_request(req: &Request<Body>, target: &TargetState) -> Response<Body> {
+    let mut group = None;
+    let mut epochs = u64::MAX;
+    for (key, value) in query_params(req) {
+        match key.as_str() {
+            "group" => group = Some(query_group(target, value)),
+            "epochs" => match value.parse() {
+                Ok(value) => epochs = value,
+                Err(_) => return invalid_param_response(&key),
+            },
+            _ => {}
+        }
+    }
+    let history = target.participation.history.lock().unwrap();
+    let json = to_string(&history.query(group.as_deref(), epochs)).unwrap();
+    target_json_response(target, json)
+}
+
+/// Target of an API request: the only one, or the one named by the `target` query parameter
+fn request_target<'a>(req: &Request<Body>, state: &'a ServerState) -> Option<&'a TargetState> {
+    if let [target] = state.targets.as_slice() {
+        return Some(target);
+    }
+    let name = query_params(req)
+        .into_iter()
+        .find(|(key, _)| key == "target")
+        .map(|(_, value)| value);
+    state
+        .targets
+        .iter()
+        .find(|target| name.as_ref() == Some(&target.name))
+        .map(|target| &**target)
+}
+
+/// `/api/v1/*` path of an unversioned `/api/*` alias, other paths unchanged
+fn api_v1_path(path: &str) -> &str {
+    match path {
+        "/api/participation" => "/api/v1/participation",
+        "/api/missing" => "/api/v1/missing",
+        "/api/worst" => "/api/v1/worst",
+        "/api/history" => "/api/v1/history",
+        _ => path,
+    }
+}
+
+/// Answer the `/api/v1/*` endpoints and their aliases, `None` for other requests
+fn handle_api_request(req: &Request<Body>, state: &ServerState) -> Option<Response<Body>> {
+    let path = api_v1_path(req.uri().path());
+    let pause = match (req.method(), path) {
+        (&Method::POST, "/api/v1/pause") => Some(true),
+        (&Method::POST, "/api/v1/resume") => Some(false),
+        (
+            _,
+            "/api/v1/active_set"
+            | "/api/v1/participation"
+            | "/api/v1/validators"
+            | "/api/v1/missing"
+            | "/api/v1/worst"
+            | "/api/v1/history"
+            | "/api/v1/report",
+        ) => None,
+        _ => return None,
+    };
+    let Some(target) = request_target(req, state) else {
+        return Some(json_response(
+            StatusCode::BAD_REQUEST,
+            r#"{"error":"missing or unknown target"}"#.to_owned(),
+        ));
+    };
+    Some(match (pause, path) {
+        (Some(pause), _) => handle_pause_request(req, state, target, pause),
+        (None, "/api/v1/active_set") => {
+            let json = to_string(&*target.active_set.lock().unwrap()).unwrap();
+            json_response(StatusCode::OK, json)
+        }
+        (None, "/api/v1/participation") => match &*target.participation.snapshot.lock().unwrap() {
+            Some(snapshot) => target_json_response(target, to_string(snapshot).unwrap()),
+            None => json_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                r#"{"error":"no state fetched yet"}"#.to_owned(),
+            ),
+        },
+        (None, "/api/v1/validators") => handle_validators_request(req, target),
+        (None, "/api/v1/missing") => handle_missing_request(req, target),
+        (None, "/api/v1/worst") => handle_worst_request(req, target),
+        (None, "/api/v1/history") => handle_history_request(req, target),
+        _ => target_json_response(target, target.report.lock().unwrap().render_json()),
+    })
+}
+
+/// Participation table of every target as HTML
+fn handle_status_page_request(state: &ServerState) -> Response<Body> {
+    let snapshots: Vec<_> = state
+        .targets
+        .iter()
+        .map(|target| target.participation.snapshot.lock().unwrap())
+        .collect();
+    // --redact-groups conflicts with --target, only a single target redacts
+    let redacted: Vec<_> = state
+        .targets
+        .iter()
+        .zip(&snapshots)
+        .map(|(target, snapshot)| {
+            target.redaction.as_ref().map(|redaction| {
+                (
+                    snapshot
+                        .as_ref()
+                        .map(|snapshot| redaction.redact_snapshot(snapshot)),
+                    redaction.redact_thresholds(&target.group_thresholds),
+                )
+            })
+        })
+        .collect();
+    let sections: Vec<StatusSection> = state
+        .targets
+        .iter()
+        .zip(&snapshots)
+        .zip(&redacted)
+        .map(|((target, snapshot), redacted)| {
+            let (snapshot, thresholds) = match redacted {
+                Some((snapshot, thresholds)) => (snapshot.as_ref(), thresholds),
+                None => (snapshot.as_ref(), &target.group_thresholds),
+            };
+            StatusSection {
+                name: &target.name,
+                snapshot,
+                thresholds,
+                defaults: target.color_thresholds,
+            }
+        })
+        .collect();
+    Response::builder()
+        .header("Content-Type", "text/html; charset=utf-8")
+        .body(Body::from(render_status_page(&sections)))
+        .unwrap()
+}
+
+async fn handle_metrics_server_request(
+    req: Request<Body>,
+    state: Arc<ServerState>,
+) -> Result<Response<Body>, Infallible> {
+    if let Some(response) = handle_api_request(&req, &state) {
+        return Ok(response);
+    }
+    if req.method() == Method::GET && req.uri().path() == "/" {
+        return Ok(handle_status_page_request(&state));
+    }
+    if req.method() == Method::GET && req.uri().path() == "/stream" {
+        return Ok(match request_target(&req, &state) {
+            Some(target) => handle_stream_request(target, &state.shutdown),
+            None => json_response(
+                StatusCode::BAD_REQUEST,
+                r#"{"error":"missing or unknown target"}"#.to_owned(),
+            ),
+        });
+    }
+
+    for target in &state.targets {
+        if let Some(staleness) = &target.staleness {
+            with_target(&target.name, || staleness.check());
+        }
+    }
+
+    // Create the response
+    let encoder = TextEncoder::new();
+    let mut metric_families = with_aliases(registry().gather(), &state.metric_aliases);
+    // --redact-groups conflicts with --target, only a single target redacts
+    for target in &state.targets {
+        if let Some(redaction) = &target.redaction {
+            redaction.apply(&mut metric_families);
+        }
+    }
+    with_static_labels(&mut metric_families, &state.static_labels, None);
+    for target in &state.targets {
+        let only = (state.targets.len() > 1).then_some(target.name.as_str());
+        with_static_labels(&mut metric_families, &target.static_labels, only);
+    }
+    let mut buffer = vec![];
+    encoder.encode(&metric_families, &mut buffer).unwrap();
+
+    Ok(Response::builder()
+        .header("Content-Type", encoder.format_type())
+        .body(Body::from(buffer))
+        .unwrap())
+}
+
+type ServerFuture = Pin<Box<dyn Future<Output = hyper::Result<()>> + Send>>;
+
+/// Serve the metrics and API of `state` on the connections of `incoming` until shutdown
+fn serve_on<I>(incoming: I, state: Arc<ServerState>, shutdown: &Shutdown) -> ServerFuture
+where
+    I: Accept + Send + 'static,
+    I::Conn: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    I::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let graceful = shutdown.clone();
+    let server = Server::builder(incoming)
+        .serve(make_service_fn(move |_conn: &I::Conn| {
+            let state = state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    handle_metrics_server_request(req, state.clone())
+                }))
+            }
+        }))
+        .with_graceful_shutdown(async move { graceful.wait().await });
+    Box::pin(server)
+}
+
+/// Listen on the Unix domain socket `path`, replacing a socket file left by a previous run
+#[cfg(unix)]
+fn bind_unix(
+    path: &str,
+) -> Result<impl Accept<Conn = tokio::net::UnixStream, Error = std::io::Error>> {
+    use std::os::unix::fs::FileTypeExt;
+    if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    let listener =
+        tokio::net::UnixListener::bind(path).with_context(|| format!("bind Unix socket {path}"))?;
+    Ok(hyper::server::accept::poll_fn(move |cx| {
+        listener
+            .poll_accept(cx)
+            .map(|accepted| Some(accepted.map(|(stream, _)| stream)))
+    }))
+}
+
+#[cfg(not(unix))]
+fn bind_unix(_path: &str) -> Result<AddrIncoming> {
+    Err(anyhow::anyhow!("--listen-unix is only supported on Unix"))
+}
+
+/// Bind the metrics server of `targets`, which runs until shutdown
+pub(crate) fn serve(
+    args: &Config,
+    targets: Vec<Arc<TargetState>>,
+    shutdown: &Shutdown,
+) -> Result<impl Future<Output = ()>> {
+    let server_state = Arc::new(ServerState {
+        metric_aliases: args.metric_alias.clone(),
+        static_labels: args.label.clone(),
+        api_token: args.api_token.clone(),
+        targets,
+        shutdown: shutdown.clone(),
+    });
+    let server = match &args.listen_unix {
+        Some(path) => {
+            let server = serve_on(bind_unix(path)?, server_state, shutdown);
+            println!("Server is running on unix:{}", path);
+            server
+        }
+        None => {
+            let addr = SocketAddr::new(args.address.parse()?, args.port);
+            let server = serve_on(AddrIncoming::bind(&addr)?, server_state, shutdown);
+            println!("Server is running on http://{}", addr);
+            server
+        }
+    };
+    let shutdown = shutdown.clone();
+    Ok(async move {
+        if let Err(e) = server.await {
+            eprintln!("server error: {}", e);
+            shutdown.trigger();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_v1_path_test() {
+        assert_eq!(api_v1_path("/api/history"), "/api/v1/history");
+        assert_eq!(api_v1_path("/api/v1/history"), "/api/v1/history");
+        assert_eq!(api_v1_path("/api/report"), "/api/report");
+    }
+
+    #[test]
+    fn query_params_test() {
+        let req = Request::builder()
+            .uri("/api/v1/history?group=lh%20geth&epochs=2&group=b")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            query_params(&req),
+            vec![
+                ("group".to_owned(), "lh geth".to_owned()),
+                ("epochs".to_owned(), "2".to_owned()),
+                ("group".to_owned(), "b".to_owned()),
+            ]
+        );
+        let req = Request::builder()
+            .uri("/api/v1/history")
+            .body(Body::empty())
+            .unwrap();
+        assert!(query_params(&req).is_empty());
+    }
+
+    #[test]
+    fn stale_while_syncing_test() {
+        let group = "stale-while-syncing";
+        set_gauge(&TARGET_PARTICIPATION, &[group], 0.9);
+        let staleness = Staleness {
+            max_age_secs: 1e9,
+            zero: true,
+            started_at: unix_time(),
+            groups: vec![group.to_owned()],
+        };
+
+        export_skipped_syncing(true);
+        assert_eq!(get_gauge(&PARTICIPATION_STALE, &[]), Some(1.0));
+        staleness.check();
+        assert_eq!(get_gauge(&PARTICIPATION_STALE, &[]), Some(1.0));
+        assert_eq!(get_gauge(&TARGET_PARTICIPATION, &[group]), Some(0.0));
+
+        export_skipped_syncing(false);
+        assert_eq!(get_gauge(&PARTICIPATION_STALE, &[]), Some(0.0));
+        staleness.check();
+        assert_eq!(get_gauge(&PARTICIPATION_STALE, &[]), Some(0.0));
+    }
+}
@@ -0,0 +1,53 @@
+use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// Shutdown request shared by the server and the collection tasks, which stop at their next
+/// wait instead of mid-fetch
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl Shutdown {
+    pub fn trigger(&self) {
+        self.requested.store(true, Ordering::Relaxed);
+        self.notify.notify_waiters();
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::Relaxed)
+    }
+
+    /// Resolve once shutdown is requested, immediately if it already was
+    pub async fn wait(&self) {
+        loop {
+            // Registered before checking the flag to not miss a concurrent trigger
+            let notified = self.notify.notified();
+            if self.is_requested() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Wait for SIGINT or SIGTERM, returning the signal name
+#[cfg(unix)]
+pub async fn wait_for_signal() -> Result<&'static str> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result.map(|_| "SIGINT").map_err(Into::into),
+        _ = terminate.recv() => Ok("SIGTERM"),
+    }
+}
+
+/// Wait for Ctrl-C, returning the signal name
+#[cfg(not(unix))]
+pub async fn wait_for_signal() -> Result<&'static str> {
+    tokio::signal::ctrl_c().await?;
+    Ok("SIGINT")
+}
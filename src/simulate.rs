@@ -0,0 +1,164 @@
+use crate::config::ConfigSpec;
+use crate::ranges::{range_name_of, IndexRanges};
+use crate::ssz_state::{Justification, StatePartial, Validator, FAR_FUTURE_EPOCH};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+const SIMULATED_BALANCE_GWEI: u64 = 32_000_000_000;
+/// Share of target attesters that also get the head right
+const HEAD_GIVEN_TARGET: f64 = 0.97;
+/// Share of target misses that still get the source right
+const SOURCE_GIVEN_TARGET_MISS: f64 = 0.5;
+
+/// Synthetic chain served by `--simulate`. Epochs count from 0 at start.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scenario {
+    /// Wall clock seconds between simulated epochs
+    pub seconds_per_epoch: u64,
+    /// Target participation of every group outside incidents
+    pub participation: f32,
+    pub incidents: Vec<Incident>,
+}
+
+/// Target participation of `group` set to `participation` from epoch `start` to `end` inclusive
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Incident {
+    pub group: String,
+    pub start: u64,
+    pub end: u64,
+    pub participation: f32,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            seconds_per_epoch: 12,
+            participation: 0.98,
+            incidents: vec![],
+        }
+    }
+}
+
+impl Scenario {
+    /// Parse a JSON scenario, e.g.
+    /// ```json
+    /// {"participation": 0.98, "incidents": [{"group": "lh-geth-0", "start": 5, "end": 10, "participation": 0.4}]}
+    /// ```
+    pub fn parse(input: &str) -> Result<Self> {
+        let scenario: Scenario = serde_json::from_str(input)?;
+        let valid_ratio = |ratio: f32| (0.0..=1.0).contains(&ratio);
+        if !valid_ratio(scenario.participation) {
+            return Err(anyhow!("participation must be within 0 and 1"));
+        }
+        for incident in &scenario.incidents {
+            if incident.end < incident.start || !valid_ratio(incident.participation) {
+                return Err(anyhow!("invalid incident for group {}", incident.group));
+            }
+        }
+        Ok(scenario)
+    }
+
+    /// Target participation of `group` in `epoch`, the last matching incident wins
+    pub fn participation(&self, group: &str, epoch: u64) -> f32 {
+        self.incidents
+            .iter()
+            .rev()
+            .find(|incident| {
+                incident.group == group && (incident.start..=incident.end).contains(&epoch)
+            })
+            .map_or(self.participation, |incident| incident.participation)
+    }
+
+    /// State at the start of `epoch + 1`, whose previous epoch participation is `epoch`'s.
+    /// Validators outside `ranges` participate at the base rate.
+    pub fn state(&self, config: &ConfigSpec, ranges: &IndexRanges, epoch: u64) -> StatePartial {
+        let validator_count = ranges.iter().map(|(_, range)| range.end).max().unwrap_or(0);
+        let flags = &config.participation_flags;
+        let participation: Vec<u8> = (0..validator_count)
+            .map(|index| {
+                let target_rate = match range_name_of(ranges, index) {
+                    Some(group) => self.participation(group, epoch),
+                    None => self.participation,
+                } as f64;
+                let target = unit_random(epoch, index, 0) < target_rate;
+                let source = target || unit_random(epoch, index, 1) < SOURCE_GIVEN_TARGET_MISS;
+                let head = target && unit_random(epoch, index, 2) < HEAD_GIVEN_TARGET;
+                (if source { flags.source } else { 0 })
+                    | (if target { flags.target } else { 0 })
+                    | (if head { flags.head } else { 0 })
+            })
+            .collect();
+        StatePartial {
+            slot: (epoch + 1) * config.slots_per_epoch,
+            previous_epoch_participation: participation.clone(),
+            current_epoch_participation: participation,
+            inactivity_scores: vec![0; validator_count],
+            block_roots: vec![],
+            balances: vec![SIMULATED_BALANCE_GWEI; validator_count],
+            validators: vec![
+                Validator {
+                    withdrawal_credentials: [0; 32],
+                    effective_balance: SIMULATED_BALANCE_GWEI,
+                    slashed: false,
+                    activation_eligibility_epoch: 0,
+                    activation_epoch: 0,
+                    exit_epoch: FAR_FUTURE_EPOCH,
+                    withdrawable_epoch: FAR_FUTURE_EPOCH,
+                };
+                validator_count
+            ],
+            // A healthy chain, justifying every epoch
+            justification: Justification {
+                bits: 0b1111,
+                previous_justified_epoch: epoch.saturating_sub(1),
+                current_justified_epoch: epoch,
+                finalized_epoch: epoch.saturating_sub(1),
+            },
+        }
+    }
+}
+
+/// Deterministic uniform value in [0, 1) for the validator `index` in `epoch`, one independent
+/// stream per flag
+fn unit_random(epoch: u64, index: usize, stream: u64) -> f64 {
+    let z = splitmix64(splitmix64(splitmix64(epoch) ^ index as u64) ^ stream);
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::participation::group_target_participation;
+
+    #[test]
+    fn simulated_incident() {
+        let scenario = Scenario::parse(
+            r#"{"participation": 0.95, "incidents": [{"group": "b", "start": 2, "end": 3, "participation": 0.4}]}"#,
+        )
+        .unwrap();
+        let config = ConfigSpec::mainnet();
+        let ranges = vec![("a".to_owned(), 0..2000), ("b".to_owned(), 2000..4000)];
+        let target = |epoch: u64| -> Vec<f32> {
+            let state = scenario.state(&config, &ranges, epoch);
+            group_target_participation(&config.participation_flags, &ranges, &state, epoch + 1)
+                .iter()
+                .map(|(_, _, summary)| summary.target_participation_ratio)
+                .collect()
+        };
+        for (epoch, expected) in [(1, [0.95, 0.95]), (2, [0.95, 0.4]), (4, [0.95, 0.95])] {
+            for (ratio, expected) in target(epoch).iter().zip(expected) {
+                assert!((ratio - expected).abs() < 0.03, "{ratio} vs {expected}");
+            }
+        }
+        assert!(Scenario::parse(r#"{"participation": 1.5}"#).is_err());
+    }
+}
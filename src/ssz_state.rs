@@ -3,13 +3,187 @@ use anyhow::{anyhow, Context, Result};
 use byteorder::{ByteOrder, LittleEndian};
 use bytes::{Buf, Bytes};
 use std::ops::Range;
+use std::str::FromStr;
 
-#[derive(Debug)]
+/// Fields of a beacon state relevant to metrics
+#[derive(Debug, PartialEq)]
 pub struct StatePartial {
     pub slot: u64,
     pub previous_epoch_participation: Vec<u8>,
     pub current_epoch_participation: Vec<u8>,
     pub inactivity_scores: Vec<u64>,
+    pub block_roots: Vec<[u8; 32]>,
+    pub balances: Vec<u64>,
+    pub validators: Vec<Validator>,
+    pub justification: Justification,
+}
+
+/// Justification bits and checkpoint epochs of a state
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Justification {
+    /// justification_bits, the lowest bit is set if the state's epoch is justified, the next if
+    /// the epoch before is, and so on
+    pub bits: u8,
+    pub previous_justified_epoch: u64,
+    pub current_justified_epoch: u64,
+    pub finalized_epoch: u64,
+}
+
+/// Epochs tracked by justification_bits
+pub const JUSTIFICATION_BITS_LENGTH: usize = 4;
+
+impl Justification {
+    /// Whether the epoch `epochs_ago` epochs before the state's is justified
+    pub fn is_justified(&self, epochs_ago: usize) -> bool {
+        self.bits & (1 << epochs_ago) != 0
+    }
+}
+
+/// Fields of a validator record relevant to metrics, the pubkey is not decoded
+#[derive(Debug, Clone, PartialEq)]
+pub struct Validator {
+    pub withdrawal_credentials: [u8; 32],
+    pub effective_balance: u64,
+    pub slashed: bool,
+    pub activation_eligibility_epoch: u64,
+    pub activation_epoch: u64,
+    pub exit_epoch: u64,
+    pub withdrawable_epoch: u64,
+}
+
+// class Validator(Container):
+//     pubkey: BLSPubkey - 48 bytes
+//     withdrawal_credentials: Bytes32 - 32 bytes
+//     effective_balance: Gwei - 8 bytes
+//     slashed: boolean - 1 byte
+//     activation_eligibility_epoch: Epoch - 8 bytes
+//     activation_epoch: Epoch - 8 bytes
+//     exit_epoch: Epoch - 8 bytes
+//     withdrawable_epoch: Epoch - 8 bytes
+pub const FAR_FUTURE_EPOCH: u64 = u64::MAX;
+
+const VALIDATOR_SIZE: usize = 48 + 32 + 8 + 1 + 8 + 8 + 8 + 8;
+
+impl Validator {
+    fn from_ssz(buf: &[u8]) -> Self {
+        let read_u64 = |offset: usize| LittleEndian::read_u64(&buf[offset..offset + 8]);
+        Validator {
+            withdrawal_credentials: buf[48..48 + 32].try_into().unwrap(),
+            effective_balance: read_u64(48 + 32),
+            slashed: buf[48 + 32 + 8] != 0,
+            activation_eligibility_epoch: read_u64(48 + 32 + 8 + 1),
+            activation_epoch: read_u64(48 + 32 + 8 + 1 + 8),
+            exit_epoch: read_u64(48 + 32 + 8 + 1 + 16),
+            withdrawable_epoch: read_u64(48 + 32 + 8 + 1 + 24),
+        }
+    }
+
+    /// Activated and not yet exited at `epoch`
+    pub fn is_active(&self, epoch: u64) -> bool {
+        self.activation_epoch <= epoch && epoch < self.exit_epoch
+    }
+
+    /// Exit initiated but not yet effective at `epoch`
+    pub fn is_exiting(&self, epoch: u64) -> bool {
+        self.exit_epoch != FAR_FUTURE_EPOCH && epoch < self.exit_epoch
+    }
+
+    /// Exited at `epoch`, but its balance not yet withdrawable
+    pub fn is_exited(&self, epoch: u64) -> bool {
+        self.exit_epoch <= epoch && epoch < self.withdrawable_epoch
+    }
+
+    pub fn is_withdrawable(&self, epoch: u64) -> bool {
+        self.withdrawable_epoch <= epoch
+    }
+
+    /// Status at `epoch` as defined by the beacon API validators endpoint
+    pub fn status(&self, epoch: u64) -> ValidatorStatus {
+        if self.activation_epoch > epoch {
+            if self.activation_eligibility_epoch == FAR_FUTURE_EPOCH {
+                ValidatorStatus::PendingInitialized
+            } else {
+                ValidatorStatus::PendingQueued
+            }
+        } else if epoch < self.exit_epoch {
+            if self.exit_epoch == FAR_FUTURE_EPOCH {
+                ValidatorStatus::ActiveOngoing
+            } else if self.slashed {
+                ValidatorStatus::ActiveSlashed
+            } else {
+                ValidatorStatus::ActiveExiting
+            }
+        } else if epoch < self.withdrawable_epoch {
+            if self.slashed {
+                ValidatorStatus::ExitedSlashed
+            } else {
+                ValidatorStatus::ExitedUnslashed
+            }
+        } else if self.effective_balance > 0 {
+            ValidatorStatus::WithdrawalPossible
+        } else {
+            ValidatorStatus::WithdrawalDone
+        }
+    }
+}
+
+/// Validator status as defined by the beacon API
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidatorStatus {
+    PendingInitialized,
+    PendingQueued,
+    ActiveOngoing,
+    ActiveExiting,
+    ActiveSlashed,
+    ExitedUnslashed,
+    ExitedSlashed,
+    WithdrawalPossible,
+    WithdrawalDone,
+}
+
+impl ValidatorStatus {
+    pub const ALL: [ValidatorStatus; 9] = [
+        ValidatorStatus::PendingInitialized,
+        ValidatorStatus::PendingQueued,
+        ValidatorStatus::ActiveOngoing,
+        ValidatorStatus::ActiveExiting,
+        ValidatorStatus::ActiveSlashed,
+        ValidatorStatus::ExitedUnslashed,
+        ValidatorStatus::ExitedSlashed,
+        ValidatorStatus::WithdrawalPossible,
+        ValidatorStatus::WithdrawalDone,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ValidatorStatus::PendingInitialized => "pending_initialized",
+            ValidatorStatus::PendingQueued => "pending_queued",
+            ValidatorStatus::ActiveOngoing => "active_ongoing",
+            ValidatorStatus::ActiveExiting => "active_exiting",
+            ValidatorStatus::ActiveSlashed => "active_slashed",
+            ValidatorStatus::ExitedUnslashed => "exited_unslashed",
+            ValidatorStatus::ExitedSlashed => "exited_slashed",
+            ValidatorStatus::WithdrawalPossible => "withdrawal_possible",
+            ValidatorStatus::WithdrawalDone => "withdrawal_done",
+        }
+    }
+}
+
+impl StatePartial {
+    /// Whether a block was proposed at `slot`. Empty slots repeat the previous root in
+    /// block_roots. Returns `None` if `slot` is not covered by the state's block_roots.
+    pub fn block_proposed_at(&self, slot: u64) -> Option<bool> {
+        let len = self.block_roots.len() as u64;
+        if slot >= self.slot || self.slot - slot >= len {
+            return None;
+        }
+        if slot == 0 {
+            return Some(true);
+        }
+        let root = self.block_roots[(slot % len) as usize];
+        let prev_root = self.block_roots[((slot - 1) % len) as usize];
+        Some(root != prev_root)
+    }
 }
 
 // class BeaconState(Container):
@@ -48,83 +222,502 @@ pub struct StatePartial {
 //     current_sync_committee: SyncCommittee  # [New in Altair]
 //     next_sync_committee: SyncCommittee  # [New in Altair]
 
+//     # Execution
+//     latest_execution_payload_header: ExecutionPayloadHeader - 4 bytes (offset) [New in Bellatrix]
+//     # Withdrawals
+//     next_withdrawal_index: WithdrawalIndex [New in Capella]
+//     next_withdrawal_validator_index: ValidatorIndex [New in Capella]
+//     # Deep history valid from Capella onwards
+//     historical_summaries: List[HistoricalSummary, HISTORICAL_ROOTS_LIMIT] [New in Capella]
+//     ... [New in Electra] pending deposits, withdrawals and consolidations
+//
+// Phase0 has previous_epoch_attestations and current_epoch_attestations in place of the
+// participation lists, and no inactivity_scores. From Altair through Electra every field up to
+// the sync committees keeps its position, forks only append fields at the end.
+
 // const SLOTS_PER_HISTORICAL_ROOT: usize = usize::pow(2, 13);
 // const EPOCHS_PER_HISTORICAL_VECTOR: usize = usize::pow(2, 16);
 // const EPOCHS_PER_SLASHINGS_VECTOR: usize = usize::pow(2, 13);
 
-pub fn deserialize_partial_state(config: &ConfigSpec, state: &Bytes) -> Result<StatePartial> {
-    // Const derived from config
-    let slot_offset = 8 + 32;
-    let slot = read_u64(state, slot_offset).context("slot_offset out of bounds")?;
-    let previous_epoch_participation_offset_offset = 8
-        + 32  // genesis_validators_root
-        + 8   // slot
-        + 16  // fork
-        + 112 // latest_block_header
-        + 32 * config.slots_per_historical_root // block_roots
-        + 32 * config.slots_per_historical_root // state_roots
-        + 4   // historical_roots
-        + 72  // eth1_data
-        + 4   // eth1_data_votes
-        + 8   // eth1_deposit_index
-        + 4   // validators
-        + 4   // balances
-        + 32 * config.epochs_per_historical_vector // randao_mixes
-        + 8 * config.epochs_per_slashings_vector; // slashings
-
-    let current_epoch_participation_offset_offset = previous_epoch_participation_offset_offset + 4; // previous_epoch_participation
-
-    let inactivity_scores_offset_offset = current_epoch_participation_offset_offset
-        + 4   // current_epoch_participation
-        + 1   // justification_bits
-        + 40  // previous_justified_checkpoint
-        + 40  // current_justified_checkpoint
-        + 40; // finalized_checkpoint
-
-    // Read offset values from state
-    let previous_epoch_participation_offset =
-        read_offset(state, previous_epoch_participation_offset_offset)
-            .context("previous_epoch_participation_offset_offset out of bounds")?;
-    let current_epoch_participation_offset =
-        read_offset(state, current_epoch_participation_offset_offset)
-            .context("current_epoch_participation_offset_offset out of bounds")?;
-    let inactivity_scores_offset = read_offset(state, inactivity_scores_offset_offset)
-        .context("inactivity_scores_offset_offset out of bounds")?;
-
-    // Assume well-formed state, derive validator count from previous_epoch_participation size.
-    // Altair state does not have any other variable size field after inactivity_scores, however Bellatrix state does.
-    // So infering the size of inactivity_scores from previous_epoch_participation prevents this code from having
-    // to be fork aware, for states after phase0.
-    let validator_count = current_epoch_participation_offset - previous_epoch_participation_offset;
-
-    // With offset values, read slices
-    let previous_epoch_participation = slice(
-        state,
-        previous_epoch_participation_offset
-            ..(previous_epoch_participation_offset + validator_count),
-    )
-    .context("previous_epoch_participation_offset out of bounds")?
-    .to_vec();
-    let current_epoch_participation = slice(
-        state,
-        current_epoch_participation_offset..(current_epoch_participation_offset + validator_count),
-    )
-    .context("current_epoch_participation_offset out of bounds")?
-    .to_vec();
-    let inactivity_scores = convert_u8_to_u64(
-        &slice(
-            state,
-            inactivity_scores_offset..(inactivity_scores_offset + validator_count * 8),
+/// Position of the slot in all forks
+const SLOT_OFFSET: usize = 8 + 32;
+/// Position of block_roots in all forks
+const BLOCK_ROOTS_OFFSET: usize = 8 + 32 + 8 + 16 + 112;
+/// A SyncCommittee holds SYNC_COMMITTEE_SIZE pubkeys plus the aggregate pubkey
+const BLS_PUBKEY_SIZE: usize = 48;
+
+/// Response header announcing the fork of the returned state
+pub const CONSENSUS_VERSION_HEADER: &str = "Eth-Consensus-Version";
+
+/// Consensus fork of a state, which determines its SSZ layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Fork {
+    Phase0,
+    Altair,
+    Bellatrix,
+    Capella,
+    Deneb,
+    Electra,
+}
+
+impl FromStr for Fork {
+    type Err = anyhow::Error;
+
+    /// Parse the fork name as sent in the `Eth-Consensus-Version` header
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "phase0" => Ok(Fork::Phase0),
+            "altair" => Ok(Fork::Altair),
+            "bellatrix" => Ok(Fork::Bellatrix),
+            "capella" => Ok(Fork::Capella),
+            "deneb" => Ok(Fork::Deneb),
+            "electra" => Ok(Fork::Electra),
+            _ => Err(anyhow!("Unsupported fork: {}", s)),
+        }
+    }
+}
+
+impl Fork {
+    /// All forks with a participation flags layout, newest first
+    pub const WITH_PARTICIPATION: [Fork; 5] = [
+        Fork::Electra,
+        Fork::Deneb,
+        Fork::Capella,
+        Fork::Bellatrix,
+        Fork::Altair,
+    ];
+
+    /// Fork active at `epoch` according to the fork schedule of `config`
+    pub fn at_epoch(config: &ConfigSpec, epoch: u64) -> Fork {
+        let schedule = [
+            (config.electra_fork_epoch, Fork::Electra),
+            (config.deneb_fork_epoch, Fork::Deneb),
+            (config.capella_fork_epoch, Fork::Capella),
+            (config.bellatrix_fork_epoch, Fork::Bellatrix),
+            (config.altair_fork_epoch, Fork::Altair),
+        ];
+        schedule
+            .into_iter()
+            .find(|(fork_epoch, _)| fork_epoch.is_some_and(|fork_epoch| epoch >= fork_epoch))
+            .map(|(_, fork)| fork)
+            .unwrap_or(Fork::Phase0)
+    }
+}
+
+/// Positions of the offsets of the variable size fields read from the state
+struct FieldOffsets {
+    validators: usize,
+    balances: usize,
+    previous_epoch_participation: usize,
+    current_epoch_participation: usize,
+    inactivity_scores: usize,
+    /// Position of justification_bits, followed by the three checkpoints
+    justification_bits: usize,
+    /// Offset of the first variable size field after inactivity_scores, if any
+    after_inactivity_scores: Option<usize>,
+}
+
+impl FieldOffsets {
+    fn for_fork(config: &ConfigSpec, fork: Fork) -> Result<Self> {
+        if fork == Fork::Phase0 {
+            return Err(anyhow!(
+                "phase0 states have no participation flags, only altair and later are supported"
+            ));
+        }
+
+        let validators = 8
+            + 32  // genesis_validators_root
+            + 8   // slot
+            + 16  // fork
+            + 112 // latest_block_header
+            + 32 * config.slots_per_historical_root // block_roots
+            + 32 * config.slots_per_historical_root // state_roots
+            + 4   // historical_roots
+            + 72  // eth1_data
+            + 4   // eth1_data_votes
+            + 8; // eth1_deposit_index
+
+        let balances = validators + 4; // validators
+
+        let previous_epoch_participation = balances
+            + 4   // balances
+            + 32 * config.epochs_per_historical_vector // randao_mixes
+            + 8 * config.epochs_per_slashings_vector; // slashings
+
+        let current_epoch_participation = previous_epoch_participation + 4; // previous_epoch_participation
+
+        let justification_bits = current_epoch_participation + 4; // current_epoch_participation
+
+        let inactivity_scores = justification_bits
+            + 1   // justification_bits
+            + 40  // previous_justified_checkpoint
+            + 40  // current_justified_checkpoint
+            + 40; // finalized_checkpoint
+
+        let sync_committee = (config.sync_committee_size + 1) * BLS_PUBKEY_SIZE;
+        let after_inactivity_scores = if fork >= Fork::Bellatrix {
+            Some(
+                inactivity_scores
+                + 4 // inactivity_scores
+                + sync_committee // current_sync_committee
+                + sync_committee, // next_sync_committee
+            )
+        } else {
+            None
+        };
+
+        Ok(Self {
+            validators,
+            balances,
+            previous_epoch_participation,
+            current_epoch_participation,
+            inactivity_scores,
+            justification_bits,
+            after_inactivity_scores,
+        })
+    }
+}
+
+/// Name, position and value of each variable size field offset read from `state` with the
+/// layout of `fork`. A debugging aid when the decoder misbehaves against a new client version.
+pub fn describe_field_offsets(
+    config: &ConfigSpec,
+    fork: Fork,
+    state: &Bytes,
+) -> Result<Vec<(&'static str, usize, usize)>> {
+    let offsets = FieldOffsets::for_fork(config, fork)?;
+    let mut positions = vec![
+        ("validators", offsets.validators),
+        ("balances", offsets.balances),
+        (
+            "previous_epoch_participation",
+            offsets.previous_epoch_participation,
+        ),
+        (
+            "current_epoch_participation",
+            offsets.current_epoch_participation,
+        ),
+        ("inactivity_scores", offsets.inactivity_scores),
+    ];
+    if let Some(position) = offsets.after_inactivity_scores {
+        positions.push(("latest_execution_payload_header", position));
+    }
+    positions
+        .into_iter()
+        .map(|(name, position)| {
+            let value = read_offset(state, position).with_context(|| format!("{name} offset"))?;
+            Ok((name, position, value))
+        })
+        .collect()
+}
+
+/// Deserialize the fields of `StatePartial` from a full SSZ serialized state. If `fork` is not
+/// known, for example because the node did not send the `Eth-Consensus-Version` header, it is
+/// derived from the state's slot and the fork schedule in `config`.
+pub fn deserialize_partial_state(
+    config: &ConfigSpec,
+    fork: Option<Fork>,
+    state: &Bytes,
+) -> Result<StatePartial> {
+    let mut decoder = StateDecoder::new(config, fork);
+    decoder.push(state)?;
+    decoder.finish()
+}
+
+/// Record size of each decoded variable size field, in state order
+const FIELD_RECORD_SIZES: [usize; 5] = [VALIDATOR_SIZE, 8, 1, 1, 8];
+
+/// Incremental decoder of a `StatePartial` from an SSZ state received in chunks. Keeps the fixed
+/// size part up to the last offset read, decodes validators, balances, participation and
+/// inactivity scores as their bytes arrive and drops the rest, so memory stays close to the
+/// size of the decoded fields instead of the whole state.
+pub struct StateDecoder<'a> {
+    config: &'a ConfigSpec,
+    fork: Option<Fork>,
+    /// Bytes of the state received so far
+    position: usize,
+    /// Start of the state, until it covers the offsets of `FieldOffsets`
+    header: Vec<u8>,
+    layout: Option<Layout>,
+    /// Bytes of a record split across chunks
+    pending: Vec<u8>,
+    state: StatePartial,
+}
+
+/// Byte ranges of the decoded variable size fields, in state order
+struct Layout {
+    fields: [Range<usize>; 5],
+    /// inactivity_scores is the last variable size field before bellatrix, its end is only
+    /// checked against the end of the state
+    ends_state: bool,
+}
+
+impl<'a> StateDecoder<'a> {
+    pub fn new(config: &'a ConfigSpec, fork: Option<Fork>) -> Self {
+        Self {
+            config,
+            fork,
+            position: 0,
+            header: vec![],
+            layout: None,
+            pending: vec![],
+            state: StatePartial {
+                slot: 0,
+                previous_epoch_participation: vec![],
+                current_epoch_participation: vec![],
+                inactivity_scores: vec![],
+                block_roots: vec![],
+                balances: vec![],
+                validators: vec![],
+                justification: Justification::default(),
+            },
+        }
+    }
+
+    /// Whether all decoded fields were received and the rest of the state can be dropped
+    pub fn is_complete(&self) -> bool {
+        self.layout
+            .as_ref()
+            .is_some_and(|layout| !layout.ends_state && self.position >= layout.fields[4].end)
+    }
+
+    /// Consume the next `chunk` of the state
+    pub fn push(&mut self, mut chunk: &[u8]) -> Result<()> {
+        if self.layout.is_none() {
+            let header_len = self.header_len(&chunk[..chunk.len().min(SLOT_OFFSET + 8)])?;
+            let taken = chunk.len().min(header_len - self.header.len());
+            self.header.extend_from_slice(&chunk[..taken]);
+            self.position += taken;
+            chunk = &chunk[taken..];
+            if self.header.len() < header_len {
+                return Ok(());
+            }
+            self.read_layout()?;
+        }
+        let Some(layout) = &self.layout else {
+            return Ok(());
+        };
+        while !chunk.is_empty() {
+            let Some(field) = layout
+                .fields
+                .iter()
+                .position(|range| self.position < range.end)
+            else {
+                // Past the last decoded field
+                self.position += chunk.len();
+                return Ok(());
+            };
+            let range = &layout.fields[field];
+            if self.position < range.start {
+                let skipped = chunk.len().min(range.start - self.position);
+                self.position += skipped;
+                chunk = &chunk[skipped..];
+                continue;
+            }
+            let taken = chunk.len().min(range.end - self.position);
+            self.position += taken;
+            decode_records(&mut self.state, field, &mut self.pending, &chunk[..taken]);
+            chunk = &chunk[taken..];
+        }
+        Ok(())
+    }
+
+    /// Length of the start of the state holding every offset read, once its slot and so its
+    /// fork is known. `start` is the beginning of the next chunk.
+    fn header_len(&mut self, start: &[u8]) -> Result<usize> {
+        let fork = match self.fork {
+            Some(fork) => fork,
+            None => {
+                let mut slot_bytes = self.header.clone();
+                slot_bytes.extend_from_slice(start);
+                if slot_bytes.len() < SLOT_OFFSET + 8 {
+                    // Not enough to read the slot yet, buffer up to it
+                    return Ok(SLOT_OFFSET + 8);
+                }
+                let slot = read_u64(&Bytes::from(slot_bytes), SLOT_OFFSET)?;
+                Fork::at_epoch(self.config, slot / self.config.slots_per_epoch)
+            }
+        };
+        self.fork = Some(fork);
+        let offsets = FieldOffsets::for_fork(self.config, fork)?;
+        Ok(offsets
+            .after_inactivity_scores
+            .unwrap_or(offsets.inactivity_scores)
+            + 4)
+    }
+
+    /// Read the slot, block roots and field offsets from the complete header
+    fn read_layout(&mut self) -> Result<()> {
+        let config = self.config;
+        let fork = self.fork.ok_or_else(|| anyhow!("fork not known"))?;
+        let offsets = FieldOffsets::for_fork(config, fork)?;
+        let header = Bytes::from(std::mem::take(&mut self.header));
+
+        // Read offset values from state
+        let validators_offset = read_offset(&header, offsets.validators)
+            .context("validators_offset_offset out of bounds")?;
+        let balances_offset = read_offset(&header, offsets.balances)
+            .context("balances_offset_offset out of bounds")?;
+        let previous_epoch_participation_offset =
+            read_offset(&header, offsets.previous_epoch_participation)
+                .context("previous_epoch_participation_offset_offset out of bounds")?;
+        let current_epoch_participation_offset =
+            read_offset(&header, offsets.current_epoch_participation)
+                .context("current_epoch_participation_offset_offset out of bounds")?;
+        let inactivity_scores_offset = read_offset(&header, offsets.inactivity_scores)
+            .context("inactivity_scores_offset_offset out of bounds")?;
+
+        // Assume well-formed state, derive validator count from previous_epoch_participation size.
+        let validator_count = current_epoch_participation_offset
+            .checked_sub(previous_epoch_participation_offset)
+            .ok_or_else(|| anyhow!("participation offsets out of order, wrong fork {:?}?", fork))?;
+        let inactivity_scores_end = inactivity_scores_offset + validator_count * 8;
+        // inactivity_scores is bounded by the next variable size field, a mismatch with the
+        // participation lists means the layout does not correspond to the state's fork.
+        if let Some(offset_position) = offsets.after_inactivity_scores {
+            let next_offset = read_offset(&header, offset_position)
+                .context("latest_execution_payload_header_offset_offset out of bounds")?;
+            if next_offset != inactivity_scores_end {
+                return Err(anyhow!(
+                    "inactivity_scores length {} does not match validator count {}, wrong fork {:?}?",
+                    next_offset.saturating_sub(inactivity_scores_offset),
+                    validator_count,
+                    fork
+                ));
+            }
+        }
+
+        // balances is followed by previous_epoch_participation
+        if previous_epoch_participation_offset.saturating_sub(balances_offset)
+            != validator_count * 8
+        {
+            return Err(anyhow!(
+                "balances length does not match validator count {}, wrong fork {:?}?",
+                validator_count,
+                fork
+            ));
+        }
+
+        // validators is followed by balances
+        if balances_offset.saturating_sub(validators_offset) != validator_count * VALIDATOR_SIZE {
+            return Err(anyhow!(
+                "validators length does not match validator count {}, wrong fork {:?}?",
+                validator_count,
+                fork
+            ));
+        }
+
+        if validators_offset < header.len()
+            || current_epoch_participation_offset + validator_count > inactivity_scores_offset
+        {
+            return Err(anyhow!("variable size field offsets out of order"));
+        }
+
+        self.state.slot = read_u64(&header, SLOT_OFFSET).context("slot_offset out of bounds")?;
+        let bits = offsets.justification_bits;
+        // Each checkpoint is an epoch followed by a root
+        self.state.justification = Justification {
+            bits: *header
+                .get(bits)
+                .context("justification_bits out of bounds")?,
+            previous_justified_epoch: read_u64(&header, bits + 1)
+                .context("previous_justified_checkpoint out of bounds")?,
+            current_justified_epoch: read_u64(&header, bits + 1 + 40)
+                .context("current_justified_checkpoint out of bounds")?,
+            finalized_epoch: read_u64(&header, bits + 1 + 80)
+                .context("finalized_checkpoint out of bounds")?,
+        };
+        self.state.block_roots = slice(
+            &header,
+            BLOCK_ROOTS_OFFSET..(BLOCK_ROOTS_OFFSET + 32 * config.slots_per_historical_root),
         )
-        .context("current_epoch_participation_offset out of bounds")?,
-    );
+        .context("block_roots out of bounds")?
+        .chunks_exact(32)
+        .map(|root| root.try_into().unwrap())
+        .collect();
+        self.state.validators.reserve_exact(validator_count);
+        self.state.balances.reserve_exact(validator_count);
+        self.state
+            .previous_epoch_participation
+            .reserve_exact(validator_count);
+        self.state
+            .current_epoch_participation
+            .reserve_exact(validator_count);
+        self.state.inactivity_scores.reserve_exact(validator_count);
 
-    Ok(StatePartial {
-        slot,
-        previous_epoch_participation,
-        current_epoch_participation,
-        inactivity_scores,
-    })
+        self.layout = Some(Layout {
+            fields: [
+                validators_offset..balances_offset,
+                balances_offset..previous_epoch_participation_offset,
+                previous_epoch_participation_offset
+                    ..(previous_epoch_participation_offset + validator_count),
+                current_epoch_participation_offset
+                    ..(current_epoch_participation_offset + validator_count),
+                inactivity_scores_offset..inactivity_scores_end,
+            ],
+            ends_state: offsets.after_inactivity_scores.is_none(),
+        });
+        Ok(())
+    }
+
+    /// Decoded state, once the whole state or all decoded fields were pushed
+    pub fn finish(self) -> Result<StatePartial> {
+        let Some(layout) = self.layout else {
+            return Err(anyhow!(
+                "state ended at {} bytes, before its field offsets",
+                self.position
+            ));
+        };
+        let end = layout.fields[4].end;
+        if self.position < end {
+            return Err(anyhow!(
+                "state ended at {} bytes, inside its fields ending at {}",
+                self.position,
+                end
+            ));
+        }
+        if layout.ends_state && self.position != end {
+            return Err(anyhow!(
+                "inactivity_scores does not end the state of {} bytes, wrong fork {:?}?",
+                self.position,
+                self.fork
+            ));
+        }
+        Ok(self.state)
+    }
+}
+
+/// Decode the complete records of `field` in `pending` followed by `bytes`, keeping the
+/// trailing partial record in `pending`
+fn decode_records(state: &mut StatePartial, field: usize, pending: &mut Vec<u8>, bytes: &[u8]) {
+    let record_size = FIELD_RECORD_SIZES[field];
+    let mut bytes = bytes;
+    if !pending.is_empty() {
+        let missing = (record_size - pending.len()).min(bytes.len());
+        pending.extend_from_slice(&bytes[..missing]);
+        bytes = &bytes[missing..];
+        if pending.len() < record_size {
+            return;
+        }
+        let record = std::mem::take(pending);
+        decode_records(state, field, pending, &record);
+    }
+    let complete = bytes.len() - bytes.len() % record_size;
+    let (records, rest) = bytes.split_at(complete);
+    match field {
+        0 => state.validators.extend(
+            records
+                .chunks_exact(VALIDATOR_SIZE)
+                .map(Validator::from_ssz),
+        ),
+        1 => state.balances.extend(convert_u8_to_u64(records)),
+        2 => state
+            .previous_epoch_participation
+            .extend_from_slice(records),
+        3 => state.current_epoch_participation.extend_from_slice(records),
+        _ => state.inactivity_scores.extend(convert_u8_to_u64(records)),
+    }
+    pending.extend_from_slice(rest);
 }
 
 fn slice(buf: &Bytes, range: Range<usize>) -> Result<Bytes> {
@@ -153,12 +746,55 @@ fn convert_u8_to_u64(input: &[u8]) -> Vec<u64> {
     output
 }
 
+/// States for the tests of other modules
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::*;
+    use crate::config::ParticipationFlags;
+
+    /// Flags of the participation bytes of [`test_state`]: source 1, target 2 and head 4
+    pub(crate) const TEST_FLAGS: ParticipationFlags = ParticipationFlags {
+        source: 1,
+        target: 2,
+        head: 4,
+    };
+
+    /// Active validator of 32 ETH, activated at genesis and not exiting
+    pub(crate) fn test_validator() -> Validator {
+        Validator {
+            withdrawal_credentials: [0; 32],
+            effective_balance: 32_000_000_000,
+            slashed: false,
+            activation_eligibility_epoch: 0,
+            activation_epoch: 0,
+            exit_epoch: FAR_FUTURE_EPOCH,
+            withdrawable_epoch: FAR_FUTURE_EPOCH,
+        }
+    }
+
+    /// State at slot 64 with one [`test_validator`] of 32 ETH and no inactivity per byte of the
+    /// previous epoch `participation`
+    pub(crate) fn test_state(participation: Vec<u8>) -> StatePartial {
+        let count = participation.len();
+        StatePartial {
+            slot: 64,
+            previous_epoch_participation: participation,
+            current_epoch_participation: vec![0; count],
+            inactivity_scores: vec![0; count],
+            block_roots: vec![],
+            balances: vec![32_000_000_000; count],
+            validators: vec![test_validator(); count],
+            justification: Default::default(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use bytes::BytesMut;
     use serde::Deserialize;
-    use std::{error::Error, fs, str::FromStr};
+    use std::{error::Error, fs};
 
     #[derive(Deserialize, Debug)]
     struct StateJsonStr {
@@ -166,6 +802,29 @@ mod tests {
         previous_epoch_participation: Vec<String>,
         current_epoch_participation: Vec<String>,
         inactivity_scores: Vec<String>,
+        block_roots: Vec<String>,
+        balances: Vec<String>,
+        validators: Vec<ValidatorJsonStr>,
+        justification_bits: String,
+        previous_justified_checkpoint: CheckpointJsonStr,
+        current_justified_checkpoint: CheckpointJsonStr,
+        finalized_checkpoint: CheckpointJsonStr,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct CheckpointJsonStr {
+        epoch: String,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct ValidatorJsonStr {
+        withdrawal_credentials: String,
+        effective_balance: String,
+        slashed: bool,
+        activation_eligibility_epoch: String,
+        activation_epoch: String,
+        exit_epoch: String,
+        withdrawable_epoch: String,
     }
 
     fn from_vec_str<T: FromStr>(vec_str: &[String]) -> Result<Vec<T>>
@@ -179,22 +838,183 @@ mod tests {
         Ok(vec_uint)
     }
 
-    #[test]
-    fn devnet_state() {
-        let config = ConfigSpec {
+    fn devnet_config() -> ConfigSpec {
+        ConfigSpec {
             seconds_per_slot: 5,
             slots_per_epoch: 16,
             slots_per_historical_root: 8192,
             epochs_per_historical_vector: 65536,
             epochs_per_slashings_vector: 8192,
+            sync_committee_size: 512,
+            max_committees_per_slot: 64,
+            target_committee_size: 128,
+            min_per_epoch_churn_limit: 4,
+            churn_limit_quotient: 65536,
+            max_per_epoch_activation_churn_limit: 8,
+            min_per_epoch_churn_limit_electra: 128_000_000_000,
+            max_per_epoch_activation_exit_churn_limit: 256_000_000_000,
+            altair_fork_epoch: Some(0),
+            bellatrix_fork_epoch: Some(0),
+            capella_fork_epoch: Some(1035),
+            deneb_fork_epoch: None,
+            electra_fork_epoch: None,
+            participation_flags: Default::default(),
+            config_name: None,
+            deposit_chain_id: None,
+        }
+    }
+
+    fn devnet_state_bytes(slot: u64) -> Bytes {
+        let state_bytes = fs::read(format!("src/fixtures/state_{slot}.ssz")).unwrap();
+        BytesMut::from_iter(state_bytes.iter()).freeze()
+    }
+
+    #[test]
+    fn validator_exit_status() {
+        let validator = Validator {
+            withdrawal_credentials: [0; 32],
+            effective_balance: 32_000_000_000,
+            slashed: false,
+            activation_eligibility_epoch: 0,
+            activation_epoch: 0,
+            exit_epoch: 10,
+            withdrawable_epoch: 20,
         };
+        let status = |epoch| {
+            (
+                validator.is_exiting(epoch),
+                validator.is_exited(epoch),
+                validator.is_withdrawable(epoch),
+            )
+        };
+        assert_eq!(status(9), (true, false, false));
+        assert_eq!(status(10), (false, true, false));
+        assert_eq!(status(20), (false, false, true));
+
+        let active = Validator {
+            exit_epoch: FAR_FUTURE_EPOCH,
+            withdrawable_epoch: FAR_FUTURE_EPOCH,
+            ..validator
+        };
+        assert!(!active.is_exiting(9) && !active.is_exited(9) && !active.is_withdrawable(9));
+    }
+
+    #[test]
+    fn validator_status() {
+        let validator = Validator {
+            withdrawal_credentials: [0; 32],
+            effective_balance: 32_000_000_000,
+            slashed: false,
+            activation_eligibility_epoch: 5,
+            activation_epoch: 10,
+            exit_epoch: 20,
+            withdrawable_epoch: 30,
+        };
+        assert_eq!(validator.status(9), ValidatorStatus::PendingQueued);
+        assert_eq!(validator.status(10), ValidatorStatus::ActiveExiting);
+        assert_eq!(validator.status(20), ValidatorStatus::ExitedUnslashed);
+        assert_eq!(validator.status(30), ValidatorStatus::WithdrawalPossible);
+        let slashed = Validator {
+            slashed: true,
+            ..validator.clone()
+        };
+        assert_eq!(slashed.status(10), ValidatorStatus::ActiveSlashed);
+        assert_eq!(slashed.status(20), ValidatorStatus::ExitedSlashed);
+        let withdrawn = Validator {
+            effective_balance: 0,
+            ..validator.clone()
+        };
+        assert_eq!(withdrawn.status(30), ValidatorStatus::WithdrawalDone);
+        let ongoing = Validator {
+            exit_epoch: FAR_FUTURE_EPOCH,
+            withdrawable_epoch: FAR_FUTURE_EPOCH,
+            ..validator.clone()
+        };
+        assert_eq!(ongoing.status(10), ValidatorStatus::ActiveOngoing);
+        let deposited = Validator {
+            activation_eligibility_epoch: FAR_FUTURE_EPOCH,
+            activation_epoch: FAR_FUTURE_EPOCH,
+            ..ongoing
+        };
+        assert_eq!(deposited.status(10), ValidatorStatus::PendingInitialized);
+    }
+
+    #[test]
+    fn fork_at_epoch() {
+        let config = devnet_config();
+        assert_eq!(Fork::at_epoch(&config, 0), Fork::Bellatrix);
+        assert_eq!(Fork::at_epoch(&config, 1034), Fork::Bellatrix);
+        assert_eq!(Fork::at_epoch(&config, 1035), Fork::Capella);
+        assert_eq!("Capella".parse::<Fork>().unwrap(), Fork::Capella);
+        assert!("fulu-ish".parse::<Fork>().is_err());
+    }
+
+    #[test]
+    fn devnet_state_fork_from_schedule() {
+        let config = devnet_config();
+        let state = deserialize_partial_state(&config, None, &devnet_state_bytes(148990)).unwrap();
+        assert_eq!(state.slot, 148990);
+    }
+
+    #[test]
+    fn devnet_state_block_proposed_at() {
+        let config = devnet_config();
+        let state = deserialize_partial_state(&config, None, &devnet_state_bytes(148990)).unwrap();
+        assert_eq!(state.block_proposed_at(148973), Some(true));
+        assert_eq!(state.block_proposed_at(148974), Some(false));
+        assert_eq!(state.block_proposed_at(148989), Some(true));
+        assert_eq!(state.block_proposed_at(148990), None);
+        assert_eq!(state.block_proposed_at(148990 - 8192), None);
+    }
+
+    #[test]
+    fn devnet_state_in_chunks() {
+        let config = devnet_config();
+        let state_buf = devnet_state_bytes(148990);
+        let full = deserialize_partial_state(&config, None, &state_buf).unwrap();
+        for chunk_size in [7, 4096, 1 << 20] {
+            let mut decoder = StateDecoder::new(&config, None);
+            for chunk in state_buf.chunks(chunk_size) {
+                decoder.push(chunk).unwrap();
+                if decoder.is_complete() {
+                    break;
+                }
+            }
+            let state = decoder.finish().unwrap();
+            assert_eq!(state.slot, full.slot);
+            assert_eq!(state.validators, full.validators);
+            assert_eq!(state.balances, full.balances);
+            assert_eq!(state.inactivity_scores, full.inactivity_scores);
+            assert_eq!(
+                state.previous_epoch_participation,
+                full.previous_epoch_participation
+            );
+            assert_eq!(state.block_roots, full.block_roots);
+        }
+
+        let mut decoder = StateDecoder::new(&config, None);
+        decoder.push(&state_buf[..state_buf.len() / 2]).unwrap();
+        assert!(decoder.finish().is_err());
+    }
+
+    #[test]
+    fn devnet_state_wrong_fork() {
+        let config = devnet_config();
+        let state_buf = devnet_state_bytes(148990);
+        assert!(deserialize_partial_state(&config, Some(Fork::Altair), &state_buf).is_err());
+        assert!(deserialize_partial_state(&config, Some(Fork::Phase0), &state_buf).is_err());
+    }
+
+    #[test]
+    fn devnet_state() {
+        let config = devnet_config();
 
         let slot = 148990;
         let state_json = fs::read_to_string(format!("src/fixtures/state_{slot}.json")).unwrap();
-        let state_bytes = fs::read(format!("src/fixtures/state_{slot}.ssz")).unwrap();
         let state_json: StateJsonStr = serde_json::from_str(&state_json).unwrap();
-        let state_buf = BytesMut::from_iter(state_bytes.iter()).freeze();
-        let state = deserialize_partial_state(&config, &state_buf).unwrap();
+        let state =
+            deserialize_partial_state(&config, Some(Fork::Capella), &devnet_state_bytes(slot))
+                .unwrap();
 
         assert_eq!(slot, state.slot);
         assert_eq!(slot, state_json.slot.parse::<u64>().unwrap());
@@ -214,5 +1034,69 @@ mod tests {
             from_vec_str::<u64>(&state_json.inactivity_scores).unwrap(),
             "inactivity_scores"
         );
+        assert_eq!(
+            state.balances,
+            from_vec_str::<u64>(&state_json.balances).unwrap(),
+            "balances"
+        );
+        assert_eq!(
+            state.validators,
+            state_json
+                .validators
+                .iter()
+                .map(|v| Validator {
+                    withdrawal_credentials: hex::decode(&v.withdrawal_credentials[2..])
+                        .unwrap()
+                        .try_into()
+                        .unwrap(),
+                    effective_balance: v.effective_balance.parse().unwrap(),
+                    slashed: v.slashed,
+                    activation_eligibility_epoch: v.activation_eligibility_epoch.parse().unwrap(),
+                    activation_epoch: v.activation_epoch.parse().unwrap(),
+                    exit_epoch: v.exit_epoch.parse().unwrap(),
+                    withdrawable_epoch: v.withdrawable_epoch.parse().unwrap(),
+                })
+                .collect::<Vec<_>>(),
+            "validators"
+        );
+        assert_eq!(
+            state
+                .block_roots
+                .iter()
+                .map(|root| format!("0x{}", hex::encode(root)))
+                .collect::<Vec<_>>(),
+            state_json.block_roots,
+            "block_roots"
+        );
+        assert_eq!(
+            state.justification,
+            Justification {
+                bits: u8::from_str_radix(&state_json.justification_bits[2..], 16).unwrap(),
+                previous_justified_epoch: state_json
+                    .previous_justified_checkpoint
+                    .epoch
+                    .parse()
+                    .unwrap(),
+                current_justified_epoch: state_json
+                    .current_justified_checkpoint
+                    .epoch
+                    .parse()
+                    .unwrap(),
+                finalized_epoch: state_json.finalized_checkpoint.epoch.parse().unwrap(),
+            },
+            "justification"
+        );
+    }
+
+    #[test]
+    fn justification_bits() {
+        let justification = Justification {
+            bits: 0b0110,
+            ..Default::default()
+        };
+        let justified: Vec<bool> = (0..JUSTIFICATION_BITS_LENGTH)
+            .map(|epochs_ago| justification.is_justified(epochs_ago))
+            .collect();
+        assert_eq!(justified, vec![false, true, true, false]);
     }
 }
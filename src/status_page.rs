@@ -0,0 +1,150 @@
+use crate::participation::{GroupParticipation, ParticipationSnapshot, TOTAL_GROUP};
+use crate::ranges::{GroupThresholds, ThresholdsByGroup};
+use std::fmt::Write;
+
+/// Seconds between reloads of the status page
+pub const STATUS_PAGE_REFRESH_SECS: u64 = 12;
+
+/// Latest participation of one beacon node, with the thresholds coloring it
+pub struct StatusSection<'a> {
+    /// Heading of the section, empty for a single beacon node
+    pub name: &'a str,
+    pub snapshot: Option<&'a ParticipationSnapshot>,
+    pub thresholds: &'a ThresholdsByGroup,
+    /// Levels of groups without thresholds of their own
+    pub defaults: GroupThresholds,
+}
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em}\
+table{border-collapse:collapse}\
+th,td{padding:.3em .8em;text-align:right;border-bottom:1px solid #ddd}\
+th:first-child,td:first-child,th:nth-child(2),td:nth-child(2){text-align:left}\
+.ok{color:#1a7f37}.warn{color:#9a6700}.crit{color:#cf222e;font-weight:bold}";
+
+/// Self-refreshing HTML page with the participation table of each section, colored by the
+/// most severe threshold level breached
+pub fn render_status_page(sections: &[StatusSection]) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+         <meta http-equiv=\"refresh\" content=\"{STATUS_PAGE_REFRESH_SECS}\">\
+         <title>beacon-metrics-gazer</title><style>{STYLE}</style></head><body>\n"
+    );
+    for section in sections {
+        if !section.name.is_empty() {
+            let _ = writeln!(html, "<h2>{}</h2>", escape_html(section.name));
+        }
+        let Some(snapshot) = section.snapshot else {
+            html.push_str("<p>No state fetched yet</p>\n");
+            continue;
+        };
+        let _ = writeln!(
+            html,
+            "<p>Epoch {} of the state at slot {}</p>",
+            snapshot.epoch, snapshot.slot
+        );
+        html.push_str(
+            "<table><tr><th>Name</th><th>Range</th><th>Validators</th><th>Source</th>\
+             <th>Target</th><th>Head</th><th>Balance sum (ETH)</th></tr>\n",
+        );
+        for group in snapshot.groups.iter().chain([&snapshot.total]) {
+            push_group_row(&mut html, group, section);
+        }
+        html.push_str("</table>\n");
+    }
+    html.push_str("</body></html>\n");
+    html
+}
+
+fn push_group_row(html: &mut String, group: &GroupParticipation, section: &StatusSection) {
+    let thresholds = section
+        .thresholds
+        .get(&group.name)
+        .copied()
+        .unwrap_or_default()
+        .or(section.defaults);
+    let cell = |ratio: f32| {
+        let class = thresholds.level(ratio).unwrap_or("ok");
+        format!("<td class=\"{class}\">{ratio}</td>")
+    };
+    let name = match group.name.as_str() {
+        TOTAL_GROUP => "TOTAL",
+        name => name,
+    };
+    let _ = writeln!(
+        html,
+        "<tr><td>{}</td><td>{}</td><td>{}</td>{}{}{}<td>{:.2}</td></tr>",
+        escape_html(name),
+        escape_html(&group.ranges),
+        group.validators,
+        cell(group.source),
+        cell(group.target),
+        cell(group.head),
+        group.balance_gwei_sum as f64 / 1e9,
+    );
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn render_status_page_test() {
+        let group = |name: &str, target| GroupParticipation {
+            name: name.to_owned(),
+            ranges: "0..10".to_owned(),
+            validators: 10,
+            source: 1.0,
+            target,
+            head: 1.0,
+            inactivity_scores_avg: 0.0,
+            balance_gwei_sum: 320_000_000_000,
+            slashed_validators: 0,
+        };
+        let snapshot = ParticipationSnapshot {
+            slot: 64,
+            epoch: 1,
+            timestamp: 0,
+            groups: vec![group("<lh>", 0.9), group("teku", 0.5)],
+            total: group(TOTAL_GROUP, 0.7),
+        };
+        let thresholds = HashMap::from([(
+            "teku".to_owned(),
+            GroupThresholds {
+                warn: Some(0.4),
+                crit: None,
+            },
+        )]);
+        let defaults = GroupThresholds {
+            warn: Some(0.95),
+            crit: Some(0.8),
+        };
+        let html = render_status_page(&[
+            StatusSection {
+                name: "holesky",
+                snapshot: Some(&snapshot),
+                thresholds: &thresholds,
+                defaults,
+            },
+            StatusSection {
+                name: "sepolia",
+                snapshot: None,
+                thresholds: &thresholds,
+                defaults,
+            },
+        ]);
+        assert!(html.contains("<tr><td>&lt;lh&gt;</td><td>0..10</td><td>10</td><td class=\"ok\">1</td><td class=\"warn\">0.9</td>"));
+        // Own warn level, crit taken from the defaults
+        assert!(html.contains("<td>teku</td><td>0..10</td><td>10</td><td class=\"ok\">1</td><td class=\"crit\">0.5</td>"));
+        assert!(html.contains("<td>TOTAL</td>"));
+        assert!(html.contains("<td>320.00</td>"));
+        assert!(html.contains("<h2>sepolia</h2>\n<p>No state fetched yet</p>"));
+    }
+}
@@ -0,0 +1,51 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+/// Beacon node monitored next to others by one exporter, see `--target`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Target {
+    pub name: String,
+    pub url: String,
+    pub ranges_file: String,
+}
+
+/// Parse a target with format `name=url=ranges-file`. The URL may contain `=` itself, the name
+/// ends at the first and the ranges file starts after the last.
+pub fn parse_target(input: &str) -> Result<Target> {
+    let invalid = || anyhow!("Invalid target {input}, expected name=url=ranges-file");
+    let (name, rest) = input.split_once('=').ok_or_else(invalid)?;
+    let (url, ranges_file) = rest.rsplit_once('=').ok_or_else(invalid)?;
+    let valid_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+    if !valid_name || url.is_empty() || ranges_file.is_empty() {
+        return Err(invalid());
+    }
+    Ok(Target {
+        name: name.to_owned(),
+        url: url.to_owned(),
+        ranges_file: ranges_file.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_target_test() {
+        assert_eq!(
+            parse_target("holesky=http://localhost:5052?key=1=ranges/holesky.txt").unwrap(),
+            Target {
+                name: "holesky".to_owned(),
+                url: "http://localhost:5052?key=1".to_owned(),
+                ranges_file: "ranges/holesky.txt".to_owned(),
+            }
+        );
+        assert!(parse_target("holesky=http://localhost:5052").is_err());
+        assert!(parse_target("=http://localhost:5052=ranges.txt").is_err());
+        assert!(parse_target("a b=http://localhost:5052=ranges.txt").is_err());
+        assert!(parse_target("holesky==ranges.txt").is_err());
+    }
+}
@@ -1,5 +1,7 @@
 use anyhow::{anyhow, Result};
 use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
     path::Path,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
@@ -7,12 +9,22 @@ use tokio::fs;
 use url::Url;
 
 use crate::config::{ConfigSpec, Genesis};
+use crate::http_client::beacon_client;
 
-/// Given a path_or_url, if it's a valid URL download it. Else read from it as a local path
+/// Given a path_or_url, if it's a valid URL download it with the [`beacon_client`]. Else read from
+/// it as a local path
 pub async fn resolve_path_or_url(path_or_url: &str) -> Result<String> {
+    resolve_path_or_url_with(beacon_client(), path_or_url).await
+}
+
+/// [`resolve_path_or_url`] downloading with `client`
+pub async fn resolve_path_or_url_with(
+    client: &reqwest::Client,
+    path_or_url: &str,
+) -> Result<String> {
     if Url::parse(path_or_url).is_ok() {
         // If it's a valid URL
-        let response = reqwest::get(path_or_url).await?;
+        let response = client.get(path_or_url).send().await?;
         let content = response.text().await?;
         Ok(content)
     } else if Path::new(path_or_url).exists() {
@@ -67,3 +79,33 @@ pub fn to_next_epoch_start(genesis: &Genesis, config: &ConfigSpec) -> Result<Dur
         );
     Ok(start_time_next_epoch.duration_since(now)?)
 }
+
+/// Delay of a fetch after the epoch start: `stagger_fraction` of a slot plus a random share of
+/// up to `jitter_secs`, to spread replicas fetching from the same node
+pub fn fetch_delay(seconds_per_slot: u64, stagger_fraction: f64, jitter_secs: f64) -> Duration {
+    // Randomly seeded per instance, enough to not line up with another replica
+    let random = RandomState::new().build_hasher().finish();
+    let unit = (random >> 11) as f64 / (1u64 << 53) as f64;
+    Duration::from_secs_f64(seconds_per_slot as f64 * stagger_fraction + unit * jitter_secs)
+}
+
+/// Parse the fraction of a slot of `--stagger-fraction`, in [0, 1)
+pub fn parse_stagger_fraction(input: &str) -> Result<f64> {
+    let fraction: f64 = input.parse()?;
+    if !(0.0..1.0).contains(&fraction) {
+        return Err(anyhow!("Stagger fraction must be in [0, 1): {}", input));
+    }
+    Ok(fraction)
+}
+
+/// Parse the seconds of `--fetch-jitter-secs`, finite and not negative
+pub fn parse_jitter_secs(input: &str) -> Result<f64> {
+    let secs: f64 = input.parse()?;
+    if !secs.is_finite() || secs < 0.0 {
+        return Err(anyhow!(
+            "Jitter must be a non negative number of seconds: {}",
+            input
+        ));
+    }
+    Ok(secs)
+}
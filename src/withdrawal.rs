@@ -0,0 +1,100 @@
+use crate::inclusion::parse_hex;
+use crate::ranges::IndexRanges;
+use crate::ssz_state::StatePartial;
+use anyhow::{anyhow, Result};
+
+pub type Address = [u8; 20];
+
+/// Execution address of 0x01 and 0x02 withdrawal credentials, BLS (0x00) credentials have none
+pub fn withdrawal_address(credentials: &[u8; 32]) -> Option<Address> {
+    match credentials[0] {
+        0x01 | 0x02 => Some(credentials[12..].try_into().unwrap()),
+        _ => None,
+    }
+}
+
+/// Parse a 0x prefixed hex execution address
+pub fn parse_address(input: &str) -> Result<Address> {
+    parse_hex(input.trim())?
+        .try_into()
+        .map_err(|_| anyhow!("invalid address {input}, expected 20 bytes"))
+}
+
+pub fn format_address(address: &Address) -> String {
+    let hex: String = address.iter().map(|byte| format!("{byte:02x}")).collect();
+    format!("0x{hex}")
+}
+
+/// Group the validators of `state` by withdrawal address, each group named by its lowercase
+/// address. Consecutive validators of an address form one range. Only `addresses` are grouped if
+/// not empty, validators with BLS credentials are never grouped.
+pub fn ranges_by_withdrawal_address(state: &StatePartial, addresses: &[Address]) -> IndexRanges {
+    let mut ranges = IndexRanges::new();
+    let mut current: Option<(Address, usize)> = None;
+    let address_of = |index: usize| {
+        state
+            .validators
+            .get(index)
+            .and_then(|validator| withdrawal_address(&validator.withdrawal_credentials))
+            .filter(|address| addresses.is_empty() || addresses.contains(address))
+    };
+    // One past the end closes the last run
+    for index in 0..=state.validators.len() {
+        let address = address_of(index);
+        if let Some((run_address, start)) = current {
+            if address == Some(run_address) {
+                continue;
+            }
+            ranges.push((format_address(&run_address), start..index));
+        }
+        current = address.map(|address| (address, index));
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssz_state::fixtures::{test_state, test_validator};
+    use crate::ssz_state::Validator;
+
+    #[test]
+    fn ranges_by_withdrawal_address_test() {
+        let credentials = |prefix: u8, address_byte: u8| {
+            let mut credentials = [0; 32];
+            credentials[0] = prefix;
+            credentials[12..].fill(address_byte);
+            credentials
+        };
+        let validators = [
+            credentials(1, 0xaa),
+            credentials(1, 0xaa),
+            credentials(0, 0xaa),
+            credentials(2, 0xbb),
+            credentials(1, 0xaa),
+        ]
+        .into_iter()
+        .map(|withdrawal_credentials| Validator {
+            withdrawal_credentials,
+            ..test_validator()
+        })
+        .collect();
+        let state = StatePartial {
+            slot: 0,
+            validators,
+            ..test_state(vec![])
+        };
+        let aa = format!("0x{}", "aa".repeat(20));
+        let bb = format!("0x{}", "bb".repeat(20));
+        assert_eq!(
+            ranges_by_withdrawal_address(&state, &[]),
+            vec![(aa.clone(), 0..2), (bb.clone(), 3..4), (aa.clone(), 4..5)]
+        );
+        let filter = parse_address(&bb.to_uppercase().replace("0X", "0x")).unwrap();
+        assert_eq!(
+            ranges_by_withdrawal_address(&state, &[filter]),
+            vec![(bb, 3..4)]
+        );
+        assert!(parse_address("0xaabb").is_err());
+    }
+}